@@ -0,0 +1,107 @@
+//! Size, allocations and time of the `GetLayout` reply of a large menu
+//!
+//! Run with `cargo bench --bench layout`. The menu is read by a host on a peer-to-peer
+//! connection, so the allocations and the time include both ends of the socket, not only the
+//! encoding of the layout.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use ksni::menu::{CheckmarkItem, StandardItem, SubMenu};
+use ksni::raw::{RawTray, MENU_PATH};
+use ksni::MenuItem;
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+struct LargeMenu;
+
+impl ksni::Tray for LargeMenu {
+    fn id(&self) -> String {
+        "bench".into()
+    }
+    fn menu(&self) -> Vec<MenuItem<Self>> {
+        (0..100)
+            .map(|i| {
+                SubMenu {
+                    label: format!("Submenu {i}").into(),
+                    submenu: (0..20)
+                        .map(|j| {
+                            if j % 2 == 0 {
+                                StandardItem {
+                                    label: "Item".into(),
+                                    icon_name: "document-open".into(),
+                                    ..Default::default()
+                                }
+                                .into()
+                            } else {
+                                CheckmarkItem {
+                                    label: "Option".into(),
+                                    checked: j % 3 == 0,
+                                    ..Default::default()
+                                }
+                                .into()
+                            }
+                        })
+                        .collect(),
+                    ..Default::default()
+                }
+                .into()
+            })
+            .collect()
+    }
+}
+
+async fn get_layout(menu: &zbus::Proxy<'_>) -> zbus::Result<zbus::Message> {
+    menu.call_method("GetLayout", &(0, -1, Vec::<String>::new()))
+        .await
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> zbus::Result<()> {
+    const RUNS: u32 = 20;
+    let raw = RawTray::new(LargeMenu);
+    let (tray_socket, host_socket) = tokio::net::UnixStream::pair()?;
+    let tray_conn = zbus::connection::Builder::unix_stream(tray_socket)
+        .server(zbus::Guid::generate())?
+        .p2p()
+        .serve_at(MENU_PATH, raw.dbus_menu())?
+        .build();
+    let conn = zbus::connection::Builder::unix_stream(host_socket)
+        .p2p()
+        .build();
+    let (_tray_conn, conn) = tokio::try_join!(tray_conn, conn)?;
+    // the destination is not routed on a peer-to-peer connection
+    let menu =
+        zbus::Proxy::new(&conn, "org.kde.bench", MENU_PATH, "com.canonical.dbusmenu").await?;
+
+    // also warms up the connection, the menu is already flattened by `RawTray::new`
+    let size = get_layout(&menu).await?.body().data().len();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        get_layout(&menu).await?;
+    }
+    let allocations_per_run = (ALLOCATIONS.load(Ordering::Relaxed) - allocations) / RUNS as usize;
+    println!("GetLayout of 2100 items:");
+    println!("  {size} bytes");
+    println!("  {allocations_per_run} allocations");
+    println!("  {:?}", start.elapsed() / RUNS);
+    Ok(())
+}
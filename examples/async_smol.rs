@@ -71,7 +71,6 @@ impl ksni::Tray for MyTray {
                         ..Default::default()
                     },
                 ],
-                ..Default::default()
             }
             .into(),
             CheckmarkItem {
@@ -68,7 +68,6 @@ impl ksni::Tray for MyTray {
                         ..Default::default()
                     },
                 ],
-                ..Default::default()
             }
             .into(),
             CheckmarkItem {
@@ -0,0 +1,275 @@
+//! Exports a large generated menu, then reads it back over D-Bus the way a host does,
+//! checking the ID mapping and the `children-display` flags.
+//!
+//! The tray is served on a peer-to-peer connection to itself, no session bus is needed. Exits
+//! with a non-zero status if any check failed.
+
+use std::collections::HashMap;
+
+use ksni::menu::*;
+use ksni::raw::{RawTray, ITEM_PATH, MENU_PATH};
+use serde::Deserialize;
+use zbus::zvariant::{OwnedValue, Str, Type, Value};
+
+const SUBMENUS: usize = 20;
+const ITEMS_PER_SUBMENU: usize = 25;
+
+struct Conformance {
+    checked: Vec<bool>,
+    // changes the layout
+    extra_items: usize,
+}
+
+impl ksni::Tray for Conformance {
+    fn id(&self) -> String {
+        "ksni-conformance".into()
+    }
+    fn title(&self) -> String {
+        "ksni conformance test".into()
+    }
+    fn menu(&self) -> Vec<ksni::MenuItem<Self>> {
+        let mut menu: Vec<ksni::MenuItem<Self>> = (0..SUBMENUS)
+            .map(|i| {
+                let submenu = (0..ITEMS_PER_SUBMENU)
+                    .map(|j| match j % 5 {
+                        0 => MenuItem::Separator,
+                        1 => CheckmarkItem {
+                            label: format!("{i}.{j}").into(),
+                            checked: self.checked[i],
+                            activate: Box::new(move |this: &mut Self| {
+                                this.checked[i] = !this.checked[i]
+                            }),
+                            ..Default::default()
+                        }
+                        .into(),
+                        2 => SubMenu {
+                            label: format!("{i}.{j}").into(),
+                            submenu: vec![StandardItem {
+                                label: format!("{i}.{j}.0").into(),
+                                ..Default::default()
+                            }
+                            .into()],
+                            ..Default::default()
+                        }
+                        .into(),
+                        _ => StandardItem {
+                            label: format!("{i}.{j}").into(),
+                            ..Default::default()
+                        }
+                        .into(),
+                    })
+                    .collect();
+                SubMenu {
+                    label: format!("{i}").into(),
+                    submenu,
+                    ..Default::default()
+                }
+                .into()
+            })
+            .collect();
+        for i in 0..self.extra_items {
+            menu.push(
+                StandardItem {
+                    label: format!("extra {i}").into(),
+                    ..Default::default()
+                }
+                .into(),
+            );
+        }
+        menu
+    }
+}
+
+#[derive(Debug, Type, Deserialize, Value, OwnedValue)]
+struct Layout {
+    id: i32,
+    properties: HashMap<String, OwnedValue>,
+    children: Vec<OwnedValue>,
+}
+
+impl Layout {
+    fn children(&self) -> Vec<Layout> {
+        self.children
+            .iter()
+            .map(|child| {
+                Layout::try_from(child.try_clone().expect("no fd in layout"))
+                    .expect("child should be a layout")
+            })
+            .collect()
+    }
+
+    fn label(&self) -> Option<String> {
+        string_of(self.properties.get("label")?)
+    }
+
+    fn is_submenu(&self) -> bool {
+        self.properties
+            .get("children-display")
+            .and_then(string_of)
+            .is_some_and(|s| s == "submenu")
+    }
+}
+
+fn string_of(value: &OwnedValue) -> Option<String> {
+    value.downcast_ref::<Str>().ok().map(|s| s.to_string())
+}
+
+#[derive(Default)]
+struct Report {
+    failed: usize,
+}
+
+impl Report {
+    fn check(&mut self, name: &str, ok: bool) {
+        println!("[{}] {name}", if ok { "PASS" } else { "FAIL" });
+        if !ok {
+            self.failed += 1;
+        }
+    }
+}
+
+struct Menu<'a>(zbus::Proxy<'a>);
+
+impl Menu<'_> {
+    async fn layout(&self, parent: i32, depth: i32, props: &[&str]) -> zbus::Result<(u32, Layout)> {
+        self.0.call("GetLayout", &(parent, depth, props)).await
+    }
+
+    async fn group_properties(
+        &self,
+        ids: &[i32],
+        props: &[&str],
+    ) -> zbus::Result<Vec<(i32, HashMap<String, OwnedValue>)>> {
+        self.0.call("GetGroupProperties", &(ids, props)).await
+    }
+
+    async fn click(&self, id: i32) -> zbus::Result<()> {
+        let data = OwnedValue::from(0i32);
+        self.0.call("Event", &(id, "clicked", data, 0u32)).await
+    }
+}
+
+// walks the whole layout, returns (id, label, is_submenu, number of children) in depth first order
+fn walk(layout: &Layout, out: &mut Vec<(i32, Option<String>, bool, usize)>) {
+    let children = layout.children();
+    out.push((
+        layout.id,
+        layout.label(),
+        layout.is_submenu(),
+        children.len(),
+    ));
+    for child in &children {
+        walk(child, out);
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let raw = RawTray::new(Conformance {
+        checked: vec![false; SUBMENUS],
+        extra_items: 0,
+    });
+
+    // the tray and the host are the two ends of a socket
+    let (tray_socket, host_socket) = tokio::net::UnixStream::pair()?;
+    let tray_conn = zbus::connection::Builder::unix_stream(tray_socket)
+        .server(zbus::Guid::generate())?
+        .p2p()
+        .serve_at(ITEM_PATH, raw.status_notifier_item())?
+        .serve_at(MENU_PATH, raw.dbus_menu())?
+        .build();
+    let conn = zbus::connection::Builder::unix_stream(host_socket)
+        .p2p()
+        .build();
+    let (tray_conn, conn) = tokio::try_join!(tray_conn, conn)?;
+
+    // look up the menu like a host, the destination is not routed on a peer-to-peer connection
+    let service = "org.kde.StatusNotifierItem-conformance";
+    let item = zbus::Proxy::new(&conn, service, ITEM_PATH, "org.kde.StatusNotifierItem").await?;
+    let menu_path: zbus::zvariant::OwnedObjectPath = item.get_property("Menu").await?;
+    let menu = Menu(zbus::Proxy::new(&conn, service, menu_path, "com.canonical.dbusmenu").await?);
+
+    let mut report = Report::default();
+
+    let (revision, root) = menu.layout(0, -1, &[]).await?;
+    let mut nodes = Vec::new();
+    walk(&root, &mut nodes);
+    let expected_items = 1 + SUBMENUS * (1 + ITEMS_PER_SUBMENU + ITEMS_PER_SUBMENU / 5);
+    report.check(
+        "full layout contains every item",
+        nodes.len() == expected_items,
+    );
+    let mut ids: Vec<i32> = nodes.iter().map(|n| n.0).collect();
+    ids.sort_unstable();
+    ids.dedup();
+    report.check("ids are unique", ids.len() == nodes.len());
+    report.check("root id is 0", root.id == 0);
+    report.check(
+        "children-display is set if and only if an item has children",
+        nodes.iter().all(|n| n.2 == (n.3 > 0)),
+    );
+
+    let ids: Vec<i32> = nodes.iter().skip(1).map(|n| n.0).collect();
+    let props = menu.group_properties(&ids, &["label"]).await?;
+    let labels: HashMap<i32, Option<String>> = nodes.iter().map(|n| (n.0, n.1.clone())).collect();
+    report.check(
+        "GetGroupProperties matches GetLayout",
+        props.iter().all(|(id, props)| {
+            props.len() == 1 && labels.get(id) == Some(&props.get("label").and_then(string_of))
+        }),
+    );
+
+    let first_submenu = &root.children()[0];
+    let (_, shallow) = menu.layout(first_submenu.id, 0, &[]).await?;
+    report.check(
+        "recursionDepth 0 delivers no children",
+        shallow.children.is_empty() && shallow.is_submenu(),
+    );
+    let (_, one_level) = menu.layout(first_submenu.id, 1, &[]).await?;
+    let children = one_level.children();
+    report.check(
+        "recursionDepth 1 delivers one level",
+        children.len() == ITEMS_PER_SUBMENU && children.iter().all(|c| c.children.is_empty()),
+    );
+    report.check(
+        "children-display survives the recursionDepth limit",
+        children.iter().filter(|c| c.is_submenu()).count() == ITEMS_PER_SUBMENU / 5,
+    );
+
+    let checkmark = &children[1];
+    menu.click(checkmark.id).await?;
+    let props = menu
+        .group_properties(&[checkmark.id], &["toggle-state"])
+        .await?;
+    report.check(
+        "clicking a checkmark toggles it without changing ids",
+        props.first().and_then(|(_, p)| p.get("toggle-state")) == Some(&OwnedValue::from(1i32)),
+    );
+    let (new_revision, _) = menu.layout(0, -1, &[]).await?;
+    report.check(
+        "property changes keep the revision",
+        new_revision == revision,
+    );
+
+    raw.update(&tray_conn, |tray| tray.extra_items += 1).await?;
+    let (new_revision, new_root) = menu.layout(0, -1, &[]).await?;
+    report.check("layout changes bump the revision", new_revision > revision);
+    report.check(
+        "layout changes invalidate old ids",
+        menu.layout(first_submenu.id, -1, &[]).await.is_err(),
+    );
+    let mut new_nodes = Vec::new();
+    walk(&new_root, &mut new_nodes);
+    report.check(
+        "new layout contains the new item",
+        new_nodes.len() == expected_items + 1
+            && new_nodes.last().and_then(|n| n.1.as_deref()) == Some("extra 0"),
+    );
+
+    if report.failed > 0 {
+        eprintln!("{} check(s) failed", report.failed);
+        std::process::exit(1);
+    }
+    println!("all checks passed");
+    Ok(())
+}
@@ -0,0 +1,32 @@
+use ksni::TrayMethods;
+
+#[derive(Debug, ksni::StaticTray)]
+#[tray(
+    title = "Derived",
+    icon_name = "help-about",
+    tool_tip = "Generated by ksni::StaticTray"
+)]
+#[menu_item(label = "Hello", icon_name = "face-smile", action = hello)]
+#[menu_item(separator)]
+#[menu_item(label = "Exit", icon_name = "application-exit", action = exit)]
+struct MyTray {
+    count: usize,
+}
+
+impl MyTray {
+    fn hello(&mut self) {
+        self.count += 1;
+        println!("Hello #{}", self.count);
+    }
+    fn exit(&mut self) {
+        std::process::exit(0);
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    MyTray { count: 0 }.spawn().await.unwrap();
+
+    // Run forever
+    std::future::pending().await
+}
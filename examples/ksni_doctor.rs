@@ -0,0 +1,56 @@
+//! Checks whether this desktop session can show trays, and prints advice if not
+
+use ksni::diagnostics::{self, GnomeExtensionState};
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let report = diagnostics::probe().await;
+
+    let yes_no = |b: bool| if b { "yes" } else { "no" };
+    println!(
+        "XDG_CURRENT_DESKTOP:        {}",
+        report.current_desktop.as_deref().unwrap_or("(unset)")
+    );
+    match &report.session_bus_error {
+        None => println!("session bus:                reachable"),
+        Some(e) => println!("session bus:                unreachable ({e})"),
+    }
+    println!(
+        "StatusNotifierWatcher:      {}",
+        yes_no(report.watcher_present)
+    );
+    println!(
+        "StatusNotifierHost:         {}",
+        yes_no(report.host_registered)
+    );
+    if let Some(version) = report.protocol_version {
+        println!("protocol version:           {version}");
+    }
+    if report.watcher_present {
+        println!(
+            "registered items:           {}",
+            report.registered_items.len()
+        );
+        for item in &report.registered_items {
+            println!("    {item}");
+        }
+    }
+    if let Some(state) = report.gnome_appindicator {
+        let state = match state {
+            GnomeExtensionState::NotInstalled => "not installed",
+            GnomeExtensionState::Disabled => "disabled",
+            GnomeExtensionState::Enabled => "enabled",
+        };
+        println!("GNOME AppIndicator:         {state}");
+    }
+
+    println!();
+    if report.is_supported() {
+        println!("Trays are supported in this session");
+    } else {
+        for advice in report.advice() {
+            println!("* {advice}");
+        }
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,142 @@
+#ifndef KSNI_H
+#define KSNI_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * See [`Status`]
+ */
+typedef enum KsniStatus {
+  KsniStatus_Passive,
+  KsniStatus_Active,
+  KsniStatus_NeedsAttention,
+} KsniStatus;
+
+/**
+ * A tray, created by [`ksni_tray_new`] and freed by [`ksni_tray_free`]
+ */
+typedef struct KsniTray KsniTray;
+
+/**
+ * Called with the `user_data` given with it
+ */
+typedef void (*KsniCallback)(void *user_data);
+
+/**
+ * Create a tray, returns null if `id` is null or not UTF-8
+ *
+ * # Safety
+ *
+ * `id` must be null or a NUL-terminated string
+ */
+struct KsniTray *ksni_tray_new(const char *id);
+
+/**
+ * Set the title, returns `false` if an argument is invalid or the tray has been shutdown
+ *
+ * # Safety
+ *
+ * `tray` must be null or returned by [`ksni_tray_new`], `title` must be null or a
+ * NUL-terminated string
+ */
+bool ksni_tray_set_title(const struct KsniTray *tray, const char *title);
+
+/**
+ * Set the themed icon, returns `false` if an argument is invalid or the tray has been
+ * shutdown
+ *
+ * # Safety
+ *
+ * Same as [`ksni_tray_set_title`]
+ */
+bool ksni_tray_set_icon_name(const struct KsniTray *tray, const char *icon_name);
+
+/**
+ * Set the status, returns `false` if the tray is null or has been shutdown
+ *
+ * # Safety
+ *
+ * `tray` must be null or returned by [`ksni_tray_new`]
+ */
+bool ksni_tray_set_status(const struct KsniTray *tray, enum KsniStatus status);
+
+/**
+ * Append an item to the menu, `callback` is called with `user_data` when it's clicked
+ *
+ * Returns `false` if an argument is invalid or the tray has been shutdown.
+ *
+ * # Safety
+ *
+ * Same as [`ksni_tray_set_title`] for `tray` and `label`. `callback` is called on the callback
+ * thread of the tray, `user_data` must be usable there until the tray is freed.
+ */
+bool ksni_tray_add_menu_item(const struct KsniTray *tray,
+                             const char *label,
+                             KsniCallback callback,
+                             void *user_data);
+
+/**
+ * Append a separator to the menu, returns `false` if the tray is null or has been shutdown
+ *
+ * # Safety
+ *
+ * `tray` must be null or returned by [`ksni_tray_new`]
+ */
+bool ksni_tray_add_menu_separator(const struct KsniTray *tray);
+
+/**
+ * Remove all items of the menu, returns `false` if the tray is null or has been shutdown
+ *
+ * # Safety
+ *
+ * `tray` must be null or returned by [`ksni_tray_new`]
+ */
+bool ksni_tray_clear_menu(const struct KsniTray *tray);
+
+/**
+ * Run the tray service in background
+ *
+ * Returns `false` if the tray is null, has already been started, or failed to start.
+ *
+ * # Safety
+ *
+ * `tray` must be null or returned by [`ksni_tray_new`]
+ */
+bool ksni_tray_spawn(const struct KsniTray *tray);
+
+/**
+ * Run the tray service on the current thread, returns after [`ksni_tray_shutdown`]
+ *
+ * Returns `false` if the tray is null, has already been started, or failed to start.
+ *
+ * # Safety
+ *
+ * `tray` must be null or returned by [`ksni_tray_new`]
+ */
+bool ksni_tray_run(const struct KsniTray *tray);
+
+/**
+ * Shutdown the tray service and wait for it, does nothing if it's not running
+ *
+ * # Safety
+ *
+ * `tray` must be null or returned by [`ksni_tray_new`]
+ */
+void ksni_tray_shutdown(const struct KsniTray *tray);
+
+/**
+ * Shutdown the tray service and free the tray
+ *
+ * Waits for the running callback unless called from it, no callback is called after this.
+ *
+ * # Safety
+ *
+ * `tray` must be null or returned by [`ksni_tray_new`], and not used after this
+ */
+void ksni_tray_free(struct KsniTray *tray);
+
+#endif  /* KSNI_H */
@@ -0,0 +1,301 @@
+//! Derive macros for [ksni](https://docs.rs/ksni), use them through the "derive" feature of ksni
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, DeriveInput, Ident, LitStr};
+
+/// Generates a `ksni::Tray` impl from attributes
+///
+/// Properties are set with `#[tray(...)]`:
+///
+/// - `id = "..."`, defaults to `env!("CARGO_PKG_NAME")`
+/// - `title = "..."`
+/// - `icon_name = "..."`
+/// - `icon_theme_path = "..."`
+/// - `category = Hardware`, a variant of `ksni::Category`
+/// - `tool_tip = "..."`, the description of the tooltip, titled with `title`
+/// - `menu_on_activate`, sets `Tray::MENU_ON_ACTIVATE`
+///
+/// Every `#[menu_item(...)]` adds an item to the menu, in order:
+///
+/// - `label = "..."`
+/// - `icon_name = "..."`
+/// - `action = method`, calls `method(&mut self)` of the struct when the item is clicked
+/// - `separator`, a separator instead of a standard item
+///
+/// # Example
+///
+/// ```ignore
+/// #[derive(ksni::StaticTray)]
+/// #[tray(title = "My App", icon_name = "help-about")]
+/// #[menu_item(label = "Open", action = open)]
+/// #[menu_item(separator)]
+/// #[menu_item(label = "Exit", icon_name = "application-exit", action = exit)]
+/// struct MyTray;
+///
+/// impl MyTray {
+///     fn open(&mut self) {}
+///     fn exit(&mut self) {
+///         std::process::exit(0);
+///     }
+/// }
+/// ```
+#[proc_macro_derive(StaticTray, attributes(tray, menu_item))]
+pub fn derive_static_tray(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[derive(Default)]
+struct TrayAttrs {
+    id: Option<LitStr>,
+    title: Option<LitStr>,
+    icon_name: Option<LitStr>,
+    icon_theme_path: Option<LitStr>,
+    category: Option<Ident>,
+    tool_tip: Option<LitStr>,
+    menu_on_activate: bool,
+}
+
+enum MenuItemAttr {
+    Standard {
+        label: LitStr,
+        icon_name: Option<LitStr>,
+        action: Option<Ident>,
+    },
+    Separator,
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let mut tray = TrayAttrs::default();
+    let mut menu = Vec::new();
+    for attr in &input.attrs {
+        if attr.path().is_ident("tray") {
+            attr.parse_nested_meta(|meta| {
+                let key = meta
+                    .path
+                    .get_ident()
+                    .ok_or_else(|| meta.error("unsupported tray attribute"))?
+                    .to_string();
+                match key.as_str() {
+                    "id" => tray.id = Some(meta.value()?.parse()?),
+                    "title" => tray.title = Some(meta.value()?.parse()?),
+                    "icon_name" => tray.icon_name = Some(meta.value()?.parse()?),
+                    "icon_theme_path" => tray.icon_theme_path = Some(meta.value()?.parse()?),
+                    "category" => tray.category = Some(meta.value()?.parse()?),
+                    "tool_tip" => tray.tool_tip = Some(meta.value()?.parse()?),
+                    "menu_on_activate" => tray.menu_on_activate = true,
+                    _ => return Err(meta.error("unsupported tray attribute")),
+                }
+                Ok(())
+            })?;
+        } else if attr.path().is_ident("menu_item") {
+            let mut label: Option<LitStr> = None;
+            let mut icon_name = None;
+            let mut action = None;
+            let mut separator = false;
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("label") {
+                    label = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("icon_name") {
+                    icon_name = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("action") {
+                    action = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("separator") {
+                    separator = true;
+                } else {
+                    return Err(meta.error("unsupported menu_item attribute"));
+                }
+                Ok(())
+            })?;
+            menu.push(match (separator, label) {
+                (true, None) => MenuItemAttr::Separator,
+                (true, Some(label)) => {
+                    return Err(syn::Error::new(
+                        label.span(),
+                        "a separator can not have a label",
+                    ))
+                }
+                (false, Some(label)) => MenuItemAttr::Standard {
+                    label,
+                    icon_name,
+                    action,
+                },
+                (false, None) => {
+                    return Err(syn::Error::new_spanned(
+                        attr,
+                        "`label` or `separator` is required",
+                    ))
+                }
+            });
+        }
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let id = match &tray.id {
+        Some(id) => quote!(#id.into()),
+        None => quote!(env!("CARGO_PKG_NAME").into()),
+    };
+    let mut methods = Vec::new();
+    if tray.menu_on_activate {
+        methods.push(quote! {
+            const MENU_ON_ACTIVATE: bool = true;
+        });
+    }
+    for (method, value) in [
+        ("title", &tray.title),
+        ("icon_name", &tray.icon_name),
+        ("icon_theme_path", &tray.icon_theme_path),
+    ] {
+        if let Some(value) = value {
+            let method = Ident::new(method, value.span());
+            methods.push(quote! {
+                fn #method(&self) -> ::std::string::String {
+                    #value.into()
+                }
+            });
+        }
+    }
+    if let Some(category) = &tray.category {
+        methods.push(quote! {
+            fn category(&self) -> ::ksni::Category {
+                ::ksni::Category::#category
+            }
+        });
+    }
+    if let Some(description) = &tray.tool_tip {
+        let title = match &tray.title {
+            Some(title) => quote!(#title.into()),
+            None => quote!(::std::default::Default::default()),
+        };
+        methods.push(quote! {
+            fn tool_tip(&self) -> ::ksni::ToolTip {
+                ::ksni::ToolTip {
+                    title: #title,
+                    description: #description.into(),
+                    ..::std::default::Default::default()
+                }
+            }
+        });
+    }
+    if !menu.is_empty() {
+        let items = menu.iter().map(|item| match item {
+            MenuItemAttr::Separator => quote!(::ksni::MenuItem::Separator),
+            MenuItemAttr::Standard {
+                label,
+                icon_name,
+                action,
+            } => {
+                let icon_name = icon_name.iter();
+                let action = action.iter();
+                quote! {
+                    ::ksni::menu::StandardItem {
+                        label: #label.into(),
+                        #(icon_name: #icon_name.into(),)*
+                        #(activate: ::std::boxed::Box::new(|this: &mut Self| this.#action()),)*
+                        ..::std::default::Default::default()
+                    }
+                    .into()
+                }
+            }
+        });
+        methods.push(quote! {
+            fn menu(&self) -> ::std::vec::Vec<::ksni::MenuItem<Self>> {
+                ::std::vec![#(#items),*]
+            }
+        });
+    }
+
+    Ok(quote! {
+        impl #impl_generics ::ksni::Tray for #name #ty_generics #where_clause {
+            fn id(&self) -> ::std::string::String {
+                #id
+            }
+            #(#methods)*
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use syn::parse_quote;
+
+    fn expand_err(input: DeriveInput) -> String {
+        expand(input).map(|_| ()).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_expand() {
+        let output = expand(parse_quote! {
+            #[tray(id = "my-tray", title = "My App", tool_tip = "Running", menu_on_activate)]
+            #[menu_item(label = "Open", action = open)]
+            #[menu_item(separator)]
+            struct MyTray;
+        })
+        .unwrap()
+        .to_string();
+        let expected = quote! {
+            fn tool_tip(&self) -> ::ksni::ToolTip {
+                ::ksni::ToolTip {
+                    title: "My App".into(),
+                    description: "Running".into(),
+                    ..::std::default::Default::default()
+                }
+            }
+        };
+        assert!(output.contains(&expected.to_string()));
+        assert!(output.contains(
+            &quote!(
+                const MENU_ON_ACTIVATE: bool = true;
+            )
+            .to_string()
+        ));
+        assert!(output.contains(&quote!(this.open()).to_string()));
+        assert!(output.contains(&quote!(::ksni::MenuItem::Separator).to_string()));
+        // only the properties that are set
+        assert!(!output.contains("fn icon_name"));
+        assert!(!output.contains("fn category"));
+    }
+
+    #[test]
+    fn test_default_id() {
+        let output = expand(parse_quote!(
+            struct MyTray;
+        ))
+        .unwrap()
+        .to_string();
+        assert!(output.contains(&quote!(env!("CARGO_PKG_NAME")).to_string()));
+        assert!(!output.contains("fn menu"));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            expand_err(parse_quote! {
+                #[tray(icon = "help-about")]
+                struct MyTray;
+            }),
+            "unsupported tray attribute"
+        );
+        assert_eq!(
+            expand_err(parse_quote! {
+                #[menu_item(separator, label = "Open")]
+                struct MyTray;
+            }),
+            "a separator can not have a label"
+        );
+        assert_eq!(
+            expand_err(parse_quote! {
+                #[menu_item(action = open)]
+                struct MyTray;
+            }),
+            "`label` or `separator` is required"
+        );
+    }
+}
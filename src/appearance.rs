@@ -0,0 +1,70 @@
+//! Follows the color scheme of the desktop with the XDG Settings portal
+//!
+//! See https://flatpak.github.io/xdg-desktop-portal/docs/doc-org.freedesktop.portal.Settings.html
+
+use futures_util::StreamExt;
+use zbus::zvariant::OwnedValue;
+use zbus::Connection;
+
+use crate::compat::mpsc;
+use crate::{ColorScheme, HandleReuest};
+
+const NAMESPACE: &str = "org.freedesktop.appearance";
+const KEY: &str = "color-scheme";
+
+async fn settings(conn: &Connection) -> zbus::Result<zbus::Proxy<'static>> {
+    zbus::Proxy::new(
+        conn,
+        "org.freedesktop.portal.Desktop",
+        "/org/freedesktop/portal/desktop",
+        "org.freedesktop.portal.Settings",
+    )
+    .await
+}
+
+fn parse(value: &OwnedValue) -> ColorScheme {
+    // `Read` wraps the value in another variant
+    let value = match value.downcast_ref::<zbus::zvariant::Value>() {
+        Ok(inner) => u32::try_from(&inner),
+        Err(_) => u32::try_from(value),
+    };
+    match value {
+        Ok(1) => ColorScheme::Dark,
+        Ok(2) => ColorScheme::Light,
+        _ => ColorScheme::NoPreference,
+    }
+}
+
+/// Current color scheme, `None` if the portal is not available
+pub(crate) async fn read(conn: &Connection) -> Option<ColorScheme> {
+    let settings = settings(conn).await.ok()?;
+    let value: OwnedValue = match settings.call("ReadOne", &(NAMESPACE, KEY)).await {
+        Ok(value) => value,
+        // `ReadOne` is only available since version 2 of the interface
+        Err(_) => settings.call("Read", &(NAMESPACE, KEY)).await.ok()?,
+    };
+    Some(parse(&value))
+}
+
+/// Forward changes of the color scheme to the service loop until it stops
+pub(crate) async fn watch(
+    conn: Connection,
+    sender: mpsc::UnboundedSender<HandleReuest>,
+) -> zbus::Result<()> {
+    let settings = settings(&conn).await?;
+    let mut changed = settings
+        .receive_signal_with_args("SettingChanged", &[(0, NAMESPACE), (1, KEY)])
+        .await?;
+    while let Some(msg) = changed.next().await {
+        let Ok((_, _, value)) = msg.body().deserialize::<(String, String, OwnedValue)>() else {
+            continue;
+        };
+        if sender
+            .send(HandleReuest::ColorScheme(parse(&value)))
+            .is_err()
+        {
+            break;
+        }
+    }
+    Ok(())
+}
@@ -1,11 +1,11 @@
 //! The blocking API
 
-use std::sync::Arc;
 use std::thread;
 
 use crate::{
-    compat::{self, mpsc},
-    private, service, Error, Tray,
+    compat, private, CloseReason, Error, Hint, HostCapabilities, MessageSender, MessageTray,
+    PanicGuard, PropertyFlags, PropertyValue, RegistrationInfo, ServiceStats, SignalRecord, Tray,
+    TrayServiceBuilder, TrayStateBlob, UpdatePreview,
 };
 
 /// Provides blocking methods for [`Tray`]
@@ -28,19 +28,24 @@ pub trait TrayMethods: Tray + private::Sealed {
     }
     #[doc(hidden)]
     fn spawn_with_name(self, own_name: bool) -> Result<Handle<Self>, Error> {
-        let (handle_tx, handle_rx) = mpsc::unbounded_channel();
-        let service = service::Service::new(self);
-        let service_loop = compat::block_on(service::run(service.clone(), handle_rx, own_name))?;
+        TrayServiceBuilder::new(self)
+            .disable_dbus_name(!own_name)
+            .spawn_blocking()
+    }
+}
+impl<T: Tray> TrayMethods for T {}
+
+impl<T: Tray> TrayServiceBuilder<T> {
+    /// Run the tray service in background, returns a blocking [`Handle`]
+    #[cfg_attr(docsrs, doc(cfg(feature = "blocking")))]
+    pub fn spawn_blocking(self) -> Result<Handle<T>, Error> {
+        let (handle, service_loop) = compat::block_on(self.build())?;
         thread::spawn(move || {
             compat::block_on(service_loop);
         });
-        Ok(Handle(crate::Handle {
-            service: Arc::downgrade(&service),
-            sender: handle_tx,
-        }))
+        Ok(Handle(handle))
     }
 }
-impl<T: Tray> TrayMethods for T {}
 
 /// Handle to the tray
 pub struct Handle<T>(crate::Handle<T>);
@@ -54,15 +59,170 @@ impl<T> Handle<T> {
         compat::block_on(self.0.update(f))
     }
 
+    /// Update the tray, only checks the parts described by `hint` for changes
+    ///
+    /// See [`crate::Handle::update_hint`]
+    pub fn update_hint<R, F: FnOnce(&mut T) -> R>(&self, hint: Hint, f: F) -> Option<R> {
+        compat::block_on(self.0.update_hint(hint, f))
+    }
+
+    /// Update the tray without waiting for the service loop, e.g. from the main loop of a GUI
+    ///
+    /// `f` is queued and returns immediately, the service loop runs it and checks the tray for
+    /// changes like [`Self::update`]. Queued closures run in the order of the calls, and before
+    /// the closure of any [`Self::update`] started after this returns, so an `update` also waits
+    /// for the changes queued before it. See [`crate::Handle::try_update_sync`].
+    ///
+    /// Returns `false` if the tray service has been shutdown.
+    pub fn update_no_wait<F: FnOnce(&mut T) + Send + 'static>(&self, f: F) -> bool {
+        self.0.try_update_sync(f)
+    }
+
+    /// Send the changes of volatile menu properties, see [`crate::Handle::flush_menu`]
+    pub fn flush_menu(&self) -> Option<()> {
+        compat::block_on(self.0.flush_menu())
+    }
+
+    /// Show all menu items as disabled, see [`crate::Handle::set_menu_enabled`]
+    pub fn set_menu_enabled(&self, enabled: bool) -> Option<()> {
+        compat::block_on(self.0.set_menu_enabled(enabled))
+    }
+
+    /// Switch the locale of the tray, see [`Tray::locale_changed`]
+    ///
+    /// Returns `None` if the tray service has been shutdown.
+    pub fn set_locale(&self, locale: &str) -> Option<()>
+    where
+        T: Tray,
+    {
+        compat::block_on(self.0.set_locale(locale))
+    }
+
+    /// Activate a menu item like a click of the host, see [`crate::Handle::activate_item`]
+    pub fn activate_item(&self, path: impl Into<crate::menu::MenuPath>) -> Option<bool>
+    where
+        T: Tray,
+    {
+        compat::block_on(self.0.activate_item(path))
+    }
+
+    /// The state to pass to a restarted tray, see [`crate::Handle::export_state`]
+    pub fn export_state(&self) -> Option<TrayStateBlob>
+    where
+        T: Tray,
+    {
+        compat::block_on(self.0.export_state())
+    }
+
+    /// Replace the whole tray, see [`crate::Handle::replace_tray`]
+    pub fn replace_tray(&self, tray: T) -> Option<T> {
+        compat::block_on(self.0.replace_tray(tray))
+    }
+
+    /// Replace the value of a property, see [`crate::Handle::override_property`]
+    pub fn override_property(&self, value: PropertyValue) -> Option<()> {
+        compat::block_on(self.0.override_property(value))
+    }
+
+    /// Remove the overrides of `properties`, see [`crate::Handle::clear_override`]
+    pub fn clear_override(&self, properties: PropertyFlags) -> Option<()> {
+        compat::block_on(self.0.clear_override(properties))
+    }
+
+    /// Language of the desktop session, see [`crate::Handle::host_locale`]
+    pub fn host_locale(&self) -> Option<String> {
+        compat::block_on(self.0.host_locale())
+    }
+
+    /// Features the tray host can display, see [`crate::Handle::host_capabilities`]
+    pub fn host_capabilities(&self) -> Option<HostCapabilities> {
+        compat::block_on(self.0.host_capabilities())
+    }
+
+    /// Close the D-Bus connection and register the tray again, see [`crate::Handle::restart`]
+    pub fn restart(&self) -> Option<Result<(), Error>> {
+        compat::block_on(self.0.restart())
+    }
+
     /// Shutdown the tray service
     pub fn shutdown(&self) -> ShutdownAwaiter {
         ShutdownAwaiter(self.0.shutdown())
     }
 
+    /// Shutdown the tray service, waiting at most `timeout`, see
+    /// [`crate::Handle::shutdown_with_timeout`]
+    pub fn shutdown_with_timeout(&self, timeout: std::time::Duration) -> bool {
+        compat::block_on(self.0.shutdown_with_timeout(timeout))
+    }
+
+    /// What an update would send to the host, see [`crate::Handle::preview`]
+    pub fn preview<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> Option<(R, UpdatePreview)>
+    where
+        T: Tray + Clone,
+    {
+        compat::block_on(self.0.preview(f))
+    }
+
+    /// Wait until the tray service stopped, see [`crate::Handle::closed`]
+    pub fn closed(&self) -> CloseReason {
+        compat::block_on(self.0.closed())
+    }
+
+    /// The recorded signals, see [`crate::Handle::signal_log`]
+    pub fn signal_log(&self) -> Vec<SignalRecord> {
+        self.0.signal_log()
+    }
+
+    /// State of the service loop, see [`crate::Handle::stats`]
+    pub fn stats(&self) -> ServiceStats {
+        self.0.stats()
+    }
+
+    /// A handle which doesn't keep the tray service running, see [`crate::Handle::downgrade`]
+    pub fn downgrade(&self) -> WeakHandle<T> {
+        WeakHandle(self.0.downgrade())
+    }
+
     /// Returns `true` if the tray service has been shutdown
     pub fn is_closed(&self) -> bool {
         self.0.is_closed()
     }
+
+    /// A guard shutting down the tray if it's dropped during a panic, see
+    /// [`crate::Handle::panic_guard`]
+    pub fn panic_guard(&self) -> PanicGuard {
+        self.0.panic_guard()
+    }
+
+    /// The D-Bus name registered to the StatusNotifierWatcher, see
+    /// [`crate::Handle::bus_name`]
+    pub fn bus_name(&self) -> Option<String> {
+        self.0.bus_name()
+    }
+
+    /// Names and paths of the tray on the bus, see [`crate::Handle::registration_info`]
+    pub fn registration_info(&self) -> Option<RegistrationInfo> {
+        self.0.registration_info()
+    }
+
+    /// Convert into an async [`crate::Handle`] of the same tray service
+    ///
+    /// Use [`Self::clone`] first if you want to keep this one.
+    pub fn into_async(self) -> crate::Handle<T> {
+        self.0
+    }
+}
+
+impl<T> crate::Handle<T> {
+    /// Convert into a blocking [`Handle`] of the same tray service
+    ///
+    /// Use [`Self::clone`] first if you want to keep this one.
+    ///
+    /// Do NOT call the methods of the blocking handle in an async context.
+    #[cfg_attr(docsrs, doc(cfg(feature = "blocking")))]
+    pub fn into_blocking(self) -> Handle<T> {
+        Handle(self)
+    }
 }
 
 /// Returned by [`Handle::shutdown`]
@@ -80,3 +240,31 @@ impl<T> Clone for Handle<T> {
         Handle(self.0.clone())
     }
 }
+
+impl<T: MessageTray> Handle<T> {
+    /// Send a message to [`MessageTray::on_message`], see [`crate::Handle::send`]
+    pub fn send(&self, message: T::Message) -> bool {
+        self.0.send(message)
+    }
+
+    /// A sender of messages, see [`crate::Handle::message_sender`]
+    pub fn message_sender(&self) -> MessageSender<T::Message> {
+        self.0.message_sender()
+    }
+}
+
+/// A [`Handle`] which doesn't keep the tray service running, see [`Handle::downgrade`]
+pub struct WeakHandle<T>(crate::WeakHandle<T>);
+
+impl<T> WeakHandle<T> {
+    /// Returns `None` if all [`Handle`]s were dropped, or the tray service has been shutdown
+    pub fn upgrade(&self) -> Option<Handle<T>> {
+        self.0.upgrade().map(Handle)
+    }
+}
+
+impl<T> Clone for WeakHandle<T> {
+    fn clone(&self) -> Self {
+        WeakHandle(self.0.clone())
+    }
+}
@@ -0,0 +1,427 @@
+//! C API, for applications in other languages
+//!
+//! A replacement for libappindicator: a tray with an icon, a title and a menu of items calling
+//! function pointers. Build ksni as a shared library, the header `include/ksni.h` is generated
+//! with [cbindgen]:
+//!
+//! ```sh
+//! cargo rustc --release --features capi --crate-type cdylib
+//! cbindgen --config cbindgen.toml --output include/ksni.h
+//! ```
+//!
+//! The tray is changed by the `ksni_tray_set_*` functions before and after it's started, also
+//! from the callbacks. Callbacks of a tray are called one at a time on a thread of its own, they
+//! can also shutdown or free the tray.
+//!
+//! ```c
+//! #include "ksni.h"
+//!
+//! static void quit(void *user_data) {
+//!     ksni_tray_shutdown(user_data);
+//! }
+//!
+//! int main(void) {
+//!     KsniTray *tray = ksni_tray_new("my-app");
+//!     ksni_tray_set_icon_name(tray, "help-about");
+//!     ksni_tray_add_menu_item(tray, "Quit", quit, tray);
+//!     ksni_tray_run(tray);
+//!     ksni_tray_free(tray);
+//! }
+//! ```
+//!
+//! [cbindgen]: https://github.com/mozilla/cbindgen
+
+use std::ffi::{c_char, c_void, CStr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::ThreadId;
+
+use crate::menu::StandardItem;
+use crate::simple::{Handle, TrayService};
+use crate::{MenuItem, Status, Tray};
+
+/// A tray, created by [`ksni_tray_new`] and freed by [`ksni_tray_free`]
+pub struct KsniTray {
+    // taken when the service is started
+    service: Mutex<Option<TrayService<CTray>>>,
+    handle: Handle<CTray>,
+    callbacks: Arc<Callbacks>,
+    // the thread calling the callbacks
+    callback_thread: ThreadId,
+}
+
+// shared with the thread calling the callbacks
+#[derive(Default)]
+struct Callbacks {
+    // set by `ksni_tray_free`, no callback is called after it
+    freed: AtomicBool,
+    // locked while a callback is called
+    running: Mutex<()>,
+}
+
+/// See [`Status`]
+#[repr(C)]
+pub enum KsniStatus {
+    Passive,
+    Active,
+    NeedsAttention,
+}
+
+/// Called with the `user_data` given with it
+pub type KsniCallback = Option<extern "C" fn(user_data: *mut c_void)>;
+
+#[derive(Clone, Copy)]
+struct UserData(*mut c_void);
+
+// the caller of `ksni_tray_add_menu_item` promises that `user_data` can be used on the thread of
+// the tray service
+unsafe impl Send for UserData {}
+
+impl UserData {
+    // a method captures the whole `UserData` in closures, not the pointer
+    fn get(self) -> *mut c_void {
+        self.0
+    }
+}
+
+enum CItem {
+    Standard {
+        label: String,
+        callback: KsniCallback,
+        user_data: UserData,
+    },
+    Separator,
+}
+
+type Call = (extern "C" fn(user_data: *mut c_void), UserData);
+
+struct CTray {
+    // to the thread calling the callbacks, the service is locked while `activate` is called
+    calls: mpsc::Sender<Call>,
+    id: String,
+    title: String,
+    icon_name: String,
+    status: Status,
+    menu: Vec<CItem>,
+}
+
+impl Tray for CTray {
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    fn title(&self) -> String {
+        self.title.clone()
+    }
+
+    fn icon_name(&self) -> String {
+        self.icon_name.clone()
+    }
+
+    fn status(&self) -> Status {
+        self.status
+    }
+
+    fn menu(&self) -> Vec<MenuItem<Self>> {
+        self.menu
+            .iter()
+            .map(|item| match *item {
+                CItem::Standard {
+                    ref label,
+                    callback,
+                    user_data,
+                } => StandardItem {
+                    label: label.clone().into(),
+                    activate: Box::new(move |this: &mut Self| {
+                        if let Some(callback) = callback {
+                            // the thread is gone after `ksni_tray_free`
+                            let _ = this.calls.send((callback, user_data));
+                        }
+                    }),
+                    ..Default::default()
+                }
+                .into(),
+                CItem::Separator => MenuItem::Separator,
+            })
+            .collect()
+    }
+}
+
+// `None` if it's null or not UTF-8
+unsafe fn to_string(s: *const c_char) -> Option<String> {
+    if s.is_null() {
+        return None;
+    }
+    CStr::from_ptr(s).to_str().ok().map(str::to_owned)
+}
+
+// `false` if the tray is null, or the service has been shutdown
+unsafe fn update(tray: *const KsniTray, f: impl FnOnce(&mut CTray)) -> bool {
+    let Some(tray) = tray.as_ref() else {
+        return false;
+    };
+    tray.handle.update(f).is_some()
+}
+
+fn spawn_callback_thread(calls: mpsc::Receiver<Call>, callbacks: Arc<Callbacks>) -> ThreadId {
+    let thread = std::thread::spawn(move || {
+        // ends when the tray is dropped with the service
+        for (callback, user_data) in calls {
+            let _running = callbacks.running.lock().unwrap_or_else(|e| e.into_inner());
+            if callbacks.freed.load(Ordering::Acquire) {
+                break;
+            }
+            callback(user_data.get());
+        }
+    });
+    thread.thread().id()
+}
+
+/// Create a tray, returns null if `id` is null or not UTF-8
+///
+/// # Safety
+///
+/// `id` must be null or a NUL-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn ksni_tray_new(id: *const c_char) -> *mut KsniTray {
+    let Some(id) = to_string(id) else {
+        return std::ptr::null_mut();
+    };
+    let (calls, receiver) = mpsc::channel();
+    let callbacks = Arc::new(Callbacks::default());
+    let callback_thread = spawn_callback_thread(receiver, callbacks.clone());
+    let service = TrayService::new(CTray {
+        calls,
+        id,
+        title: String::new(),
+        icon_name: String::new(),
+        status: Status::Active,
+        menu: Vec::new(),
+    });
+    Box::into_raw(Box::new(KsniTray {
+        handle: service.handle(),
+        service: Mutex::new(Some(service)),
+        callbacks,
+        callback_thread,
+    }))
+}
+
+/// Set the title, returns `false` if an argument is invalid or the tray has been shutdown
+///
+/// # Safety
+///
+/// `tray` must be null or returned by [`ksni_tray_new`], `title` must be null or a
+/// NUL-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn ksni_tray_set_title(tray: *const KsniTray, title: *const c_char) -> bool {
+    let Some(title) = to_string(title) else {
+        return false;
+    };
+    update(tray, |tray| tray.title = title)
+}
+
+/// Set the themed icon, returns `false` if an argument is invalid or the tray has been
+/// shutdown
+///
+/// # Safety
+///
+/// Same as [`ksni_tray_set_title`]
+#[no_mangle]
+pub unsafe extern "C" fn ksni_tray_set_icon_name(
+    tray: *const KsniTray,
+    icon_name: *const c_char,
+) -> bool {
+    let Some(icon_name) = to_string(icon_name) else {
+        return false;
+    };
+    update(tray, |tray| tray.icon_name = icon_name)
+}
+
+/// Set the status, returns `false` if the tray is null or has been shutdown
+///
+/// # Safety
+///
+/// `tray` must be null or returned by [`ksni_tray_new`]
+#[no_mangle]
+pub unsafe extern "C" fn ksni_tray_set_status(tray: *const KsniTray, status: KsniStatus) -> bool {
+    let status = match status {
+        KsniStatus::Passive => Status::Passive,
+        KsniStatus::Active => Status::Active,
+        KsniStatus::NeedsAttention => Status::NeedsAttention,
+    };
+    update(tray, |tray| tray.status = status)
+}
+
+/// Append an item to the menu, `callback` is called with `user_data` when it's clicked
+///
+/// Returns `false` if an argument is invalid or the tray has been shutdown.
+///
+/// # Safety
+///
+/// Same as [`ksni_tray_set_title`] for `tray` and `label`. `callback` is called on the callback
+/// thread of the tray, `user_data` must be usable there until the tray is freed.
+#[no_mangle]
+pub unsafe extern "C" fn ksni_tray_add_menu_item(
+    tray: *const KsniTray,
+    label: *const c_char,
+    callback: KsniCallback,
+    user_data: *mut c_void,
+) -> bool {
+    let Some(label) = to_string(label) else {
+        return false;
+    };
+    update(tray, |tray| {
+        tray.menu.push(CItem::Standard {
+            label,
+            callback,
+            user_data: UserData(user_data),
+        })
+    })
+}
+
+/// Append a separator to the menu, returns `false` if the tray is null or has been shutdown
+///
+/// # Safety
+///
+/// `tray` must be null or returned by [`ksni_tray_new`]
+#[no_mangle]
+pub unsafe extern "C" fn ksni_tray_add_menu_separator(tray: *const KsniTray) -> bool {
+    update(tray, |tray| tray.menu.push(CItem::Separator))
+}
+
+/// Remove all items of the menu, returns `false` if the tray is null or has been shutdown
+///
+/// # Safety
+///
+/// `tray` must be null or returned by [`ksni_tray_new`]
+#[no_mangle]
+pub unsafe extern "C" fn ksni_tray_clear_menu(tray: *const KsniTray) -> bool {
+    update(tray, |tray| tray.menu.clear())
+}
+
+/// Run the tray service in background
+///
+/// Returns `false` if the tray is null, has already been started, or failed to start.
+///
+/// # Safety
+///
+/// `tray` must be null or returned by [`ksni_tray_new`]
+#[no_mangle]
+pub unsafe extern "C" fn ksni_tray_spawn(tray: *const KsniTray) -> bool {
+    start(tray, TrayService::spawn)
+}
+
+/// Run the tray service on the current thread, returns after [`ksni_tray_shutdown`]
+///
+/// Returns `false` if the tray is null, has already been started, or failed to start.
+///
+/// # Safety
+///
+/// `tray` must be null or returned by [`ksni_tray_new`]
+#[no_mangle]
+pub unsafe extern "C" fn ksni_tray_run(tray: *const KsniTray) -> bool {
+    start(tray, TrayService::run)
+}
+
+unsafe fn start(
+    tray: *const KsniTray,
+    f: impl FnOnce(TrayService<CTray>) -> Result<(), crate::Error>,
+) -> bool {
+    let Some(tray) = tray.as_ref() else {
+        return false;
+    };
+    let service = tray
+        .service
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take();
+    service.is_some_and(|service| f(service).is_ok())
+}
+
+/// Shutdown the tray service and wait for it, does nothing if it's not running
+///
+/// # Safety
+///
+/// `tray` must be null or returned by [`ksni_tray_new`]
+#[no_mangle]
+pub unsafe extern "C" fn ksni_tray_shutdown(tray: *const KsniTray) {
+    if let Some(tray) = tray.as_ref() {
+        tray.handle.shutdown();
+    }
+}
+
+/// Shutdown the tray service and free the tray
+///
+/// Waits for the running callback unless called from it, no callback is called after this.
+///
+/// # Safety
+///
+/// `tray` must be null or returned by [`ksni_tray_new`], and not used after this
+#[no_mangle]
+pub unsafe extern "C" fn ksni_tray_free(tray: *mut KsniTray) {
+    if tray.is_null() {
+        return;
+    }
+    let tray = Box::from_raw(tray);
+    tray.callbacks.freed.store(true, Ordering::Release);
+    if std::thread::current().id() != tray.callback_thread {
+        drop(tray.callbacks.running.lock());
+    }
+    tray.handle.shutdown();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::time::{Duration, Instant};
+
+    static CALLED: AtomicUsize = AtomicUsize::new(0);
+
+    extern "C" fn free_tray(user_data: *mut c_void) {
+        CALLED.fetch_add(1, Ordering::SeqCst);
+        unsafe { ksni_tray_free(user_data.cast()) };
+    }
+
+    #[test]
+    fn test_smoke() {
+        unsafe {
+            assert!(ksni_tray_new(std::ptr::null()).is_null());
+            let tray = ksni_tray_new(c"smoke".as_ptr());
+            assert!(ksni_tray_set_title(tray, c"Smoke".as_ptr()));
+            assert!(!ksni_tray_set_title(tray, std::ptr::null()));
+            assert!(ksni_tray_set_status(tray, KsniStatus::NeedsAttention));
+            assert!(ksni_tray_add_menu_separator(tray));
+            assert!(ksni_tray_add_menu_item(
+                tray,
+                c"Quit".as_ptr(),
+                Some(free_tray),
+                tray.cast()
+            ));
+            let (title, status) = (*tray)
+                .handle
+                .update(|t| (t.title.clone(), t.status))
+                .unwrap();
+            assert_eq!(title, "Smoke");
+            assert_eq!(status, Status::NeedsAttention);
+
+            // clicked twice, the second click is dropped by the free in the first callback
+            (*tray).handle.update(|t| {
+                for _ in 0..2 {
+                    let mut menu = t.menu();
+                    let MenuItem::Standard(item) = &mut menu[1] else {
+                        unreachable!()
+                    };
+                    (item.activate)(t);
+                }
+            });
+        }
+        let start = Instant::now();
+        while CALLED.load(Ordering::SeqCst) == 0 {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(CALLED.load(Ordering::SeqCst), 1);
+    }
+}
@@ -6,7 +6,37 @@ mod tokio {
     use std::future::Future;
 
     pub use tokio::select;
-    pub use tokio::sync::Mutex;
+    pub use tokio::sync::{Mutex, OwnedMutexGuard, OwnedSemaphorePermit, Semaphore};
+    pub use tokio::time::sleep;
+
+    // the time of the timers, paused and advanced with the "test-util" feature
+    pub fn now() -> std::time::Instant {
+        tokio::time::Instant::now().into_std()
+    }
+
+    #[cfg(feature = "test-util")]
+    pub mod clock {
+        pub use tokio::time::{advance, pause, resume};
+    }
+
+    pub async fn lock_owned<T>(mutex: std::sync::Arc<Mutex<T>>) -> OwnedMutexGuard<T> {
+        mutex.lock_owned().await
+    }
+
+    pub async fn acquire_owned(semaphore: std::sync::Arc<Semaphore>) -> OwnedSemaphorePermit {
+        semaphore
+            .acquire_owned()
+            .await
+            .expect("the semaphore should never be closed")
+    }
+
+    // run a blocking function without blocking the runtime
+    pub async fn unblock<R: Send + 'static>(f: impl FnOnce() -> R + Send + 'static) -> R {
+        match tokio::task::spawn_blocking(f).await {
+            Ok(r) => r,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
 
     // remove the return value to compat with async-io
     pub fn spawn<F>(future: F)
@@ -56,7 +86,106 @@ mod async_io {
     static EXECUTOR: OnceCell<Executor> = OnceCell::new();
 
     pub use async_io::block_on;
-    pub use async_lock::Mutex;
+    pub use async_lock::{
+        Mutex, MutexGuardArc as OwnedMutexGuard, Semaphore,
+        SemaphoreGuardArc as OwnedSemaphorePermit,
+    };
+
+    pub async fn lock_owned<T>(mutex: std::sync::Arc<Mutex<T>>) -> OwnedMutexGuard<T> {
+        mutex.lock_arc().await
+    }
+
+    pub async fn acquire_owned(semaphore: std::sync::Arc<Semaphore>) -> OwnedSemaphorePermit {
+        semaphore.acquire_arc().await
+    }
+
+    // run a blocking function on a new thread
+    pub async fn unblock<R: Send + 'static>(f: impl FnOnce() -> R + Send + 'static) -> R {
+        let (tx, rx) = futures_channel::oneshot::channel();
+        std::thread::spawn(move || {
+            let _ = tx.send(std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)));
+        });
+        match rx.await.expect("the thread should send the result") {
+            Ok(r) => r,
+            Err(e) => std::panic::resume_unwind(e),
+        }
+    }
+
+    pub fn now() -> std::time::Instant {
+        #[cfg(feature = "test-util")]
+        if let Some(now) = clock::now() {
+            return now;
+        }
+        std::time::Instant::now()
+    }
+
+    pub async fn sleep(duration: std::time::Duration) {
+        #[cfg(feature = "test-util")]
+        if let Some(now) = clock::now() {
+            return clock::sleep_until(now + duration).await;
+        }
+        async_io::Timer::after(duration).await;
+    }
+
+    // a global clock for `now` and `sleep`, like the paused time of tokio
+    #[cfg(feature = "test-util")]
+    pub mod clock {
+        use std::sync::{Mutex, MutexGuard};
+        use std::task::{Poll, Waker};
+        use std::time::{Duration, Instant};
+
+        struct Paused {
+            now: Instant,
+            sleeping: Vec<Waker>,
+        }
+
+        static PAUSED: Mutex<Option<Paused>> = Mutex::new(None);
+
+        fn paused() -> MutexGuard<'static, Option<Paused>> {
+            PAUSED.lock().unwrap_or_else(|e| e.into_inner())
+        }
+
+        pub fn pause() {
+            paused().get_or_insert_with(|| Paused {
+                now: Instant::now(),
+                sleeping: Vec::new(),
+            });
+        }
+
+        // sleeping tasks wake up immediately
+        pub fn resume() {
+            if let Some(paused) = paused().take() {
+                paused.sleeping.into_iter().for_each(Waker::wake);
+            }
+        }
+
+        pub async fn advance(duration: Duration) {
+            let sleeping = {
+                let mut paused = paused();
+                let paused = paused.as_mut().expect("time should be paused");
+                paused.now += duration;
+                std::mem::take(&mut paused.sleeping)
+            };
+            sleeping.into_iter().for_each(Waker::wake);
+            // let the woken tasks run
+            futures_lite::future::yield_now().await;
+        }
+
+        pub(super) fn now() -> Option<Instant> {
+            paused().as_ref().map(|paused| paused.now)
+        }
+
+        pub(super) async fn sleep_until(deadline: Instant) {
+            std::future::poll_fn(|cx| match &mut *paused() {
+                Some(paused) if paused.now < deadline => {
+                    paused.sleeping.push(cx.waker().clone());
+                    Poll::Pending
+                }
+                _ => Poll::Ready(()),
+            })
+            .await
+        }
+    }
 
     pub fn spawn<F>(future: F)
     where
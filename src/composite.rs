@@ -0,0 +1,292 @@
+//! A tray made of independent components
+//!
+//! Each [`Component`] (e.g. a plugin of a system monitor) contributes a section of the menu and
+//! a status. The menu sections are joined with separators, and the most important status is
+//! shown.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use ksni::composite::{Component, CompositeTray};
+//! use ksni::menu::StandardItem;
+//! use ksni::{MenuItem, TrayMethods};
+//!
+//! struct Cpu {
+//!     load: f32,
+//! }
+//!
+//! impl Component for Cpu {
+//!     fn status(&self) -> ksni::Status {
+//!         if self.load > 0.9 {
+//!             ksni::Status::NeedsAttention
+//!         } else {
+//!             ksni::Status::Active
+//!         }
+//!     }
+//!     fn menu(&self) -> Vec<MenuItem<Self>> {
+//!         vec![StandardItem {
+//!             label: format!("CPU {:.0}%", self.load * 100.0).into(),
+//!             ..Default::default()
+//!         }
+//!         .into()]
+//!     }
+//! }
+//!
+//! # async fn f() -> Result<(), ksni::Error> {
+//! let mut tray = CompositeTray::new("system-monitor").icon_name("utilities-system-monitor");
+//! let cpu = tray.add(Cpu { load: 0.0 });
+//! let handle = tray.spawn().await?;
+//!
+//! let cpu = cpu.handle(&handle);
+//! cpu.update(|cpu| cpu.load = 0.5).await;
+//! # Ok(()) }
+//! ```
+
+use std::any::Any;
+use std::marker::PhantomData;
+
+use crate::{Handle, MenuItem, Status, Tray};
+
+/// A part of a [`CompositeTray`]
+pub trait Component: Send + 'static {
+    /// Status of the component, the tray shows the most important status of all components
+    ///
+    /// Default is [`Status::Active`]
+    fn status(&self) -> Status {
+        Status::Active
+    }
+
+    /// The section of the menu of this component
+    fn menu(&self) -> Vec<MenuItem<Self>>
+    where
+        Self: Sized,
+    {
+        Default::default()
+    }
+}
+
+// object safe part of `Component`
+trait AnyComponent: Send {
+    fn status(&self) -> Status;
+    fn menu(&self, index: usize) -> Vec<MenuItem<CompositeTray>>;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<C: Component> AnyComponent for C {
+    fn status(&self) -> Status {
+        Component::status(self)
+    }
+
+    fn menu(&self, index: usize) -> Vec<MenuItem<CompositeTray>> {
+        Component::menu(self)
+            .into_iter()
+            .map(|item| {
+                item.map(
+                    move |tray: &CompositeTray| tray.get::<C>(index),
+                    move |tray: &mut CompositeTray| tray.get_mut::<C>(index),
+                )
+            })
+            .collect()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Identifies a component added to a [`CompositeTray`]
+pub struct ComponentKey<C> {
+    index: usize,
+    _component: PhantomData<fn() -> C>,
+}
+
+impl<C> Clone for ComponentKey<C> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<C> Copy for ComponentKey<C> {}
+
+impl<C: Component> ComponentKey<C> {
+    /// A handle updating only this component
+    pub fn handle(self, handle: &Handle<CompositeTray>) -> ComponentHandle<C> {
+        ComponentHandle {
+            handle: handle.clone(),
+            key: self,
+        }
+    }
+}
+
+/// Handle to a component of a spawned [`CompositeTray`]
+pub struct ComponentHandle<C> {
+    handle: Handle<CompositeTray>,
+    key: ComponentKey<C>,
+}
+
+impl<C> Clone for ComponentHandle<C> {
+    fn clone(&self) -> Self {
+        ComponentHandle {
+            handle: self.handle.clone(),
+            key: self.key,
+        }
+    }
+}
+
+impl<C: Component> ComponentHandle<C> {
+    /// Update the component, see [`Handle::update`]
+    pub async fn update<R, F: FnOnce(&mut C) -> R>(&self, f: F) -> Option<R> {
+        let key = self.key;
+        self.handle
+            .update(move |tray| f(tray.component_mut(key)))
+            .await
+    }
+}
+
+/// A [`Tray`] merging the menus and statuses of its components, see the
+/// [module level documentation](self)
+pub struct CompositeTray {
+    id: String,
+    title: String,
+    icon_name: String,
+    components: Vec<Box<dyn AnyComponent>>,
+}
+
+impl CompositeTray {
+    pub fn new(id: impl Into<String>) -> Self {
+        CompositeTray {
+            id: id.into(),
+            title: String::new(),
+            icon_name: String::new(),
+            components: Vec::new(),
+        }
+    }
+
+    /// See [`Tray::title`]
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    /// See [`Tray::icon_name`]
+    pub fn icon_name(mut self, icon_name: impl Into<String>) -> Self {
+        self.icon_name = icon_name.into();
+        self
+    }
+
+    /// Add a component, its menu section comes after the sections of the previous components
+    pub fn add<C: Component>(&mut self, component: C) -> ComponentKey<C> {
+        self.components.push(Box::new(component));
+        ComponentKey {
+            index: self.components.len() - 1,
+            _component: PhantomData,
+        }
+    }
+
+    pub fn component<C: Component>(&self, key: ComponentKey<C>) -> &C {
+        self.get(key.index).expect("key of another CompositeTray")
+    }
+
+    pub fn component_mut<C: Component>(&mut self, key: ComponentKey<C>) -> &mut C {
+        self.get_mut(key.index)
+            .expect("key of another CompositeTray")
+    }
+
+    fn get<C: Component>(&self, index: usize) -> Option<&C> {
+        self.components.get(index)?.as_any().downcast_ref()
+    }
+
+    fn get_mut<C: Component>(&mut self, index: usize) -> Option<&mut C> {
+        self.components.get_mut(index)?.as_any_mut().downcast_mut()
+    }
+}
+
+impl Tray for CompositeTray {
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    fn title(&self) -> String {
+        self.title.clone()
+    }
+
+    fn icon_name(&self) -> String {
+        self.icon_name.clone()
+    }
+
+    fn status(&self) -> Status {
+        self.components
+            .iter()
+            .map(|component| component.status())
+            .max_by_key(|status| match status {
+                Status::Passive => 0,
+                Status::Active => 1,
+                Status::NeedsAttention => 2,
+            })
+            .unwrap_or(Status::Active)
+    }
+
+    fn menu(&self) -> Vec<MenuItem<Self>> {
+        let mut menu = Vec::new();
+        for (index, component) in self.components.iter().enumerate() {
+            let section = component.menu(index);
+            if section.is_empty() {
+                continue;
+            }
+            if !menu.is_empty() {
+                menu.push(MenuItem::Separator);
+            }
+            menu.extend(section);
+        }
+        menu
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::menu::StandardItem;
+
+    struct Counter(u32);
+
+    impl Component for Counter {
+        fn status(&self) -> Status {
+            if self.0 > 0 {
+                Status::NeedsAttention
+            } else {
+                Status::Passive
+            }
+        }
+        fn menu(&self) -> Vec<MenuItem<Self>> {
+            vec![StandardItem {
+                label: self.0.to_string().into(),
+                activate: Box::new(|this: &mut Self| this.0 += 1),
+                ..Default::default()
+            }
+            .into()]
+        }
+    }
+
+    #[test]
+    fn test_composite() {
+        let mut tray = CompositeTray::new("test");
+        let a = tray.add(Counter(0));
+        let b = tray.add(Counter(0));
+        assert_eq!(tray.status(), Status::Passive);
+
+        let menu = tray.menu();
+        assert_eq!(menu.len(), 3);
+        assert!(matches!(menu[1], MenuItem::Separator));
+        if let MenuItem::Standard(item) = &menu[2] {
+            (item.activate)(&mut tray);
+        }
+        assert_eq!(tray.component(a).0, 0);
+        assert_eq!(tray.component(b).0, 1);
+        assert_eq!(tray.status(), Status::NeedsAttention);
+    }
+}
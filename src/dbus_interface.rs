@@ -1,17 +1,38 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
+use zbus::message::Header;
 use zbus::zvariant::{ObjectPath, OwnedValue, Type, Value};
 use zbus::{object_server::SignalEmitter, Connection};
 
-use crate::compat::Mutex;
-use crate::service::Service;
+use crate::compat::{self, Mutex};
+use crate::service::{lock, Service};
 use crate::{Icon, ToolTip, Tray};
 
 pub const SNI_PATH: ObjectPath = ObjectPath::from_static_str_unchecked("/StatusNotifierItem");
 pub const MENU_PATH: ObjectPath = ObjectPath::from_static_str_unchecked("/MenuBar");
 
+// calls a callback of the tray, on a blocking thread if
+// `TrayServiceBuilder::blocking_callbacks` is set. The service stays locked until it returns, but
+// other tasks of the runtime are not blocked
+async fn callback<T: Tray, R: Send + 'static>(
+    service: &Arc<Mutex<Service<T>>>,
+    f: impl FnOnce(&mut Service<T>) -> R + Send + 'static,
+) -> (compat::OwnedMutexGuard<Service<T>>, R) {
+    let mut service = compat::lock_owned(service.clone()).await;
+    if service.blocking_callbacks() {
+        compat::unblock(move || {
+            let r = service.shutdown_on_panic(f);
+            (service, r)
+        })
+        .await
+    } else {
+        let r = service.shutdown_on_panic(f);
+        (service, r)
+    }
+}
+
 #[zbus::proxy(
     interface = "org.kde.StatusNotifierWatcher",
     default_service = "org.kde.StatusNotifierWatcher",
@@ -46,14 +67,137 @@ pub trait StatusNotifierWatcher {
     fn status_notifier_host_unregistered(&self) -> zbus::Result<()>;
 }
 
-pub struct StatusNotifierItem<T>(Arc<Mutex<Service<T>>>);
+pub struct StatusNotifierItem<T>(Arc<Mutex<Service<T>>>, std::sync::Mutex<Option<Snapshot>>);
 
 impl<T> StatusNotifierItem<T> {
-    pub fn new(service: Arc<Mutex<Service<T>>>) -> Self {
-        Self(service)
+    pub(crate) fn new(service: Arc<Mutex<Service<T>>>) -> Self {
+        Self(service, Default::default())
+    }
+}
+
+impl<T: Tray> StatusNotifierItem<T> {
+    // zbus reads the properties one by one for `GetAll`, which is called by hosts on registration
+    // and usually after every signal. The first property of a `GetAll` takes a snapshot of all
+    // properties, which serves all reads until the tray is changed, see `Service::generation`.
+    // Other reads don't take a snapshot, a host only interested in a property doesn't pay for
+    // all of them
+    async fn read<R>(
+        &self,
+        header: Option<Header<'_>>,
+        cached: impl Fn(&Snapshot) -> R,
+        get: impl FnOnce(&Service<T>) -> R,
+    ) -> R {
+        let service = self.0.lock().await;
+        let generation = service.generation();
+        let mut snapshot = lock(&self.1);
+        if let Some(snapshot) = snapshot.as_ref().filter(|s| s.generation == generation) {
+            return cached(snapshot);
+        }
+        if !header.as_ref().is_some_and(is_get_all) {
+            return get(&service);
+        }
+        let new = Snapshot::new(generation, &service);
+        let value = cached(&new);
+        *snapshot = Some(new);
+        value
+    }
+}
+
+// a property converted to a `Value` once per change, see `Service::encoded`
+pub(crate) struct Encoded<V>(pub(crate) OwnedValue, std::marker::PhantomData<V>);
+
+impl<V> Encoded<V> {
+    pub(crate) fn new(value: OwnedValue) -> Self {
+        Encoded(value, std::marker::PhantomData)
+    }
+}
+
+impl<V: Type> Type for Encoded<V> {
+    const SIGNATURE: &'static zbus::zvariant::Signature = V::SIGNATURE;
+}
+
+impl<V> Clone for Encoded<V> {
+    fn clone(&self) -> Self {
+        Encoded::new(
+            self.0
+                .try_clone()
+                .expect("pixmaps have no file descriptors"),
+        )
     }
 }
 
+impl<V> From<Encoded<V>> for Value<'_> {
+    fn from(encoded: Encoded<V>) -> Self {
+        encoded.0.into()
+    }
+}
+
+fn is_get_all(header: &Header<'_>) -> bool {
+    header
+        .member()
+        .is_some_and(|member| member.as_str() == "GetAll")
+}
+
+macro_rules! def_snapshot {
+    ($($name:ident: $type:ty = |$service:ident| $value:expr;)+) => {
+        // properties of the item at `generation`
+        struct Snapshot {
+            generation: u64,
+            $($name: $type),+
+        }
+
+        impl Snapshot {
+            fn new<T: Tray>(generation: u64, service: &Service<T>) -> Self {
+                Self {
+                    generation,
+                    $($name: Self::$name(service)),+
+                }
+            }
+
+            $(fn $name<T: Tray>($service: &Service<T>) -> $type {
+                $value
+            })+
+        }
+    };
+}
+
+def_snapshot! {
+    category: crate::Category = |service| service.get_category();
+    id: String = |service| service.get_id();
+    title: String = |service| service.get_title();
+    status: crate::Status = |service| service.get_status();
+    window_id: i32 = |service| service.get_window_id();
+    icon_theme_path: String = |service| service.get_icon_theme_path();
+    menu: ObjectPath<'static> = |service| service.menu_path();
+    icon_name: String = |service| service.shown_icon_name();
+    icon_pixmap: Encoded<Vec<Icon>> = |service| service.encoded(
+        || service.icon_pixmap_key(),
+        || service.order_pixmap(service.shown_icon_pixmap()),
+    );
+    icon_accessible_desc: String = |service| service.get_icon_accessible_desc();
+    overlay_icon_name: String = |service| service.get_overlay_icon_name();
+    overlay_icon_pixmap: Encoded<Vec<Icon>> = |service| service.encoded(
+        || service.overlay_icon_pixmap_key(),
+        || service.order_pixmap(service.get_overlay_icon_pixmap()),
+    );
+    attention_icon_name: String = |service| service.shown_attention_icon_name();
+    attention_icon_pixmap: Encoded<Vec<Icon>> = |service| service.encoded(
+        || service.attention_icon_pixmap_key(),
+        || service.order_pixmap(service.shown_attention_icon_pixmap()),
+    );
+    attention_movie_name: String = |service| service.get_attention_movie_name();
+    attention_accessible_desc: String = |service| service.get_attention_accessible_desc();
+    tool_tip: Encoded<ToolTip> = |service| service.encoded(
+        || service.tool_tip_key(),
+        || {
+            let mut tool_tip = service.get_tool_tip();
+            tool_tip.icon_pixmap = service.order_pixmap(tool_tip.icon_pixmap);
+            tool_tip
+        },
+    );
+    ayatana_label: (String, String) = |service| service.ayatana_label();
+}
+
 #[zbus::interface(name = "org.kde.StatusNotifierItem")]
 impl<T: Tray> StatusNotifierItem<T> {
     // show a self rendered menu, not supported by ksni
@@ -76,20 +220,31 @@ impl<T: Tray> StatusNotifierItem<T> {
             // https://github.com/KDE/plasma-workspace/blob/4a98130f76bcae4211d3f9b10e4a7b760613ffc6/applets/systemtray/package/contents/ui/items/StatusNotifierItem.qml#L44-L57
             Err(zbus::fdo::Error::UnknownMethod("ItemIsMenu".into()))
         } else {
-            let mut service = self.0.lock().await; // do NOT use any self methods after this
-            service.call_activate(conn, x, y).await;
+            let (mut service, called) = callback(&self.0, move |s| s.call_activate(x, y)).await;
+            if called {
+                let _ = service.update(conn).await;
+            }
             Ok(())
         }
     }
 
+    async fn provide_xdg_activation_token(&self, token: String) -> zbus::fdo::Result<()> {
+        let mut service = self.0.lock().await; // do NOT use any self methods after this
+        service.provide_xdg_activation_token(token);
+        Ok(())
+    }
+
     async fn secondary_activate(
         &self,
         #[zbus(connection)] conn: &Connection,
         x: i32,
         y: i32,
     ) -> zbus::fdo::Result<()> {
-        let mut service = self.0.lock().await; // do NOT use any self methods after this
-        service.call_secondary_activate(conn, x, y).await;
+        let (mut service, called) =
+            callback(&self.0, move |s| s.call_secondary_activate(x, y)).await;
+        if called {
+            let _ = service.update(conn).await;
+        }
         Ok(())
     }
 
@@ -99,51 +254,74 @@ impl<T: Tray> StatusNotifierItem<T> {
         delta: i32,
         dir: crate::Orientation,
     ) -> zbus::fdo::Result<()> {
-        let mut service = self.0.lock().await; // do NOT use any self methods after this
-        service.call_scroll(conn, delta, dir).await;
+        let (mut service, called) = callback(&self.0, move |s| s.call_scroll(delta, dir)).await;
+        if called {
+            let _ = service.update(conn).await;
+        }
         Ok(())
     }
 
     // properties
     #[zbus(property)]
-    async fn category(&self) -> zbus::fdo::Result<crate::Category> {
-        let service = self.0.lock().await; // do NOT use any self methods after this
-        Ok(service.get_category())
+    async fn category(
+        &self,
+        #[zbus(header)] header: Option<Header<'_>>,
+    ) -> zbus::fdo::Result<crate::Category> {
+        Ok(self
+            .read(header, |s| s.category.clone(), Snapshot::category)
+            .await)
     }
 
     #[zbus(property)]
-    async fn id(&self) -> zbus::fdo::Result<String> {
-        let service = self.0.lock().await; // do NOT use any self methods after this
-        Ok(service.get_id())
+    async fn id(&self, #[zbus(header)] header: Option<Header<'_>>) -> zbus::fdo::Result<String> {
+        Ok(self.read(header, |s| s.id.clone(), Snapshot::id).await)
     }
 
     #[zbus(property)]
-    async fn title(&self) -> zbus::fdo::Result<String> {
-        let service = self.0.lock().await; // do NOT use any self methods after this
-        Ok(service.get_title())
+    async fn title(&self, #[zbus(header)] header: Option<Header<'_>>) -> zbus::fdo::Result<String> {
+        Ok(self
+            .read(header, |s| s.title.clone(), Snapshot::title)
+            .await)
     }
 
     #[zbus(property)]
-    async fn status(&self) -> zbus::fdo::Result<crate::Status> {
-        let service = self.0.lock().await; // do NOT use any self methods after this
-        Ok(service.get_status())
+    async fn status(
+        &self,
+        #[zbus(header)] header: Option<Header<'_>>,
+    ) -> zbus::fdo::Result<crate::Status> {
+        Ok(self.read(header, |s| s.status, Snapshot::status).await)
     }
 
     #[zbus(property)]
-    async fn window_id(&self) -> zbus::fdo::Result<i32> {
-        let service = self.0.lock().await; // do NOT use any self methods after this
-        Ok(service.get_window_id())
+    async fn window_id(
+        &self,
+        #[zbus(header)] header: Option<Header<'_>>,
+    ) -> zbus::fdo::Result<i32> {
+        Ok(self
+            .read(header, |s| s.window_id, Snapshot::window_id)
+            .await)
     }
 
     #[zbus(property)]
-    async fn icon_theme_path(&self) -> zbus::fdo::Result<String> {
-        let service = self.0.lock().await; // do NOT use any self methods after this
-        Ok(service.get_icon_theme_path())
+    async fn icon_theme_path(
+        &self,
+        #[zbus(header)] header: Option<Header<'_>>,
+    ) -> zbus::fdo::Result<String> {
+        Ok(self
+            .read(
+                header,
+                |s| s.icon_theme_path.clone(),
+                Snapshot::icon_theme_path,
+            )
+            .await)
     }
 
     #[zbus(property)]
-    fn menu(&self) -> zbus::fdo::Result<ObjectPath<'_>> {
-        Ok(MENU_PATH)
+    async fn menu(
+        &self,
+        #[zbus(header)] header: Option<Header<'_>>,
+    ) -> zbus::fdo::Result<ObjectPath<'static>> {
+        Ok(self.read(header, |s| s.menu.clone(), Snapshot::menu).await)
     }
 
     #[zbus(property)]
@@ -152,51 +330,154 @@ impl<T: Tray> StatusNotifierItem<T> {
     }
 
     #[zbus(property)]
-    async fn icon_name(&self) -> zbus::fdo::Result<String> {
-        let service = self.0.lock().await; // do NOT use any self methods after this
-        Ok(service.get_icon_name())
+    async fn icon_name(
+        &self,
+        #[zbus(header)] header: Option<Header<'_>>,
+    ) -> zbus::fdo::Result<String> {
+        Ok(self
+            .read(header, |s| s.icon_name.clone(), Snapshot::icon_name)
+            .await)
     }
 
     #[zbus(property)]
-    async fn icon_pixmap(&self) -> zbus::fdo::Result<Vec<Icon>> {
-        let service = self.0.lock().await; // do NOT use any self methods after this
-        Ok(service.get_icon_pixmap())
+    async fn icon_pixmap(
+        &self,
+        #[zbus(header)] header: Option<Header<'_>>,
+    ) -> zbus::fdo::Result<Encoded<Vec<Icon>>> {
+        Ok(self
+            .read(header, |s| s.icon_pixmap.clone(), Snapshot::icon_pixmap)
+            .await)
     }
 
     #[zbus(property)]
-    async fn overlay_icon_name(&self) -> zbus::fdo::Result<String> {
-        let service = self.0.lock().await; // do NOT use any self methods after this
-        Ok(service.get_overlay_icon_name())
+    async fn icon_accessible_desc(
+        &self,
+        #[zbus(header)] header: Option<Header<'_>>,
+    ) -> zbus::fdo::Result<String> {
+        Ok(self
+            .read(
+                header,
+                |s| s.icon_accessible_desc.clone(),
+                Snapshot::icon_accessible_desc,
+            )
+            .await)
     }
 
     #[zbus(property)]
-    async fn overlay_icon_pixmap(&self) -> zbus::fdo::Result<Vec<Icon>> {
-        let service = self.0.lock().await; // do NOT use any self methods after this
-        Ok(service.get_overlay_icon_pixmap())
+    async fn overlay_icon_name(
+        &self,
+        #[zbus(header)] header: Option<Header<'_>>,
+    ) -> zbus::fdo::Result<String> {
+        Ok(self
+            .read(
+                header,
+                |s| s.overlay_icon_name.clone(),
+                Snapshot::overlay_icon_name,
+            )
+            .await)
     }
 
     #[zbus(property)]
-    async fn attention_icon_name(&self) -> zbus::fdo::Result<String> {
-        let service = self.0.lock().await; // do NOT use any self methods after this
-        Ok(service.get_attention_icon_name())
+    async fn overlay_icon_pixmap(
+        &self,
+        #[zbus(header)] header: Option<Header<'_>>,
+    ) -> zbus::fdo::Result<Encoded<Vec<Icon>>> {
+        Ok(self
+            .read(
+                header,
+                |s| s.overlay_icon_pixmap.clone(),
+                Snapshot::overlay_icon_pixmap,
+            )
+            .await)
     }
 
     #[zbus(property)]
-    async fn attention_icon_pixmap(&self) -> zbus::fdo::Result<Vec<Icon>> {
-        let service = self.0.lock().await; // do NOT use any self methods after this
-        Ok(service.get_attention_icon_pixmap())
+    async fn attention_icon_name(
+        &self,
+        #[zbus(header)] header: Option<Header<'_>>,
+    ) -> zbus::fdo::Result<String> {
+        Ok(self
+            .read(
+                header,
+                |s| s.attention_icon_name.clone(),
+                Snapshot::attention_icon_name,
+            )
+            .await)
     }
 
     #[zbus(property)]
-    async fn attention_movie_name(&self) -> zbus::fdo::Result<String> {
-        let service = self.0.lock().await; // do NOT use any self methods after this
-        Ok(service.get_attention_movie_name())
+    async fn attention_icon_pixmap(
+        &self,
+        #[zbus(header)] header: Option<Header<'_>>,
+    ) -> zbus::fdo::Result<Encoded<Vec<Icon>>> {
+        Ok(self
+            .read(
+                header,
+                |s| s.attention_icon_pixmap.clone(),
+                Snapshot::attention_icon_pixmap,
+            )
+            .await)
     }
 
     #[zbus(property)]
-    async fn tool_tip(&self) -> zbus::fdo::Result<ToolTip> {
-        let service = self.0.lock().await; // do NOT use any self methods after this
-        Ok(service.get_tool_tip())
+    async fn attention_movie_name(
+        &self,
+        #[zbus(header)] header: Option<Header<'_>>,
+    ) -> zbus::fdo::Result<String> {
+        Ok(self
+            .read(
+                header,
+                |s| s.attention_movie_name.clone(),
+                Snapshot::attention_movie_name,
+            )
+            .await)
+    }
+
+    #[zbus(property)]
+    async fn attention_accessible_desc(
+        &self,
+        #[zbus(header)] header: Option<Header<'_>>,
+    ) -> zbus::fdo::Result<String> {
+        Ok(self
+            .read(
+                header,
+                |s| s.attention_accessible_desc.clone(),
+                Snapshot::attention_accessible_desc,
+            )
+            .await)
+    }
+
+    #[zbus(property)]
+    async fn tool_tip(
+        &self,
+        #[zbus(header)] header: Option<Header<'_>>,
+    ) -> zbus::fdo::Result<Encoded<ToolTip>> {
+        Ok(self
+            .read(header, |s| s.tool_tip.clone(), Snapshot::tool_tip)
+            .await)
+    }
+
+    // Ayatana extension, empty without `TrayServiceBuilder::ayatana_compat`
+    #[zbus(property, name = "XAyatanaLabel")]
+    async fn x_ayatana_label(
+        &self,
+        #[zbus(header)] header: Option<Header<'_>>,
+    ) -> zbus::fdo::Result<String> {
+        let cached = |s: &Snapshot| s.ayatana_label.0.clone();
+        Ok(self
+            .read(header, cached, |service| service.ayatana_label().0)
+            .await)
+    }
+
+    #[zbus(property, name = "XAyatanaLabelGuide")]
+    async fn x_ayatana_label_guide(
+        &self,
+        #[zbus(header)] header: Option<Header<'_>>,
+    ) -> zbus::fdo::Result<String> {
+        let cached = |s: &Snapshot| s.ayatana_label.1.clone();
+        Ok(self
+            .read(header, cached, |service| service.ayatana_label().1)
+            .await)
     }
 
     // signals
@@ -217,19 +498,87 @@ impl<T: Tray> StatusNotifierItem<T> {
 
     #[zbus(signal)]
     pub async fn new_status(ctxt: &SignalEmitter<'_>, status: &str) -> zbus::Result<()>;
+
+    #[zbus(signal, name = "XAyatanaNewLabel")]
+    pub async fn x_ayatana_new_label(
+        ctxt: &SignalEmitter<'_>,
+        label: &str,
+        guide: &str,
+    ) -> zbus::Result<()>;
+}
+
+// see `TrayServiceBuilder::application_id`
+pub struct Application<T>(Arc<Mutex<Service<T>>>);
+
+impl<T> Application<T> {
+    pub(crate) fn new(service: Arc<Mutex<Service<T>>>) -> Self {
+        Self(service)
+    }
+}
+
+#[zbus::interface(name = "org.freedesktop.Application")]
+impl<T: Tray> Application<T> {
+    async fn activate(
+        &self,
+        #[zbus(connection)] conn: &Connection,
+        platform_data: HashMap<String, OwnedValue>,
+    ) -> zbus::fdo::Result<()> {
+        let token = activation_token(&platform_data);
+        let (mut service, ()) =
+            callback(&self.0, move |s| s.tray.application_activate(token)).await;
+        let _ = service.update(conn).await;
+        Ok(())
+    }
+
+    async fn open(
+        &self,
+        #[zbus(connection)] conn: &Connection,
+        uris: Vec<String>,
+        platform_data: HashMap<String, OwnedValue>,
+    ) -> zbus::fdo::Result<()> {
+        let token = activation_token(&platform_data);
+        let (mut service, ()) =
+            callback(&self.0, move |s| s.tray.application_open(uris, token)).await;
+        let _ = service.update(conn).await;
+        Ok(())
+    }
+
+    async fn activate_action(
+        &self,
+        #[zbus(connection)] conn: &Connection,
+        action_name: String,
+        _parameter: Vec<OwnedValue>,
+        platform_data: HashMap<String, OwnedValue>,
+    ) -> zbus::fdo::Result<()> {
+        let token = activation_token(&platform_data);
+        let (mut service, ()) = callback(&self.0, move |s| {
+            s.tray.application_action(action_name, token)
+        })
+        .await;
+        let _ = service.update(conn).await;
+        Ok(())
+    }
+}
+
+// `desktop-startup-id` is the X11 equivalent
+fn activation_token(platform_data: &HashMap<String, OwnedValue>) -> Option<String> {
+    ["activation-token", "desktop-startup-id"]
+        .iter()
+        .filter_map(|key| platform_data.get(*key))
+        .find_map(|value| value.downcast_ref::<&str>().ok().map(str::to_owned))
 }
 
-#[derive(Debug, Default, Type, Serialize, Deserialize, Value, OwnedValue)]
+#[derive(Debug, Default, Type, Serialize, Value, OwnedValue)]
 pub struct Layout {
     pub id: i32,
-    pub properties: HashMap<String, OwnedValue>,
+    pub properties: crate::menu::PropertyMap,
     pub children: Vec<OwnedValue>,
 }
 
 pub struct DbusMenu<T>(Arc<Mutex<Service<T>>>);
 
 impl<T> DbusMenu<T> {
-    pub fn new(service: Arc<Mutex<Service<T>>>) -> Self {
+    pub(crate) fn new(service: Arc<Mutex<Service<T>>>) -> Self {
         Self(service)
     }
 }
@@ -244,6 +593,9 @@ impl<T: Tray> DbusMenu<T> {
         property_names: Vec<String>,
     ) -> zbus::fdo::Result<(u32, Layout)> {
         let service = self.0.lock().await; // do NOT use any self methods after this
+        if parent_id == 0 {
+            service.stats.menu_requested();
+        }
         let tree = service.build_layout(
             parent_id,
             if recursion_depth < 0 {
@@ -261,7 +613,7 @@ impl<T: Tray> DbusMenu<T> {
         &self,
         ids: Vec<i32>,
         property_names: Vec<String>,
-    ) -> zbus::fdo::Result<Vec<(i32, HashMap<String, OwnedValue>)>> {
+    ) -> zbus::fdo::Result<Vec<(i32, crate::menu::PropertyMap)>> {
         let service = self.0.lock().await; // do NOT use any self methods after this
         let items = ids
             .into_iter()
@@ -290,10 +642,12 @@ impl<T: Tray> DbusMenu<T> {
         data: OwnedValue,
         timestamp: u32,
     ) -> zbus::fdo::Result<()> {
-        let mut service = self.0.lock().await; // do NOT use any self methods after this
-        service
-            .event(conn, true, id, &event_id, data, timestamp)
-            .await
+        let (mut service, called) =
+            callback(&self.0, move |s| s.event(id, &event_id, data, timestamp)).await;
+        if called? {
+            service.update(conn).await?;
+        }
+        Ok(())
     }
 
     async fn event_group(
@@ -304,21 +658,21 @@ impl<T: Tray> DbusMenu<T> {
         if events.is_empty() {
             return Err(zbus::fdo::Error::InvalidArgs("Empty events".into()));
         }
-        let mut service = self.0.lock().await; // do NOT use any self methods after this
         let events_len = events.len();
-        let last_id = events
-            .last()
-            .expect("`events.is_empty` should been checked")
-            .0;
-        let mut not_found = Vec::with_capacity(events_len);
-        for (id, event_id, data, timestamp) in events {
-            if service
-                .event(conn, id == last_id, id, &event_id, data, timestamp)
-                .await
-                .is_err()
-            {
-                not_found.push(id);
+        let (mut service, (called, not_found)) = callback(&self.0, move |s| {
+            let mut called = false;
+            let mut not_found = Vec::with_capacity(events_len);
+            for (id, event_id, data, timestamp) in events {
+                match s.event(id, &event_id, data, timestamp) {
+                    Ok(c) => called |= c,
+                    Err(_) => not_found.push(id),
+                }
             }
+            (called, not_found)
+        })
+        .await;
+        if called {
+            service.update(conn).await?;
         }
         if not_found.len() == events_len {
             Err(zbus::fdo::Error::InvalidArgs(
@@ -329,11 +683,17 @@ impl<T: Tray> DbusMenu<T> {
         }
     }
 
-    async fn about_to_show(&self) -> zbus::fdo::Result<bool> {
+    async fn about_to_show(&self, id: i32) -> zbus::fdo::Result<bool> {
+        if id == 0 {
+            self.0.lock().await.stats.menu_requested();
+        }
         Ok(false)
     }
 
-    async fn about_to_show_group(&self) -> zbus::fdo::Result<(Vec<i32>, Vec<i32>)> {
+    async fn about_to_show_group(&self, ids: Vec<i32>) -> zbus::fdo::Result<(Vec<i32>, Vec<i32>)> {
+        if ids.contains(&0) {
+            self.0.lock().await.stats.menu_requested();
+        }
         Ok(Default::default())
     }
 
@@ -373,8 +733,8 @@ impl<T: Tray> DbusMenu<T> {
     #[zbus(signal)]
     pub async fn items_properties_updated(
         ctxt: &SignalEmitter<'_>,
-        updated_props: Vec<(i32, HashMap<String, OwnedValue>)>,
-        removed_props: Vec<(i32, Vec<String>)>,
+        updated_props: Vec<(i32, crate::menu::PropertyMap)>,
+        removed_props: Vec<(i32, Vec<zbus::zvariant::Str<'static>>)>,
     ) -> zbus::Result<()>;
 
     #[zbus(signal)]
@@ -384,3 +744,77 @@ impl<T: Tray> DbusMenu<T> {
         parent: i32,
     ) -> zbus::Result<()>;
 }
+
+#[cfg(all(test, feature = "tokio"))]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_get_all_snapshot() {
+        struct Counted(&'static str, Arc<AtomicUsize>);
+        impl Tray for Counted {
+            fn id(&self) -> String {
+                "counted".into()
+            }
+            fn title(&self) -> String {
+                self.1.fetch_add(1, Ordering::AcqRel);
+                self.0.into()
+            }
+        }
+        let reads = Arc::new(AtomicUsize::new(0));
+        let (sender, _receiver) = compat::mpsc::unbounded_channel();
+        let service = Service::new(
+            Counted("a", reads.clone()),
+            crate::service::Config::default(),
+            sender,
+        );
+        let (server, client) = tokio::net::UnixStream::pair().unwrap();
+        let server = zbus::connection::Builder::unix_stream(server)
+            .server(zbus::Guid::generate())
+            .unwrap()
+            .p2p()
+            .serve_at(SNI_PATH, StatusNotifierItem::new(service.clone()))
+            .unwrap()
+            .build();
+        let client = zbus::connection::Builder::unix_stream(client).p2p().build();
+        let (_server, client) = futures_util::future::join(server, client).await;
+        // not routed on a p2p connection
+        let props = zbus::fdo::PropertiesProxy::builder(&client.unwrap())
+            .destination("org.kde.StatusNotifierItem-test")
+            .unwrap()
+            .path(SNI_PATH)
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+        let interface =
+            zbus::names::InterfaceName::from_static_str_unchecked("org.kde.StatusNotifierItem");
+        let title =
+            |all: HashMap<String, OwnedValue>| String::try_from(all["Title"].clone()).unwrap();
+        reads.store(0, Ordering::Release);
+
+        // interleaved calls share the snapshot
+        let (a, b) = futures_util::future::join(
+            props.get_all(interface.clone()),
+            props.get_all(interface.clone()),
+        )
+        .await;
+        assert_eq!(
+            (title(a.unwrap()), title(b.unwrap())),
+            ("a".into(), "a".into())
+        );
+        props.get(interface.clone(), "Title").await.unwrap();
+        assert_eq!(reads.load(Ordering::Acquire), 1);
+
+        // a changed tray is read again
+        {
+            let mut service = service.lock().await;
+            service.bump_generation();
+            service.tray.0 = "b";
+        }
+        let all = props.get_all(interface.clone()).await.unwrap();
+        assert_eq!(title(all), "b");
+        assert_eq!(reads.load(Ordering::Acquire), 2);
+    }
+}
@@ -0,0 +1,64 @@
+/// Desktop environment the defaults of the tray are adjusted for
+///
+/// See [`TrayServiceBuilder::desktop_profile`](crate::TrayServiceBuilder::desktop_profile), the
+/// profile is only used if set there.
+///
+/// # Examples
+///
+/// ```
+/// use ksni::DesktopProfile;
+///
+/// assert_eq!(DesktopProfile::from_xdg_current_desktop("GNOME"), DesktopProfile::Gnome);
+/// assert_eq!(
+///     DesktopProfile::from_xdg_current_desktop("ubuntu:GNOME"),
+///     DesktopProfile::Gnome
+/// );
+/// assert_eq!(DesktopProfile::from_xdg_current_desktop("xfce"), DesktopProfile::Xfce);
+/// assert_eq!(DesktopProfile::from_xdg_current_desktop("sway"), DesktopProfile::Generic);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DesktopProfile {
+    /// The defaults of ksni
+    #[default]
+    Generic,
+    /// KDE Plasma, the reference implementation of StatusNotifierItem
+    Kde,
+    /// GNOME with the AppIndicator extension
+    ///
+    /// The extension shows [`Tray::icon_name`](crate::Tray::icon_name) even if the icon theme
+    /// doesn't have it, so the name is hidden if an icon pixmap is set.
+    Gnome,
+    Lxqt,
+    Xfce,
+}
+
+impl DesktopProfile {
+    /// The profile of the current desktop, from `$XDG_CURRENT_DESKTOP`
+    pub fn detect() -> Self {
+        std::env::var("XDG_CURRENT_DESKTOP")
+            .map(|desktops| Self::from_xdg_current_desktop(&desktops))
+            .unwrap_or_default()
+    }
+
+    /// The profile of the first known desktop in a colon separated list, ignoring case
+    ///
+    /// Distributions put their own name first, e.g. `ubuntu:GNOME`, the following entries name
+    /// the desktops it's derived from.
+    pub fn from_xdg_current_desktop(desktops: &str) -> Self {
+        desktops
+            .split(':')
+            .find_map(|desktop| match desktop.to_ascii_lowercase().as_str() {
+                "kde" => Some(Self::Kde),
+                "gnome" => Some(Self::Gnome),
+                "lxqt" => Some(Self::Lxqt),
+                "xfce" => Some(Self::Xfce),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn prefers_icon_pixmap(self) -> bool {
+        self == Self::Gnome
+    }
+}
@@ -0,0 +1,271 @@
+//! Checks whether the desktop session can show trays
+//!
+//! Useful to show a "tray not supported" message instead of silently running without a tray,
+//! see the `ksni-doctor` example for a command line tool built with it
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # async fn f() {
+//! let report = ksni::diagnostics::probe().await;
+//! if !report.is_supported() {
+//!     for advice in report.advice() {
+//!         eprintln!("{advice}");
+//!     }
+//! }
+//! # }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+
+use zbus::fdo::DBusProxy;
+use zbus::zvariant::OwnedValue;
+use zbus::Connection;
+
+use crate::dbus_interface::StatusNotifierWatcherProxy;
+use crate::SessionTarget;
+
+const WATCHER_NAME: &str = "org.kde.StatusNotifierWatcher";
+const GNOME_APPINDICATOR_EXTENSIONS: &[&str] = &[
+    "appindicatorsupport@rgcjonas.gmail.com",
+    "ubuntu-appindicators@ubuntu.com",
+];
+
+/// State of the AppIndicator extension of GNOME Shell
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum GnomeExtensionState {
+    NotInstalled,
+    Disabled,
+    Enabled,
+}
+
+/// Result of [`probe`]
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Report {
+    /// Error while connecting to the session bus, `None` if connected
+    pub session_bus_error: Option<String>,
+    /// A StatusNotifierWatcher is running
+    pub watcher_present: bool,
+    /// A StatusNotifierHost registered to the watcher
+    pub host_registered: bool,
+    /// `ProtocolVersion` of the watcher
+    pub protocol_version: Option<i32>,
+    /// Items currently registered to the watcher
+    pub registered_items: Vec<String>,
+    /// Value of `XDG_CURRENT_DESKTOP`
+    pub current_desktop: Option<String>,
+    /// State of the AppIndicator extension, `None` if GNOME Shell is not running
+    pub gnome_appindicator: Option<GnomeExtensionState>,
+}
+
+impl Report {
+    /// Whether a tray will be shown, spawning a tray returns [`Error::WontShow`] otherwise
+    ///
+    /// [`Error::WontShow`]: crate::Error::WontShow
+    pub fn is_supported(&self) -> bool {
+        self.session_bus_error.is_none() && self.watcher_present && self.host_registered
+    }
+
+    /// Human readable advice for the problems found, empty if trays are supported
+    pub fn advice(&self) -> Vec<String> {
+        let mut advice = Vec::new();
+        if let Some(e) = &self.session_bus_error {
+            advice.push(format!(
+                "Can not connect to the session bus ({e}), check that DBUS_SESSION_BUS_ADDRESS \
+                 is set and a dbus-daemon is running for this session"
+            ));
+            return advice;
+        }
+        match self.gnome_appindicator {
+            Some(GnomeExtensionState::NotInstalled) => advice.push(
+                "GNOME Shell has no built-in tray, install the AppIndicator and \
+                 KStatusNotifierItem Support extension"
+                    .into(),
+            ),
+            Some(GnomeExtensionState::Disabled) => advice.push(
+                "The AppIndicator extension of GNOME Shell is installed but disabled, enable \
+                 it with `gnome-extensions enable appindicatorsupport@rgcjonas.gmail.com`"
+                    .into(),
+            ),
+            _ => {}
+        }
+        if !self.watcher_present {
+            advice.push(
+                "No StatusNotifierWatcher is running, the desktop environment or panel does not \
+                 support StatusNotifierItem, or its tray is not started"
+                    .into(),
+            );
+        } else if !self.host_registered {
+            advice.push(
+                "A StatusNotifierWatcher is running but no StatusNotifierHost registered, \
+                 add a tray/system tray widget to the panel"
+                    .into(),
+            );
+        }
+        advice
+    }
+}
+
+/// Inspect the session bus
+///
+/// Must be called in the context of the async runtime
+pub async fn probe() -> Report {
+    let mut report = Report {
+        session_bus_error: None,
+        watcher_present: false,
+        host_registered: false,
+        protocol_version: None,
+        registered_items: Vec::new(),
+        current_desktop: std::env::var("XDG_CURRENT_DESKTOP").ok(),
+        gnome_appindicator: None,
+    };
+    let conn = match Connection::session().await {
+        Ok(conn) => conn,
+        Err(e) => {
+            report.session_bus_error = Some(e.to_string());
+            return report;
+        }
+    };
+
+    report.gnome_appindicator = gnome_appindicator_state(&conn).await;
+
+    let dbus_object = match DBusProxy::new(&conn).await {
+        Ok(proxy) => proxy,
+        Err(e) => {
+            report.session_bus_error = Some(e.to_string());
+            return report;
+        }
+    };
+    let watcher_name = WATCHER_NAME.try_into().expect("valid bus name");
+    report.watcher_present = dbus_object
+        .name_has_owner(watcher_name)
+        .await
+        .unwrap_or(false);
+    if !report.watcher_present {
+        return report;
+    }
+
+    let Ok(watcher) = StatusNotifierWatcherProxy::new(&conn).await else {
+        return report;
+    };
+    report.host_registered = watcher
+        .is_status_notifier_host_registered()
+        .await
+        .unwrap_or(false);
+    report.protocol_version = watcher.protocol_version().await.ok();
+    report.registered_items = watcher
+        .registered_status_notifier_items()
+        .await
+        .unwrap_or_default();
+    report
+}
+
+async fn gnome_appindicator_state(conn: &Connection) -> Option<GnomeExtensionState> {
+    let extensions = zbus::Proxy::new(
+        conn,
+        "org.gnome.Shell",
+        "/org/gnome/Shell",
+        "org.gnome.Shell.Extensions",
+    )
+    .await
+    .ok()?;
+    let mut found = None;
+    for uuid in GNOME_APPINDICATOR_EXTENSIONS {
+        // fails if GNOME Shell is not running
+        let info: HashMap<String, OwnedValue> =
+            extensions.call("GetExtensionInfo", &(uuid,)).await.ok()?;
+        // see ExtensionState in gnome-shell/js/misc/extensionUtils.js
+        let state = match info.get("state").map(f64::try_from) {
+            Some(Ok(1.0)) => GnomeExtensionState::Enabled,
+            Some(_) => GnomeExtensionState::Disabled,
+            None => GnomeExtensionState::NotInstalled,
+        };
+        found = Some(found.map_or(state, |prev: GnomeExtensionState| prev.max(state)));
+    }
+    found
+}
+
+/// A session bus with a StatusNotifierWatcher, see [`find_watchers`]
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct WatcherInfo {
+    /// Pass it to [`TrayServiceBuilder::session`](crate::TrayServiceBuilder::session) to show a
+    /// tray on this bus
+    pub session: SessionTarget,
+    /// Unique name of the watcher on the bus
+    pub owner: String,
+    /// A StatusNotifierHost registered to the watcher
+    pub host_registered: bool,
+}
+
+/// Find the session buses with a StatusNotifierWatcher
+///
+/// Checks the session bus of the process, and the user buses in `/run/user` that can be
+/// connected to, each bus once. On a multi-seat system, there's a bus for every user logged in.
+///
+/// Must be called in the context of the async runtime
+pub async fn find_watchers() -> Vec<WatcherInfo> {
+    let mut targets = vec![SessionTarget::Current];
+    if let Ok(entries) = std::fs::read_dir("/run/user") {
+        let mut sockets: Vec<_> = entries
+            .filter_map(|entry| Some(entry.ok()?.path().join("bus")))
+            .filter(|path| path.exists())
+            .collect();
+        sockets.sort();
+        targets.extend(
+            sockets
+                .into_iter()
+                .map(|path| SessionTarget::Address(format!("unix:path={}", path.display()))),
+        );
+    }
+
+    let mut watchers = Vec::new();
+    let mut seen_buses = HashSet::new();
+    for session in targets {
+        let Ok(builder) = session.connection_builder() else {
+            continue;
+        };
+        let Ok(conn) = builder.build().await else {
+            continue;
+        };
+        // the bus of the process is usually one in `/run/user` too
+        if !seen_buses.insert(conn.server_guid().to_string()) {
+            continue;
+        }
+        let Ok(dbus_object) = DBusProxy::new(&conn).await else {
+            continue;
+        };
+        let watcher_name = WATCHER_NAME.try_into().expect("valid bus name");
+        let Ok(owner) = dbus_object.get_name_owner(watcher_name).await else {
+            continue;
+        };
+        let host_registered = match StatusNotifierWatcherProxy::new(&conn).await {
+            Ok(watcher) => watcher
+                .is_status_notifier_host_registered()
+                .await
+                .unwrap_or(false),
+            Err(_) => false,
+        };
+        watchers.push(WatcherInfo {
+            session,
+            owner: owner.to_string(),
+            host_registered,
+        });
+    }
+    watchers
+}
+
+/// Blocking version of [`find_watchers`]
+#[cfg(feature = "blocking")]
+#[cfg_attr(docsrs, doc(cfg(feature = "blocking")))]
+pub fn find_watchers_blocking() -> Vec<WatcherInfo> {
+    crate::compat::block_on(find_watchers())
+}
+
+/// Blocking version of [`probe`]
+#[cfg(feature = "blocking")]
+#[cfg_attr(docsrs, doc(cfg(feature = "blocking")))]
+pub fn probe_blocking() -> Report {
+    crate::compat::block_on(probe())
+}
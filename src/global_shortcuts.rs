@@ -0,0 +1,153 @@
+//! Binds the shortcuts of menu items with the XDG GlobalShortcuts portal
+//!
+//! See https://flatpak.github.io/xdg-desktop-portal/docs/doc-org.freedesktop.portal.GlobalShortcuts.html
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use futures_util::future::{self, Either};
+use futures_util::StreamExt;
+use zbus::zvariant::{OwnedObjectPath, OwnedValue, Value};
+use zbus::Connection;
+
+use crate::compat::{mpsc, oneshot};
+use crate::HandleReuest;
+
+const PORTAL_NAME: &str = "org.freedesktop.portal.Desktop";
+const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+const INTERFACE: &str = "org.freedesktop.portal.GlobalShortcuts";
+
+static TOKEN_COUNTER: AtomicUsize = AtomicUsize::new(1);
+
+/// Convert the shortcut of a menu item to a trigger of the XDG shortcuts specification
+///
+/// The portal only supports a single key combination, returns `None` for others
+pub(crate) fn portal_trigger(shortcut: &[Vec<String>]) -> Option<String> {
+    let [keys] = shortcut else {
+        return None;
+    };
+    let (key, modifiers) = keys.split_last()?;
+    let mut trigger: Vec<&str> = modifiers
+        .iter()
+        .map(|modifier| match modifier.as_str() {
+            "Control" => "CTRL",
+            "Alt" => "ALT",
+            "Shift" => "SHIFT",
+            "Super" => "LOGO",
+            other => other,
+        })
+        .collect();
+    trigger.push(key);
+    Some(trigger.join("+"))
+}
+
+/// Create a portal session, bind `shortcuts` (trigger, description), then forward activations
+/// to the service loop until it stops or `stop` is dropped, which closes the session
+pub(crate) async fn bind(
+    conn: Connection,
+    shortcuts: Vec<(String, String)>,
+    sender: mpsc::UnboundedSender<HandleReuest>,
+    mut stop: oneshot::Receiver<()>,
+) -> zbus::Result<()> {
+    let portal = zbus::Proxy::new(&conn, PORTAL_NAME, PORTAL_PATH, INTERFACE).await?;
+    // subscribe before binding, so no activation is missed
+    let mut activated = portal.receive_signal("Activated").await?;
+
+    let token = format!(
+        "ksni_{}_{}",
+        std::process::id(),
+        TOKEN_COUNTER.fetch_add(1, Ordering::AcqRel)
+    );
+    let sender_name = conn
+        .unique_name()
+        .expect("unique name should be set after connected")
+        .trim_start_matches(':')
+        .replace('.', "_");
+    // the request path is predictable, subscribe to the response before the call to avoid races
+    let request_path = format!("{PORTAL_PATH}/request/{sender_name}/{token}");
+    let request = zbus::Proxy::new(
+        &conn,
+        PORTAL_NAME,
+        request_path,
+        "org.freedesktop.portal.Request",
+    )
+    .await?;
+    let mut response = request.receive_signal("Response").await?;
+
+    let options: HashMap<&str, Value> = HashMap::from([
+        ("handle_token", Value::from(token.as_str())),
+        ("session_handle_token", Value::from(token.as_str())),
+    ]);
+    let _: OwnedObjectPath = portal.call("CreateSession", &(options,)).await?;
+    let Some(msg) = response.next().await else {
+        return Ok(());
+    };
+    let (code, results): (u32, HashMap<String, OwnedValue>) = msg.body().deserialize()?;
+    if code != 0 {
+        return Ok(()); // cancelled by the user
+    }
+    let session = match results.get("session_handle").map(|v| &**v) {
+        Some(Value::ObjectPath(path)) => OwnedObjectPath::from(path.to_owned()),
+        Some(Value::Str(path)) => OwnedObjectPath::try_from(path.as_str())?.clone(),
+        _ => return Ok(()),
+    };
+
+    let shortcuts: Vec<(String, HashMap<&str, Value>)> = shortcuts
+        .iter()
+        .map(|(trigger, description)| {
+            let options = HashMap::from([
+                ("description", Value::from(description.as_str())),
+                ("preferred_trigger", Value::from(trigger.as_str())),
+            ]);
+            (trigger.clone(), options)
+        })
+        .collect();
+    let options: HashMap<&str, Value> = HashMap::new();
+    let _: OwnedObjectPath = portal
+        .call("BindShortcuts", &(&session, shortcuts, "", options))
+        .await?;
+
+    while let Either::Left((Some(msg), _)) = future::select(activated.next(), &mut stop).await {
+        let Ok((session_handle, id, _timestamp, _options)) =
+            msg.body()
+                .deserialize::<(OwnedObjectPath, String, u64, HashMap<String, OwnedValue>)>()
+        else {
+            continue;
+        };
+        if session_handle != session {
+            continue;
+        }
+        if sender.send(HandleReuest::GlobalShortcut(id)).is_err() {
+            break;
+        }
+    }
+    // replaced by the shortcuts of a new menu, the portal would keep both bound
+    let session = zbus::Proxy::new(
+        &conn,
+        PORTAL_NAME,
+        session.as_ref(),
+        "org.freedesktop.portal.Session",
+    )
+    .await?;
+    session.call("Close", &()).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_portal_trigger() {
+        let shortcut = vec![vec![
+            "Control".to_owned(),
+            "Shift".to_owned(),
+            "S".to_owned(),
+        ]];
+        assert_eq!(portal_trigger(&shortcut).as_deref(), Some("CTRL+SHIFT+S"));
+        let chord = vec![
+            vec!["Control".to_owned(), "Q".to_owned()],
+            vec!["X".to_owned()],
+        ];
+        assert_eq!(portal_trigger(&chord), None);
+    }
+}
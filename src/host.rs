@@ -0,0 +1,199 @@
+//! Features supported by the tray host
+//!
+//! Hosts implement different subsets of StatusNotifierItem and dbusmenu, and don't advertise
+//! them. The host is identified by the process owning the StatusNotifierWatcher, known hosts
+//! are matched against a built-in table.
+//!
+//! This is a best-effort guess: the process name is only read on Linux, from `/proc`, and the
+//! watcher may run in another process than the host showing the items.
+
+use zbus::fdo::DBusProxy;
+use zbus::names::BusName;
+use zbus::Connection;
+
+use crate::MenuItem;
+
+const WATCHER_NAME: &str = "org.kde.StatusNotifierWatcher";
+
+/// Features a tray host can display, see [`Handle::host_capabilities`]
+///
+/// # Examples
+///
+/// ```no_run
+/// # async fn f(handle: ksni::Handle<impl ksni::Tray>, menu: Vec<ksni::MenuItem<()>>) {
+/// use ksni::HostCapabilities;
+///
+/// if let Some(host) = handle.host_capabilities().await {
+///     let missing = HostCapabilities::used_by(&menu).difference(host);
+///     if missing.contains(HostCapabilities::MENU_SHORTCUT) {
+///         eprintln!("menu shortcuts are not shown by this host");
+///     }
+/// }
+/// # }
+/// ```
+///
+/// [`Handle::host_capabilities`]: crate::Handle::host_capabilities
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct HostCapabilities(u32);
+
+impl HostCapabilities {
+    /// The context menu
+    pub const MENU: Self = Self(1 << 0);
+    /// `icon_name` of menu items
+    pub const MENU_ICON_NAME: Self = Self(1 << 1);
+    /// `icon_data` of menu items
+    pub const MENU_ICON_DATA: Self = Self(1 << 2);
+    /// `shortcut` of menu items
+    pub const MENU_SHORTCUT: Self = Self(1 << 3);
+    /// [`Tray::tool_tip`](crate::Tray::tool_tip)
+    pub const TOOL_TIP: Self = Self(1 << 4);
+    /// [`Tray::overlay_icon_name`](crate::Tray::overlay_icon_name) and
+    /// [`Tray::overlay_icon_pixmap`](crate::Tray::overlay_icon_pixmap)
+    pub const OVERLAY_ICON: Self = Self(1 << 5);
+    /// [`Tray::attention_movie_name`](crate::Tray::attention_movie_name)
+    pub const ATTENTION_MOVIE: Self = Self(1 << 6);
+
+    /// No feature
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// All features
+    pub const fn all() -> Self {
+        Self((1 << 7) - 1)
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    /// Features in `self` but not in `other`
+    pub const fn difference(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+
+    /// Menu features used by the items
+    pub fn used_by<T>(menu: &[MenuItem<T>]) -> Self {
+        let mut used = Self::empty();
+        let mut add = |icon_name: &str, icon_data: &[u8], shortcut: &[Vec<String>]| {
+            if !icon_name.is_empty() {
+                used |= Self::MENU_ICON_NAME;
+            }
+            if !icon_data.is_empty() {
+                used |= Self::MENU_ICON_DATA;
+            }
+            if !shortcut.is_empty() {
+                used |= Self::MENU_SHORTCUT;
+            }
+        };
+        for (_, item) in crate::menu::iter(menu) {
+            match item {
+                MenuItem::Standard(item) => add(&item.icon_name, &item.icon_data, &item.shortcut),
+                MenuItem::Checkmark(item) => add(&item.icon_name, &item.icon_data, &item.shortcut),
+                MenuItem::SubMenu(item) => add(&item.icon_name, &item.icon_data, &item.shortcut),
+                MenuItem::RadioGroup(group) => {
+                    for item in &group.options {
+                        add(&item.icon_name, &item.icon_data, &item.shortcut);
+                    }
+                }
+                MenuItem::Separator => {}
+            }
+        }
+        if !menu.is_empty() {
+            used |= Self::MENU;
+        }
+        used
+    }
+
+    // capabilities of a host by the name of its process
+    fn of_process(name: &str) -> Self {
+        match name {
+            "plasmashell" | "kded5" | "kded6" => Self::all(),
+            // AppIndicator extension
+            "gnome-shell" => {
+                Self::MENU | Self::MENU_ICON_NAME | Self::MENU_ICON_DATA | Self::OVERLAY_ICON
+            }
+            "waybar" => {
+                Self::MENU
+                    | Self::MENU_ICON_NAME
+                    | Self::MENU_ICON_DATA
+                    | Self::MENU_SHORTCUT
+                    | Self::TOOL_TIP
+            }
+            // don't warn about hosts we know nothing about
+            _ => Self::all(),
+        }
+    }
+}
+
+impl std::ops::BitOr for HostCapabilities {
+    type Output = Self;
+    fn bitor(self, rhs: Self) -> Self {
+        self.union(rhs)
+    }
+}
+
+impl std::ops::BitOrAssign for HostCapabilities {
+    fn bitor_assign(&mut self, rhs: Self) {
+        *self = self.union(rhs);
+    }
+}
+
+// None if no watcher is running
+pub(crate) async fn capabilities(conn: &Connection) -> Option<HostCapabilities> {
+    let dbus_object = DBusProxy::new(conn).await.ok()?;
+    let watcher = BusName::try_from(WATCHER_NAME).expect("valid bus name");
+    let pid = dbus_object
+        .get_connection_unix_process_id(watcher)
+        .await
+        .ok()?;
+    Some(HostCapabilities::of_process(&process_name(pid)))
+}
+
+// empty if unknown, the process may be in another PID namespace
+#[cfg(target_os = "linux")]
+fn process_name(pid: u32) -> String {
+    let name = std::fs::read_to_string(format!("/proc/{pid}/comm")).unwrap_or_default();
+    name.trim_end().to_owned()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn process_name(_pid: u32) -> String {
+    String::new()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::menu::{StandardItem, SubMenu};
+
+    #[test]
+    fn test_used_by() {
+        let menu: Vec<MenuItem<()>> = vec![SubMenu {
+            label: "a".into(),
+            submenu: vec![StandardItem {
+                label: "b".into(),
+                shortcut: vec![vec!["Control".into(), "q".into()]],
+                ..Default::default()
+            }
+            .into()],
+            ..Default::default()
+        }
+        .into()];
+        let used = HostCapabilities::used_by(&menu);
+        assert_eq!(
+            used,
+            HostCapabilities::MENU | HostCapabilities::MENU_SHORTCUT
+        );
+        let gnome = HostCapabilities::of_process("gnome-shell");
+        assert_eq!(used.difference(gnome), HostCapabilities::MENU_SHORTCUT);
+    }
+}
@@ -0,0 +1,210 @@
+//! Pixmaps of themed icons, for hosts that only show `IconPixmap`
+//!
+//! Looking up and decoding icons accesses the file system, the service does it off the runtime
+//! before each update, see `prefetch`. Property reads only use the results.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::service::lock;
+use crate::Icon;
+
+// sizes commonly used by panels
+const SIZES: [u16; 5] = [16, 22, 24, 32, 48];
+// a tray uses a few names, the cache is cleared if it has more
+const MAX_CACHED: usize = 64;
+
+struct Cache {
+    theme: Option<String>,
+    // see `has_icon`
+    found: BTreeMap<String, bool>,
+    pixmaps: BTreeMap<String, Vec<Icon>>,
+}
+
+static CACHE: Mutex<Cache> = Mutex::new(Cache {
+    theme: None,
+    found: BTreeMap::new(),
+    pixmaps: BTreeMap::new(),
+});
+
+// the pixmaps of `name`, `None` if not looked up yet
+pub(crate) fn cached_lookup(name: &str) -> Option<Vec<Icon>> {
+    lock(&CACHE).pixmaps.get(name).cloned()
+}
+
+pub(crate) fn is_cached(name: &str) -> bool {
+    lock(&CACHE).pixmaps.contains_key(name)
+}
+
+// whether `name` is in the theme, `None` if not looked up yet
+pub(crate) fn cached_has_icon(name: &str) -> Option<bool> {
+    lock(&CACHE).found.get(name).copied()
+}
+
+// resolve each list of names like `icons::resolve` and look up the pixmaps of the resolved
+// names, on a blocking thread if any of them is not cached
+pub(crate) async fn prefetch(names: Vec<Vec<String>>) {
+    let cached = |names: &Vec<String>| {
+        let name = crate::icons::resolve_cached(names);
+        (names.len() < 2 || names.iter().all(|name| cached_has_icon(name).is_some()))
+            && (name.is_empty() || is_cached(&name))
+    };
+    if names.iter().all(cached) {
+        return;
+    }
+    crate::compat::unblock(move || {
+        let theme = theme();
+        for names in names {
+            let name = crate::icons::resolve(&names);
+            if !name.is_empty() && !is_cached(&name) {
+                let icons = lookup(&theme, &name);
+                insert(&mut lock(&CACHE).pixmaps, name, icons);
+            }
+        }
+    })
+    .await
+}
+
+fn insert<V>(map: &mut BTreeMap<String, V>, name: String, value: V) {
+    if map.len() >= MAX_CACHED {
+        map.clear();
+    }
+    map.insert(name, value);
+}
+
+// read once, changing the theme needs a restart like most applications
+fn theme() -> String {
+    if let Some(theme) = &lock(&CACHE).theme {
+        return theme.clone();
+    }
+    let theme = freedesktop_icons::default_theme_gtk().unwrap_or_else(|| "hicolor".to_owned());
+    lock(&CACHE).theme = Some(theme.clone());
+    theme
+}
+
+// `name` in the icon theme of the desktop, PNG files decoded, SVG files rendered at each size
+fn lookup(theme: &str, name: &str) -> Vec<Icon> {
+    let mut seen = HashSet::new();
+    SIZES
+        .iter()
+        .filter_map(|&size| {
+            let path = freedesktop_icons::lookup(name)
+                .with_theme(theme)
+                .with_size(size)
+                .with_cache()
+                .find()?;
+            Some((path, size))
+        })
+        .filter_map(|(path, size)| match path.extension()?.to_str()? {
+            "png" => seen.insert(path.clone()).then(|| decode_png(&path))?,
+            "svg" | "svgz" => render_svg(&path, size.into()),
+            _ => None,
+        })
+        .collect()
+}
+
+// any size and format, the host may render SVG icons. Accesses the file system if not cached
+pub(crate) fn has_icon(name: &str) -> bool {
+    if let Some(found) = cached_has_icon(name) {
+        return found;
+    }
+    let found = freedesktop_icons::lookup(name)
+        .with_theme(&theme())
+        .with_cache()
+        .find()
+        .is_some();
+    insert(&mut lock(&CACHE).found, name.to_owned(), found);
+    found
+}
+
+fn decode_png(path: &Path) -> Option<Icon> {
+    let file = std::fs::File::open(path).ok()?;
+    let mut decoder = png::Decoder::new(std::io::BufReader::new(file));
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().ok()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).ok()?;
+    let pixels = &buf[..info.buffer_size()];
+
+    let mut data = Vec::with_capacity(info.width as usize * info.height as usize * 4);
+    let mut push = |r, g, b, a| data.extend_from_slice(&[a, r, g, b]);
+    match info.color_type {
+        png::ColorType::Rgba => pixels
+            .chunks_exact(4)
+            .for_each(|p| push(p[0], p[1], p[2], p[3])),
+        png::ColorType::Rgb => pixels
+            .chunks_exact(3)
+            .for_each(|p| push(p[0], p[1], p[2], 0xff)),
+        png::ColorType::GrayscaleAlpha => pixels
+            .chunks_exact(2)
+            .for_each(|p| push(p[0], p[0], p[0], p[1])),
+        png::ColorType::Grayscale => pixels.iter().for_each(|&p| push(p, p, p, 0xff)),
+        // expanded by `Transformations::EXPAND`
+        png::ColorType::Indexed => return None,
+    }
+    Some(Icon {
+        width: info.width as i32,
+        height: info.height as i32,
+        data,
+    })
+}
+
+fn render_svg(path: &Path, size: u32) -> Option<Icon> {
+    use resvg::{tiny_skia, usvg};
+
+    let data = std::fs::read(path).ok()?;
+    let tree = usvg::Tree::from_data(&data, &usvg::Options::default()).ok()?;
+    let mut pixmap = tiny_skia::Pixmap::new(size, size)?;
+    let scale = size as f32 / tree.size().width().max(tree.size().height());
+    resvg::render(
+        &tree,
+        tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+    let data = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|p| {
+            let p = p.demultiply();
+            [p.alpha(), p.red(), p.green(), p.blue()]
+        })
+        .collect();
+    Some(Icon {
+        width: size as i32,
+        height: size as i32,
+        data,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_resolve_cached() {
+        let names = ["ksni-test-missing", "ksni-test-found"];
+        // not looked up, the first one is used without accessing the theme
+        assert_eq!(crate::icons::resolve_cached(&names), names[0]);
+        insert(&mut lock(&CACHE).found, names[0].to_owned(), false);
+        insert(&mut lock(&CACHE).found, names[1].to_owned(), true);
+        assert_eq!(crate::icons::resolve_cached(&names), names[1]);
+        assert!(has_icon(names[1]));
+    }
+
+    #[test]
+    fn test_render_svg() {
+        let path = std::env::temp_dir().join(format!("ksni-icon-{}.svg", std::process::id()));
+        let svg = r##"<svg xmlns="http://www.w3.org/2000/svg" width="16" height="16">
+            <rect x="0" y="0" width="8" height="16" fill="#ff0000"/>
+        </svg>"##;
+        std::fs::write(&path, svg).unwrap();
+        let icon = render_svg(&path, 32).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!((icon.width, icon.height), (32, 32));
+        // scaled, the left half is red and the right half is transparent
+        assert_eq!(icon.data[..4], [0xff, 0xff, 0, 0]);
+        assert_eq!(icon.data[31 * 4..32 * 4], [0; 4]);
+    }
+}
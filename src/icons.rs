@@ -0,0 +1,337 @@
+//! Names of the freedesktop standard icons
+//!
+//! Icon themes following the [Icon Naming Specification] provide these names, use them in
+//! [`Tray::icon_name`](crate::Tray::icon_name) or the `icon_name` of menu items to avoid typos.
+//!
+//! ```
+//! struct MyTray;
+//!
+//! impl ksni::Tray for MyTray {
+//!     fn id(&self) -> String {
+//!         "my-tray".into()
+//!     }
+//!     fn icon_name(&self) -> String {
+//!         ksni::icons::NETWORK_WIRELESS.into()
+//!     }
+//! }
+//! ```
+//!
+//! [Icon Naming Specification]: https://specifications.freedesktop.org/icon-naming-spec/latest/
+
+macro_rules! icons {
+    ($($name:ident = $value:literal,)*) => {
+        $(
+            #[doc = concat!("`\"", $value, "\"`")]
+            pub const $name: &str = $value;
+        )*
+
+        /// Every name of this module
+        pub const ALL: &[&str] = &[$($name),*];
+    };
+}
+
+icons! {
+    // actions
+    ADDRESS_BOOK_NEW = "address-book-new",
+    APPLICATION_EXIT = "application-exit",
+    APPOINTMENT_NEW = "appointment-new",
+    CALL_START = "call-start",
+    CALL_STOP = "call-stop",
+    CONTACT_NEW = "contact-new",
+    DOCUMENT_NEW = "document-new",
+    DOCUMENT_OPEN = "document-open",
+    DOCUMENT_OPEN_RECENT = "document-open-recent",
+    DOCUMENT_PAGE_SETUP = "document-page-setup",
+    DOCUMENT_PRINT = "document-print",
+    DOCUMENT_PRINT_PREVIEW = "document-print-preview",
+    DOCUMENT_PROPERTIES = "document-properties",
+    DOCUMENT_REVERT = "document-revert",
+    DOCUMENT_SAVE = "document-save",
+    DOCUMENT_SAVE_AS = "document-save-as",
+    DOCUMENT_SEND = "document-send",
+    EDIT_CLEAR = "edit-clear",
+    EDIT_COPY = "edit-copy",
+    EDIT_CUT = "edit-cut",
+    EDIT_DELETE = "edit-delete",
+    EDIT_FIND = "edit-find",
+    EDIT_FIND_REPLACE = "edit-find-replace",
+    EDIT_PASTE = "edit-paste",
+    EDIT_REDO = "edit-redo",
+    EDIT_SELECT_ALL = "edit-select-all",
+    EDIT_UNDO = "edit-undo",
+    FOLDER_NEW = "folder-new",
+    FORMAT_INDENT_LESS = "format-indent-less",
+    FORMAT_INDENT_MORE = "format-indent-more",
+    FORMAT_JUSTIFY_CENTER = "format-justify-center",
+    FORMAT_JUSTIFY_FILL = "format-justify-fill",
+    FORMAT_JUSTIFY_LEFT = "format-justify-left",
+    FORMAT_JUSTIFY_RIGHT = "format-justify-right",
+    FORMAT_TEXT_DIRECTION_LTR = "format-text-direction-ltr",
+    FORMAT_TEXT_DIRECTION_RTL = "format-text-direction-rtl",
+    FORMAT_TEXT_BOLD = "format-text-bold",
+    FORMAT_TEXT_ITALIC = "format-text-italic",
+    FORMAT_TEXT_UNDERLINE = "format-text-underline",
+    FORMAT_TEXT_STRIKETHROUGH = "format-text-strikethrough",
+    GO_BOTTOM = "go-bottom",
+    GO_DOWN = "go-down",
+    GO_FIRST = "go-first",
+    GO_HOME = "go-home",
+    GO_JUMP = "go-jump",
+    GO_LAST = "go-last",
+    GO_NEXT = "go-next",
+    GO_PREVIOUS = "go-previous",
+    GO_TOP = "go-top",
+    GO_UP = "go-up",
+    HELP_ABOUT = "help-about",
+    HELP_CONTENTS = "help-contents",
+    HELP_FAQ = "help-faq",
+    INSERT_IMAGE = "insert-image",
+    INSERT_LINK = "insert-link",
+    INSERT_OBJECT = "insert-object",
+    INSERT_TEXT = "insert-text",
+    LIST_ADD = "list-add",
+    LIST_REMOVE = "list-remove",
+    MAIL_FORWARD = "mail-forward",
+    MAIL_MARK_IMPORTANT = "mail-mark-important",
+    MAIL_MARK_JUNK = "mail-mark-junk",
+    MAIL_MARK_NOTJUNK = "mail-mark-notjunk",
+    MAIL_MARK_READ = "mail-mark-read",
+    MAIL_MARK_UNREAD = "mail-mark-unread",
+    MAIL_MESSAGE_NEW = "mail-message-new",
+    MAIL_REPLY_ALL = "mail-reply-all",
+    MAIL_REPLY_SENDER = "mail-reply-sender",
+    MAIL_SEND = "mail-send",
+    MAIL_SEND_RECEIVE = "mail-send-receive",
+    MEDIA_EJECT = "media-eject",
+    MEDIA_PLAYBACK_PAUSE = "media-playback-pause",
+    MEDIA_PLAYBACK_START = "media-playback-start",
+    MEDIA_PLAYBACK_STOP = "media-playback-stop",
+    MEDIA_RECORD = "media-record",
+    MEDIA_SEEK_BACKWARD = "media-seek-backward",
+    MEDIA_SEEK_FORWARD = "media-seek-forward",
+    MEDIA_SKIP_BACKWARD = "media-skip-backward",
+    MEDIA_SKIP_FORWARD = "media-skip-forward",
+    OBJECT_FLIP_HORIZONTAL = "object-flip-horizontal",
+    OBJECT_FLIP_VERTICAL = "object-flip-vertical",
+    OBJECT_ROTATE_LEFT = "object-rotate-left",
+    OBJECT_ROTATE_RIGHT = "object-rotate-right",
+    PROCESS_STOP = "process-stop",
+    SYSTEM_LOCK_SCREEN = "system-lock-screen",
+    SYSTEM_LOG_OUT = "system-log-out",
+    SYSTEM_RUN = "system-run",
+    SYSTEM_SEARCH = "system-search",
+    SYSTEM_REBOOT = "system-reboot",
+    SYSTEM_SHUTDOWN = "system-shutdown",
+    TOOLS_CHECK_SPELLING = "tools-check-spelling",
+    VIEW_FULLSCREEN = "view-fullscreen",
+    VIEW_REFRESH = "view-refresh",
+    VIEW_RESTORE = "view-restore",
+    VIEW_SORT_ASCENDING = "view-sort-ascending",
+    VIEW_SORT_DESCENDING = "view-sort-descending",
+    WINDOW_CLOSE = "window-close",
+    WINDOW_NEW = "window-new",
+    ZOOM_FIT_BEST = "zoom-fit-best",
+    ZOOM_IN = "zoom-in",
+    ZOOM_ORIGINAL = "zoom-original",
+    ZOOM_OUT = "zoom-out",
+
+    // applications
+    ACCESSORIES_CALCULATOR = "accessories-calculator",
+    ACCESSORIES_CHARACTER_MAP = "accessories-character-map",
+    ACCESSORIES_DICTIONARY = "accessories-dictionary",
+    ACCESSORIES_TEXT_EDITOR = "accessories-text-editor",
+    HELP_BROWSER = "help-browser",
+    MULTIMEDIA_VOLUME_CONTROL = "multimedia-volume-control",
+    PREFERENCES_DESKTOP_ACCESSIBILITY = "preferences-desktop-accessibility",
+    PREFERENCES_DESKTOP_FONT = "preferences-desktop-font",
+    PREFERENCES_DESKTOP_KEYBOARD = "preferences-desktop-keyboard",
+    PREFERENCES_DESKTOP_LOCALE = "preferences-desktop-locale",
+    PREFERENCES_DESKTOP_MULTIMEDIA = "preferences-desktop-multimedia",
+    PREFERENCES_DESKTOP_SCREENSAVER = "preferences-desktop-screensaver",
+    PREFERENCES_DESKTOP_THEME = "preferences-desktop-theme",
+    PREFERENCES_DESKTOP_WALLPAPER = "preferences-desktop-wallpaper",
+    SYSTEM_FILE_MANAGER = "system-file-manager",
+    SYSTEM_SOFTWARE_INSTALL = "system-software-install",
+    SYSTEM_SOFTWARE_UPDATE = "system-software-update",
+    UTILITIES_SYSTEM_MONITOR = "utilities-system-monitor",
+    UTILITIES_TERMINAL = "utilities-terminal",
+
+    // devices
+    AUDIO_CARD = "audio-card",
+    AUDIO_INPUT_MICROPHONE = "audio-input-microphone",
+    BATTERY = "battery",
+    CAMERA_PHOTO = "camera-photo",
+    CAMERA_VIDEO = "camera-video",
+    CAMERA_WEB = "camera-web",
+    COMPUTER = "computer",
+    DRIVE_HARDDISK = "drive-harddisk",
+    DRIVE_OPTICAL = "drive-optical",
+    DRIVE_REMOVABLE_MEDIA = "drive-removable-media",
+    INPUT_GAMING = "input-gaming",
+    INPUT_KEYBOARD = "input-keyboard",
+    INPUT_MOUSE = "input-mouse",
+    INPUT_TABLET = "input-tablet",
+    MEDIA_FLASH = "media-flash",
+    MEDIA_FLOPPY = "media-floppy",
+    MEDIA_OPTICAL = "media-optical",
+    MEDIA_TAPE = "media-tape",
+    MODEM = "modem",
+    MULTIMEDIA_PLAYER = "multimedia-player",
+    NETWORK_WIRED = "network-wired",
+    NETWORK_WIRELESS = "network-wireless",
+    PDA = "pda",
+    PHONE = "phone",
+    PRINTER = "printer",
+    SCANNER = "scanner",
+    VIDEO_DISPLAY = "video-display",
+
+    // emblems
+    EMBLEM_DEFAULT = "emblem-default",
+    EMBLEM_DOCUMENTS = "emblem-documents",
+    EMBLEM_DOWNLOADS = "emblem-downloads",
+    EMBLEM_FAVORITE = "emblem-favorite",
+    EMBLEM_IMPORTANT = "emblem-important",
+    EMBLEM_MAIL = "emblem-mail",
+    EMBLEM_PHOTOS = "emblem-photos",
+    EMBLEM_READONLY = "emblem-readonly",
+    EMBLEM_SHARED = "emblem-shared",
+    EMBLEM_SYMBOLIC_LINK = "emblem-symbolic-link",
+    EMBLEM_SYNCHRONIZED = "emblem-synchronized",
+    EMBLEM_SYSTEM = "emblem-system",
+    EMBLEM_UNREADABLE = "emblem-unreadable",
+
+    // places
+    FOLDER = "folder",
+    FOLDER_REMOTE = "folder-remote",
+    NETWORK_SERVER = "network-server",
+    NETWORK_WORKGROUP = "network-workgroup",
+    START_HERE = "start-here",
+    USER_BOOKMARKS = "user-bookmarks",
+    USER_DESKTOP = "user-desktop",
+    USER_HOME = "user-home",
+    USER_TRASH = "user-trash",
+
+    // status
+    APPOINTMENT_MISSED = "appointment-missed",
+    APPOINTMENT_SOON = "appointment-soon",
+    AUDIO_VOLUME_HIGH = "audio-volume-high",
+    AUDIO_VOLUME_LOW = "audio-volume-low",
+    AUDIO_VOLUME_MEDIUM = "audio-volume-medium",
+    AUDIO_VOLUME_MUTED = "audio-volume-muted",
+    BATTERY_CAUTION = "battery-caution",
+    BATTERY_LOW = "battery-low",
+    DIALOG_ERROR = "dialog-error",
+    DIALOG_INFORMATION = "dialog-information",
+    DIALOG_PASSWORD = "dialog-password",
+    DIALOG_QUESTION = "dialog-question",
+    DIALOG_WARNING = "dialog-warning",
+    FOLDER_DRAG_ACCEPT = "folder-drag-accept",
+    FOLDER_OPEN = "folder-open",
+    FOLDER_VISITING = "folder-visiting",
+    IMAGE_LOADING = "image-loading",
+    IMAGE_MISSING = "image-missing",
+    MAIL_ATTACHMENT = "mail-attachment",
+    MAIL_UNREAD = "mail-unread",
+    MAIL_READ = "mail-read",
+    MAIL_REPLIED = "mail-replied",
+    MAIL_SIGNED = "mail-signed",
+    MAIL_SIGNED_VERIFIED = "mail-signed-verified",
+    MEDIA_PLAYLIST_REPEAT = "media-playlist-repeat",
+    MEDIA_PLAYLIST_SHUFFLE = "media-playlist-shuffle",
+    NETWORK_ERROR = "network-error",
+    NETWORK_IDLE = "network-idle",
+    NETWORK_OFFLINE = "network-offline",
+    NETWORK_RECEIVE = "network-receive",
+    NETWORK_TRANSMIT = "network-transmit",
+    NETWORK_TRANSMIT_RECEIVE = "network-transmit-receive",
+    PRINTER_ERROR = "printer-error",
+    PRINTER_PRINTING = "printer-printing",
+    SECURITY_HIGH = "security-high",
+    SECURITY_MEDIUM = "security-medium",
+    SECURITY_LOW = "security-low",
+    SOFTWARE_UPDATE_AVAILABLE = "software-update-available",
+    SOFTWARE_UPDATE_URGENT = "software-update-urgent",
+    SYNC_ERROR = "sync-error",
+    SYNC_SYNCHRONIZING = "sync-synchronizing",
+    TASK_DUE = "task-due",
+    TASK_PAST_DUE = "task-past-due",
+    USER_AVAILABLE = "user-available",
+    USER_AWAY = "user-away",
+    USER_IDLE = "user-idle",
+    USER_OFFLINE = "user-offline",
+    USER_TRASH_FULL = "user-trash-full",
+    WEATHER_CLEAR = "weather-clear",
+    WEATHER_CLEAR_NIGHT = "weather-clear-night",
+    WEATHER_FEW_CLOUDS = "weather-few-clouds",
+    WEATHER_FEW_CLOUDS_NIGHT = "weather-few-clouds-night",
+    WEATHER_FOG = "weather-fog",
+    WEATHER_OVERCAST = "weather-overcast",
+    WEATHER_SEVERE_ALERT = "weather-severe-alert",
+    WEATHER_SHOWERS = "weather-showers",
+    WEATHER_SHOWERS_SCATTERED = "weather-showers-scattered",
+    WEATHER_SNOW = "weather-snow",
+    WEATHER_STORM = "weather-storm",
+}
+
+/// The first of `names` the icon theme of the desktop has
+///
+/// Only checked with the "icon-theme" feature, the host usually uses the same theme. Without
+/// it, or if the theme has none of them, it's the first name. Empty if `names` is empty.
+///
+/// The theme is read from the file system the first time a name is checked, don't call this
+/// from async code. The service resolves [`Tray::icon_names`](crate::Tray::icon_names) on a
+/// blocking thread.
+///
+/// ```
+/// let name = ksni::icons::resolve(&["my-app-tray", ksni::icons::MAIL_UNREAD]);
+/// # let _ = name;
+/// ```
+pub fn resolve<S: AsRef<str>>(names: &[S]) -> String {
+    #[cfg(feature = "icon-theme")]
+    let has_icon = crate::icon_theme::has_icon;
+    #[cfg(not(feature = "icon-theme"))]
+    let has_icon = |_: &str| false;
+    first_of(names, has_icon)
+}
+
+// `resolve` without accessing the file system, names not looked up yet are skipped, see
+// `icon_theme::prefetch`
+pub(crate) fn resolve_cached<S: AsRef<str>>(names: &[S]) -> String {
+    #[cfg(feature = "icon-theme")]
+    let has_icon = |name: &str| crate::icon_theme::cached_has_icon(name) == Some(true);
+    #[cfg(not(feature = "icon-theme"))]
+    let has_icon = |_: &str| false;
+    first_of(names, has_icon)
+}
+
+fn first_of<S: AsRef<str>>(names: &[S], has_icon: impl Fn(&str) -> bool) -> String {
+    names
+        .iter()
+        .find(|name| has_icon(name.as_ref()))
+        .or_else(|| names.first())
+        .map(|name| name.as_ref().to_owned())
+        .unwrap_or_default()
+}
+
+/// Whether `name` is a standard icon or a more specific variant of one
+///
+/// Icon themes fall back to the standard name by removing dash-separated parts from the end,
+/// e.g. `"network-wireless-signal-good-symbolic"` to `"network-wireless"`.
+///
+/// ```
+/// assert!(ksni::icons::is_standard("battery-low"));
+/// assert!(ksni::icons::is_standard("network-wireless-signal-good-symbolic"));
+/// assert!(!ksni::icons::is_standard("my-app"));
+/// ```
+pub fn is_standard(name: &str) -> bool {
+    let mut name = name;
+    loop {
+        if ALL.contains(&name) {
+            return true;
+        }
+        match name.rsplit_once('-') {
+            Some((prefix, _)) => name = prefix,
+            None => return false,
+        }
+    }
+}
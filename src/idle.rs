@@ -0,0 +1,74 @@
+//! Follows the idle state of the session, with the screensaver of the session or logind
+//!
+//! See https://specifications.freedesktop.org/idle-inhibit-spec/latest/ and
+//! https://www.freedesktop.org/software/systemd/man/latest/org.freedesktop.login1.html
+
+use futures_util::future::{self, Either};
+use futures_util::StreamExt;
+use zbus::Connection;
+
+use crate::compat::{mpsc, oneshot};
+use crate::HandleReuest;
+
+// where the idle state is read from, logind is only used without a screensaver
+pub(crate) enum IdleMonitor {
+    ScreenSaver(zbus::Proxy<'static>),
+    Logind(zbus::Proxy<'static>),
+}
+
+impl IdleMonitor {
+    /// The monitor and the current state, `None` if neither is available
+    pub async fn new(conn: &Connection) -> Option<(Self, bool)> {
+        if let Ok(screensaver) = zbus::Proxy::new(
+            conn,
+            "org.freedesktop.ScreenSaver",
+            "/org/freedesktop/ScreenSaver",
+            "org.freedesktop.ScreenSaver",
+        )
+        .await
+        {
+            if let Ok(active) = screensaver.call("GetActive", &()).await {
+                return Some((IdleMonitor::ScreenSaver(screensaver), active));
+            }
+        }
+        let system = Connection::system().await.ok()?;
+        let session = zbus::Proxy::new(
+            &system,
+            "org.freedesktop.login1",
+            "/org/freedesktop/login1/session/auto",
+            "org.freedesktop.login1.Session",
+        )
+        .await
+        .ok()?;
+        let idle = session.get_property("IdleHint").await.ok()?;
+        Some((IdleMonitor::Logind(session), idle))
+    }
+
+    /// Forward changes of the idle state to the service loop until `stop` is dropped
+    ///
+    /// The connection to the system bus is closed with it.
+    pub async fn watch(
+        self,
+        sender: mpsc::UnboundedSender<HandleReuest>,
+        mut stop: oneshot::Receiver<()>,
+    ) -> zbus::Result<()> {
+        let mut changed = match self {
+            IdleMonitor::ScreenSaver(screensaver) => screensaver
+                .receive_signal("ActiveChanged")
+                .await?
+                .filter_map(|msg| async move { msg.body().deserialize::<bool>().ok() })
+                .boxed(),
+            IdleMonitor::Logind(session) => session
+                .receive_property_changed::<bool>("IdleHint")
+                .await
+                .filter_map(|changed| async move { changed.get().await.ok() })
+                .boxed(),
+        };
+        while let Either::Left((Some(idle), _)) = future::select(changed.next(), &mut stop).await {
+            if sender.send(HandleReuest::SessionIdle(idle)).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
@@ -21,23 +21,113 @@
 //! ksni = { version = "0.3", features = ["blocking"] }
 //! ```
 //!
+//! # Derive
+//!
+//! Enable the "derive" feature to generate [`Tray`] impls of simple trays from attributes, see
+//! [`StaticTray`]
+//!
+//! # Menu validation
+//!
+//! Enable the "menu-validation" feature to check the menu on every update and report problems
+//! to [`Tray::on_menu_warning`], see [`menu::validate`]
+//!
+//! # Icon theme
+//!
+//! Some hosts only show [`Tray::icon_pixmap`]. Enable the "icon-theme" feature to look up
+//! [`Tray::icon_name`] in the icon theme of the desktop and publish the PNG files found as
+//! pixmaps, if the tray has no pixmap. Same for the attention icon. SVG files are rendered at
+//! common panel sizes, the theme is read on a blocking thread before updates. The first name of
+//! [`Tray::icon_names`] the theme has is used.
+//!
+//! # Global shortcuts
+//!
+//! Shortcuts of menu items are only hints displayed by the host. Enable the "global-hotkey"
+//! feature to bind them with the [XDG GlobalShortcuts portal], so pressing them activates the
+//! items. Only shortcuts with a single key combination are bound, they are bound again when a
+//! menu update changes them.
+//!
+//! # Presets
+//!
+//! Enable the "presets" feature to get ready-made battery, network and volume indicators, see
+//! the [`presets`] module
+//!
+//! # Test utilities
+//!
+//! Enable the "test-util" feature in dev-dependencies to pause the clock of the timers in tests,
+//! see the [`testing`] module
+//!
 //! [Tokio]: https://tokio.rs
+//! [XDG GlobalShortcuts portal]: https://flatpak.github.io/xdg-desktop-portal/docs/doc-org.freedesktop.portal.GlobalShortcuts.html
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::Ordering;
 use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
 
+use futures_util::future::Either;
+use futures_util::FutureExt;
+
+mod appearance;
 #[cfg(feature = "blocking")]
 #[cfg_attr(docsrs, doc(cfg(feature = "blocking")))]
 pub mod blocking;
+#[cfg(feature = "capi")]
+#[cfg_attr(docsrs, doc(cfg(feature = "capi")))]
+pub mod capi;
 mod compat;
+pub mod composite;
 mod dbus_interface;
+mod desktop;
+pub mod diagnostics;
+#[cfg(feature = "global-hotkey")]
+mod global_shortcuts;
+mod host;
+#[cfg(feature = "icon-theme")]
+mod icon_theme;
+pub mod icons;
+mod idle;
+mod locale;
 pub mod menu;
+#[cfg(feature = "menu-file")]
+#[cfg_attr(docsrs, doc(cfg(feature = "menu-file")))]
+pub mod menu_file;
+pub mod notify;
+pub mod persistence;
+#[cfg(feature = "presets")]
+#[cfg_attr(docsrs, doc(cfg(feature = "presets")))]
+pub mod presets;
+pub mod raw;
 mod service;
+#[cfg(feature = "blocking")]
+#[cfg_attr(docsrs, doc(cfg(feature = "blocking")))]
+pub mod simple;
+pub mod stateful;
+pub mod testing;
 mod tray;
+mod validation;
+pub mod value;
 
+pub use desktop::DesktopProfile;
+pub use host::HostCapabilities;
+pub use locale::{system_locale, LocalizedString};
 #[doc(inline)]
 pub use menu::{MenuItem, TextDirection};
-pub use tray::{Category, Icon, Orientation, Status, ToolTip};
+pub use service::{PropertyValue, TrayStateBlob};
+pub use tray::{
+    ActivateInfo, AttentionLevel, Category, ColorScheme, HostEvent, Icon, Orientation,
+    PropertyFlags, ScrollEvent, Status, ThemedIcon, ToolTip, TrayAction,
+};
+pub use validation::{validate, Diagnostic};
+
+#[cfg(feature = "derive")]
+#[cfg_attr(docsrs, doc(cfg(feature = "derive")))]
+pub use ksni_derive::StaticTray;
+
+/// The version of zbus used by ksni, for the connection shared by
+/// [`Handle::with_sni_interface`]
+pub use zbus;
 
 use crate::compat::{mpsc, oneshot, Mutex};
 
@@ -76,6 +166,33 @@ pub trait Tray: Sized + Send + 'static {
     /// an hint to the item where to show eventual windows (if any).
     fn activate(&mut self, _x: i32, _y: i32) {}
 
+    /// Like [`Self::activate`], with the XDG activation token provided by the host
+    ///
+    /// Plasma sends a token with `ProvideXdgActivationToken` before activating the item, other
+    /// hosts don't, so [`ActivateInfo::xdg_activation_token`] may be `None`.
+    ///
+    /// Default calls [`Self::activate`]
+    fn activate_with_info(&mut self, info: ActivateInfo) {
+        self.activate(info.x, info.y)
+    }
+
+    /// The application was activated through `org.freedesktop.Application`, e.g. it was
+    /// launched again, see [`TrayServiceBuilder::application_id`]
+    ///
+    /// `activation_token` is the XDG activation token of the launcher, if it sent one.
+    #[allow(unused_variables)]
+    fn application_activate(&mut self, activation_token: Option<String>) {}
+
+    /// Files or URIs were opened with the application through `org.freedesktop.Application`,
+    /// see [`Self::application_activate`]
+    #[allow(unused_variables)]
+    fn application_open(&mut self, uris: Vec<String>, activation_token: Option<String>) {}
+
+    /// An action of the desktop file was activated through `org.freedesktop.Application`, see
+    /// [`Self::application_activate`]
+    #[allow(unused_variables)]
+    fn application_action(&mut self, action: String, activation_token: Option<String>) {}
+
     /// Is to be considered a secondary and less important form of activation
     /// compared to Activate.
     /// This is typically a consequence of user input, such as mouse middle
@@ -95,6 +212,13 @@ pub trait Tray: Sized + Send + 'static {
     /// request.
     fn scroll(&mut self, _delta: i32, _orientation: Orientation) {}
 
+    /// Like [`Self::scroll`], with the details of the scroll in a struct that can be extended
+    ///
+    /// Default calls [`Self::scroll`]
+    fn scroll_event(&mut self, event: ScrollEvent) {
+        self.scroll(event.delta, event.orientation)
+    }
+
     /// Describes the category of this item.
     fn category(&self) -> Category {
         Category::ApplicationStatus
@@ -126,22 +250,71 @@ pub trait Tray: Sized + Send + 'static {
         Default::default()
     }
 
-    /// The item only support the context menu, the visualization
-    /// should prefer showing the menu or sending ContextMenu()
-    /// instead of Activate()
+    // The item only support the context menu, the visualization
+    // should prefer showing the menu or sending ContextMenu()
+    // instead of Activate()
     // fn item_is_menu() -> bool { false }
 
     /// The StatusNotifierItem can carry an icon that can be used by the
     /// visualization to identify the item.
+    ///
+    /// If empty, the first available name of [`Self::icon_names`] is used.
     fn icon_name(&self) -> String {
         Default::default()
     }
 
+    /// Icon names in order of preference, used if [`Self::icon_name`] is empty
+    ///
+    /// Saves hardcoding icon names per desktop, e.g. a name of the app, then a symbolic one that
+    /// only some themes have, then a standard one. See [`icons::resolve`] for how the name is
+    /// picked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # struct MyTray;
+    /// # impl ksni::Tray for MyTray {
+    /// # fn id(&self) -> String { "my-tray".into() }
+    /// fn icon_names(&self) -> Vec<String> {
+    ///     vec![
+    ///         "my-app-tray".into(),
+    ///         "mail-unread-symbolic".into(),
+    ///         ksni::icons::MAIL_UNREAD.into(),
+    ///     ]
+    /// }
+    /// # }
+    /// ```
+    fn icon_names(&self) -> Vec<String> {
+        Default::default()
+    }
+
     /// Carries an ARGB32 binary representation of the icon
+    ///
+    /// The result is kept until the next update, property reads from hosts don't call this
+    /// again, see [`Handle::update`]
     fn icon_pixmap(&self) -> Vec<Icon> {
         Default::default()
     }
 
+    /// Description of the icon for accessibility tools (e.g. screen readers), defaults to
+    /// [`Self::title`] on most hosts
+    ///
+    /// This is a KDE extension of the specification.
+    fn icon_accessible_desc(&self) -> String {
+        Default::default()
+    }
+
+    /// Device pixel ratio of the panel that shows the icon, e.g. `Some(2)` on Hi-DPI displays
+    ///
+    /// When set, every pixmap list (including the one in [`Self::tool_tip`]) is reordered
+    /// so that the smallest pixmap that doesn't need to be upscaled comes first, hosts
+    /// that simply pick the first entry will get the crisp one.
+    ///
+    /// Default is `None`, the pixmaps are published in the order they are returned
+    fn icon_scale_hint(&self) -> Option<u32> {
+        None
+    }
+
     /// The Freedesktop-compliant name of an icon. This can be used by the
     /// visualization to indicate extra state information, for instance as an
     /// overlay for the main icon.
@@ -151,6 +324,8 @@ pub trait Tray: Sized + Send + 'static {
 
     /// ARGB32 binary representation of the overlay icon described in the
     /// previous paragraph.
+    ///
+    /// Cached like [`Tray::icon_pixmap`]
     fn overlay_icon_pixmap(&self) -> Vec<Icon> {
         Default::default()
     }
@@ -161,12 +336,87 @@ pub trait Tray: Sized + Send + 'static {
         Default::default()
     }
 
+    /// How urgent the attention is, chooses the attention icon with
+    /// [`Tray::attention_level_icon_name`]
+    ///
+    /// Hosts only know about one attention state, the level is not sent to them.
+    fn attention_level(&self) -> AttentionLevel {
+        Default::default()
+    }
+
+    /// The attention icon of a level, replaces [`Tray::attention_icon_name`] for the current
+    /// [`Tray::attention_level`]
+    ///
+    /// An empty name falls back to the icon of the lower levels, then to
+    /// [`Tray::attention_icon_name`]. The name is only read again when the level changes, it
+    /// should only depend on `level`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use ksni::AttentionLevel;
+    /// # struct MyTray;
+    /// # impl ksni::Tray for MyTray {
+    /// # fn id(&self) -> String { "my-tray".into() }
+    /// fn attention_icon_name(&self) -> String {
+    ///     "dialog-information".into()
+    /// }
+    /// fn attention_level_icon_name(&self, level: AttentionLevel) -> String {
+    ///     match level {
+    ///         AttentionLevel::Warning => "dialog-warning".into(),
+    ///         AttentionLevel::Critical => "dialog-error".into(),
+    ///         _ => String::new(),
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    #[allow(unused_variables)]
+    fn attention_level_icon_name(&self, level: AttentionLevel) -> String {
+        Default::default()
+    }
+
+    /// A desktop notification shown when the status becomes [`Status::NeedsAttention`]
+    ///
+    /// Closed when the status changes again, or the tray is shut down. Useful on hosts hiding tray
+    /// icons, see [`notify`]. Read once per change of the status, `None` shows nothing.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use ksni::notify::Notification;
+    /// # struct MyTray;
+    /// # impl ksni::Tray for MyTray {
+    /// # fn id(&self) -> String { "my-tray".into() }
+    /// fn attention_notification(&self) -> Option<Notification> {
+    ///     Some(Notification {
+    ///         summary: "Battery low".into(),
+    ///         icon_name: "battery-caution".into(),
+    ///         urgency: self.attention_level().into(),
+    ///         ..Default::default()
+    ///     })
+    /// }
+    /// # }
+    /// ```
+    fn attention_notification(&self) -> Option<notify::Notification> {
+        None
+    }
+
     /// ARGB32 binary representation of the requesting attention icon describe in
     /// the previous paragraph.
+    ///
+    /// Cached like [`Tray::icon_pixmap`]
     fn attention_icon_pixmap(&self) -> Vec<Icon> {
         Default::default()
     }
 
+    /// Description of the requesting attention icon for accessibility tools (e.g. screen
+    /// readers)
+    ///
+    /// This is a KDE extension of the specification.
+    fn attention_accessible_desc(&self) -> String {
+        Default::default()
+    }
+
     /// An item can also specify an animation associated to the
     /// RequestingAttention state.
     /// This should be either a Freedesktop-compliant icon name or a full path.
@@ -179,16 +429,45 @@ pub trait Tray: Sized + Send + 'static {
     /// Data structure that describes extra information associated to this item,
     /// that can be visualized for instance by a tooltip (or by any other mean
     /// the visualization consider appropriate.
+    ///
+    /// Cached like [`Tray::icon_pixmap`]
     fn tool_tip(&self) -> ToolTip {
         Default::default()
     }
 
+    /// Like [`Tray::tool_tip`], `None` clears the tooltip
+    ///
+    /// `None` is sent as an empty [`ToolTip`], hosts show it differently, KDE Plasma shows
+    /// [`Tray::title`] instead.
+    ///
+    /// Default is `Some(self.tool_tip())`, [`Tray::tool_tip`] isn't called if this is
+    /// implemented.
+    fn tool_tip_opt(&self) -> Option<ToolTip> {
+        Some(self.tool_tip())
+    }
+
     /// Represents the way the text direction of the application.  This
     /// allows the server to handle mismatches intelligently.
     fn text_direction(&self) -> TextDirection {
         TextDirection::LeftToRight
     }
 
+    /// A short text shown next to the icon, e.g. a percentage
+    ///
+    /// This is an Ayatana extension of the specification, only published with
+    /// [`TrayServiceBuilder::ayatana_compat`].
+    fn label(&self) -> String {
+        Default::default()
+    }
+
+    /// The longest text the label is expected to have, hosts use it to reserve space and avoid
+    /// moving other items when the label changes
+    ///
+    /// Only published with [`TrayServiceBuilder::ayatana_compat`], like [`Tray::label`].
+    fn label_guide(&self) -> String {
+        Default::default()
+    }
+
     /// The menu you want to display
     ///
     /// See examples in root documentation
@@ -196,6 +475,268 @@ pub trait Tray: Sized + Send + 'static {
         Default::default()
     }
 
+    /// The menu, generated with what ksni knows about the host
+    ///
+    /// Replaces [`Tray::menu`] if implemented, e.g. to leave out items the host can't display.
+    /// Like the other getters it must not change the tray, also not through interior
+    /// mutability, or changes are not detected. Updating the tray from it panics instead of
+    /// deadlocking.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use ksni::menu::{MenuGenerationContext, StandardItem};
+    /// # use ksni::{HostCapabilities, MenuItem};
+    /// # struct MyTray;
+    /// # impl ksni::Tray for MyTray {
+    /// # fn id(&self) -> String { "my-tray".into() }
+    /// fn menu_with(&self, ctx: &MenuGenerationContext) -> Vec<MenuItem<Self>> {
+    ///     let shortcuts = ctx
+    ///         .host_capabilities
+    ///         .map_or(true, |host| host.contains(HostCapabilities::MENU_SHORTCUT));
+    ///     vec![StandardItem {
+    ///         label: if shortcuts { "Open".into() } else { "Open (Ctrl+O)".into() },
+    ///         shortcut: vec![vec!["Control".into(), "o".into()]],
+    ///         ..Default::default()
+    ///     }
+    ///     .into()]
+    /// }
+    /// # }
+    /// ```
+    // the default impl don't use this parameter, but it should be used by user, so keep the name
+    // without _ for autocomplete
+    #[allow(unused_variables)]
+    fn menu_with(&self, ctx: &menu::MenuGenerationContext) -> Vec<MenuItem<Self>> {
+        self.menu()
+    }
+
+    /// A menu that is already flattened, replaces [`Tray::menu`] if it returns `Some`
+    ///
+    /// For bindings building the menu programmatically. Every entry is an item with the indices
+    /// of its children, the first entry is the root, usually [`menu::RawMenuItem::default`].
+    /// Every other item must be the child of exactly one item, otherwise an empty menu is shown
+    /// and [`Tray::on_menu_warning`] is called. It's read as often as [`Tray::menu`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use ksni::menu::{RawMenuItem, StandardItem};
+    /// # struct MyTray;
+    /// # impl ksni::Tray for MyTray {
+    /// # fn id(&self) -> String { "my-tray".into() }
+    /// fn raw_menu(&self) -> Option<Vec<(RawMenuItem<Self>, Vec<usize>)>> {
+    ///     Some(vec![
+    ///         (RawMenuItem::default(), vec![1, 2]),
+    ///         (StandardItem::default().into(), vec![]),
+    ///         (RawMenuItem::separator(), vec![]),
+    ///     ])
+    /// }
+    /// # }
+    /// ```
+    fn raw_menu(&self) -> Option<Vec<(menu::RawMenuItem<Self>, Vec<usize>)>> {
+        None
+    }
+
+    /// A dbusmenu exported by the application itself, e.g. by a Qt `QMenu`
+    ///
+    /// If set, ksni doesn't serve its own menu and [`Self::menu`] is never called, the `Menu`
+    /// property points to this path. The menu must be exported on the connection of the tray:
+    /// the property is a path on the bus name of the item, there is no way to point it at
+    /// another bus name.
+    ///
+    /// Only called once when the service starts.
+    fn external_menu_path(&self) -> Option<zbus::zvariant::ObjectPath<'static>> {
+        None
+    }
+
+    /// Properties that never change while the tray service is running
+    ///
+    /// Every [`Handle::update`] hashes all properties to find out what has changed, this can be
+    /// expensive for big pixmaps. Properties returned here are skipped, changes to them will
+    /// never be signaled to the host.
+    ///
+    /// Only called once when the service starts.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # struct X;
+    /// # impl ksni::Tray for X {
+    /// # fn id(&self) -> String { "x".into() }
+    /// fn const_properties(&self) -> ksni::PropertyFlags {
+    ///     ksni::PropertyFlags::ICON_PIXMAP | ksni::PropertyFlags::ATTENTION_ICON_PIXMAP
+    /// }
+    /// # }
+    /// ```
+    fn const_properties(&self) -> PropertyFlags {
+        PropertyFlags::empty()
+    }
+
+    /// The locale of the tray changed
+    ///
+    /// Called by [`Handle::set_locale`] with a locale other than the current one, which is
+    /// [`system_locale`] when the tray is spawned. Store it and translate the texts with it
+    /// (e.g. using [`LocalizedString`]), changed labels will be sent to the host.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use ksni::LocalizedString;
+    /// struct MyTray {
+    ///     // initialized with `ksni::system_locale()`
+    ///     locale: String,
+    /// }
+    ///
+    /// impl ksni::Tray for MyTray {
+    ///     # fn id(&self) -> String { "x".into() }
+    ///     fn locale_changed(&mut self, locale: &str) {
+    ///         self.locale = locale.to_owned();
+    ///     }
+    ///     fn title(&self) -> String {
+    ///         LocalizedString::new("Music Player")
+    ///             .with("fr", "Lecteur de musique")
+    ///             .get(&self.locale)
+    ///             .into()
+    ///     }
+    /// }
+    /// ```
+    // the default impl don't use this parameter, but it should be used by user, so keep the name
+    // without _ for autocomplete
+    #[allow(unused_variables)]
+    fn locale_changed(&mut self, locale: &str) {}
+
+    /// The menu returned by [`Self::menu`] has a problem, see [`menu::validate`]
+    ///
+    /// Only called with the "menu-validation" feature, on every menu update. Invalid layouts of
+    /// [`Self::raw_menu`] are always reported.
+    #[allow(unused_variables)]
+    fn on_menu_warning(&self, warning: menu::MenuWarning) {}
+
+    /// A menu item was activated, called after its own callback
+    ///
+    /// `path` is the position of the item in [`Self::menu`], or in the tree of
+    /// [`Self::raw_menu`], see [`menu::MenuPath`]. Useful to handle the clicks of a generated
+    /// menu in one place, e.g. with [`menu::get_mut`] on a copy of the menu.
+    #[allow(unused_variables)]
+    fn menu_item_activated(&mut self, path: &menu::MenuPath) {}
+
+    /// The host opened the menu, or a submenu
+    ///
+    /// `path` is the position of the submenu, the root for the whole menu, see
+    /// [`menu::MenuPath::is_root`]. Changes are sent to the host, e.g. to fill the menu only
+    /// when it's shown. Not all hosts send this event.
+    #[allow(unused_variables)]
+    fn menu_opened(&mut self, path: &menu::MenuPath) {}
+
+    /// The host closed the menu, or a submenu, see [`Self::menu_opened`]
+    #[allow(unused_variables)]
+    fn menu_closed(&mut self, path: &menu::MenuPath) {}
+
+    /// The host sent a menu event other than `clicked`, `hovered`, `opened` and `closed`, e.g.
+    /// for an item of a vendor type
+    ///
+    /// Return `true` if the tray was changed, the changes are sent to the host. Otherwise the
+    /// event is passed to [`Self::unknown_menu_event`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use ksni::menu::{MenuEventData, MenuPath};
+    /// # struct MyTray { volume: f64 }
+    /// # impl ksni::Tray for MyTray {
+    /// # fn id(&self) -> String { "my-tray".into() }
+    /// fn menu_event(&mut self, path: &MenuPath, event_id: &str, data: &MenuEventData) -> bool {
+    ///     match (event_id, data) {
+    ///         ("value-changed", MenuEventData::Double(volume)) => {
+    ///             self.volume = *volume;
+    ///             true
+    ///         }
+    ///         _ => false,
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    #[allow(unused_variables)]
+    fn menu_event(
+        &mut self,
+        path: &menu::MenuPath,
+        event_id: &str,
+        data: &menu::MenuEventData,
+    ) -> bool {
+        false
+    }
+
+    /// The host sent a menu event ksni doesn't know, the event is ignored
+    ///
+    /// Known events are `clicked`, `hovered`, `opened` and `closed`, other events are passed to
+    /// [`Self::menu_event`] first. Log it to debug new hosts.
+    #[allow(unused_variables)]
+    fn unknown_menu_event(&self, path: &menu::MenuPath, event_id: &str) {}
+
+    /// An icon pixmap larger than [`TrayServiceBuilder::max_icon_size`] was downscaled
+    ///
+    /// Called once for every original size. Provide smaller pixmaps to avoid downscaling after
+    /// every icon change.
+    #[allow(unused_variables)]
+    fn on_icon_downscaled(&self, width: i32, height: i32) {}
+
+    /// The color scheme of the desktop changed
+    ///
+    /// Only called if [`TrayServiceBuilder::watch_color_scheme`] is enabled, once before the tray
+    /// is registered and after every change. Store it and pick the icons with it (e.g. using
+    /// [`ThemedIcon`]), changed icons will be sent to the host.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use ksni::{ColorScheme, ThemedIcon};
+    /// struct MyTray {
+    ///     scheme: ColorScheme,
+    /// }
+    ///
+    /// impl ksni::Tray for MyTray {
+    ///     # fn id(&self) -> String { "x".into() }
+    ///     fn color_scheme_changed(&mut self, scheme: ColorScheme) {
+    ///         self.scheme = scheme;
+    ///     }
+    ///     fn icon_name(&self) -> String {
+    ///         ThemedIcon::new("my-app-light", "my-app-dark").name(self.scheme)
+    ///     }
+    /// }
+    /// ```
+    // the default impl don't use this parameter, but it should be used by user, so keep the name
+    // without _ for autocomplete
+    #[allow(unused_variables)]
+    fn color_scheme_changed(&mut self, scheme: ColorScheme) {}
+
+    /// The session became idle, e.g. the screen is locked, or active again
+    ///
+    /// Only called if [`TrayServiceBuilder::watch_session_idle`] is enabled, once before the tray
+    /// is registered and after every change. Pause the work only visible in the tray, e.g.
+    /// rebuilding an icon on a timer, while idle. ksni pauses the refresh of
+    /// [dynamic labels](menu::StandardItem::dynamic_label) and delayed signals, see
+    /// [`Handle::is_session_idle`].
+    // the default impl don't use this parameter, but it should be used by user, so keep the name
+    // without _ for autocomplete
+    #[allow(unused_variables)]
+    fn session_idle(&mut self, idle: bool) {}
+
+    /// The well-known name of the tray was taken by another connection
+    ///
+    /// The name is requested again, it's acquired once the other connection releases it. The
+    /// tray is registered under the unique name of the connection until then, see
+    /// [`Self::name_acquired`]. Not called with
+    /// [`TrayServiceBuilder::disable_dbus_name`].
+    // the default impl don't use this parameter, but it should be used by user, so keep the name
+    // without _ for autocomplete
+    #[allow(unused_variables)]
+    fn name_lost(&self, name: &str) {}
+
+    /// The well-known name of the tray is owned again after [`Self::name_lost`], and the tray
+    /// registered with it
+    #[allow(unused_variables)]
+    fn name_acquired(&self, name: &str) {}
+
     /// The `org.kde.StatusNotifierWatcher` is back to online
     ///
     /// This method will only be called after [`watcher_offline`]
@@ -216,6 +757,58 @@ pub trait Tray: Sized + Send + 'static {
     }
 }
 
+/// What an update changes, see [`Handle::update_hint`]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Hint {
+    /// Properties of the tray item, like the icons, [`Tray::title`] or [`Tray::tool_tip`]
+    ///
+    /// [`Tray::menu`] will not be called
+    Properties,
+    /// Only [`Tray::menu`]
+    Menu,
+    /// Anything, same as [`Handle::update`]
+    All,
+}
+
+/// The session bus a tray is shown on, see [`TrayServiceBuilder::session`]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum SessionTarget {
+    /// The session bus of the process, from `DBUS_SESSION_BUS_ADDRESS`
+    #[default]
+    Current,
+    /// The bus at a D-Bus address, e.g. `"unix:path=/run/user/1000/bus"`
+    ///
+    /// See [`diagnostics::find_watchers`] for the buses with a StatusNotifierWatcher.
+    Address(String),
+}
+
+impl SessionTarget {
+    pub(crate) fn connection_builder(&self) -> zbus::Result<zbus::connection::Builder<'_>> {
+        match self {
+            SessionTarget::Current => zbus::connection::Builder::session(),
+            SessionTarget::Address(address) => zbus::connection::Builder::address(address.as_str()),
+        }
+    }
+}
+
+/// Why the tray service stopped, see [`Handle::closed`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CloseReason {
+    /// [`Handle::shutdown`] was called, also by a [`PanicGuard`],
+    /// [`TrayServiceBuilder::shutdown_on_panic`] or [`TrayServiceBuilder::shutdown_on_drop`]
+    Shutdown,
+    /// [`Tray::watcher_offline`] returned `false`
+    WatcherOffline,
+    /// Connecting failed, only with [`TrayServiceBuilder::defer_connection`] or after
+    /// [`Handle::restart`]
+    ConnectionFailed(String),
+    /// The service loop was dropped before it stopped, e.g. with its runtime
+    Aborted,
+}
+
 /// Why is the tray offline
 #[derive(Debug)]
 #[non_exhaustive]
@@ -264,10 +857,18 @@ pub enum Error {
     /// Starting the tray before the initialization of the desktop environment finished may
     /// cause this error
     ///
+    /// ksni does not draw anything by itself, so there is no built-in fallback (e.g. a
+    /// layer-shell popup on compositors without a host). Use [`diagnostics::probe`] to tell the
+    /// user why the tray is missing, and offer the actions of the menu in your own windows.
+    ///
     /// [StatusNotifierHost]: https://www.freedesktop.org/wiki/Specifications/StatusNotifierItem/StatusNotifierHost/
     /// [StatusNotifierItem]: https://www.freedesktop.org/wiki/Specifications/StatusNotifierItem/
     /// [Freedesktop System tray]: https://specifications.freedesktop.org/systemtray-spec/0.4/
     WontShow,
+    /// Another process owns the [application id](TrayServiceBuilder::application_id)
+    ///
+    /// The running instance has been activated, this one should exit.
+    AlreadyRunning,
 }
 
 impl std::fmt::Display for Error {
@@ -277,6 +878,7 @@ impl std::fmt::Display for Error {
             Dbus(e) => write!(f, "D-Bus connection error: {e}"),
             Watcher(e) => write!(f, "failed to register to the StatusNotifierWatcher: {e}"),
             WontShow => write!(f, "no StatusNotifierHost exists"),
+            AlreadyRunning => write!(f, "another instance of the application is running"),
         }
     }
 }
@@ -287,11 +889,60 @@ impl std::error::Error for Error {
         match self {
             Dbus(e) => e.source(),
             Watcher(e) => e.source(),
-            WontShow => None,
+            WontShow | AlreadyRunning => None,
         }
     }
 }
 
+/// A [`Tray`] updated with messages, see [`Handle::send`]
+///
+/// Producers only need the message type, not the fields of the tray, see
+/// [`Handle::message_sender`].
+///
+/// # Examples
+///
+/// ```no_run
+/// # use ksni::TrayMethods;
+/// struct MyTray {
+///     unread: u32,
+/// }
+///
+/// enum Mail {
+///     Received,
+///     AllRead,
+/// }
+///
+/// impl ksni::Tray for MyTray {
+///     fn id(&self) -> String {
+///         "my-mail".into()
+///     }
+/// }
+///
+/// impl ksni::MessageTray for MyTray {
+///     type Message = Mail;
+///     fn on_message(&mut self, message: Mail) {
+///         match message {
+///             Mail::Received => self.unread += 1,
+///             Mail::AllRead => self.unread = 0,
+///         }
+///     }
+/// }
+///
+/// # async fn f() {
+/// let handle = MyTray { unread: 0 }.spawn().await.unwrap();
+/// let sender = handle.message_sender();
+/// std::thread::spawn(move || sender.send(Mail::Received));
+/// # }
+/// ```
+pub trait MessageTray: Tray {
+    /// Sent by [`Handle::send`] and [`MessageSender::send`]
+    type Message: Send + 'static;
+
+    /// Called by the service loop for every message, then the tray is checked for changes like
+    /// [`Handle::update`]
+    fn on_message(&mut self, message: Self::Message);
+}
+
 /// Provides methods for [`Tray`]
 // the returned `Future` of all methods is always `Send`, because `Tray: Send` and `Self: Tray`
 // verified by `_assert_tray_methods_returned_future_is_send`
@@ -331,14 +982,10 @@ pub trait TrayMethods: Tray + private::Sealed {
     // sealed trait, safe to add private methods
     #[doc(hidden)]
     async fn spawn_with_name(self, own_name: bool) -> Result<Handle<Self>, Error> {
-        let (handle_tx, handle_rx) = mpsc::unbounded_channel();
-        let service = service::Service::new(self);
-        let service_loop = service::run(service.clone(), handle_rx, own_name).await?;
-        compat::spawn(service_loop);
-        Ok(Handle {
-            service: Arc::downgrade(&service),
-            sender: handle_tx,
-        })
+        TrayServiceBuilder::new(self)
+            .disable_dbus_name(!own_name)
+            .spawn()
+            .await
     }
 }
 impl<T: Tray> TrayMethods for T {}
@@ -347,23 +994,761 @@ fn _assert_tray_methods_returned_future_is_send<T: Tray + Clone>(x: T) {
     fn assert_send<T: Send>(_: T) {}
     assert_send(x.clone().spawn());
     assert_send(x.clone().spawn_without_dbus_name());
+    assert_send(TrayServiceBuilder::new(x.clone()).spawn());
 }
 
-mod private {
-    pub trait Sealed {}
-    impl<T: crate::Tray> Sealed for T {}
+/// Configures the tray service before spawning it
+///
+/// [`TrayMethods::spawn`] is a shortcut of `TrayServiceBuilder::new(tray).spawn()`
+///
+/// # Examples
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// # struct MyTray;
+/// # impl ksni::Tray for MyTray {
+/// #     fn id(&self) -> String { "my-tray".into() }
+/// # }
+/// # async fn f() -> Result<(), ksni::Error> {
+/// let handle = ksni::TrayServiceBuilder::new(MyTray)
+///     .min_tool_tip_interval(Duration::from_millis(500))
+///     .spawn()
+///     .await?;
+/// # Ok(()) }
+/// ```
+pub struct TrayServiceBuilder<T> {
+    tray: T,
+    own_name: bool,
+    defer_connection: bool,
+    shutdown_on_drop: bool,
+    config: service::Config,
 }
 
-pub(crate) enum HandleReuest {
-    Update(oneshot::Sender<()>),
-    Shutdown(oneshot::Sender<()>),
-}
+impl<T: Tray> TrayServiceBuilder<T> {
+    /// Create a builder with the default configuration
+    pub fn new(tray: T) -> Self {
+        TrayServiceBuilder {
+            tray,
+            own_name: true,
+            defer_connection: false,
+            shutdown_on_drop: false,
+            config: Default::default(),
+        }
+    }
 
-/// Handle to the tray
-pub struct Handle<T> {
-    service: Weak<Mutex<service::Service<T>>>,
-    sender: mpsc::UnboundedSender<HandleReuest>,
-}
+    /// Do not request a dbus well-known name
+    ///
+    /// See [`TrayMethods::spawn_without_dbus_name`]
+    ///
+    /// Default is `false`
+    pub fn disable_dbus_name(mut self, disable: bool) -> Self {
+        self.own_name = !disable;
+        self
+    }
+
+    /// Connect to D-Bus on the first update where the tray is not [`Status::Passive`]
+    ///
+    /// For trays that are only shown under certain conditions. The handle is returned without
+    /// connecting, [`Handle::registration_info`] is empty until the tray is connected, and the
+    /// methods using the connection return `None`, like [`Handle::with_sni_interface`]. Errors
+    /// while connecting are passed to [`Tray::watcher_offline`] and stop the tray.
+    ///
+    /// Default is `false`
+    pub fn defer_connection(mut self, defer: bool) -> Self {
+        self.defer_connection = defer;
+        self
+    }
+
+    /// Minimum interval between two `NewToolTip` signals
+    ///
+    /// Some hosts (e.g. KDE Plasma) flicker the tooltip when it changes while visible. If the
+    /// tooltip changes faster than `interval`, the signal is delayed and only the final content
+    /// will be sent.
+    ///
+    /// Default is no limit
+    pub fn min_tool_tip_interval(mut self, interval: Duration) -> Self {
+        self.config.min_tool_tip_interval = Some(interval);
+        self
+    }
+
+    /// Minimum interval between two `LayoutUpdated` signals
+    ///
+    /// Every layout change makes hosts fetch the whole menu again. If the menu is built up by
+    /// many small updates (e.g. items added one by one from an async source), the changes within
+    /// `interval` are merged into a single signal with the final revision. Property changes of
+    /// the items added meanwhile are not signaled, hosts read them with the layout.
+    ///
+    /// Default is no limit
+    pub fn min_layout_update_interval(mut self, interval: Duration) -> Self {
+        self.config.min_layout_update_interval = Some(interval);
+        self
+    }
+
+    /// Timeout of the D-Bus method calls made by the tray service
+    ///
+    /// Applies to the calls to the `StatusNotifierWatcher` (registering the item, reading its
+    /// properties), so a stalled watcher or host can not block the tray service forever. Calls
+    /// that timed out are reported as [`Error::Dbus`] while spawning, or passed to
+    /// [`Tray::watcher_offline`] after that.
+    ///
+    /// Default is no timeout
+    pub fn method_timeout(mut self, timeout: Duration) -> Self {
+        self.config.method_timeout = Some(timeout);
+        self
+    }
+
+    /// Adjust the defaults of other options for a desktop environment
+    ///
+    /// Options set explicitly are kept. See [`DesktopProfile`] for the adjustments, and
+    /// [`DesktopProfile::detect`] for the profile of the current desktop.
+    ///
+    /// Default is [`DesktopProfile::Generic`]
+    pub fn desktop_profile(mut self, profile: DesktopProfile) -> Self {
+        self.config.desktop_profile = profile;
+        self
+    }
+
+    /// Hide [`Tray::icon_name`] if [`Tray::icon_pixmap`] is not empty
+    ///
+    /// Some hosts show the name even if the icon theme doesn't have it, which leaves an empty
+    /// space instead of the pixmap.
+    ///
+    /// Default is `false`, or `true` with the [`DesktopProfile::Gnome`]
+    /// [profile](Self::desktop_profile)
+    pub fn prefer_icon_pixmap(mut self, prefer: bool) -> Self {
+        self.config.prefer_icon_pixmap = Some(prefer);
+        self
+    }
+
+    /// Send the changes of an update as one `PropertiesChanged` signal
+    ///
+    /// By default every changed part (title, icon, overlay and attention icon, tool tip) has its
+    /// own signal, like `NewTitle` and `NewIcon`, and some hosts redraw the item for each of them.
+    /// With this option, the names of the changed properties are sent in a single standard
+    /// `org.freedesktop.DBus.Properties.PropertiesChanged` signal instead. Only use it for hosts
+    /// that watch it, KDE Plasma only reacts to the `New*` signals. `NewStatus` is always sent.
+    ///
+    /// Default is `false`
+    pub fn coalesce_signals(mut self, coalesce: bool) -> Self {
+        self.config.coalesce_signals = coalesce;
+        self
+    }
+
+    /// Remove the tray from the host when a callback of the tray panics
+    ///
+    /// The panic is caught by the service task, which closes the connection, the panics of
+    /// callbacks called for the host (e.g. [`Tray::activate`]) make it shutdown too. Otherwise the
+    /// item stays visible until the connection is dropped. Panics of other threads are not seen,
+    /// use [`Handle::panic_guard`] for them.
+    ///
+    /// Default is `false`
+    pub fn shutdown_on_panic(mut self, shutdown: bool) -> Self {
+        self.config.shutdown_on_panic = shutdown;
+        self
+    }
+
+    /// Export `org.freedesktop.Application` for a single instance application
+    ///
+    /// The service owns `id` as a D-Bus name and serves the interface at the path derived from
+    /// it, like `/org/example/App` for `org.example.App`. Launchers of a `DBusActivatable`
+    /// desktop file call it, which is routed to [`Tray::application_activate`],
+    /// [`Tray::application_open`] and [`Tray::application_action`]. If another process already
+    /// owns `id`, it's activated and starting the tray fails with [`Error::AlreadyRunning`].
+    ///
+    /// Default is `None`
+    pub fn application_id(mut self, id: impl Into<String>) -> Self {
+        self.config.application_id = Some(id.into());
+        self
+    }
+
+    /// Don't send changes of these menu item properties, e.g. the `"label"` of a countdown
+    ///
+    /// Names are the dbusmenu properties: `"label"`, `"enabled"`, `"visible"`, `"icon-name"`,
+    /// `"icon-data"`, `"shortcut"`, `"toggle-state"`, ... Hosts get the current values when
+    /// they read the layout again, which happens after a layout change or
+    /// [`Handle::flush_menu`].
+    ///
+    /// Default is empty
+    pub fn volatile_menu_properties<S: Into<String>>(
+        mut self,
+        properties: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.config.volatile_menu_properties = properties.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Downscale icon pixmaps wider or higher than `size` pixels
+    ///
+    /// Some hosts misbehave with huge pixmaps, and every pixmap is sent in full in the property
+    /// messages. Applies to the icon, overlay, attention and tool tip pixmaps, keeping the
+    /// aspect ratio. [`Tray::on_icon_downscaled`] is called for such pixmaps.
+    ///
+    /// Default is no limit, the pixmaps are sent as-is
+    pub fn max_icon_size(mut self, size: u32) -> Self {
+        self.config.max_icon_size = Some(size);
+        self
+    }
+
+    /// Omit `icon_data` of menu items bigger than `bytes`, `icon_name` is still sent
+    ///
+    /// Menus with many icons make big messages, which are slow over a remote D-Bus connection
+    /// (e.g. forwarded with waypipe or ssh). Use `0` to strip all icon data.
+    ///
+    /// Default is no limit
+    pub fn max_menu_icon_data(mut self, bytes: usize) -> Self {
+        self.config.max_menu_icon_data = Some(bytes);
+        self
+    }
+
+    /// Split menus with more than `max_children` items into pages, moving the rest into a
+    /// submenu labeled `more_label`, e.g. `"More…"`
+    ///
+    /// Some hosts get slow or cut off menus with more than about 100 items in one submenu. The
+    /// pages are only added to what's sent to the host, [`Tray::menu`] and the
+    /// [`MenuPath`](menu::MenuPath)s of the items don't change. Callbacks for a page, e.g.
+    /// [`Tray::menu_opened`], get the path of the submenu it belongs to.
+    ///
+    /// Default is no limit
+    pub fn paginate_menu(mut self, max_children: usize, more_label: impl Into<String>) -> Self {
+        self.config.menu_pagination = Some((max_children, more_label.into()));
+        self
+    }
+
+    /// Record the last `capacity` signals sent to the host, see [`Handle::signal_log`]
+    ///
+    /// Helps to reproduce bugs where the host shows something else than the tray, e.g. in a
+    /// debugging overlay or attached to bug reports.
+    ///
+    /// Default is `0`, disabled
+    pub fn signal_log(mut self, capacity: usize) -> Self {
+        self.config.signal_log = capacity;
+        self
+    }
+
+    /// Count the messages of each D-Bus interface on the connection, see
+    /// [`ServiceStats::interfaces`]
+    ///
+    /// Interfaces served by the app on the same connection, e.g. with
+    /// [`Handle::with_sni_interface`], are counted too, so the traffic of the tray can be told
+    /// apart from the rest of the app.
+    ///
+    /// Default is `false`
+    pub fn count_messages(mut self, count: bool) -> Self {
+        self.config.count_messages = count;
+        self
+    }
+
+    /// Show the tray on another session bus than the one of the process
+    ///
+    /// For session managers and other apps serving several sessions on a multi-seat system,
+    /// spawn a tray for each of them.
+    ///
+    /// Default is [`SessionTarget::Current`]
+    pub fn session(mut self, target: SessionTarget) -> Self {
+        self.config.session = target;
+        self
+    }
+
+    /// Call `hook` with a [`Metric`] on registration, re-registration, each signal sent and
+    /// errors of the service loop
+    ///
+    /// For wiring the health of a long-running tray into a metrics pipeline, e.g. by
+    /// incrementing Prometheus counters. `hook` is called on the service loop, it should return
+    /// quickly.
+    ///
+    /// Default is `None`
+    pub fn metrics_hook(mut self, hook: impl Fn(Metric) + Send + Sync + 'static) -> Self {
+        self.config.metrics_hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// Report a [`Metric::SlowMenuUpdate`] to the [`Self::metrics_hook`] when checking the menu
+    /// for changes takes longer than `threshold`
+    ///
+    /// Every update calls [`Tray::menu`] and compares the whole menu with the served one, which
+    /// gets slow with thousands of items. Use [`Handle::update_hint`] with [`Hint::Properties`]
+    /// for updates that don't change the menu, [`StandardItem::no_auto_update`] for items that
+    /// don't change it when clicked, and keep rarely used items out of large submenus.
+    ///
+    /// Default is `None`
+    ///
+    /// [`StandardItem::no_auto_update`]: menu::StandardItem::no_auto_update
+    pub fn slow_menu_threshold(mut self, threshold: Duration) -> Self {
+        self.config.slow_menu_threshold = Some(threshold);
+        self
+    }
+
+    /// Follow the color scheme of the desktop with the XDG Settings portal
+    ///
+    /// See [`Tray::color_scheme_changed`]. Without the portal, the tray keeps running and the
+    /// method is never called.
+    ///
+    /// Default is `false`
+    pub fn watch_color_scheme(mut self, watch: bool) -> Self {
+        self.config.watch_color_scheme = watch;
+        self
+    }
+
+    /// Follow the idle state of the session, with `org.freedesktop.ScreenSaver` or logind
+    ///
+    /// See [`Tray::session_idle`]. Without both, the tray keeps running and the method is never
+    /// called.
+    ///
+    /// Default is `false`
+    pub fn watch_session_idle(mut self, watch: bool) -> Self {
+        self.config.watch_session_idle = watch;
+        self
+    }
+
+    /// Ignore repeated activations within `interval`
+    ///
+    /// Applies to [`Tray::activate`], [`Tray::secondary_activate`] and clicks on the same menu
+    /// item, so a double click or a host sending duplicated events only triggers them once.
+    ///
+    /// Default is no debouncing
+    pub fn activate_debounce(mut self, interval: Duration) -> Self {
+        self.config.activate_debounce = Some(interval);
+        self
+    }
+
+    /// Change what a click on the tray does
+    ///
+    /// Useful on hosts where some clicks are not available, e.g. middle click on a touchpad.
+    /// The default mapping calls the method of the same name, [`HostEvent::DoubleClick`] is not
+    /// detected unless mapped.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # async fn f<T: ksni::Tray>(tray: T) -> Result<(), ksni::Error> {
+    /// use ksni::{HostEvent, TrayAction};
+    ///
+    /// let handle = ksni::TrayServiceBuilder::new(tray)
+    ///     .map_event(HostEvent::DoubleClick, TrayAction::SecondaryActivate)
+    ///     .spawn()
+    ///     .await?;
+    /// # Ok(()) }
+    /// ```
+    pub fn map_event(mut self, event: HostEvent, action: TrayAction) -> Self {
+        self.config.event_map.insert(event, action);
+        self
+    }
+
+    /// Limit the number of updates waiting for the tray service
+    ///
+    /// Every update is queued until the service has checked the tray for changes. With a
+    /// capacity, [`Handle::update`] (and the other methods sending updates) waits for a free slot
+    /// before changing the tray, so many tasks updating in a loop can not grow the queue without
+    /// bound. The slot is freed once the service handled the update, even if the caller stopped
+    /// waiting for it.
+    ///
+    /// Default is unbounded
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0, no update could ever be sent.
+    pub fn channel_capacity(mut self, capacity: usize) -> Self {
+        assert!(capacity > 0, "the channel capacity must be at least 1");
+        self.config.channel_capacity = Some(capacity);
+        self
+    }
+
+    /// Call [`Tray::activate`], [`Tray::secondary_activate`], [`Tray::scroll`] and menu item
+    /// callbacks on a blocking thread
+    ///
+    /// Use it if the callbacks do blocking I/O. They are run with `spawn_blocking` on Tokio, or
+    /// on a new thread with async-io, so other tasks of the runtime keep running. D-Bus calls to
+    /// the tray still wait until the callback returns.
+    ///
+    /// Default is `false`
+    pub fn blocking_callbacks(mut self, blocking: bool) -> Self {
+        self.config.blocking_callbacks = blocking;
+        self
+    }
+
+    /// Ignore menu clicks with an older timestamp than the last click applied to the same item
+    ///
+    /// Hosts may deliver events out of order, e.g. when they are queued while reconnecting. A
+    /// stale click on a checkbox would undo the newer one. Events with a zero timestamp, which
+    /// some hosts always send, are never dropped. The timestamp is available to the callbacks
+    /// with [`menu::event_timestamp`].
+    ///
+    /// Default is `false`
+    pub fn drop_stale_menu_events(mut self, drop: bool) -> Self {
+        self.config.drop_stale_menu_events = drop;
+        self
+    }
+
+    /// Keep the ids of menu items with a key across layout changes, see
+    /// [`StandardItem::key`](menu::StandardItem::key)
+    ///
+    /// When the layout changes, all items get new ids, so events of the host for the previous
+    /// layout are dropped. Items keeping their ids stay open, or focused by assistive
+    /// technologies, on hosts that support it. Items without a key always get new ids: they are
+    /// not matched by their position or label, unrelated items sharing a label would take each
+    /// other's ids.
+    ///
+    /// Default is `false`
+    pub fn stable_menu_ids(mut self, stable: bool) -> Self {
+        self.config.stable_menu_ids = stable;
+        self
+    }
+
+    /// Publish the Ayatana extensions of the item, for hosts of ayatana-indicators, e.g. the
+    /// panels of Raspberry Pi OS or MATE
+    ///
+    /// Serves [`Tray::label`] and [`Tray::label_guide`] as `XAyatanaLabel` and
+    /// `XAyatanaLabelGuide`, and emits `XAyatanaNewLabel` when they change. Without it the
+    /// properties are empty.
+    ///
+    /// Default is `false`
+    pub fn ayatana_compat(mut self, compat: bool) -> Self {
+        self.config.ayatana_compat = compat;
+        self
+    }
+
+    /// Publish the state of the previous process until the first update, see
+    /// [`Handle::export_state`]
+    ///
+    /// For fast restarts, e.g. after a self-update, the host shows the same icon while the app
+    /// starts, and the menu items keep their ids. The properties of the tray are shown after
+    /// 5 seconds even without an update. A state exported by another version of ksni may not be
+    /// accepted by [`TrayStateBlob::from_bytes`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # fn f<T: ksni::Tray>(tray: T) {
+    /// let mut builder = ksni::TrayServiceBuilder::new(tray);
+    /// if let Ok(bytes) = std::fs::read("/run/user/1000/my-app.state") {
+    ///     if let Some(state) = ksni::TrayStateBlob::from_bytes(&bytes) {
+    ///         builder = builder.restore_state(state);
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    ///
+    /// Default is nothing restored
+    pub fn restore_state(mut self, state: TrayStateBlob) -> Self {
+        self.config.restore_state = Some(state);
+        self
+    }
+
+    /// Shutdown the tray service when the last [`Handle`] is dropped
+    ///
+    /// [`WeakHandle`]s don't keep it running. Otherwise the service runs until
+    /// [`Handle::shutdown`], even if no handle is left.
+    ///
+    /// Default is `false`
+    pub fn shutdown_on_drop(mut self, shutdown: bool) -> Self {
+        self.shutdown_on_drop = shutdown;
+        self
+    }
+
+    /// Run the tray service in background
+    pub async fn spawn(self) -> Result<Handle<T>, Error> {
+        let (handle, service_loop) = self.build().await?;
+        compat::spawn(service_loop);
+        Ok(handle)
+    }
+
+    // shared with the blocking API, which runs the service loop in its own thread
+    pub(crate) async fn build(self) -> Result<(Handle<T>, impl Future<Output = ()>), Error> {
+        let (handle_tx, handle_rx) = mpsc::unbounded_channel();
+        let update_limit = self
+            .config
+            .channel_capacity
+            .map(|capacity| Arc::new(compat::Semaphore::new(capacity)));
+        let shutdown_on_panic = self.config.shutdown_on_panic;
+        let service = service::Service::new(self.tray, self.config, handle_tx.clone());
+        let registration = Arc::new(service::Registration::default());
+        let (stats, sync_updates) = {
+            let service = service.lock().await;
+            (service.stats.clone(), service.sync_updates.clone())
+        };
+        let service_loop = if self.defer_connection {
+            Either::Left(service::run_deferred(
+                service.clone(),
+                handle_rx,
+                self.own_name,
+                Arc::downgrade(&registration),
+            ))
+        } else {
+            let (info, connection, service_loop) =
+                service::run(service.clone(), handle_rx, self.own_name, None).await?;
+            registration.set(info, connection);
+            Either::Right(service::serve(
+                service.clone(),
+                self.own_name,
+                Arc::downgrade(&registration),
+                service_loop,
+            ))
+        };
+        let token = Arc::new(HandleToken {
+            sender: handle_tx.clone(),
+            shutdown_on_drop: self.shutdown_on_drop,
+        });
+        let mut close_guard = service::CloseGuard::new(registration.clone());
+        let connection = registration.clone();
+        let service_loop = async move {
+            if !shutdown_on_panic {
+                close_guard.set(service_loop.await);
+                return;
+            }
+            match std::panic::AssertUnwindSafe(service_loop)
+                .catch_unwind()
+                .await
+            {
+                Ok(reason) => close_guard.set(reason),
+                // the connection is kept by the registration, close it to remove the item now
+                Err(_) => {
+                    if let Some(conn) = connection.connection() {
+                        let _ = conn.close().await;
+                    }
+                    close_guard.set(CloseReason::Shutdown);
+                }
+            }
+        };
+        Ok((
+            Handle {
+                service: Arc::downgrade(&service),
+                sender: handle_tx,
+                update_limit,
+                registration,
+                stats,
+                sync_updates,
+                token,
+            },
+            service_loop,
+        ))
+    }
+}
+
+mod private {
+    pub trait Sealed {}
+    impl<T: crate::Tray> Sealed for T {}
+}
+
+pub(crate) enum HandleReuest {
+    // holds a slot of `TrayServiceBuilder::channel_capacity` until it's handled
+    Update(
+        Hint,
+        oneshot::Sender<()>,
+        Option<compat::OwnedSemaphorePermit>,
+    ),
+    // sent by the service itself when a delayed signal is due
+    Refresh,
+    // sent by the service itself when dynamic labels need to be refreshed
+    RefreshLabels,
+    // sent by the service itself, see `TrayServiceBuilder::restore_state`
+    RestoredStateExpired,
+    ColorScheme(ColorScheme),
+    SessionIdle(bool),
+    #[cfg(feature = "global-hotkey")]
+    GlobalShortcut(String),
+    Connection(oneshot::Sender<zbus::Connection>),
+    Restart(oneshot::Sender<Result<(), Error>>),
+    Shutdown(oneshot::Sender<()>),
+}
+
+/// Handle to the tray
+///
+/// The tray service keeps running when all handles are dropped, until [`Handle::shutdown`],
+/// unless [`TrayServiceBuilder::shutdown_on_drop`] is set. See [`Handle::downgrade`] for a handle
+/// which doesn't count.
+pub struct Handle<T> {
+    service: Weak<Mutex<service::Service<T>>>,
+    sender: mpsc::UnboundedSender<HandleReuest>,
+    // see `TrayServiceBuilder::channel_capacity`
+    update_limit: Option<Arc<compat::Semaphore>>,
+    registration: Arc<service::Registration>,
+    stats: Arc<service::Stats>,
+    sync_updates: service::SyncUpdates<T>,
+    token: Arc<HandleToken>,
+}
+
+// shared by the `Handle`s of a tray, see `TrayServiceBuilder::shutdown_on_drop`
+struct HandleToken {
+    sender: mpsc::UnboundedSender<HandleReuest>,
+    shutdown_on_drop: bool,
+}
+
+impl Drop for HandleToken {
+    fn drop(&mut self) {
+        if self.shutdown_on_drop {
+            request_shutdown(&self.sender);
+        }
+    }
+}
+
+/// A [`Handle`] which doesn't keep the tray service running, see [`Handle::downgrade`]
+pub struct WeakHandle<T> {
+    service: Weak<Mutex<service::Service<T>>>,
+    sender: mpsc::UnboundedSender<HandleReuest>,
+    update_limit: Option<Arc<compat::Semaphore>>,
+    registration: Arc<service::Registration>,
+    stats: Arc<service::Stats>,
+    sync_updates: service::SyncUpdates<T>,
+    token: Weak<HandleToken>,
+}
+
+impl<T> WeakHandle<T> {
+    /// Returns `None` if all [`Handle`]s were dropped, or the tray service has been shutdown
+    pub fn upgrade(&self) -> Option<Handle<T>> {
+        let token = self.token.upgrade()?;
+        if self.sender.is_closed() {
+            return None;
+        }
+        Some(Handle {
+            service: self.service.clone(),
+            sender: self.sender.clone(),
+            update_limit: self.update_limit.clone(),
+            registration: self.registration.clone(),
+            stats: self.stats.clone(),
+            sync_updates: self.sync_updates.clone(),
+            token,
+        })
+    }
+}
+
+impl<T> Clone for WeakHandle<T> {
+    fn clone(&self) -> Self {
+        WeakHandle {
+            service: self.service.clone(),
+            sender: self.sender.clone(),
+            update_limit: self.update_limit.clone(),
+            registration: self.registration.clone(),
+            stats: self.stats.clone(),
+            sync_updates: self.sync_updates.clone(),
+            token: self.token.clone(),
+        }
+    }
+}
+
+/// How the tray was registered, see [`Handle::registration_info`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RegistrationInfo {
+    /// See [`Handle::bus_name`]
+    pub bus_name: String,
+    /// Unique name of the D-Bus connection, e.g. `":1.42"`
+    pub unique_name: String,
+    /// Object path of the StatusNotifierItem
+    pub item_path: String,
+    /// Object path of the dbusmenu
+    pub menu_path: String,
+    /// ID of the process the tray runs in, part of the generated [`Self::bus_name`]
+    pub process_id: u32,
+}
+
+/// A signal sent to the host, see [`TrayServiceBuilder::signal_log`]
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct SignalRecord {
+    pub time: std::time::SystemTime,
+    /// `"org.kde.StatusNotifierItem"` or `"com.canonical.dbusmenu"`
+    pub interface: &'static str,
+    /// Member name of the signal, e.g. `"NewIcon"`
+    pub signal: &'static str,
+    /// What changed, e.g. the property of a `PropertiesChanged` signal or the revision of a
+    /// `LayoutUpdated` signal
+    pub summary: String,
+}
+
+/// State of the service loop, see [`Handle::stats`]
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct ServiceStats {
+    /// Number of [`Handle`]s of the tray, including the one `stats` is called on
+    pub handles: usize,
+    /// Updates requested by handles the service loop hasn't started on yet
+    pub queued_updates: usize,
+    /// When the service loop last finished checking the tray for changes
+    pub last_update: Option<Instant>,
+    /// How long that check took, including sending the signals
+    pub last_update_duration: Option<Duration>,
+    /// When a change signal was last sent to the host
+    pub last_signal: Option<Instant>,
+    /// Time from the last `AboutToShow` or `GetLayout` call of the host for the whole menu to
+    /// the click on an item that followed
+    ///
+    /// Includes the time the service loop took to serve the layout, a long latency with a short
+    /// `last_update_duration` points at the host.
+    pub last_menu_latency: Option<Duration>,
+    /// How long calling [`Tray::menu`] and comparing the result with the served menu took in the
+    /// last update that checked the menu, part of `last_update_duration`
+    ///
+    /// It grows with the number of items, see [`TrayServiceBuilder::slow_menu_threshold`].
+    pub last_menu_diff_duration: Option<Duration>,
+    /// Items of the menu in the last update that checked the menu, including the ones in
+    /// submenus and the options of radio groups
+    pub menu_items: usize,
+    /// Messages keyed by the D-Bus interface, only counted with
+    /// [`TrayServiceBuilder::count_messages`]
+    pub interfaces: HashMap<String, InterfaceStats>,
+}
+
+/// What an update would send to the host, see [`Handle::preview`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct UpdatePreview {
+    /// Properties of the tray that would change, each sends a signal
+    pub properties: PropertyFlags,
+    /// The layout of the menu would change, the host reads the whole menu again
+    pub layout_changed: bool,
+    /// Menu items whose properties would change, unless the layout changes
+    pub updated_items: usize,
+}
+
+impl UpdatePreview {
+    /// Whether the update would send nothing
+    pub fn is_empty(&self) -> bool {
+        self.properties.is_empty() && !self.layout_changed && self.updated_items == 0
+    }
+}
+
+/// An event of the service loop, see [`TrayServiceBuilder::metrics_hook`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Metric {
+    /// The tray was registered to the StatusNotifierWatcher
+    Registered,
+    /// The tray was registered again after the StatusNotifierWatcher restarted
+    Reregistered,
+    /// A change signal was sent to the host
+    SignalSent {
+        /// `"org.kde.StatusNotifierItem"` or `"com.canonical.dbusmenu"`
+        interface: &'static str,
+        /// Member name of the signal, e.g. `"NewIcon"`
+        signal: &'static str,
+    },
+    /// A menu item was clicked, see [`ServiceStats::last_menu_latency`]
+    MenuLatency(Duration),
+    /// An update took longer than [`TrayServiceBuilder::slow_menu_threshold`] to check the menu
+    SlowMenuUpdate {
+        /// See [`ServiceStats::last_menu_diff_duration`]
+        duration: Duration,
+        /// See [`ServiceStats::menu_items`]
+        items: usize,
+    },
+    /// A D-Bus error the service loop kept running after, e.g. a failed re-registration or a
+    /// signal that couldn't be sent
+    Error(String),
+}
+
+/// Messages of a D-Bus interface, see [`ServiceStats::interfaces`]
+///
+/// Property reads are method calls of `org.freedesktop.DBus.Properties`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct InterfaceStats {
+    /// Method calls received
+    pub method_calls: u64,
+    /// Signals received, e.g. from the StatusNotifierWatcher or the bus
+    pub signals_received: u64,
+    /// Signals sent by ksni, not the ones sent by the app
+    pub signals_sent: u64,
+}
 
 impl<T> Handle<T> {
     /// Update the tray
@@ -371,16 +1756,349 @@ impl<T> Handle<T> {
     /// Returns the result of `f`, returns `None` if the tray service
     /// has been shutdown.
     pub async fn update<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> Option<R> {
-        if let Some(service) = self.service.upgrade() {
-            // NOTE: free the lock before send any message
-            let r = f(&mut service.lock().await.tray);
-            let (tx, rx) = oneshot::channel();
-            if self.sender.send(HandleReuest::Update(tx)).is_ok() {
-                let _ = rx.await;
-                return Some(r);
-            }
+        self.update_hint(Hint::All, f).await
+    }
+
+    /// Update the tray, only checks the parts described by `hint` for changes
+    ///
+    /// Rebuilding and diffing the menu is the most expensive part of an update, use
+    /// [`Hint::Properties`] to skip it if `f` only changes things like the icon. Changes not
+    /// covered by `hint` will be sent on the next update.
+    ///
+    /// Returns the result of `f`, returns `None` if the tray service
+    /// has been shutdown.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # struct MyTray { syncing: bool }
+    /// # impl ksni::Tray for MyTray { fn id(&self) -> String { "x".into() } }
+    /// # async fn f(handle: ksni::Handle<MyTray>) {
+    /// // `syncing` is only used by `icon_name`
+    /// handle
+    ///     .update_hint(ksni::Hint::Properties, |tray| tray.syncing = true)
+    ///     .await;
+    /// # }
+    /// ```
+    pub async fn update_hint<R, F: FnOnce(&mut T) -> R>(&self, hint: Hint, f: F) -> Option<R> {
+        assert!(
+            !menu::is_generating(),
+            "a tray can't be updated while generating its menu, the service is locked"
+        );
+        let permit = self.acquire_update_slot().await;
+        let service = self.service.upgrade()?;
+        // NOTE: free the lock before send any message
+        let r = {
+            let mut service = service.lock().await;
+            service.apply_sync_updates();
+            service.bump_generation();
+            f(&mut service.tray)
+        };
+        self.send_update(hint, permit).await?;
+        Some(r)
+    }
+
+    /// Update the tray from a sync context without waiting, e.g. a signal handler or an FFI
+    /// callback
+    ///
+    /// `f` is queued and run by the service loop, then the tray is checked for changes like
+    /// [`Self::update`]. Queued closures run in the order of the calls, and before the closure
+    /// of any update started after this returns. Not limited by
+    /// [`TrayServiceBuilder::channel_capacity`].
+    ///
+    /// Returns `false` if the tray service has been shutdown.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # struct MyTray { unread: u32 }
+    /// # impl ksni::Tray for MyTray { fn id(&self) -> String { "x".into() } }
+    /// # fn f(handle: ksni::Handle<MyTray>) {
+    /// // e.g. in a callback of a C library
+    /// handle.try_update_sync(|tray| tray.unread += 1);
+    /// # }
+    /// ```
+    pub fn try_update_sync<F: FnOnce(&mut T) + Send + 'static>(&self, f: F) -> bool {
+        if self.service.strong_count() == 0 {
+            return false;
         }
-        None
+        service::lock(&self.sync_updates).push_back(Box::new(f));
+        // nobody waits for the reply
+        let (tx, _) = oneshot::channel();
+        self.stats.queued_updates.fetch_add(1, Ordering::AcqRel);
+        if self
+            .sender
+            .send(HandleReuest::Update(Hint::All, tx, None))
+            .is_err()
+        {
+            self.stats.queued_updates.fetch_sub(1, Ordering::AcqRel);
+            service::lock(&self.sync_updates).clear();
+            return false;
+        }
+        true
+    }
+
+    /// Make the host read the menu again if a
+    /// [volatile property](TrayServiceBuilder::volatile_menu_properties) has changed
+    ///
+    /// Checks the menu for changes like an update with [`Hint::Menu`]. Returns `None` if the tray
+    /// service has been shutdown.
+    pub async fn flush_menu(&self) -> Option<()> {
+        let permit = self.acquire_update_slot().await;
+        let service = self.service.upgrade()?;
+        service.lock().await.flush_requested = true;
+        self.send_update(Hint::Menu, permit).await
+    }
+
+    /// Show all menu items as disabled, or as they are again
+    ///
+    /// The menu of the tray is kept as is, e.g. while the app is busy. Clicks and global
+    /// shortcuts of the items are ignored while disabled.
+    ///
+    /// Returns `None` if the tray service has been shutdown.
+    pub async fn set_menu_enabled(&self, enabled: bool) -> Option<()> {
+        let permit = self.acquire_update_slot().await;
+        let service = self.service.upgrade()?;
+        if !service.lock().await.set_menu_enabled(enabled) {
+            return Some(());
+        }
+        self.send_update(Hint::Menu, permit).await
+    }
+
+    /// Replace the whole tray, and send every change to the host
+    ///
+    /// Returns the old tray, returns `None` if the tray service has been shutdown.
+    /// [`Tray::id`] and [const properties](Tray::const_properties) are not expected to change.
+    ///
+    /// # Examples
+    ///
+    /// Trays with different models can be wrapped in an enum that delegates to them
+    ///
+    /// ```no_run
+    /// # struct LoggedOut;
+    /// # impl ksni::Tray for LoggedOut { fn id(&self) -> String { "x".into() } }
+    /// # struct LoggedIn { user: String }
+    /// # impl ksni::Tray for LoggedIn { fn id(&self) -> String { "x".into() } }
+    /// enum AppTray {
+    ///     LoggedOut(LoggedOut),
+    ///     LoggedIn(LoggedIn),
+    /// }
+    ///
+    /// impl ksni::Tray for AppTray {
+    ///     fn id(&self) -> String {
+    ///         "my-app".into()
+    ///     }
+    ///     fn title(&self) -> String {
+    ///         match self {
+    ///             AppTray::LoggedOut(tray) => tray.title(),
+    ///             AppTray::LoggedIn(tray) => tray.title(),
+    ///         }
+    ///     }
+    ///     // menu items act on `AppTray`, so the menus are built here
+    ///     fn menu(&self) -> Vec<ksni::MenuItem<Self>> {
+    ///         use ksni::menu::StandardItem;
+    ///         match self {
+    ///             AppTray::LoggedOut(_) => vec![StandardItem {
+    ///                 label: "Log in".into(),
+    ///                 ..Default::default()
+    ///             }
+    ///             .into()],
+    ///             AppTray::LoggedIn(tray) => vec![StandardItem {
+    ///                 label: format!("Log out {}", tray.user).into(),
+    ///                 activate: Box::new(|this: &mut Self| *this = AppTray::LoggedOut(LoggedOut)),
+    ///                 ..Default::default()
+    ///             }
+    ///             .into()],
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// # async fn f(handle: ksni::Handle<AppTray>) {
+    /// let user = "me".to_owned();
+    /// handle.replace_tray(AppTray::LoggedIn(LoggedIn { user })).await;
+    /// # }
+    /// ```
+    pub async fn replace_tray(&self, tray: T) -> Option<T> {
+        let permit = self.acquire_update_slot().await;
+        let service = self.service.upgrade()?;
+        let old = service.lock().await.replace_tray(tray);
+        self.send_update(Hint::All, permit).await?;
+        Some(old)
+    }
+
+    /// Replace the value of a property, the [`Tray`] method is not called until
+    /// [`Self::clear_override`]
+    ///
+    /// Useful for QA and theming tools, e.g. forcing the dark variant of an icon without changing
+    /// the state of the app. [Const properties](Tray::const_properties) can be overridden, but the
+    /// change will not be signaled to the host.
+    ///
+    /// Returns `None` if the tray service has been shutdown.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # async fn f<T>(handle: ksni::Handle<T>) {
+    /// use ksni::{PropertyFlags, PropertyValue};
+    ///
+    /// handle
+    ///     .override_property(PropertyValue::IconName("my-app-dark".into()))
+    ///     .await;
+    /// // back to `Tray::icon_name`
+    /// handle.clear_override(PropertyFlags::ICON_NAME).await;
+    /// # }
+    /// ```
+    pub async fn override_property(&self, value: PropertyValue) -> Option<()> {
+        let permit = self.acquire_update_slot().await;
+        let service = self.service.upgrade()?;
+        service.lock().await.set_override(value);
+        self.send_update(Hint::Properties, permit).await
+    }
+
+    /// Remove the overrides of `properties`, see [`Self::override_property`]
+    ///
+    /// Returns `None` if the tray service has been shutdown.
+    pub async fn clear_override(&self, properties: PropertyFlags) -> Option<()> {
+        let permit = self.acquire_update_slot().await;
+        let service = self.service.upgrade()?;
+        service.lock().await.clear_overrides(properties);
+        self.send_update(Hint::Properties, permit).await
+    }
+
+    // wait for a free slot if the capacity is limited, held by the queued update
+    async fn acquire_update_slot(&self) -> Option<compat::OwnedSemaphorePermit> {
+        match &self.update_limit {
+            Some(limit) => Some(compat::acquire_owned(limit.clone()).await),
+            None => None,
+        }
+    }
+
+    // ask the service loop to check for changes, wait until it's done
+    async fn send_update(
+        &self,
+        hint: Hint,
+        permit: Option<compat::OwnedSemaphorePermit>,
+    ) -> Option<()> {
+        let (tx, rx) = oneshot::channel();
+        self.stats.queued_updates.fetch_add(1, Ordering::AcqRel);
+        if self
+            .sender
+            .send(HandleReuest::Update(hint, tx, permit))
+            .is_err()
+        {
+            self.stats.queued_updates.fetch_sub(1, Ordering::AcqRel);
+            return None;
+        }
+        let _ = rx.await;
+        Some(())
+    }
+
+    /// Run `f` with the D-Bus connection of the tray and a [`SignalEmitter`] of the
+    /// `/StatusNotifierItem` object
+    ///
+    /// This is an escape hatch for vendor extensions of the specification, like emitting signals
+    /// of another interface on the same object, or serving extra interfaces at the same path with
+    /// [`Connection::object_server`]. ksni does not know anything done with them, closing the
+    /// connection or removing the built-in interfaces will break the tray.
+    ///
+    /// Returns `None` if the tray service has been shutdown, or isn't connected yet with
+    /// [`TrayServiceBuilder::defer_connection`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # async fn f<T>(handle: ksni::Handle<T>) {
+    /// handle
+    ///     .with_sni_interface(|_conn, emitter| async move {
+    ///         emitter
+    ///             .emit("org.example.TrayExtension", "Pulse", &(3u32,))
+    ///             .await
+    ///     })
+    ///     .await;
+    /// # }
+    /// ```
+    ///
+    /// [`SignalEmitter`]: zbus::object_server::SignalEmitter
+    /// [`Connection::object_server`]: zbus::Connection::object_server
+    pub async fn with_sni_interface<F, Fut>(&self, f: F) -> Option<Fut::Output>
+    where
+        F: FnOnce(zbus::Connection, zbus::object_server::SignalEmitter<'static>) -> Fut,
+        Fut: Future,
+    {
+        let conn = self.connection().await?;
+        let emitter = zbus::object_server::SignalEmitter::new(&conn, dbus_interface::SNI_PATH)
+            .expect("SNI_PATH should be valid")
+            .into_owned();
+        Some(f(conn, emitter).await)
+    }
+
+    /// The D-Bus name registered to the StatusNotifierWatcher
+    ///
+    /// The generated `org.kde.StatusNotifierItem-PID-ID` name, or the unique name of the
+    /// connection if [`TrayServiceBuilder::disable_dbus_name`] is set
+    ///
+    /// Returns `None` if the tray isn't connected yet, see [`Handle::registration_info`].
+    pub fn bus_name(&self) -> Option<String> {
+        self.registration.info().map(|info| info.bus_name)
+    }
+
+    /// Names and paths of the tray on the bus, as registered to the StatusNotifierWatcher
+    ///
+    /// Useful for logging, or to let another process talk to the tray directly. Updated by
+    /// [`Handle::restart`].
+    ///
+    /// Returns `None` if the tray isn't connected yet, with
+    /// [`TrayServiceBuilder::defer_connection`].
+    pub fn registration_info(&self) -> Option<RegistrationInfo> {
+        self.registration.info()
+    }
+
+    /// Language of the desktop session, e.g. `"de_DE"`
+    ///
+    /// The specification has no way for hosts to advertise their language, so this reads the
+    /// language chosen in the desktop settings from AccountsService, falling back to the system
+    /// locale of systemd-localed. Unlike [`system_locale`], it's not affected by the environment
+    /// of the process. Pass it to [`Handle::set_locale`] to follow the desktop.
+    ///
+    /// Returns `None` if the tray service has been shutdown, isn't connected yet with
+    /// [`TrayServiceBuilder::defer_connection`], or none of them is available.
+    pub async fn host_locale(&self) -> Option<String> {
+        let conn = self.connection().await?;
+        locale::host_locale(&conn).await
+    }
+
+    /// Features the tray host can display
+    ///
+    /// The host is identified by the process running the StatusNotifierWatcher, on a best-effort
+    /// basis, only on Linux. Unknown hosts are assumed to support everything. Compare with
+    /// [`HostCapabilities::used_by`] to warn about, or avoid, features that won't be shown.
+    ///
+    /// Returns `None` if the tray service has been shutdown, isn't connected yet with
+    /// [`TrayServiceBuilder::defer_connection`], or no watcher is running.
+    pub async fn host_capabilities(&self) -> Option<HostCapabilities> {
+        let conn = self.connection().await?;
+        host::capabilities(&conn).await
+    }
+
+    pub(crate) async fn connection(&self) -> Option<zbus::Connection> {
+        let (tx, rx) = oneshot::channel();
+        self.sender.send(HandleReuest::Connection(tx)).ok()?;
+        rx.await.ok()
+    }
+
+    /// Close the D-Bus connection and register the tray again, with the same tray value
+    ///
+    /// A heavier hammer than [`Handle::update`], to recover from a host which got out of sync,
+    /// e.g. showing an outdated menu, without restarting the app. The tray disappears from the
+    /// host, then the registration runs again like in [`TrayMethods::spawn`]. The bus name is
+    /// kept, but the connection has a new unique name, see [`Handle::registration_info`].
+    ///
+    /// If the registration fails, the error is returned and the tray service stops with
+    /// [`CloseReason::ConnectionFailed`]. Returns `None` if the tray service has been shutdown.
+    pub async fn restart(&self) -> Option<Result<(), Error>> {
+        let (tx, rx) = oneshot::channel();
+        self.sender.send(HandleReuest::Restart(tx)).ok()?;
+        rx.await.ok()
     }
 
     /// Shutdown the tray service
@@ -393,10 +2111,292 @@ impl<T> Handle<T> {
         }
     }
 
+    /// Shutdown the tray service, waiting at most `timeout`
+    ///
+    /// If the service loop doesn't stop in time, e.g. a callback of the tray is stuck, the D-Bus
+    /// connection is closed, which removes the tray from the host. Returns `true` if the
+    /// shutdown was graceful. The shutdown is requested immediately, so it's safe to drop the
+    /// returned future early.
+    pub async fn shutdown_with_timeout(&self, timeout: Duration) -> bool {
+        let shutdown = self.shutdown();
+        let deadline = std::pin::pin!(compat::sleep(timeout));
+        match futures_util::future::select(shutdown, deadline).await {
+            Either::Left(_) => true,
+            Either::Right(_) => {
+                // only used to force a shutdown, other requests go through the service loop
+                if let Some(conn) = self.registration.connection() {
+                    let _ = conn.close().await;
+                }
+                false
+            }
+        }
+    }
+
+    /// A handle which doesn't keep the tray service running, for callbacks and other tasks
+    /// that shouldn't outlive the app
+    ///
+    /// See [`TrayServiceBuilder::shutdown_on_drop`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # async fn f<T: ksni::Tray>(handle: ksni::Handle<T>) {
+    /// let weak = handle.downgrade();
+    /// std::thread::spawn(move || loop {
+    ///     std::thread::sleep(std::time::Duration::from_secs(60));
+    ///     let Some(handle) = weak.upgrade() else { break };
+    ///     // refresh the tray with `handle`
+    /// #   drop(handle);
+    /// });
+    /// # }
+    /// ```
+    pub fn downgrade(&self) -> WeakHandle<T> {
+        WeakHandle {
+            service: self.service.clone(),
+            sender: self.sender.clone(),
+            update_limit: self.update_limit.clone(),
+            registration: self.registration.clone(),
+            stats: self.stats.clone(),
+            sync_updates: self.sync_updates.clone(),
+            token: Arc::downgrade(&self.token),
+        }
+    }
+
+    /// Returns `true` if the session is idle, see [`Tray::session_idle`]
+    ///
+    /// Always `false` without [`TrayServiceBuilder::watch_session_idle`].
+    pub fn is_session_idle(&self) -> bool {
+        self.stats.session_idle.load(Ordering::Acquire)
+    }
+
     /// Returns `true` if the tray service has been shutdown
     pub fn is_closed(&self) -> bool {
         self.sender.is_closed()
     }
+
+    /// Wait until the tray service stopped, for any reason
+    ///
+    /// Resolves immediately if it already stopped. Useful to exit the app together with the
+    /// tray, wherever the shutdown came from.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # async fn f<T>(handle: ksni::Handle<T>) {
+    /// if handle.closed().await == ksni::CloseReason::WatcherOffline {
+    ///     eprintln!("the tray is gone, exiting");
+    /// }
+    /// # }
+    /// ```
+    pub fn closed(&self) -> impl Future<Output = CloseReason> + Send + 'static {
+        let closed = self.registration.closed();
+        async move {
+            match closed {
+                Ok(reason) => reason,
+                Err(rx) => rx.await.unwrap_or(CloseReason::Aborted),
+            }
+        }
+    }
+
+    /// A guard shutting down the tray if it's dropped during a panic
+    ///
+    /// Keep it in the scope of a thread whose panic leaves the application broken, so the tray
+    /// doesn't stay visible. See [`TrayServiceBuilder::shutdown_on_panic`] for the callbacks of
+    /// the tray.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # fn f<T: Send + 'static>(handle: ksni::Handle<T>) {
+    /// std::thread::spawn(move || {
+    ///     let _guard = handle.panic_guard();
+    ///     // the work of the application
+    /// });
+    /// # }
+    /// ```
+    pub fn panic_guard(&self) -> PanicGuard {
+        PanicGuard {
+            sender: self.sender.clone(),
+        }
+    }
+
+    /// The signals recorded with [`TrayServiceBuilder::signal_log`], oldest first
+    ///
+    /// Empty if the log is disabled.
+    pub fn signal_log(&self) -> Vec<SignalRecord> {
+        self.stats.signal_log()
+    }
+
+    /// State of the service loop, for monitoring long-running daemons
+    ///
+    /// Doesn't wait for the service loop, so it works even if the loop is stuck. A growing
+    /// `queued_updates` with an old `last_update` means the loop stopped making progress.
+    pub fn stats(&self) -> ServiceStats {
+        // only handles hold the token, weak handles and the service hold `registration` too
+        self.stats.snapshot(Arc::strong_count(&self.token))
+    }
+}
+
+impl<T: Tray + Clone> Handle<T> {
+    /// What [`Self::update`] would send to the host if `f` changed the tray, without changing it
+    ///
+    /// `f` is called on a clone of the tray. Useful for tools and tests, or to only update if
+    /// something would change.
+    ///
+    /// Returns the result of `f` and the preview, returns `None` if the tray service has been
+    /// shutdown.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[derive(Clone)]
+    /// # struct MyTray { unread: u32 }
+    /// # impl ksni::Tray for MyTray { fn id(&self) -> String { "x".into() } }
+    /// # async fn f(handle: ksni::Handle<MyTray>) {
+    /// let (_, preview) = handle.preview(|tray| tray.unread = 0).await.unwrap();
+    /// if !preview.is_empty() {
+    ///     handle.update(|tray| tray.unread = 0).await;
+    /// }
+    /// # }
+    /// ```
+    pub async fn preview<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> Option<(R, UpdatePreview)> {
+        let service = self.service.upgrade()?;
+        let service = service.lock().await;
+        let mut tray = service.tray.clone();
+        let r = f(&mut tray);
+        Some((r, service.preview(&tray)))
+    }
+}
+
+impl<T: Tray> Handle<T> {
+    /// The state to pass to a restarted tray, see [`TrayServiceBuilder::restore_state`]
+    ///
+    /// Captures the title, status, icon and tooltip sent to the host, and the ids of the menu
+    /// items. Returns `None` if the tray service has been shutdown.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # async fn f<T: ksni::Tray>(handle: ksni::Handle<T>) {
+    /// // before replacing the executable
+    /// let state = handle.export_state().await.unwrap();
+    /// std::fs::write("/run/user/1000/my-app.state", state.to_bytes()).unwrap();
+    /// # }
+    /// ```
+    pub async fn export_state(&self) -> Option<TrayStateBlob> {
+        let service = self.service.upgrade()?;
+        let state = service.lock().await.export_state();
+        Some(state)
+    }
+
+    /// Switch the locale of the tray, see [`Tray::locale_changed`]
+    ///
+    /// Nothing happens if `locale` is the current one. Returns `None` if the tray service has
+    /// been shutdown.
+    pub async fn set_locale(&self, locale: &str) -> Option<()> {
+        assert!(
+            !menu::is_generating(),
+            "a tray can't be updated while generating its menu, the service is locked"
+        );
+        let permit = self.acquire_update_slot().await;
+        let service = self.service.upgrade()?;
+        let changed = service.lock().await.set_locale(locale);
+        if changed {
+            self.send_update(Hint::All, permit).await?;
+        }
+        Some(())
+    }
+
+    /// Activate a menu item like a click of the host
+    ///
+    /// `path` is the position of the item in [`Tray::menu`], see [`menu::MenuPath`]. Calls the
+    /// callback of the item and [`Tray::menu_item_activated`], then sends the changes unless
+    /// the item has [`menu::StandardItem::no_auto_update`] set. Useful to share a code path
+    /// with a menu item, e.g. for a keyboard shortcut of the app.
+    ///
+    /// Returns `Some(false)` if there's no enabled item at `path`, and `None` if the tray
+    /// service has been shutdown.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # struct MyTray;
+    /// # impl ksni::Tray for MyTray { fn id(&self) -> String { "x".into() } }
+    /// # async fn f(handle: ksni::Handle<MyTray>) {
+    /// // the first item of the second submenu
+    /// handle.activate_item([1, 0]).await;
+    /// # }
+    /// ```
+    pub async fn activate_item(&self, path: impl Into<menu::MenuPath>) -> Option<bool> {
+        let path = path.into();
+        let permit = self.acquire_update_slot().await;
+        let service = self.service.upgrade()?;
+        let activated = {
+            let mut service = service.lock().await;
+            service.apply_sync_updates();
+            service.activate_item(&path)
+        };
+        match activated {
+            Some(true) => self.send_update(Hint::All, permit).await?,
+            Some(false) => {}
+            None => return Some(false),
+        }
+        Some(true)
+    }
+}
+
+impl<T: MessageTray> Handle<T> {
+    /// Send a message to [`MessageTray::on_message`], without waiting
+    ///
+    /// Messages are handled in order, like [`Self::try_update_sync`]. Returns `false` if the tray
+    /// service has been shutdown.
+    pub fn send(&self, message: T::Message) -> bool {
+        self.try_update_sync(move |tray| tray.on_message(message))
+    }
+
+    /// A sender of messages, which doesn't depend on the type of the tray
+    ///
+    /// It counts as a [`Handle`] for [`TrayServiceBuilder::shutdown_on_drop`].
+    pub fn message_sender(&self) -> MessageSender<T::Message> {
+        let handle = self.clone();
+        MessageSender(Arc::new(move |message| handle.send(message)))
+    }
+}
+
+/// Sends messages to a [`MessageTray`], see [`Handle::message_sender`]
+pub struct MessageSender<M>(Arc<dyn Fn(M) -> bool + Send + Sync>);
+
+impl<M> MessageSender<M> {
+    /// See [`Handle::send`]
+    pub fn send(&self, message: M) -> bool {
+        (self.0)(message)
+    }
+}
+
+impl<M> Clone for MessageSender<M> {
+    fn clone(&self) -> Self {
+        MessageSender(self.0.clone())
+    }
+}
+
+/// Returned by [`Handle::panic_guard`]
+#[must_use = "the tray is only shut down if the guard is dropped during a panic"]
+pub struct PanicGuard {
+    sender: mpsc::UnboundedSender<HandleReuest>,
+}
+
+impl Drop for PanicGuard {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            request_shutdown(&self.sender);
+        }
+    }
+}
+
+// doesn't wait, usable in a panic
+fn request_shutdown(sender: &mpsc::UnboundedSender<HandleReuest>) {
+    let (tx, _) = oneshot::channel();
+    let _ = sender.send(HandleReuest::Shutdown(tx));
 }
 
 /// Returned by [`Handle::shutdown`]
@@ -448,6 +2448,81 @@ impl<T> Clone for Handle<T> {
         Handle {
             service: self.service.clone(),
             sender: self.sender.clone(),
+            update_limit: self.update_limit.clone(),
+            registration: self.registration.clone(),
+            stats: self.stats.clone(),
+            sync_updates: self.sync_updates.clone(),
+            token: self.token.clone(),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Empty;
+
+    impl Tray for Empty {
+        fn id(&self) -> String {
+            "test".into()
+        }
+    }
+
+    #[test]
+    fn test_stats_handles() {
+        let (sender, _receiver) = mpsc::unbounded_channel();
+        let registration = Arc::new(service::Registration::default());
+        let handle = Handle::<Empty> {
+            service: Weak::new(),
+            sender: sender.clone(),
+            update_limit: None,
+            registration: registration.clone(),
+            stats: Arc::new(service::Stats::new(&Default::default())),
+            sync_updates: Default::default(),
+            token: Arc::new(HandleToken {
+                sender,
+                shutdown_on_drop: false,
+            }),
+        };
+        let _guard = service::CloseGuard::new(registration);
+        let weak = handle.downgrade();
+        assert_eq!(handle.stats().handles, 1);
+        let upgraded = weak.upgrade().unwrap();
+        assert_eq!(handle.stats().handles, 2);
+        drop(upgraded);
+        assert_eq!(handle.stats().handles, 1);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_channel_capacity() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let service = service::Service::new(Empty, Default::default(), sender.clone());
+        let limit = Arc::new(compat::Semaphore::new(1));
+        let handle = Handle::<Empty> {
+            service: Arc::downgrade(&service),
+            sender: sender.clone(),
+            update_limit: Some(limit.clone()),
+            registration: Default::default(),
+            stats: Arc::new(service::Stats::new(&Default::default())),
+            sync_updates: Default::default(),
+            token: Arc::new(HandleToken {
+                sender,
+                shutdown_on_drop: false,
+            }),
+        };
+        // the caller stops waiting, the update is still queued
+        let update = handle.update(|_| ());
+        let _ = tokio::time::timeout(std::time::Duration::from_millis(10), update).await;
+        assert_eq!(limit.available_permits(), 0);
+        drop(receiver.recv().await);
+        assert_eq!(limit.available_permits(), 1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_zero_channel_capacity() {
+        let _ = TrayServiceBuilder::new(Empty).channel_capacity(0);
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+
+use zbus::fdo::DBusProxy;
+use zbus::zvariant::OwnedObjectPath;
+use zbus::Connection;
+
+/// A string with translations
+///
+/// See [`Tray::locale_changed`]
+///
+/// # Examples
+///
+/// ```
+/// let quit = ksni::LocalizedString::new("Quit")
+///     .with("de", "Beenden")
+///     .with("zh_TW", "結束");
+/// assert_eq!(quit.get("de_AT.UTF-8"), "Beenden");
+/// assert_eq!(quit.get("zh_TW"), "結束");
+/// assert_eq!(quit.get("fr_FR"), "Quit");
+/// ```
+///
+/// [`Tray::locale_changed`]: crate::Tray::locale_changed
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LocalizedString {
+    default: String,
+    translations: HashMap<String, String>,
+}
+
+impl LocalizedString {
+    /// Create a string used when no translation matches the locale
+    pub fn new(default: impl Into<String>) -> Self {
+        LocalizedString {
+            default: default.into(),
+            translations: HashMap::new(),
+        }
+    }
+
+    /// Add a translation
+    ///
+    /// `locale` is a POSIX locale name without the codeset, e.g. `"de"` or `"pt_BR"`
+    pub fn with(mut self, locale: impl Into<String>, text: impl Into<String>) -> Self {
+        self.translations.insert(locale.into(), text.into());
+        self
+    }
+
+    /// Get the translation of `locale`
+    ///
+    /// `"pt_BR.UTF-8@modifier"` tries `"pt_BR"` first, then `"pt"`, and falls back to the
+    /// default string
+    pub fn get(&self, locale: &str) -> &str {
+        let locale = strip_codeset(locale);
+        let language = locale.split('_').next().unwrap_or(locale);
+        self.translations
+            .get(locale)
+            .or_else(|| self.translations.get(language))
+            .unwrap_or(&self.default)
+    }
+}
+
+impl From<&str> for LocalizedString {
+    fn from(s: &str) -> Self {
+        LocalizedString::new(s)
+    }
+}
+
+impl From<String> for LocalizedString {
+    fn from(s: String) -> Self {
+        LocalizedString::new(s)
+    }
+}
+
+fn strip_codeset(locale: &str) -> &str {
+    locale
+        .split(['.', '@'])
+        .next()
+        .expect("split always returns at least one item")
+}
+
+/// Locale of the process, from `LC_ALL`, `LC_MESSAGES` or `LANG`
+///
+/// Returns `"C"` if none of them is set
+pub fn system_locale() -> String {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .into_iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .map(|value| strip_codeset(&value).to_owned())
+        .unwrap_or_else(|| "C".into())
+}
+
+/// Language of the desktop session, see [`Handle::host_locale`]
+///
+/// [`Handle::host_locale`]: crate::Handle::host_locale
+pub(crate) async fn host_locale(session: &Connection) -> Option<String> {
+    let locale = match user_language(session).await {
+        Some(language) => language,
+        None => system_language().await?,
+    };
+    Some(strip_codeset(&locale).to_owned())
+}
+
+// the language chosen in the settings of the desktop, stored by AccountsService
+async fn user_language(session: &Connection) -> Option<String> {
+    let unique_name = session.unique_name()?.to_owned();
+    let uid = DBusProxy::new(session)
+        .await
+        .ok()?
+        .get_connection_unix_user(unique_name.into())
+        .await
+        .ok()?;
+    let system = Connection::system().await.ok()?;
+    let accounts = zbus::Proxy::new(
+        &system,
+        "org.freedesktop.Accounts",
+        "/org/freedesktop/Accounts",
+        "org.freedesktop.Accounts",
+    )
+    .await
+    .ok()?;
+    let user: OwnedObjectPath = accounts
+        .call("FindUserById", &(i64::from(uid),))
+        .await
+        .ok()?;
+    let user = zbus::Proxy::new(
+        &system,
+        "org.freedesktop.Accounts",
+        user,
+        "org.freedesktop.Accounts.User",
+    )
+    .await
+    .ok()?;
+    let language: String = user.get_property("Language").await.ok()?;
+    Some(language).filter(|l| !l.is_empty())
+}
+
+// the system locale, from systemd-localed
+async fn system_language() -> Option<String> {
+    let system = Connection::system().await.ok()?;
+    let locale1 = zbus::Proxy::new(
+        &system,
+        "org.freedesktop.locale1",
+        "/org/freedesktop/locale1",
+        "org.freedesktop.locale1",
+    )
+    .await
+    .ok()?;
+    let locale: Vec<String> = locale1.get_property("Locale").await.ok()?;
+    ["LC_MESSAGES=", "LANG="].into_iter().find_map(|prefix| {
+        locale
+            .iter()
+            .find_map(|var| var.strip_prefix(prefix))
+            .filter(|value| !value.is_empty())
+            .map(str::to_owned)
+    })
+}
@@ -1,8 +1,10 @@
 //! Types used to construct a menu
 
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fmt;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use serde::Serialize;
 use zbus::zvariant::{OwnedValue, Str, Type, Value};
@@ -79,7 +81,853 @@ pub enum MenuItem<T> {
     RadioGroup(RadioGroup<T>),
 }
 
+impl<T> MenuItem<T> {
+    /// Label of the item, `None` for separators and radio groups
+    pub fn label(&self) -> Option<&str> {
+        match self {
+            MenuItem::Standard(item) => Some(&item.label),
+            MenuItem::Checkmark(item) => Some(&item.label),
+            MenuItem::SubMenu(item) => Some(&item.label),
+            MenuItem::Separator | MenuItem::RadioGroup(_) => None,
+        }
+    }
+
+    /// Children of the item, `None` if it's not a [`SubMenu`]
+    pub fn submenu(&self) -> Option<&[MenuItem<T>]> {
+        match self {
+            MenuItem::SubMenu(item) => Some(&item.submenu),
+            _ => None,
+        }
+    }
+
+    /// Mutable children of the item, `None` if it's not a [`SubMenu`]
+    pub fn submenu_mut(&mut self) -> Option<&mut Vec<MenuItem<T>>> {
+        match self {
+            MenuItem::SubMenu(item) => Some(&mut item.submenu),
+            _ => None,
+        }
+    }
+}
+
+impl<T: 'static> MenuItem<T> {
+    /// Convert the item into an item of a tray `U` that contains a `T`, e.g. to reuse the menu
+    /// of a part of a bigger tray
+    ///
+    /// `get` and `get_mut` find the `T` in `U`, callbacks are skipped if they return `None`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ksni::menu::*;
+    ///
+    /// struct Player {
+    ///     playing: bool,
+    /// }
+    /// struct App {
+    ///     player: Player,
+    /// }
+    ///
+    /// let item: MenuItem<Player> = StandardItem {
+    ///     label: "Play".into(),
+    ///     activate: Box::new(|player: &mut Player| player.playing = true),
+    ///     ..Default::default()
+    /// }
+    /// .into();
+    /// let item: MenuItem<App> = item.map(|app: &App| Some(&app.player), |app| Some(&mut app.player));
+    /// ```
+    pub fn map<U: 'static>(
+        self,
+        get: impl Fn(&U) -> Option<&T> + Send + Sync + 'static,
+        get_mut: impl Fn(&mut U) -> Option<&mut T> + Send + Sync + 'static,
+    ) -> MenuItem<U> {
+        self.map_with(&Lens {
+            get: Arc::new(get),
+            get_mut: Arc::new(get_mut),
+        })
+    }
+
+    fn map_with<U: 'static>(self, lens: &Lens<U, T>) -> MenuItem<U> {
+        match self {
+            MenuItem::Standard(item) => {
+                let get_mut = lens.get_mut.clone();
+                let activate = item.activate;
+                MenuItem::Standard(StandardItem {
+                    label: item.label,
+                    enabled: item.enabled,
+                    visible: item.visible,
+                    icon_name: item.icon_name,
+                    icon_data: item.icon_data,
+                    shortcut: item.shortcut,
+                    disposition: item.disposition,
+                    no_auto_update: item.no_auto_update,
+                    confirm: item.confirm,
+                    key: item.key,
+                    dynamic_label: item.dynamic_label.map(|dynamic| {
+                        let get = lens.get.clone();
+                        let label = dynamic.label;
+                        DynamicLabel::new(dynamic.interval, move |this: &U| {
+                            get(this).map(&label).unwrap_or_default()
+                        })
+                    }),
+                    activate: Box::new(move |this: &mut U| {
+                        if let Some(this) = get_mut(this) {
+                            activate(this)
+                        }
+                    }),
+                })
+            }
+            MenuItem::Separator => MenuItem::Separator,
+            MenuItem::Checkmark(item) => {
+                let get_mut = lens.get_mut.clone();
+                let activate = item.activate;
+                MenuItem::Checkmark(CheckmarkItem {
+                    label: item.label,
+                    enabled: item.enabled,
+                    visible: item.visible,
+                    checked: item.checked,
+                    icon_name: item.icon_name,
+                    icon_data: item.icon_data,
+                    shortcut: item.shortcut,
+                    disposition: item.disposition,
+                    key: item.key,
+                    activate: Box::new(move |this: &mut U| {
+                        if let Some(this) = get_mut(this) {
+                            activate(this)
+                        }
+                    }),
+                })
+            }
+            MenuItem::SubMenu(item) => MenuItem::SubMenu(SubMenu {
+                label: item.label,
+                enabled: item.enabled,
+                visible: item.visible,
+                icon_name: item.icon_name,
+                icon_data: item.icon_data,
+                shortcut: item.shortcut,
+                disposition: item.disposition,
+                key: item.key,
+                submenu: item
+                    .submenu
+                    .into_iter()
+                    .map(|item| item.map_with(lens))
+                    .collect(),
+            }),
+            MenuItem::RadioGroup(group) => {
+                let get_mut = lens.get_mut.clone();
+                let select = group.select;
+                MenuItem::RadioGroup(RadioGroup {
+                    selected: group.selected,
+                    select: Box::new(move |this: &mut U, index| {
+                        if let Some(this) = get_mut(this) {
+                            select(this, index)
+                        }
+                    }),
+                    options: group.options,
+                })
+            }
+        }
+    }
+}
+
+// see `MenuItem::map`
+#[allow(clippy::type_complexity)]
+struct Lens<U, T> {
+    get: Arc<dyn Fn(&U) -> Option<&T> + Send + Sync>,
+    get_mut: Arc<dyn Fn(&mut U) -> Option<&mut T> + Send + Sync>,
+}
+
+/// Position of an item in a menu tree, the indices of the item and its parents in their menus,
+/// starting from the root
+///
+/// Options of a [`RadioGroup`] have the path of the group and the index of the option. Unlike
+/// the ids sent to the host, a path only changes if the items before it are added or removed.
+///
+/// # Examples
+///
+/// ```
+/// use ksni::menu::MenuPath;
+///
+/// let path = MenuPath::from(vec![1, 0]);
+/// assert_eq!(path.parent(), Some(MenuPath::from(vec![1])));
+/// assert_eq!(path.child(2), MenuPath::from(vec![1, 0, 2]));
+/// assert_eq!(path[0], 1);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MenuPath(Vec<usize>);
+
+impl MenuPath {
+    /// The path of the root menu, no item has it
+    pub fn root() -> Self {
+        MenuPath(Vec::new())
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Path of the menu containing the item, `None` for the root
+    pub fn parent(&self) -> Option<MenuPath> {
+        let (_, parent) = self.0.split_last()?;
+        Some(MenuPath(parent.to_vec()))
+    }
+
+    /// Path of the item at `index` of this submenu
+    pub fn child(&self, index: usize) -> MenuPath {
+        let mut path = self.0.clone();
+        path.push(index);
+        MenuPath(path)
+    }
+
+    pub fn as_slice(&self) -> &[usize] {
+        &self.0
+    }
+}
+
+impl std::ops::Deref for MenuPath {
+    type Target = [usize];
+
+    fn deref(&self) -> &[usize] {
+        &self.0
+    }
+}
+
+impl From<Vec<usize>> for MenuPath {
+    fn from(path: Vec<usize>) -> Self {
+        MenuPath(path)
+    }
+}
+
+impl From<&[usize]> for MenuPath {
+    fn from(path: &[usize]) -> Self {
+        MenuPath(path.to_vec())
+    }
+}
+
+impl<const N: usize> From<[usize; N]> for MenuPath {
+    fn from(path: [usize; N]) -> Self {
+        MenuPath(path.to_vec())
+    }
+}
+
+impl From<MenuPath> for Vec<usize> {
+    fn from(path: MenuPath) -> Self {
+        path.0
+    }
+}
+
+impl PartialEq<[usize]> for MenuPath {
+    fn eq(&self, other: &[usize]) -> bool {
+        self.0 == other
+    }
+}
+
+impl<const N: usize> PartialEq<[usize; N]> for MenuPath {
+    fn eq(&self, other: &[usize; N]) -> bool {
+        self.0 == other
+    }
+}
+
+impl fmt::Display for MenuPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+
+/// Iterate over a menu tree depth first
+///
+/// Yields the [`MenuPath`] of every item.
+///
+/// # Examples
+///
+/// ```
+/// use ksni::menu::*;
+///
+/// let menu: Vec<MenuItem<()>> = vec![
+///     SubMenu {
+///         label: "a".into(),
+///         submenu: vec![StandardItem { label: "a1".into(), ..Default::default() }.into()],
+///         ..Default::default()
+///     }
+///     .into(),
+///     MenuItem::Separator,
+/// ];
+/// let paths: Vec<MenuPath> = iter(&menu).map(|(path, _)| path).collect();
+/// assert_eq!(paths, [[0].as_slice(), &[0, 0], &[1]].map(MenuPath::from));
+/// ```
+pub fn iter<T>(menu: &[MenuItem<T>]) -> impl Iterator<Item = (MenuPath, &MenuItem<T>)> {
+    // (path of the menu, remaining items of the menu)
+    let mut stack = vec![(MenuPath::root(), menu.iter().enumerate())];
+    std::iter::from_fn(move || loop {
+        let (path, items) = stack.last_mut()?;
+        let Some((index, item)) = items.next() else {
+            stack.pop();
+            continue;
+        };
+        let item_path = path.child(index);
+        if let Some(submenu) = item.submenu() {
+            stack.push((item_path.clone(), submenu.iter().enumerate()));
+        }
+        return Some((item_path, item));
+    })
+}
+
+/// Visit every item of a menu tree depth first, with its path (see [`iter`])
+///
+/// Items are visited before their children, so changes to the children of a [`SubMenu`] are
+/// visited too
+pub fn walk_mut<T>(menu: &mut [MenuItem<T>], mut f: impl FnMut(&MenuPath, &mut MenuItem<T>)) {
+    fn walk<T>(
+        menu: &mut [MenuItem<T>],
+        path: &mut MenuPath,
+        f: &mut impl FnMut(&MenuPath, &mut MenuItem<T>),
+    ) {
+        for (index, item) in menu.iter_mut().enumerate() {
+            path.0.push(index);
+            f(path, item);
+            if let Some(submenu) = item.submenu_mut() {
+                walk(submenu, path, f);
+            }
+            path.0.pop();
+        }
+    }
+    walk(menu, &mut MenuPath::root(), &mut f);
+}
+
+/// Get the item at `path`, a [`MenuPath`] or a slice of indices
+pub fn get_mut<'a, T>(menu: &'a mut [MenuItem<T>], path: &[usize]) -> Option<&'a mut MenuItem<T>> {
+    let (last, parents) = path.split_last()?;
+    let mut menu = menu;
+    for &index in parents {
+        menu = menu.get_mut(index)?.submenu_mut()?;
+    }
+    menu.get_mut(*last)
+}
+
+/// Find the first item matching `predicate` depth first
+pub fn find_mut<T>(
+    menu: &mut [MenuItem<T>],
+    mut predicate: impl FnMut(&MenuItem<T>) -> bool,
+) -> Option<&mut MenuItem<T>> {
+    let path = iter(menu).find(|(_, item)| predicate(item))?.0;
+    get_mut(menu, &path)
+}
+
+/// Find the first item with `label` depth first
+pub fn find_by_label_mut<'a, T>(
+    menu: &'a mut [MenuItem<T>],
+    label: &str,
+) -> Option<&'a mut MenuItem<T>> {
+    find_mut(menu, |item| item.label() == Some(label))
+}
+
+/// A problem found by [`validate`]
+///
+/// `path` is the path of the item, see [`MenuPath`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MenuWarning {
+    /// [`RadioGroup::selected`] is not an index of the options, no option is shown as selected
+    RadioSelectedOutOfRange {
+        path: MenuPath,
+        selected: usize,
+        options: usize,
+    },
+    /// A [`RadioGroup`] without options, it's not shown
+    EmptyRadioGroup { path: MenuPath },
+    /// A [`SubMenu`] without items, it's shown as a standard item
+    EmptySubMenu { path: MenuPath },
+    /// A shortcut with an empty key combination or key name, hosts may reject the menu
+    InvalidShortcut { path: MenuPath },
+    /// A radio item selected in a group that already has a selected item
+    ///
+    /// Hosts group consecutive radio items, so adjacent [`RadioGroup`]s are a single group.
+    MultipleRadioSelected { path: MenuPath },
+    /// A checkmark between the radio items of a group, hosts may split the group or not
+    CheckmarkInRadioGroup { path: MenuPath },
+    /// The `key` of the item is already used by another item of the menu
+    DuplicateKey { path: MenuPath, key: String },
+    /// The item at `index` of [`Tray::raw_menu`] is not a valid child, an empty menu is shown
+    ///
+    /// It's either out of range, the root, the child of more than one item, part of a cycle,
+    /// or not reachable from the root. Always reported.
+    ///
+    /// [`Tray::raw_menu`]: crate::Tray::raw_menu
+    InvalidRawMenu { index: usize },
+}
+
+impl fmt::Display for MenuWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use MenuWarning::*;
+        match self {
+            RadioSelectedOutOfRange {
+                path,
+                selected,
+                options,
+            } => write!(
+                f,
+                "radio group {path} selects option {selected} but has {options} options"
+            ),
+            EmptyRadioGroup { path } => write!(f, "radio group {path} has no options"),
+            EmptySubMenu { path } => write!(f, "submenu {path} has no items"),
+            InvalidShortcut { path } => write!(f, "item {path} has an empty shortcut key"),
+            MultipleRadioSelected { path } => write!(
+                f,
+                "radio item {path} is selected in a group with another selected item"
+            ),
+            CheckmarkInRadioGroup { path } => {
+                write!(f, "checkmark {path} is between the items of a radio group")
+            }
+            DuplicateKey { path, key } => write!(f, "item {path} reuses the key {key:?}"),
+            InvalidRawMenu { index } => write!(f, "raw menu item {index} is not a valid child"),
+        }
+    }
+}
+
+/// An `activate` callback receiving a payload of the item
+///
+/// The callbacks are replaced every time the menu is updated, so the payload always belongs to
+/// the item that was clicked, even after the items of a generated menu were reordered.
+///
+/// # Examples
+///
+/// ```
+/// # use std::path::PathBuf;
+/// # use ksni::menu::*;
+/// # struct MyTray { recent: Vec<PathBuf> }
+/// # impl MyTray { fn open(&mut self, _: &PathBuf) {} }
+/// # fn f(this: &MyTray) -> Vec<ksni::MenuItem<MyTray>> {
+/// this.recent
+///     .iter()
+///     .map(|path| {
+///         StandardItem {
+///             label: path.display().to_string().into(),
+///             activate: with_data(path.clone(), |this: &mut MyTray, path| this.open(path)),
+///             ..Default::default()
+///         }
+///         .into()
+///     })
+///     .collect()
+/// # }
+/// ```
+pub fn with_data<T, D: Send + 'static>(
+    data: D,
+    f: impl Fn(&mut T, &D) + Send + 'static,
+) -> Box<dyn Fn(&mut T) + Send> {
+    Box::new(move |this| f(this, &data))
+}
+
+/// A `select` callback of a [`RadioGroup`] receiving the payload of the selected option
+///
+/// `data` has one payload per option, in the same order, see [`with_data`].
+#[allow(clippy::type_complexity)]
+pub fn select_with_data<T, D: Send + 'static>(
+    data: Vec<D>,
+    f: impl Fn(&mut T, &D) + Send + 'static,
+) -> Box<dyn Fn(&mut T, usize) + Send> {
+    Box::new(move |this, index| {
+        if let Some(data) = data.get(index) {
+            f(this, data)
+        }
+    })
+}
+
+/// Toggles of an [`ApplyGroup`] that are not applied yet
+///
+/// Keep it in the tray and give a clone to the group every time the menu is built, clones share
+/// the changes.
+#[derive(Clone, Debug, Default)]
+pub struct PendingChecks(Arc<Mutex<HashMap<usize, bool>>>);
+
+impl PendingChecks {
+    /// Returns `true` if no option was toggled since the last apply
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Drop the pending toggles
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<usize, bool>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Checkboxes whose toggles are applied together by an "Apply" item
+///
+/// Clicking an option only changes its checkmark, the `apply` callback gets the state of all
+/// options once the apply item is clicked. `checked` of the options is the applied state,
+/// their `activate` callbacks are not used.
+///
+/// # Examples
+///
+/// ```
+/// # use ksni::menu::*;
+/// struct MyTray {
+///     notify: bool,
+///     sound: bool,
+///     pending: PendingChecks,
+/// }
+///
+/// # fn f(this: &MyTray) -> Vec<ksni::MenuItem<MyTray>> {
+/// ApplyGroup {
+///     options: vec![
+///         CheckmarkItem {
+///             label: "Notifications".into(),
+///             checked: this.notify,
+///             ..Default::default()
+///         },
+///         CheckmarkItem {
+///             label: "Sound".into(),
+///             checked: this.sound,
+///             ..Default::default()
+///         },
+///     ],
+///     apply: Box::new(|this: &mut MyTray, checked| {
+///         this.notify = checked[0];
+///         this.sound = checked[1];
+///     }),
+///     pending: this.pending.clone(),
+///     ..Default::default()
+/// }
+/// .into_items()
+/// # }
+/// ```
+pub struct ApplyGroup<T> {
+    pub options: Vec<CheckmarkItem<T>>,
+    /// Label of the apply item, it's disabled while nothing is pending
+    pub apply_label: Cow<'static, str>,
+    #[allow(clippy::type_complexity)]
+    pub apply: Box<dyn Fn(&mut T, &[bool]) + Send>,
+    pub pending: PendingChecks,
+}
+
+impl<T> Default for ApplyGroup<T> {
+    fn default() -> Self {
+        Self {
+            options: Vec::new(),
+            apply_label: "Apply".into(),
+            apply: Box::new(|_, _| {}),
+            pending: PendingChecks::default(),
+        }
+    }
+}
+
+impl<T: 'static> ApplyGroup<T> {
+    /// The options showing the pending state, followed by the apply item
+    pub fn into_items(self) -> Vec<MenuItem<T>> {
+        let applied: Vec<bool> = self.options.iter().map(|option| option.checked).collect();
+        let pending = self.pending;
+        let changes = pending.lock().clone();
+        let mut items: Vec<MenuItem<T>> = self
+            .options
+            .into_iter()
+            .enumerate()
+            .map(|(index, option)| {
+                let pending = pending.clone();
+                let applied = option.checked;
+                CheckmarkItem {
+                    checked: changes.get(&index).copied().unwrap_or(applied),
+                    activate: Box::new(move |_: &mut T| {
+                        let mut changes = pending.lock();
+                        let checked = !changes.get(&index).copied().unwrap_or(applied);
+                        if checked == applied {
+                            changes.remove(&index);
+                        } else {
+                            changes.insert(index, checked);
+                        }
+                    }),
+                    ..option
+                }
+                .into()
+            })
+            .collect();
+        let apply = self.apply;
+        items.push(
+            StandardItem {
+                label: self.apply_label,
+                enabled: !changes.is_empty(),
+                activate: Box::new(move |this: &mut T| {
+                    let changes = std::mem::take(&mut *pending.lock());
+                    let checked: Vec<bool> = applied
+                        .iter()
+                        .enumerate()
+                        .map(|(index, applied)| changes.get(&index).copied().unwrap_or(*applied))
+                        .collect();
+                    apply(this, &checked);
+                }),
+                ..Default::default()
+            }
+            .into(),
+        );
+        items
+    }
+}
+
+thread_local! {
+    static EVENT_TIMESTAMP: std::cell::Cell<Option<u32>> = const { std::cell::Cell::new(None) };
+    static GENERATING: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+/// What [`Tray::menu_with`](crate::Tray::menu_with) can use to generate the menu
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct MenuGenerationContext {
+    /// Features of the host, `None` until the tray is connected or if no watcher is running, see
+    /// [`Handle::host_capabilities`](crate::Handle::host_capabilities)
+    pub host_capabilities: Option<crate::HostCapabilities>,
+    /// Revision of the layout last sent to the host, increased on every change of the layout
+    pub revision: u32,
+}
+
+// the service is locked while the menu is generated, see `Handle::update_hint`
+pub(crate) fn is_generating() -> bool {
+    GENERATING.with(|g| g.get())
+}
+
+pub(crate) fn generating<R>(f: impl FnOnce() -> R) -> R {
+    struct Reset;
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            GENERATING.with(|g| g.set(false));
+        }
+    }
+    assert!(
+        !GENERATING.with(|g| g.replace(true)),
+        "the menu of a tray is generated while generating a menu, `Tray::menu` must not \
+         update the tray"
+    );
+    let _reset = Reset;
+    f()
+}
+
+/// Timestamp the host sent with the menu event being handled
+///
+/// Only set while an `activate` callback runs, `None` elsewhere. It's usually the X11 server
+/// time of the click, or zero if the host doesn't know it.
+pub fn event_timestamp() -> Option<u32> {
+    EVENT_TIMESTAMP.with(|t| t.get())
+}
+
+pub(crate) fn with_event_timestamp<R>(timestamp: u32, f: impl FnOnce() -> R) -> R {
+    struct Reset(Option<u32>);
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            EVENT_TIMESTAMP.with(|t| t.set(self.0));
+        }
+    }
+    let _reset = Reset(EVENT_TIMESTAMP.with(|t| t.replace(Some(timestamp))));
+    f()
+}
+
+/// The data of a menu event, see [`Tray::menu_event`](crate::Tray::menu_event)
+///
+/// The built-in events carry no data, hosts send an empty string or `0`. Items of vendor types
+/// send a value, e.g. the position of a slider.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum MenuEventData {
+    /// An empty string, sent by most hosts for the events without data
+    None,
+    Bool(bool),
+    /// Any integer type that fits in an `i32`
+    Int(i32),
+    Double(f64),
+    Str(String),
+    /// Anything else, e.g. a structure
+    Other(OwnedValue),
+}
+
+impl From<OwnedValue> for MenuEventData {
+    fn from(value: OwnedValue) -> Self {
+        fn decode(value: &Value<'_>) -> Option<MenuEventData> {
+            let int = |v: Option<i32>| v.map(MenuEventData::Int);
+            match value {
+                Value::Str(s) if s.is_empty() => Some(MenuEventData::None),
+                Value::Str(s) => Some(MenuEventData::Str(s.to_string())),
+                Value::Bool(b) => Some(MenuEventData::Bool(*b)),
+                Value::U8(n) => int(Some((*n).into())),
+                Value::I16(n) => int(Some((*n).into())),
+                Value::U16(n) => int(Some((*n).into())),
+                Value::I32(n) => int(Some(*n)),
+                Value::U32(n) => int(i32::try_from(*n).ok()),
+                Value::I64(n) => int(i32::try_from(*n).ok()),
+                Value::U64(n) => int(i32::try_from(*n).ok()),
+                Value::F64(n) => Some(MenuEventData::Double(*n)),
+                // some hosts wrap the data in another variant
+                Value::Value(inner) => decode(inner),
+                _ => None,
+            }
+        }
+        decode(&value).unwrap_or(MenuEventData::Other(value))
+    }
+}
+
+/// Check a menu for states that hosts can't display correctly
+///
+/// With the "menu-validation" feature, menus are checked on every update and the warnings are
+/// passed to [`Tray::on_menu_warning`](crate::Tray::on_menu_warning), including the ones of
+/// [`Tray::raw_menu`](crate::Tray::raw_menu).
+pub fn validate<T>(menu: &[MenuItem<T>]) -> Vec<MenuWarning> {
+    fn invalid_shortcut(shortcut: &[Vec<String>]) -> bool {
+        shortcut
+            .iter()
+            .any(|keys| keys.is_empty() || keys.iter().any(String::is_empty))
+    }
+
+    // the toggles of the items in `menu`, a radio group has one per option
+    fn toggles<'a, T>(
+        path: &'a MenuPath,
+        menu: &'a [MenuItem<T>],
+    ) -> impl Iterator<Item = (MenuPath, Toggle)> + 'a {
+        menu.iter().enumerate().flat_map(move |(index, item)| {
+            let path = path.child(index);
+            let toggles: Vec<_> = match item {
+                MenuItem::RadioGroup(group) => (0..group.options.len())
+                    .map(|option| {
+                        let selected = option == group.selected;
+                        (path.child(option), Toggle::Radio { selected })
+                    })
+                    .collect(),
+                MenuItem::Checkmark(_) => vec![(path, Toggle::Checkmark)],
+                _ => vec![(path, Toggle::Other)],
+            };
+            toggles
+        })
+    }
+
+    let mut warnings = Vec::new();
+    let mut keys = Keys::default();
+    check_radio_groups(toggles(&MenuPath::root(), menu), &mut warnings);
+    for (path, item) in iter(menu) {
+        let shortcut = match item {
+            MenuItem::Standard(item) => {
+                keys.check(&path, item.key.as_deref(), &mut warnings);
+                &item.shortcut
+            }
+            MenuItem::Checkmark(item) => {
+                keys.check(&path, item.key.as_deref(), &mut warnings);
+                &item.shortcut
+            }
+            MenuItem::SubMenu(item) => {
+                keys.check(&path, item.key.as_deref(), &mut warnings);
+                if item.submenu.is_empty() {
+                    warnings.push(MenuWarning::EmptySubMenu { path: path.clone() });
+                }
+                check_radio_groups(toggles(&path, &item.submenu), &mut warnings);
+                &item.shortcut
+            }
+            MenuItem::RadioGroup(group) => {
+                if group.options.is_empty() {
+                    warnings.push(MenuWarning::EmptyRadioGroup { path });
+                    continue;
+                }
+                if group.selected >= group.options.len() {
+                    warnings.push(MenuWarning::RadioSelectedOutOfRange {
+                        path: path.clone(),
+                        selected: group.selected,
+                        options: group.options.len(),
+                    });
+                }
+                if group
+                    .options
+                    .iter()
+                    .any(|option| invalid_shortcut(&option.shortcut))
+                {
+                    warnings.push(MenuWarning::InvalidShortcut { path });
+                }
+                continue;
+            }
+            MenuItem::Separator => continue,
+        };
+        if invalid_shortcut(shortcut) {
+            warnings.push(MenuWarning::InvalidShortcut { path });
+        }
+    }
+    warnings
+}
+
+// `validate` of a checked `Tray::raw_menu`, only the problems that can't be checked before
+// it's flattened
+#[cfg(feature = "menu-validation")]
+pub(crate) fn validate_raw<T>(menu: &[(RawMenuItem<T>, Vec<usize>)]) -> Vec<MenuWarning> {
+    let mut warnings = Vec::new();
+    let mut keys = Keys::default();
+    for (item, children) in menu {
+        let toggles = children.iter().map(|&child| {
+            let child = &menu[child].0;
+            let toggle = match child.toggle_type {
+                ToggleType::Radio => Toggle::Radio {
+                    selected: child.toggle_state == ToggleState::On,
+                },
+                ToggleType::Checkmark => Toggle::Checkmark,
+                ToggleType::Null => Toggle::Other,
+            };
+            (child.path.clone(), toggle)
+        });
+        check_radio_groups(toggles, &mut warnings);
+        keys.check(&item.path, item.key.as_deref(), &mut warnings);
+    }
+    warnings
+}
+
+// see `validate`
+enum Toggle {
+    Radio { selected: bool },
+    Checkmark,
+    Other,
+}
+
+// the items of a menu, consecutive radio items are a group, even if separated by checkmarks
+fn check_radio_groups(
+    items: impl Iterator<Item = (MenuPath, Toggle)>,
+    warnings: &mut Vec<MenuWarning>,
+) {
+    // (has a selected item, checkmarks after the last radio item)
+    let mut group: Option<(bool, Vec<MenuPath>)> = None;
+    for (path, toggle) in items {
+        match (toggle, &mut group) {
+            (Toggle::Radio { selected }, Some((has_selected, checkmarks))) => {
+                warnings.extend(
+                    checkmarks
+                        .drain(..)
+                        .map(|path| MenuWarning::CheckmarkInRadioGroup { path }),
+                );
+                if selected && *has_selected {
+                    warnings.push(MenuWarning::MultipleRadioSelected { path });
+                }
+                *has_selected |= selected;
+            }
+            (Toggle::Radio { selected }, None) => group = Some((selected, Vec::new())),
+            (Toggle::Checkmark, Some((_, checkmarks))) => checkmarks.push(path),
+            _ => group = None,
+        }
+    }
+}
+
+// the keys used in a menu, see `StandardItem::key`
+#[derive(Default)]
+struct Keys<'a>(std::collections::HashSet<&'a str>);
+
+impl<'a> Keys<'a> {
+    fn check(&mut self, path: &MenuPath, key: Option<&'a str>, warnings: &mut Vec<MenuWarning>) {
+        if let Some(key) = key {
+            if !self.0.insert(key) {
+                warnings.push(MenuWarning::DuplicateKey {
+                    path: path.clone(),
+                    key: key.to_owned(),
+                });
+            }
+        }
+    }
+}
+
 /// Menu item, the standard one
+///
+/// Labels and icon names of all items are `Cow<'static, str>`, set fixed ones with
+/// `"text".into()` to avoid allocating them on every menu update, and computed ones with
+/// `format!(..).into()`.
 pub struct StandardItem<T> {
     /// Text of the item, except that:
     /// -# two consecutive underscore characters "__" are displayed as a
@@ -88,13 +936,13 @@ pub struct StandardItem<T> {
     /// -# the first of those remaining underscore characters (unless it is
     /// the last character in the string) indicates that the following
     /// character is the access key.
-    pub label: String,
+    pub label: Cow<'static, str>,
     /// Whether the item can be activated or not.
     pub enabled: bool,
     /// True if the item is visible in the menu.
     pub visible: bool,
     /// Icon name of the item, following the freedesktop.org icon spec.
-    pub icon_name: String,
+    pub icon_name: Cow<'static, str>,
     /// PNG data of the icon.
     pub icon_data: Vec<u8>,
     /// The shortcut of the item. Each array represents the key press
@@ -109,19 +957,43 @@ pub struct StandardItem<T> {
     /// How the menuitem feels the information it's displaying to the
     /// user should be presented.
     pub disposition: Disposition,
+    /// Don't check the tray for changes after `activate`
+    ///
+    /// For items clicked often whose callback changes nothing visible, e.g. a counter only shown
+    /// elsewhere. Changes made anyway are sent with the next update.
+    pub no_auto_update: bool,
+    /// Replaces `label` with a label refreshed on a timer, see [`DynamicLabel`]
+    pub dynamic_label: Option<DynamicLabel<T>>,
+    /// Ask for a confirmation before calling `activate`, for destructive actions
+    ///
+    /// Clicking the item shows it as a submenu generated by ksni, with the message and the
+    /// choices to confirm or cancel, until one of them or another item is clicked. `activate`
+    /// and [`Tray::menu_item_activated`](crate::Tray::menu_item_activated) are only called when
+    /// confirming, the choices are never reported. Its disposition is [`Disposition::Alert`]
+    /// while confirming.
+    pub confirm: Option<ConfirmSpec>,
+    /// Identifies the item across layout changes, see
+    /// [`TrayServiceBuilder::stable_menu_ids`](crate::TrayServiceBuilder::stable_menu_ids)
+    ///
+    /// Keys must be unique in the menu, only the first item with a key keeps its id.
+    pub key: Option<Cow<'static, str>>,
     pub activate: Box<dyn Fn(&mut T) + Send>,
 }
 
 impl<T> Default for StandardItem<T> {
     fn default() -> Self {
         StandardItem {
-            label: String::default(),
+            label: Cow::default(),
             enabled: true,
             visible: true,
-            icon_name: String::default(),
+            icon_name: Cow::default(),
             icon_data: Vec::default(),
             shortcut: Vec::default(),
             disposition: Disposition::Normal,
+            no_auto_update: false,
+            dynamic_label: None,
+            confirm: None,
+            key: None,
             activate: Box::new(|_this| {}),
         }
     }
@@ -145,7 +1017,11 @@ impl<T: 'static> From<StandardItem<T>> for RawMenuItem<T> {
             icon_data: item.icon_data,
             shortcut: item.shortcut,
             disposition: item.disposition,
-            on_clicked: Box::new(move |this: &mut T, _id| {
+            no_auto_update: item.no_auto_update,
+            dynamic_label: item.dynamic_label,
+            confirm: item.confirm,
+            key: item.key,
+            on_clicked: Box::new(move |this: &mut T, _path| {
                 (activate)(this);
             }),
             ..Default::default()
@@ -153,6 +1029,132 @@ impl<T: 'static> From<StandardItem<T>> for RawMenuItem<T> {
     }
 }
 
+/// The choices of a [`StandardItem::confirm`]
+///
+/// # Examples
+///
+/// ```
+/// # use ksni::menu::{ConfirmSpec, StandardItem};
+/// # struct MyTray;
+/// let item = StandardItem::<MyTray> {
+///     label: "Delete All".into(),
+///     confirm: Some(ConfirmSpec {
+///         message: "Delete all files?".into(),
+///         ..Default::default()
+///     }),
+///     activate: Box::new(|_this| { /* delete */ }),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfirmSpec {
+    /// Shown disabled above the choices, nothing if empty
+    pub message: Cow<'static, str>,
+    /// Label of the choice calling `activate`, default is `"Yes"`
+    pub confirm_label: Cow<'static, str>,
+    /// Label of the choice doing nothing, default is `"No"`
+    pub cancel_label: Cow<'static, str>,
+}
+
+impl Default for ConfirmSpec {
+    fn default() -> Self {
+        ConfirmSpec {
+            message: Cow::default(),
+            confirm_label: "Yes".into(),
+            cancel_label: "No".into(),
+        }
+    }
+}
+
+// shows the choices of the item at `path` asking for a confirmation, returns false if there's
+// no such item, see `StandardItem::confirm`
+pub(crate) fn show_confirmation<T>(
+    menu: &mut Vec<(RawMenuItem<T>, Vec<usize>)>,
+    path: &MenuPath,
+) -> bool {
+    let Some(index) = menu
+        .iter()
+        .position(|(item, _)| item.path == *path && item.confirm.is_some())
+    else {
+        return false;
+    };
+    let item = &mut menu[index].0;
+    item.disposition = Disposition::Alert;
+    let Some(confirm) = item.confirm.clone() else {
+        return false;
+    };
+    // the choices are not items of the tray, the path of the item is never reported for them
+    let choice = |label, confirmed| RawMenuItem {
+        label,
+        confirm_choice: Some(confirmed),
+        path: path.clone(),
+        ..Default::default()
+    };
+    let mut choices = Vec::new();
+    if !confirm.message.is_empty() {
+        choices.push(RawMenuItem {
+            enabled: false,
+            ..choice(confirm.message, false)
+        });
+    }
+    choices.push(RawMenuItem {
+        disposition: Disposition::Alert,
+        ..choice(confirm.confirm_label, true)
+    });
+    choices.push(choice(confirm.cancel_label, false));
+    for choice in choices {
+        let child = menu.len();
+        menu.push((choice, Vec::new()));
+        menu[index].1.push(child);
+    }
+    true
+}
+
+/// A label computed from the tray, refreshed every `interval`
+///
+/// Useful for relative times like "Synced 3 min ago". The service calls `label` on a timer and
+/// only sends the labels that changed, [`Tray::menu`] is not called for it.
+///
+/// # Examples
+///
+/// ```
+/// # use std::time::{Duration, Instant};
+/// # use ksni::menu::*;
+/// # struct MyTray { last_sync: Instant }
+/// # fn f() -> StandardItem<MyTray> {
+/// StandardItem {
+///     dynamic_label: Some(DynamicLabel::new(Duration::from_secs(60), |this: &MyTray| {
+///         format!("Synced {} min ago", this.last_sync.elapsed().as_secs() / 60)
+///     })),
+///     ..Default::default()
+/// }
+/// # }
+/// ```
+///
+/// [`Tray::menu`]: crate::Tray::menu
+pub struct DynamicLabel<T> {
+    /// How often the label is refreshed, at most every [`DynamicLabel::MIN_INTERVAL`]
+    pub interval: Duration,
+    pub label: Box<dyn Fn(&T) -> String + Send>,
+}
+
+impl<T> DynamicLabel<T> {
+    /// Shorter intervals are raised to this, a zero interval would refresh the menu in a loop
+    pub const MIN_INTERVAL: Duration = Duration::from_millis(100);
+
+    pub fn new(interval: Duration, label: impl Fn(&T) -> String + Send + 'static) -> Self {
+        DynamicLabel {
+            interval,
+            label: Box::new(label),
+        }
+    }
+
+    // the interval actually used
+    pub(crate) fn refresh_interval(&self) -> Duration {
+        self.interval.max(Self::MIN_INTERVAL)
+    }
+}
+
 /// Menu item, a container of another menu tree
 pub struct SubMenu<T> {
     /// Text of the item, except that:
@@ -162,13 +1164,13 @@ pub struct SubMenu<T> {
     /// -# the first of those remaining underscore characters (unless it is
     /// the last character in the string) indicates that the following
     /// character is the access key.
-    pub label: String,
+    pub label: Cow<'static, str>,
     /// Whether the item can be activated or not.
     pub enabled: bool,
     /// True if the item is visible in the menu.
     pub visible: bool,
     /// Icon name of the item, following the freedesktop.org icon spec.
-    pub icon_name: String,
+    pub icon_name: Cow<'static, str>,
     /// PNG data of the icon.
     pub icon_data: Vec<u8>,
     /// The shortcut of the item. Each array represents the key press
@@ -183,19 +1185,22 @@ pub struct SubMenu<T> {
     /// How the menuitem feels the information it's displaying to the
     /// user should be presented.
     pub disposition: Disposition,
+    /// Identifies the item across layout changes, see [`StandardItem::key`]
+    pub key: Option<Cow<'static, str>>,
     pub submenu: Vec<MenuItem<T>>,
 }
 
 impl<T> Default for SubMenu<T> {
     fn default() -> Self {
         Self {
-            label: String::default(),
+            label: Cow::default(),
             enabled: true,
             visible: true,
-            icon_name: String::default(),
+            icon_name: Cow::default(),
             icon_data: Vec::default(),
             shortcut: Vec::default(),
             disposition: Disposition::Normal,
+            key: None,
             submenu: Vec::default(),
         }
     }
@@ -218,7 +1223,8 @@ impl<T> From<SubMenu<T>> for RawMenuItem<T> {
             icon_data: item.icon_data,
             shortcut: item.shortcut,
             disposition: item.disposition,
-            on_clicked: Box::new(move |_this: &mut T, _id| Default::default()),
+            key: item.key,
+            on_clicked: Box::new(move |_this: &mut T, _path| Default::default()),
             ..Default::default()
         }
     }
@@ -233,14 +1239,14 @@ pub struct CheckmarkItem<T> {
     /// -# the first of those remaining underscore characters (unless it is
     /// the last character in the string) indicates that the following
     /// character is the access key.
-    pub label: String,
+    pub label: Cow<'static, str>,
     /// Whether the item can be activated or not.
     pub enabled: bool,
     /// True if the item is visible in the menu.
     pub visible: bool,
     pub checked: bool,
     /// PNG data of the icon.
-    pub icon_name: String,
+    pub icon_name: Cow<'static, str>,
     /// PNG data of the icon.
     pub icon_data: Vec<u8>,
     /// The shortcut of the item. Each array represents the key press
@@ -255,20 +1261,23 @@ pub struct CheckmarkItem<T> {
     /// How the menuitem feels the information it's displaying to the
     /// user should be presented.
     pub disposition: Disposition,
+    /// Identifies the item across layout changes, see [`StandardItem::key`]
+    pub key: Option<Cow<'static, str>>,
     pub activate: Box<dyn Fn(&mut T) + Send>,
 }
 
 impl<T> Default for CheckmarkItem<T> {
     fn default() -> Self {
         CheckmarkItem {
-            label: String::default(),
+            label: Cow::default(),
             enabled: true,
             visible: true,
             checked: false,
-            icon_name: String::default(),
+            icon_name: Cow::default(),
             icon_data: Vec::default(),
             shortcut: Vec::default(),
             disposition: Disposition::Normal,
+            key: None,
             activate: Box::new(|_this| {}),
         }
     }
@@ -298,10 +1307,15 @@ impl<T: 'static> From<CheckmarkItem<T>> for RawMenuItem<T> {
                 ToggleState::Off
             },
             disposition: item.disposition,
-            on_clicked: Box::new(move |this: &mut T, _id| {
+            no_auto_update: false,
+            dynamic_label: None,
+            confirm: None,
+            confirm_choice: None,
+            key: item.key,
+            path: MenuPath::root(),
+            on_clicked: Box::new(move |this: &mut T, _path| {
                 (activate)(this);
             }),
-            ..Default::default()
         }
     }
 }
@@ -309,6 +1323,7 @@ impl<T: 'static> From<CheckmarkItem<T>> for RawMenuItem<T> {
 /// Menu item, contains [`RadioItem`]
 pub struct RadioGroup<T> {
     pub selected: usize,
+    #[allow(clippy::type_complexity)]
     pub select: Box<dyn Fn(&mut T, usize) + Send>,
     pub options: Vec<RadioItem>,
 }
@@ -338,13 +1353,13 @@ pub struct RadioItem {
     /// -# the first of those remaining underscore characters (unless it is
     /// the last character in the string) indicates that the following
     /// character is the access key.
-    pub label: String,
+    pub label: Cow<'static, str>,
     /// Whether the item can be activated or not.
     pub enabled: bool,
     /// True if the item is visible in the menu.
     pub visible: bool,
     /// Icon name of the item, following the freedesktop.org icon spec.
-    pub icon_name: String,
+    pub icon_name: Cow<'static, str>,
     /// PNG data of the icon.
     pub icon_data: Vec<u8>,
     /// The shortcut of the item. Each array represents the key press
@@ -364,10 +1379,10 @@ pub struct RadioItem {
 impl Default for RadioItem {
     fn default() -> Self {
         Self {
-            label: String::default(),
+            label: Cow::default(),
             enabled: true,
             visible: true,
-            icon_name: String::default(),
+            icon_name: Cow::default(),
             icon_data: Vec::default(),
             shortcut: Vec::default(),
             disposition: Disposition::Normal,
@@ -375,7 +1390,11 @@ impl Default for RadioItem {
     }
 }
 
-pub(crate) struct RawMenuItem<T> {
+/// A menu item in the form sent to the host, see [`Tray::raw_menu`](crate::Tray::raw_menu)
+///
+/// Convert it from the other items, [`StandardItem`], [`CheckmarkItem`] and [`SubMenu`] (without
+/// its items), or use [`RawMenuItem::separator`] and [`RawMenuItem::radio`].
+pub struct RawMenuItem<T> {
     r#type: ItemType,
     /// Text of the item, except that:
     /// -# two consecutive underscore characters "__" are displayed as a
@@ -384,13 +1403,13 @@ pub(crate) struct RawMenuItem<T> {
     /// -# the first of those remaining underscore characters (unless it is
     /// the last character in the string) indicates that the following
     /// character is the access key.
-    label: String,
+    label: Cow<'static, str>,
     /// Whether the item can be activated or not.
     enabled: bool,
     /// True if the item is visible in the menu.
     visible: bool,
     /// Icon name of the item, following the freedesktop.org icon spec.
-    icon_name: String,
+    icon_name: Cow<'static, str>,
     /// PNG data of the icon.
     icon_data: Vec<u8>,
     /// The shortcut of the item. Each array represents the key press
@@ -413,29 +1432,48 @@ pub(crate) struct RawMenuItem<T> {
     /// How the menuitem feels the information it's displaying to the
     /// user should be presented.
     disposition: Disposition,
-    pub on_clicked: Box<dyn Fn(&mut T, usize) + Send>,
+    // see `StandardItem::no_auto_update`, not sent to the host
+    pub(crate) no_auto_update: bool,
+    pub(crate) dynamic_label: Option<DynamicLabel<T>>,
+    // see `StandardItem::confirm`, not sent to the host
+    pub(crate) confirm: Option<ConfirmSpec>,
+    // a choice of a shown confirmation, true if it confirms
+    pub(crate) confirm_choice: Option<bool>,
+    // see `StandardItem::key`, not sent to the host
+    key: Option<Cow<'static, str>>,
+    // see `Tray::menu_item_activated`
+    pub(crate) path: MenuPath,
+    #[allow(clippy::type_complexity)]
+    pub(crate) on_clicked: Box<dyn Fn(&mut T, &MenuPath) + Send>,
 }
 
 macro_rules! if_not_default_then_insert {
-    ($map: ident, $item: ident, $default: ident, $filter: ident, $property: ident) => {
-        if_not_default_then_insert!($map, $item, $default, $filter, $property, (|r| r));
+    ($map: ident, $item: ident, $default: ident, $filter: ident, $property: ident, $property_name: literal) => {
+        if_not_default_then_insert!(
+            $map,
+            $item,
+            $default,
+            $filter,
+            $property,
+            $property_name,
+            (|r| r)
+        );
     };
-    ($map: ident, $item: ident, $default: ident, $filter: ident, $property: ident, $to_refarg: tt) => {{
-        let name = stringify!($property).replace('_', "-");
-        if_not_default_then_insert!($map, $item, $default, $filter, $property, name, $to_refarg);
-    }};
-    ($map: ident, $item: ident, $default: ident, $filter: ident, $property: ident, $property_name: tt, $to_refarg: tt) => {
-        if ($filter.is_empty() || $filter.contains(&$property_name.to_string()))
+    ($map: ident, $item: ident, $default: ident, $filter: ident, $property: ident, $property_name: literal, $to_refarg: tt) => {
+        if ($filter.is_empty() || $filter.iter().any(|name| name == $property_name))
             && $item.$property != $default.$property
         {
             $map.insert(
-                $property_name.to_string(),
+                Str::from_static($property_name),
                 OwnedValue::from($to_refarg($item.$property.clone())),
             );
         }
     };
 }
 
+// property names are interned, `GetLayout` repeats them for every item
+pub(crate) type PropertyMap = HashMap<Str<'static>, OwnedValue>;
+
 impl<T> fmt::Debug for RawMenuItem<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "Item {}", self.label)
@@ -443,8 +1481,30 @@ impl<T> fmt::Debug for RawMenuItem<T> {
 }
 
 impl<T> RawMenuItem<T> {
-    pub(crate) fn to_dbus_map(&self, property_filter: &[String]) -> HashMap<String, OwnedValue> {
-        let mut properties: HashMap<String, OwnedValue> = HashMap::with_capacity(11);
+    pub fn separator() -> Self {
+        RawMenuItem {
+            r#type: ItemType::Separator,
+            ..Default::default()
+        }
+    }
+
+    /// Identifies the item across layout changes, see [`StandardItem::key`]
+    pub fn with_key(mut self, key: impl Into<Cow<'static, str>>) -> Self {
+        self.key = Some(key.into());
+        self
+    }
+
+    pub(crate) fn key(&self) -> Option<&str> {
+        self.key.as_deref()
+    }
+
+    pub(crate) fn with_path(mut self, path: MenuPath) -> Self {
+        self.path = path;
+        self
+    }
+
+    pub(crate) fn to_dbus_map(&self, property_filter: &[String]) -> PropertyMap {
+        let mut properties = PropertyMap::with_capacity(11);
 
         let default: RawMenuItem<T> = RawMenuItem::default();
         if_not_default_then_insert!(
@@ -462,17 +1522,33 @@ impl<T> RawMenuItem<T> {
             default,
             property_filter,
             label,
-            (|r: String| -> Str { r.into() })
+            "label",
+            (|r: Cow<'static, str>| -> Str { r.into() })
+        );
+        if_not_default_then_insert!(
+            properties,
+            self,
+            default,
+            property_filter,
+            enabled,
+            "enabled"
+        );
+        if_not_default_then_insert!(
+            properties,
+            self,
+            default,
+            property_filter,
+            visible,
+            "visible"
         );
-        if_not_default_then_insert!(properties, self, default, property_filter, enabled);
-        if_not_default_then_insert!(properties, self, default, property_filter, visible);
         if_not_default_then_insert!(
             properties,
             self,
             default,
             property_filter,
             icon_name,
-            (|r: String| -> Str { r.into() })
+            "icon-name",
+            (|r: Cow<'static, str>| -> Str { r.into() })
         );
         if_not_default_then_insert!(
             properties,
@@ -480,6 +1556,7 @@ impl<T> RawMenuItem<T> {
             default,
             property_filter,
             icon_data,
+            "icon-data",
             (|r: Vec<u8>| -> OwnedValue {
                 Value::from(r)
                     .try_into()
@@ -492,31 +1569,84 @@ impl<T> RawMenuItem<T> {
             default,
             property_filter,
             shortcut,
+            "shortcut",
             (|r: Vec<Vec<String>>| -> OwnedValue {
                 Value::from(r)
                     .try_into()
                     .expect("unreachable: Vec<Vec<String>> to OwnedValue")
             })
         );
-        if_not_default_then_insert!(properties, self, default, property_filter, toggle_type);
-        if_not_default_then_insert!(properties, self, default, property_filter, toggle_state);
-        if_not_default_then_insert!(properties, self, default, property_filter, disposition);
+        if_not_default_then_insert!(
+            properties,
+            self,
+            default,
+            property_filter,
+            toggle_type,
+            "toggle-type"
+        );
+        if_not_default_then_insert!(
+            properties,
+            self,
+            default,
+            property_filter,
+            toggle_state,
+            "toggle-state"
+        );
+        if_not_default_then_insert!(
+            properties,
+            self,
+            default,
+            property_filter,
+            disposition,
+            "disposition"
+        );
 
         properties
     }
 
-    pub(crate) fn diff(&self, other: &Self) -> Option<(HashMap<String, OwnedValue>, Vec<String>)> {
+    #[cfg(any(feature = "global-hotkey", all(test, feature = "tokio")))]
+    pub(crate) fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub(crate) fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    #[cfg(feature = "global-hotkey")]
+    pub(crate) fn shortcut(&self) -> &[Vec<String>] {
+        &self.shortcut
+    }
+
+    /// Evaluate the dynamic label, returns true if the label changed
+    pub(crate) fn refresh_dynamic_label(&mut self, tray: &T) -> bool {
+        let Some(dynamic_label) = &self.dynamic_label else {
+            return false;
+        };
+        let label = (dynamic_label.label)(tray);
+        if label == self.label {
+            return false;
+        }
+        self.label = label.into();
+        true
+    }
+
+    /// Drop `icon_data` bigger than `max` bytes, `icon_name` is kept
+    pub(crate) fn limit_icon_data(&mut self, max: usize) {
+        if self.icon_data.len() > max {
+            self.icon_data = Vec::new();
+        }
+    }
+
+    pub(crate) fn diff(&self, other: &Self) -> Option<(PropertyMap, Vec<Str<'static>>)> {
         let default = Self::default();
-        let mut updated_props: HashMap<String, OwnedValue> = HashMap::new();
+        let mut updated_props = PropertyMap::new();
         let mut removed_props = Vec::new();
         if self.r#type != other.r#type {
             if other.r#type == default.r#type {
                 removed_props.push("type".into());
             } else {
-                updated_props.insert(
-                    "type".into(),
-                    <OwnedValue as From<Str>>::from(other.r#type.to_string().into()),
-                );
+                updated_props.insert("type".into(), OwnedValue::from(other.r#type.clone()));
             }
         }
         if self.label != other.label {
@@ -579,10 +1709,7 @@ impl<T> RawMenuItem<T> {
             if other.toggle_type == default.toggle_type {
                 removed_props.push("toggle-type".into());
             } else {
-                updated_props.insert(
-                    "toggle-type".into(),
-                    <OwnedValue as From<Str>>::from(other.toggle_type.to_string().into()),
-                );
+                updated_props.insert("toggle-type".into(), OwnedValue::from(other.toggle_type));
             }
         }
         if self.toggle_state != other.toggle_state {
@@ -599,10 +1726,7 @@ impl<T> RawMenuItem<T> {
             if other.disposition == default.disposition {
                 removed_props.push("disposition".into());
             } else {
-                updated_props.insert(
-                    "disposition".into(),
-                    <OwnedValue as From<Str>>::from(other.disposition.to_string().into()),
-                );
+                updated_props.insert("disposition".into(), OwnedValue::from(other.disposition));
             }
         }
         if updated_props.is_empty() && removed_props.is_empty() {
@@ -617,17 +1741,56 @@ impl<T> Default for RawMenuItem<T> {
     fn default() -> Self {
         RawMenuItem {
             r#type: ItemType::Standard,
-            label: String::default(),
+            label: Cow::default(),
             enabled: true,
             visible: true,
-            icon_name: String::default(),
+            icon_name: Cow::default(),
             icon_data: Vec::default(),
             shortcut: Vec::default(),
             toggle_type: ToggleType::Null,
             toggle_state: ToggleState::Indeterminate,
             disposition: Disposition::Normal,
+            no_auto_update: false,
+            dynamic_label: None,
+            confirm: None,
+            confirm_choice: None,
+            key: None,
             //submenu: Vec::default(),
-            on_clicked: Box::new(|_this: &mut T, _id| Default::default()),
+            path: MenuPath::root(),
+            on_clicked: Box::new(|_this: &mut T, _path| Default::default()),
+        }
+    }
+}
+
+impl<T: 'static> RawMenuItem<T> {
+    /// An option of a radio group, `on_selected` is called when it's clicked
+    ///
+    /// The group is only a convention of the host, the options are the consecutive radio items
+    /// of a menu. Update `selected` of all options in the next [`Tray::raw_menu`].
+    ///
+    /// [`Tray::raw_menu`]: crate::Tray::raw_menu
+    pub fn radio(
+        option: RadioItem,
+        selected: bool,
+        on_selected: impl Fn(&mut T) + Send + 'static,
+    ) -> Self {
+        RawMenuItem {
+            r#type: ItemType::Standard,
+            label: option.label,
+            enabled: option.enabled,
+            visible: option.visible,
+            icon_name: option.icon_name,
+            icon_data: option.icon_data,
+            shortcut: option.shortcut,
+            toggle_type: ToggleType::Radio,
+            toggle_state: if selected {
+                ToggleState::On
+            } else {
+                ToggleState::Off
+            },
+            disposition: option.disposition,
+            on_clicked: Box::new(move |this: &mut T, _path| on_selected(this)),
+            ..Default::default()
         }
     }
 }
@@ -754,44 +1917,46 @@ pub(crate) fn menu_flatten<T: 'static>(
     let mut list: Vec<(RawMenuItem<T>, Vec<usize>)> =
         vec![(RawMenuItem::default(), Vec::with_capacity(items.len()))];
 
-    let mut stack = vec![(items, 0)]; // (menu, menu's parent)
+    // (menu, menu's parent, path of the menu, position of the first item in the menu)
+    let mut stack = vec![(items, 0, MenuPath::root(), 0)];
 
-    while let Some((mut current_menu, parent_index)) = stack.pop() {
+    while let Some((mut current_menu, parent_index, parent_path, mut position)) = stack.pop() {
         while !current_menu.is_empty() {
+            let path = parent_path.child(position);
+            position += 1;
             match current_menu.remove(0) {
                 MenuItem::Standard(item) => {
                     let index = list.len();
-                    list.push((item.into(), Vec::new()));
+                    list.push((RawMenuItem::from(item).with_path(path), Vec::new()));
                     // Add self to parent's submenu
                     list[parent_index].1.push(index);
                 }
                 MenuItem::Separator => {
-                    let item = RawMenuItem {
-                        r#type: ItemType::Separator,
-                        ..Default::default()
-                    };
+                    let item = RawMenuItem::separator().with_path(path);
                     let index = list.len();
                     list.push((item, Vec::new()));
                     list[parent_index].1.push(index);
                 }
                 MenuItem::Checkmark(item) => {
                     let index = list.len();
-                    list.push((item.into(), Vec::new()));
+                    list.push((RawMenuItem::from(item).with_path(path), Vec::new()));
                     list[parent_index].1.push(index);
                 }
                 MenuItem::SubMenu(mut item) => {
-                    let submenu = std::mem::replace(&mut item.submenu, Default::default());
+                    let submenu = std::mem::take(&mut item.submenu);
                     let index = list.len();
-                    list.push((item.into(), Vec::with_capacity(submenu.len())));
+                    list.push((
+                        RawMenuItem::from(item).with_path(path.clone()),
+                        Vec::with_capacity(submenu.len()),
+                    ));
                     list[parent_index].1.push(index);
                     if !submenu.is_empty() {
-                        stack.push((current_menu, parent_index));
-                        stack.push((submenu, index));
+                        stack.push((current_menu, parent_index, parent_path, position));
+                        stack.push((submenu, index, path, 0));
                         break;
                     }
                 }
                 MenuItem::RadioGroup(group) => {
-                    let offset = list.len();
                     let on_selected = Arc::new(Mutex::new(group.select));
                     for (idx, option) in group.options.into_iter().enumerate() {
                         let on_selected = on_selected.clone();
@@ -810,10 +1975,17 @@ pub(crate) fn menu_flatten<T: 'static>(
                                 ToggleState::Off
                             },
                             disposition: option.disposition,
-                            on_clicked: Box::new(move |this: &mut T, id| {
-                                (on_selected.lock().unwrap())(this, id - offset);
+                            no_auto_update: false,
+                            dynamic_label: None,
+                            confirm: None,
+                            confirm_choice: None,
+                            key: None,
+                            path: path.child(idx),
+                            on_clicked: Box::new(move |this: &mut T, path| {
+                                // the index of the option
+                                let option = path.last().copied().unwrap_or_default();
+                                (on_selected.lock().unwrap())(this, option);
                             }),
-                            ..Default::default()
                         };
                         let index = list.len();
                         list.push((item, Vec::new()));
@@ -827,6 +1999,69 @@ pub(crate) fn menu_flatten<T: 'static>(
     list
 }
 
+// set the paths of a checked raw menu, from the position of the items in their parents
+pub(crate) fn assign_paths<T>(menu: &mut [(RawMenuItem<T>, Vec<usize>)]) {
+    let mut stack = vec![0];
+    while let Some(parent) = stack.pop() {
+        for position in 0..menu[parent].1.len() {
+            let child = menu[parent].1[position];
+            menu[child].0.path = menu[parent].0.path.child(position);
+            stack.push(child);
+        }
+    }
+}
+
+// moves the children after the first `max_children - 1` of every item into a submenu, which is
+// paginated again, see `TrayServiceBuilder::paginate_menu`
+pub(crate) fn paginate<T>(
+    menu: &mut Vec<(RawMenuItem<T>, Vec<usize>)>,
+    max_children: usize,
+    more_label: &str,
+) {
+    // a page needs at least one item besides the next page
+    let max_children = max_children.max(2);
+    let mut index = 0;
+    while index < menu.len() {
+        if menu[index].1.len() > max_children {
+            let rest = menu[index].1.split_off(max_children - 1);
+            let page = RawMenuItem {
+                label: more_label.to_owned().into(),
+                path: menu[index].0.path.clone(),
+                ..Default::default()
+            };
+            let page_index = menu.len();
+            menu.push((page, rest));
+            menu[index].1.push(page_index);
+        }
+        index += 1;
+    }
+}
+
+/// Returns the index of the first item that is out of range, visited twice from the root,
+/// or never visited
+pub(crate) fn check_flattened<T>(menu: &[(RawMenuItem<T>, Vec<usize>)]) -> Result<(), usize> {
+    if menu.is_empty() {
+        return Err(0);
+    }
+    let mut visited = vec![false; menu.len()];
+    visited[0] = true;
+    let mut stack = vec![0];
+    while let Some(index) = stack.pop() {
+        for &child in &menu[index].1 {
+            // a second parent, or a cycle back to an ancestor
+            match visited.get_mut(child) {
+                Some(visited) if !*visited => *visited = true,
+                _ => return Err(child),
+            }
+            stack.push(child);
+        }
+    }
+    match visited.iter().position(|visited| !visited) {
+        Some(orphan) => Err(orphan),
+        None => Ok(()),
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -837,6 +2072,73 @@ mod test {
         assert_eq!(TextDirection::RightToLeft.to_string(), "rtl");
     }
 
+    #[test]
+    fn test_menu_event_data() {
+        let data = |v: Value<'static>| MenuEventData::from(OwnedValue::try_from(v).unwrap());
+        assert_eq!(data(Value::from("")), MenuEventData::None);
+        assert_eq!(data(Value::from(0.5)), MenuEventData::Double(0.5));
+        assert_eq!(data(Value::from(7u32)), MenuEventData::Int(7));
+        assert_eq!(
+            data(Value::Value(Box::new(Value::from(-3i64)))),
+            MenuEventData::Int(-3)
+        );
+        assert!(matches!(
+            data(Value::from(u64::MAX)),
+            MenuEventData::Other(_)
+        ));
+    }
+
+    #[test]
+    fn test_paginate() {
+        let items = (0..5)
+            .map(|i| {
+                StandardItem {
+                    label: i.to_string().into(),
+                    ..Default::default()
+                }
+                .into()
+            })
+            .collect();
+        let mut menu = menu_flatten::<()>(items);
+        paginate(&mut menu, 2, "More");
+        assert_eq!(menu[0].1, [1, 6]);
+        assert_eq!(menu[6].1, [2, 7]);
+        assert_eq!(menu[7].1, [3, 8]);
+        assert_eq!(menu[8].1, [4, 5]);
+        assert_eq!(menu[8].0.label, "More");
+        assert_eq!(menu[5].0.path, MenuPath::from(vec![4]));
+    }
+
+    #[test]
+    fn test_confirm() {
+        let menu: Vec<MenuItem<()>> = vec![StandardItem {
+            label: "Delete".into(),
+            confirm: Some(ConfirmSpec {
+                message: "Sure?".into(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+        .into()];
+        let mut menu = menu_flatten(menu);
+        assert!(menu[1].1.is_empty());
+        assert_eq!(menu[1].0.disposition, Disposition::Normal);
+
+        assert!(show_confirmation(&mut menu, &MenuPath::from(vec![0])));
+        assert_eq!(menu[1].1, [2, 3, 4]);
+        assert_eq!(menu[1].0.disposition, Disposition::Alert);
+        let labels: Vec<_> = menu[2..].iter().map(|(item, _)| &*item.label).collect();
+        assert_eq!(labels, ["Sure?", "Yes", "No"]);
+        assert!(!menu[2].0.enabled);
+        let choices: Vec<_> = menu[2..]
+            .iter()
+            .map(|(item, _)| item.confirm_choice)
+            .collect();
+        assert_eq!(choices, [Some(false), Some(true), Some(false)]);
+
+        assert!(!show_confirmation(&mut menu, &MenuPath::from(vec![1])));
+    }
+
     #[test]
     fn test_menu_flatten() {
         let x: Vec<MenuItem<()>> = vec![
@@ -985,5 +2287,215 @@ mod test {
         assert_eq!(r[7].0.label, expect[7].0.label);
         assert_eq!(r[8].0.label, expect[8].0.label);
         assert_eq!(r[9].0.label, expect[9].0.label);
+        assert!(r[0].0.path.is_root());
+        assert_eq!(r[4].0.path, [0, 1]);
+        assert_eq!(r[9].0.path, [2, 1, 0]);
+    }
+
+    #[test]
+    fn test_apply_group() {
+        let pending = PendingChecks::default();
+        let group = |applied: &[bool]| {
+            ApplyGroup {
+                options: applied
+                    .iter()
+                    .map(|&checked| CheckmarkItem {
+                        checked,
+                        ..Default::default()
+                    })
+                    .collect(),
+                apply: Box::new(|this: &mut Vec<bool>, checked| *this = checked.to_vec()),
+                pending: pending.clone(),
+                ..Default::default()
+            }
+            .into_items()
+        };
+        let click = |items: &[MenuItem<Vec<bool>>], index: usize, this: &mut Vec<bool>| match &items
+            [index]
+        {
+            MenuItem::Checkmark(item) => (item.activate)(this),
+            MenuItem::Standard(item) => (item.activate)(this),
+            _ => unreachable!(),
+        };
+        let checked = |items: &[MenuItem<Vec<bool>>]| -> Vec<bool> {
+            items
+                .iter()
+                .filter_map(|item| match item {
+                    MenuItem::Checkmark(item) => Some(item.checked),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        let mut applied = vec![false, true];
+        let items = group(&applied);
+        click(&items, 0, &mut applied);
+        click(&items, 1, &mut applied);
+        assert_eq!(applied, [false, true]);
+        let items = group(&applied);
+        assert_eq!(checked(&items), [true, false]);
+        // toggled back
+        click(&items, 1, &mut applied);
+        let items = group(&applied);
+        assert_eq!(checked(&items), [true, true]);
+        click(&items, 2, &mut applied);
+        assert_eq!(applied, [true, true]);
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn test_dynamic_label_interval() {
+        let label = |interval| DynamicLabel::new(interval, |_: &()| String::new());
+        assert_eq!(
+            label(Duration::ZERO).refresh_interval(),
+            DynamicLabel::<()>::MIN_INTERVAL
+        );
+        let minute = Duration::from_secs(60);
+        assert_eq!(label(minute).refresh_interval(), minute);
+    }
+
+    #[test]
+    fn test_check_flattened() {
+        let item = |children: &[usize]| (RawMenuItem::<()>::default(), children.to_vec());
+        assert_eq!(
+            check_flattened(&[item(&[1, 2]), item(&[]), item(&[])]),
+            Ok(())
+        );
+        assert_eq!(
+            check_flattened(&[item(&[1]), item(&[2]), item(&[])]),
+            Ok(())
+        );
+        assert_eq!(check_flattened::<()>(&[]), Err(0));
+        assert_eq!(check_flattened(&[item(&[1, 2])]), Err(1));
+        assert_eq!(check_flattened(&[item(&[1]), item(&[0])]), Err(0));
+        assert_eq!(
+            check_flattened(&[item(&[1, 2]), item(&[2]), item(&[])]),
+            Err(2)
+        );
+        // cycle
+        assert_eq!(
+            check_flattened(&[item(&[1]), item(&[2]), item(&[1])]),
+            Err(1)
+        );
+        // orphan, and a cycle that can't be reached from the root
+        assert_eq!(check_flattened(&[item(&[1]), item(&[]), item(&[])]), Err(2));
+        assert_eq!(
+            check_flattened(&[item(&[]), item(&[2]), item(&[1])]),
+            Err(1)
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let menu: Vec<MenuItem<()>> = vec![
+            SubMenu {
+                label: "a".into(),
+                submenu: vec![RadioGroup {
+                    selected: 2,
+                    options: vec![RadioItem::default(), RadioItem::default()],
+                    ..Default::default()
+                }
+                .into()],
+                ..Default::default()
+            }
+            .into(),
+            SubMenu::default().into(),
+            StandardItem {
+                shortcut: vec![vec!["Control".into(), "".into()]],
+                ..Default::default()
+            }
+            .into(),
+        ];
+        assert_eq!(
+            validate(&menu),
+            [
+                MenuWarning::RadioSelectedOutOfRange {
+                    path: vec![0, 0].into(),
+                    selected: 2,
+                    options: 2,
+                },
+                MenuWarning::EmptySubMenu {
+                    path: vec![1].into()
+                },
+                MenuWarning::InvalidShortcut {
+                    path: vec![2].into()
+                },
+            ]
+        );
+    }
+
+    fn radio_group(selected: usize) -> MenuItem<()> {
+        RadioGroup {
+            selected,
+            options: vec![RadioItem::default(), RadioItem::default()],
+            ..Default::default()
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_validate_multiple_radio_selected() {
+        // adjacent groups are a single group on the host
+        let menu = vec![
+            radio_group(0),
+            radio_group(1),
+            MenuItem::Separator,
+            radio_group(0),
+        ];
+        assert_eq!(
+            validate(&menu),
+            [MenuWarning::MultipleRadioSelected {
+                path: vec![1, 1].into()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_validate_checkmark_in_radio_group() {
+        let menu = vec![
+            radio_group(0),
+            CheckmarkItem::default().into(),
+            radio_group(1),
+            CheckmarkItem::default().into(),
+        ];
+        // the checkmark doesn't split the group
+        assert_eq!(
+            validate(&menu),
+            [
+                MenuWarning::CheckmarkInRadioGroup {
+                    path: vec![1].into()
+                },
+                MenuWarning::MultipleRadioSelected {
+                    path: vec![2, 1].into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_duplicate_key() {
+        let menu: Vec<MenuItem<()>> = vec![
+            StandardItem {
+                key: Some("open".into()),
+                ..Default::default()
+            }
+            .into(),
+            SubMenu {
+                submenu: vec![CheckmarkItem {
+                    key: Some("open".into()),
+                    ..Default::default()
+                }
+                .into()],
+                ..Default::default()
+            }
+            .into(),
+        ];
+        assert_eq!(
+            validate(&menu),
+            [MenuWarning::DuplicateKey {
+                path: vec![1, 0].into(),
+                key: "open".into(),
+            }]
+        );
     }
 }
@@ -0,0 +1,304 @@
+//! Menus loaded from a JSON file, reloaded when the file changes
+//!
+//! The file describes the structure of the menu, the behaviors are registered by name in
+//! [`MenuFile`]
+//!
+//! ```json
+//! [
+//!     { "type": "standard", "label": "Open", "icon_name": "document-open", "action": "open" },
+//!     { "type": "checkmark", "label": "Mute", "action": "mute" },
+//!     { "type": "separator" },
+//!     { "type": "sub_menu", "label": "More", "submenu": [
+//!         { "type": "standard", "label": "Exit", "action": "exit" }
+//!     ] }
+//! ]
+//! ```
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use ksni::menu_file::MenuFile;
+//! use ksni::TrayMethods;
+//!
+//! struct MyTray {
+//!     muted: bool,
+//!     menu: MenuFile<Self>,
+//! }
+//!
+//! impl ksni::Tray for MyTray {
+//!     fn id(&self) -> String {
+//!         "my-tray".into()
+//!     }
+//!     fn menu(&self) -> Vec<ksni::MenuItem<Self>> {
+//!         self.menu.menu(self)
+//!     }
+//! }
+//!
+//! # async fn f() -> Result<(), Box<dyn std::error::Error>> {
+//! let menu = MenuFile::load("menu.json")?
+//!     .action("exit", |_: &mut MyTray| std::process::exit(0))
+//!     .toggle("mute", |this: &MyTray| this.muted, |this: &mut MyTray| {
+//!         this.muted = !this.muted
+//!     });
+//! let watcher = menu.watcher();
+//! let handle = MyTray { muted: false, menu }.spawn().await?;
+//! watcher.watch(handle, std::time::Duration::from_secs(1));
+//! # Ok(()) }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use serde::Deserialize;
+
+use crate::compat;
+use crate::menu::{CheckmarkItem, StandardItem, SubMenu};
+use crate::{Handle, MenuItem};
+
+/// An item in the menu file
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum MenuEntry {
+    Standard {
+        label: String,
+        #[serde(default)]
+        icon_name: String,
+        /// Name of the action registered with [`MenuFile::action`]
+        #[serde(default)]
+        action: Option<String>,
+    },
+    Checkmark {
+        label: String,
+        #[serde(default)]
+        icon_name: String,
+        /// Name of the toggle registered with [`MenuFile::toggle`]
+        action: String,
+    },
+    Separator,
+    SubMenu {
+        label: String,
+        #[serde(default)]
+        icon_name: String,
+        submenu: Vec<MenuEntry>,
+    },
+}
+
+/// Error while loading a menu file
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Failed to read the file
+    Io(std::io::Error),
+    /// The file is not a valid menu
+    Parse(serde_json::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Error::*;
+        match self {
+            Io(e) => write!(f, "failed to read the menu file: {e}"),
+            Parse(e) => write!(f, "invalid menu file: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        use Error::*;
+        match self {
+            Io(e) => Some(e),
+            Parse(e) => Some(e),
+        }
+    }
+}
+
+type Action<T> = Arc<dyn Fn(&mut T) + Send + Sync>;
+type Getter<T> = Arc<dyn Fn(&T) -> bool + Send + Sync>;
+
+/// A menu described by a file, see the [module level documentation](self)
+pub struct MenuFile<T> {
+    entries: Arc<RwLock<Vec<MenuEntry>>>,
+    path: PathBuf,
+    actions: HashMap<String, Action<T>>,
+    toggles: HashMap<String, (Getter<T>, Action<T>)>,
+}
+
+impl<T: 'static> MenuFile<T> {
+    /// Load the menu from a JSON file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref().to_owned();
+        let entries = read(&path)?;
+        Ok(MenuFile {
+            entries: Arc::new(RwLock::new(entries)),
+            path,
+            actions: HashMap::new(),
+            toggles: HashMap::new(),
+        })
+    }
+
+    /// Register the action of standard items
+    pub fn action(mut self, name: &str, f: impl Fn(&mut T) + Send + Sync + 'static) -> Self {
+        self.actions.insert(name.to_owned(), Arc::new(f));
+        self
+    }
+
+    /// Register the state and the action of checkmark items
+    pub fn toggle(
+        mut self,
+        name: &str,
+        checked: impl Fn(&T) -> bool + Send + Sync + 'static,
+        f: impl Fn(&mut T) + Send + Sync + 'static,
+    ) -> Self {
+        self.toggles
+            .insert(name.to_owned(), (Arc::new(checked), Arc::new(f)));
+        self
+    }
+
+    /// Build the menu, call this in [`Tray::menu`]
+    ///
+    /// Items with an unknown action are disabled
+    ///
+    /// [`Tray::menu`]: crate::Tray::menu
+    pub fn menu(&self, tray: &T) -> Vec<MenuItem<T>> {
+        let entries = self.entries.read().unwrap_or_else(|e| e.into_inner());
+        self.build(tray, &entries)
+    }
+
+    fn build(&self, tray: &T, entries: &[MenuEntry]) -> Vec<MenuItem<T>> {
+        entries
+            .iter()
+            .map(|entry| match entry {
+                MenuEntry::Standard {
+                    label,
+                    icon_name,
+                    action,
+                } => {
+                    let action = action.as_ref().map(|name| self.actions.get(name).cloned());
+                    StandardItem {
+                        label: label.clone().into(),
+                        icon_name: icon_name.clone().into(),
+                        enabled: !matches!(action, Some(None)),
+                        activate: match action.flatten() {
+                            Some(f) => Box::new(move |this: &mut T| f(this)),
+                            None => Box::new(|_: &mut T| {}),
+                        },
+                        ..Default::default()
+                    }
+                    .into()
+                }
+                MenuEntry::Checkmark {
+                    label,
+                    icon_name,
+                    action,
+                } => match self.toggles.get(action) {
+                    Some((checked, f)) => {
+                        let f = f.clone();
+                        CheckmarkItem {
+                            label: label.clone().into(),
+                            icon_name: icon_name.clone().into(),
+                            checked: checked(tray),
+                            activate: Box::new(move |this: &mut T| f(this)),
+                            ..Default::default()
+                        }
+                        .into()
+                    }
+                    None => CheckmarkItem {
+                        label: label.clone().into(),
+                        icon_name: icon_name.clone().into(),
+                        enabled: false,
+                        ..Default::default()
+                    }
+                    .into(),
+                },
+                MenuEntry::Separator => MenuItem::Separator,
+                MenuEntry::SubMenu {
+                    label,
+                    icon_name,
+                    submenu,
+                } => SubMenu {
+                    label: label.clone().into(),
+                    icon_name: icon_name.clone().into(),
+                    submenu: self.build(tray, submenu),
+                    ..Default::default()
+                }
+                .into(),
+            })
+            .collect()
+    }
+
+    /// Returns a [`MenuFileWatcher`] that reloads this menu
+    pub fn watcher(&self) -> MenuFileWatcher {
+        MenuFileWatcher {
+            entries: self.entries.clone(),
+            path: self.path.clone(),
+        }
+    }
+}
+
+/// Reloads a [`MenuFile`] when the file changes
+pub struct MenuFileWatcher {
+    entries: Arc<RwLock<Vec<MenuEntry>>>,
+    path: PathBuf,
+}
+
+impl MenuFileWatcher {
+    /// Check the modification time of the file every `interval`, reload the menu and update
+    /// the tray when it changes
+    ///
+    /// Invalid files are ignored, the previous menu is kept. The watcher stops after the tray
+    /// service is shutdown.
+    ///
+    /// Must be called in the context of the async runtime
+    pub fn watch<T: Send + 'static>(self, handle: Handle<T>, interval: Duration) {
+        compat::spawn(async move {
+            let mut last_modified = modified(&self.path);
+            while !handle.is_closed() {
+                compat::sleep(interval).await;
+                let current = modified(&self.path);
+                if current == last_modified {
+                    continue;
+                }
+                last_modified = current;
+                if let Ok(entries) = read(&self.path) {
+                    *self.entries.write().unwrap_or_else(|e| e.into_inner()) = entries;
+                    handle.update(|_| ()).await;
+                }
+            }
+        });
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn read(path: &Path) -> Result<Vec<MenuEntry>, Error> {
+    let data = std::fs::read(path).map_err(Error::Io)?;
+    serde_json::from_slice(&data).map_err(Error::Parse)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let entries: Vec<MenuEntry> = serde_json::from_str(
+            r#"[
+                { "type": "standard", "label": "a", "action": "a" },
+                { "type": "separator" },
+                { "type": "sub_menu", "label": "b", "submenu": [
+                    { "type": "checkmark", "label": "b1", "action": "b1" }
+                ] }
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(entries.len(), 3);
+        assert!(matches!(&entries[2], MenuEntry::SubMenu { submenu, .. } if submenu.len() == 1));
+    }
+}
@@ -0,0 +1,95 @@
+//! Desktop notifications for [`Status::NeedsAttention`], see [`Tray::attention_notification`]
+//!
+//! Some hosts hide tray icons, or only show them in an overflow menu. A notification makes sure
+//! the user sees that the tray needs attention. It's sent with `org.freedesktop.Notifications`
+//! when the status becomes [`Status::NeedsAttention`], and closed when it changes again or the
+//! tray is shut down.
+//!
+//! See https://specifications.freedesktop.org/notification-spec/latest/
+//!
+//! [`Status::NeedsAttention`]: crate::Status::NeedsAttention
+//! [`Tray::attention_notification`]: crate::Tray::attention_notification
+
+use std::collections::HashMap;
+
+use zbus::zvariant::Value;
+use zbus::Connection;
+
+use crate::AttentionLevel;
+
+/// A notification, see [`Tray::attention_notification`](crate::Tray::attention_notification)
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Notification {
+    pub summary: String,
+    /// May contain the markup described by the specification, e.g. `<b>`
+    pub body: String,
+    /// A Freedesktop-compliant icon name, or empty
+    pub icon_name: String,
+    pub urgency: Urgency,
+}
+
+/// How urgent a [`Notification`] is, servers may keep critical ones until they are closed
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Urgency {
+    Low,
+    #[default]
+    Normal,
+    Critical,
+}
+
+impl From<AttentionLevel> for Urgency {
+    fn from(level: AttentionLevel) -> Self {
+        match level {
+            AttentionLevel::Critical => Urgency::Critical,
+            _ => Urgency::Normal,
+        }
+    }
+}
+
+async fn notifications(conn: &Connection) -> zbus::Result<zbus::Proxy<'static>> {
+    zbus::Proxy::new(
+        conn,
+        "org.freedesktop.Notifications",
+        "/org/freedesktop/Notifications",
+        "org.freedesktop.Notifications",
+    )
+    .await
+}
+
+/// Shows the notification, returns its id
+pub(crate) async fn show(
+    conn: &Connection,
+    app_name: &str,
+    desktop_entry: Option<&str>,
+    notification: &Notification,
+) -> zbus::Result<u32> {
+    let mut hints: HashMap<&str, Value> = HashMap::new();
+    hints.insert("urgency", Value::U8(notification.urgency as u8));
+    if let Some(desktop_entry) = desktop_entry {
+        hints.insert("desktop-entry", Value::from(desktop_entry));
+    }
+    let actions: &[&str] = &[];
+    notifications(conn)
+        .await?
+        .call(
+            "Notify",
+            &(
+                app_name,
+                0u32,
+                &notification.icon_name,
+                &notification.summary,
+                &notification.body,
+                actions,
+                hints,
+                -1i32,
+            ),
+        )
+        .await
+}
+
+pub(crate) async fn close(conn: &Connection, id: u32) -> zbus::Result<()> {
+    notifications(conn)
+        .await?
+        .call("CloseNotification", &(id,))
+        .await
+}
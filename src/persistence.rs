@@ -0,0 +1,179 @@
+//! Remember the states of checkmarks and radio groups across restarts
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use ksni::menu::*;
+//! use ksni::persistence::StatePersistence;
+//!
+//! struct MyTray {
+//!     state: StatePersistence,
+//! }
+//!
+//! impl ksni::Tray for MyTray {
+//!     fn id(&self) -> String {
+//!         "my-tray".into()
+//!     }
+//!     fn menu(&self) -> Vec<ksni::MenuItem<Self>> {
+//!         vec![CheckmarkItem {
+//!             label: "Mute".into(),
+//!             checked: self.state.checked("mute", false),
+//!             activate: Box::new(|this: &mut Self| {
+//!                 let muted = this.state.checked("mute", false);
+//!                 let _ = this.state.set_checked("mute", !muted);
+//!             }),
+//!             ..Default::default()
+//!         }
+//!         .into()]
+//!     }
+//! }
+//!
+//! let tray = MyTray {
+//!     state: StatePersistence::file("/home/me/.local/state/my-tray/menu"),
+//! };
+//! ```
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::PathBuf;
+
+/// Where [`StatePersistence`] keeps the states
+pub trait StateStore: Send + 'static {
+    /// Read the saved states, called once when creating the [`StatePersistence`]
+    fn load(&mut self) -> io::Result<BTreeMap<String, String>>;
+    /// Write all states, called after every change
+    fn save(&mut self, state: &BTreeMap<String, String>) -> io::Result<()>;
+}
+
+/// A [`StateStore`] saving to a file, one `key=value` per line
+///
+/// A missing file is treated as empty, the parent directories are created when saving. The
+/// file is replaced atomically, a crash while saving keeps the previous states.
+pub struct FileStore {
+    path: PathBuf,
+}
+
+impl FileStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FileStore { path: path.into() }
+    }
+}
+
+impl StateStore for FileStore {
+    fn load(&mut self) -> io::Result<BTreeMap<String, String>> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(s) => Ok(parse(&s)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn save(&mut self, state: &BTreeMap<String, String>) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // renamed over the file, on the same file system
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".tmp");
+        std::fs::write(&temp, serialize(state))?;
+        std::fs::rename(&temp, &self.path)
+    }
+}
+
+fn parse(s: &str) -> BTreeMap<String, String> {
+    s.lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(k, v)| (k.to_owned(), v.to_owned()))
+        .collect()
+}
+
+fn serialize(state: &BTreeMap<String, String>) -> String {
+    state.iter().map(|(k, v)| format!("{k}={v}\n")).collect()
+}
+
+/// Saves the states of togglable menu items on change and restores them on creation
+///
+/// Keys must not contain `=` or newlines if used with [`FileStore`]. See the
+/// [module level documentation](self)
+pub struct StatePersistence {
+    store: Box<dyn StateStore>,
+    state: BTreeMap<String, String>,
+}
+
+impl StatePersistence {
+    /// Load the states from `store`, starts empty if it can not be read
+    pub fn new(store: impl StateStore) -> Self {
+        let mut store = store;
+        let state = store.load().unwrap_or_default();
+        StatePersistence {
+            store: Box::new(store),
+            state,
+        }
+    }
+
+    /// Shorthand of `StatePersistence::new(FileStore::new(path))`
+    pub fn file(path: impl Into<PathBuf>) -> Self {
+        Self::new(FileStore::new(path))
+    }
+
+    /// The saved state of a checkmark, or `default` if never saved
+    pub fn checked(&self, key: &str, default: bool) -> bool {
+        self.get(key).unwrap_or(default)
+    }
+
+    /// Update the state of a checkmark, saves it if changed
+    pub fn set_checked(&mut self, key: &str, checked: bool) -> io::Result<()> {
+        self.set(key, checked)
+    }
+
+    /// The saved selection of a radio group, or `default` if never saved
+    pub fn selected(&self, key: &str, default: usize) -> usize {
+        self.get(key).unwrap_or(default)
+    }
+
+    /// Update the selection of a radio group, saves it if changed
+    pub fn set_selected(&mut self, key: &str, selected: usize) -> io::Result<()> {
+        self.set(key, selected)
+    }
+
+    fn get<V: std::str::FromStr>(&self, key: &str) -> Option<V> {
+        self.state.get(key)?.parse().ok()
+    }
+
+    fn set(&mut self, key: &str, value: impl ToString) -> io::Result<()> {
+        let value = value.to_string();
+        if self.state.get(key) == Some(&value) {
+            return Ok(());
+        }
+        self.state.insert(key.to_owned(), value);
+        self.store.save(&self.state)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let mut state = BTreeMap::new();
+        state.insert("mute".to_owned(), "true".to_owned());
+        state.insert("mode".to_owned(), "2".to_owned());
+        assert_eq!(parse(&serialize(&state)), state);
+    }
+
+    #[test]
+    fn test_file_store() {
+        let dir = std::env::temp_dir().join(format!("ksni-persistence-{}", std::process::id()));
+        let path = dir.join("menu");
+        let mut state = BTreeMap::new();
+        state.insert("mute".to_owned(), "true".to_owned());
+        FileStore::new(&path).save(&state).unwrap();
+        state.insert("mute".to_owned(), "false".to_owned());
+        FileStore::new(&path).save(&state).unwrap();
+        assert_eq!(FileStore::new(&path).load().unwrap(), state);
+        // only the file itself is left
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
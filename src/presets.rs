@@ -0,0 +1,549 @@
+//! Ready-made trays for common indicators
+//!
+//! Each preset is a [`Tray`] of a battery, network or volume indicator, with the usual icons,
+//! tooltip and menu. The actions are left to callbacks, and the state is changed with
+//! [`Handle::update`](crate::Handle::update).
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use ksni::presets::BatteryTray;
+//! use ksni::TrayMethods;
+//!
+//! # async fn f() -> Result<(), ksni::Error> {
+//! let tray = BatteryTray::new("my-battery").on_settings(|| {
+//!     let _ = std::process::Command::new("gnome-power-statistics").spawn();
+//! });
+//! let handle = tray.spawn().await?;
+//! handle.update(|tray| tray.set_level(42, true)).await;
+//! # Ok(()) }
+//! ```
+
+use crate::menu::{CheckmarkItem, RadioGroup, RadioItem, StandardItem, SubMenu};
+use crate::{AttentionLevel, Icon, MenuItem, Orientation, Status, ToolTip, Tray};
+
+type Callback<A> = Box<dyn Fn(A) + Send>;
+
+/// A battery indicator, with the level as a badge over the icon
+///
+/// Below the low level, the tray needs attention, see [`AttentionLevel`].
+pub struct BatteryTray {
+    id: String,
+    percentage: u8,
+    charging: bool,
+    low: u8,
+    critical: u8,
+    on_settings: Option<Callback<()>>,
+}
+
+impl BatteryTray {
+    pub fn new(id: impl Into<String>) -> Self {
+        BatteryTray {
+            id: id.into(),
+            percentage: 100,
+            charging: false,
+            low: 20,
+            critical: 5,
+            on_settings: None,
+        }
+    }
+
+    /// Levels below which the tray shows a warning and a critical state
+    ///
+    /// Default is 20 and 5
+    pub fn thresholds(mut self, low: u8, critical: u8) -> Self {
+        self.low = low;
+        self.critical = critical;
+        self
+    }
+
+    /// Add a "Power Settings" item to the menu calling `f`
+    pub fn on_settings(mut self, f: impl Fn() + Send + 'static) -> Self {
+        self.on_settings = Some(Box::new(move |()| f()));
+        self
+    }
+
+    /// Set the level in percent (clamped to 100) and whether the battery is charging
+    pub fn set_level(&mut self, percentage: u8, charging: bool) {
+        self.percentage = percentage.min(100);
+        self.charging = charging;
+    }
+
+    pub fn percentage(&self) -> u8 {
+        self.percentage
+    }
+
+    pub fn charging(&self) -> bool {
+        self.charging
+    }
+}
+
+impl Tray for BatteryTray {
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    fn category(&self) -> crate::Category {
+        crate::Category::Hardware
+    }
+
+    fn title(&self) -> String {
+        "Battery".into()
+    }
+
+    fn status(&self) -> Status {
+        if !self.charging && self.percentage < self.low {
+            Status::NeedsAttention
+        } else {
+            Status::Active
+        }
+    }
+
+    fn icon_name(&self) -> String {
+        // the level icons of the freedesktop themes go in steps of 10
+        let level = (self.percentage + 5) / 10 * 10;
+        let charging = if self.charging { "-charging" } else { "" };
+        format!("battery-level-{level}{charging}-symbolic")
+    }
+
+    fn overlay_icon_pixmap(&self) -> Vec<Icon> {
+        vec![number_badge(self.percentage.into())]
+    }
+
+    fn attention_level(&self) -> AttentionLevel {
+        if self.percentage < self.critical {
+            AttentionLevel::Critical
+        } else {
+            AttentionLevel::Warning
+        }
+    }
+
+    fn attention_icon_name(&self) -> String {
+        "battery-caution-symbolic".into()
+    }
+
+    fn attention_level_icon_name(&self, level: AttentionLevel) -> String {
+        match level {
+            AttentionLevel::Critical => "battery-empty-symbolic".into(),
+            _ => Default::default(),
+        }
+    }
+
+    fn tool_tip(&self) -> ToolTip {
+        let state = if self.charging { ", charging" } else { "" };
+        ToolTip {
+            title: format!("Battery {}%{state}", self.percentage),
+            ..Default::default()
+        }
+    }
+
+    fn menu(&self) -> Vec<MenuItem<Self>> {
+        match self.on_settings {
+            Some(_) => vec![StandardItem {
+                label: "Power Settings".into(),
+                icon_name: "preferences-system-power".into(),
+                activate: Box::new(|this: &mut Self| {
+                    if let Some(f) = &this.on_settings {
+                        f(())
+                    }
+                }),
+                ..Default::default()
+            }
+            .into()],
+            None => Vec::new(),
+        }
+    }
+}
+
+/// A network shown by [`NetworkTray`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Network {
+    pub name: String,
+    /// Signal strength in percent
+    pub strength: u8,
+    pub secured: bool,
+}
+
+/// A network indicator, with a submenu of the available networks
+///
+/// Clicking a network calls the [`on_connect`](Self::on_connect) callback with its name, the
+/// tray only shows it as connected after [`set_connected`](Self::set_connected).
+pub struct NetworkTray {
+    id: String,
+    networks: Vec<Network>,
+    connected: Option<String>,
+    on_connect: Option<Callback<String>>,
+    on_disconnect: Option<Callback<()>>,
+}
+
+impl NetworkTray {
+    pub fn new(id: impl Into<String>) -> Self {
+        NetworkTray {
+            id: id.into(),
+            networks: Vec::new(),
+            connected: None,
+            on_connect: None,
+            on_disconnect: None,
+        }
+    }
+
+    /// Called with the name of the network clicked in the menu
+    pub fn on_connect(mut self, f: impl Fn(String) + Send + 'static) -> Self {
+        self.on_connect = Some(Box::new(f));
+        self
+    }
+
+    /// Add a "Disconnect" item to the menu calling `f` while connected
+    pub fn on_disconnect(mut self, f: impl Fn() + Send + 'static) -> Self {
+        self.on_disconnect = Some(Box::new(move |()| f()));
+        self
+    }
+
+    /// Replace the available networks, shown in the order given
+    pub fn set_networks(&mut self, networks: Vec<Network>) {
+        self.networks = networks;
+    }
+
+    /// Set the name of the connected network, `None` if disconnected
+    pub fn set_connected(&mut self, name: Option<String>) {
+        self.connected = name;
+    }
+
+    pub fn networks(&self) -> &[Network] {
+        &self.networks
+    }
+
+    pub fn connected(&self) -> Option<&Network> {
+        let name = self.connected.as_ref()?;
+        self.networks.iter().find(|network| &network.name == name)
+    }
+}
+
+impl Tray for NetworkTray {
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    fn category(&self) -> crate::Category {
+        crate::Category::Hardware
+    }
+
+    fn title(&self) -> String {
+        "Network".into()
+    }
+
+    fn icon_name(&self) -> String {
+        if self.connected.is_none() {
+            return "network-wireless-offline-symbolic".into();
+        }
+        // a connected network missing from the list is shown with a full signal
+        let strength = self.connected().map_or(100, |network| network.strength);
+        let signal = match strength {
+            80.. => "excellent",
+            55..=79 => "good",
+            30..=54 => "ok",
+            5..=29 => "weak",
+            _ => "none",
+        };
+        format!("network-wireless-signal-{signal}-symbolic")
+    }
+
+    fn tool_tip(&self) -> ToolTip {
+        ToolTip {
+            title: match &self.connected {
+                Some(name) => format!("Connected to {name}"),
+                None => "Disconnected".into(),
+            },
+            ..Default::default()
+        }
+    }
+
+    fn menu(&self) -> Vec<MenuItem<Self>> {
+        let networks = self
+            .networks
+            .iter()
+            .map(|network| {
+                let name = network.name.clone();
+                let lock = if network.secured { " 🔒" } else { "" };
+                CheckmarkItem {
+                    // underscores are access keys
+                    label: format!("{} ({}%){lock}", name.replace('_', "__"), network.strength)
+                        .into(),
+                    checked: self.connected.as_ref() == Some(&network.name),
+                    activate: Box::new(move |this: &mut Self| {
+                        if let Some(f) = &this.on_connect {
+                            f(name.clone())
+                        }
+                    }),
+                    ..Default::default()
+                }
+                .into()
+            })
+            .collect::<Vec<_>>();
+        let mut menu = vec![SubMenu {
+            label: "Networks".into(),
+            enabled: !networks.is_empty(),
+            submenu: networks,
+            ..Default::default()
+        }
+        .into()];
+        if self.connected.is_some() && self.on_disconnect.is_some() {
+            menu.push(MenuItem::Separator);
+            menu.push(
+                StandardItem {
+                    label: "Disconnect".into(),
+                    activate: Box::new(|this: &mut Self| {
+                        if let Some(f) = &this.on_disconnect {
+                            f(())
+                        }
+                    }),
+                    ..Default::default()
+                }
+                .into(),
+            );
+        }
+        menu
+    }
+}
+
+/// A volume indicator, clicking mutes and scrolling changes the volume
+///
+/// The menu has a mute toggle, and volume levels as a stand-in for a slider. Every change calls
+/// the [`on_change`](Self::on_change) callback with the new volume and mute state.
+pub struct VolumeTray {
+    id: String,
+    volume: u8,
+    muted: bool,
+    step: u8,
+    on_change: Option<Callback<(u8, bool)>>,
+}
+
+// the levels of the menu
+const VOLUME_LEVELS: [u8; 5] = [0, 25, 50, 75, 100];
+
+impl VolumeTray {
+    pub fn new(id: impl Into<String>) -> Self {
+        VolumeTray {
+            id: id.into(),
+            volume: 50,
+            muted: false,
+            step: 5,
+            on_change: None,
+        }
+    }
+
+    /// Volume change of a scroll step in percent
+    ///
+    /// Default is 5
+    pub fn step(mut self, step: u8) -> Self {
+        self.step = step;
+        self
+    }
+
+    /// Called with the volume in percent and the mute state after they are changed from the tray
+    pub fn on_change(mut self, f: impl Fn(u8, bool) + Send + 'static) -> Self {
+        self.on_change = Some(Box::new(move |(volume, muted)| f(volume, muted)));
+        self
+    }
+
+    /// Set the volume in percent (clamped to 100) and the mute state, doesn't call `on_change`
+    pub fn set_volume(&mut self, volume: u8, muted: bool) {
+        self.volume = volume.min(100);
+        self.muted = muted;
+    }
+
+    pub fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    pub fn muted(&self) -> bool {
+        self.muted
+    }
+
+    fn change(&mut self, volume: u8, muted: bool) {
+        self.set_volume(volume, muted);
+        if let Some(f) = &self.on_change {
+            f((self.volume, self.muted))
+        }
+    }
+}
+
+impl Tray for VolumeTray {
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    fn category(&self) -> crate::Category {
+        crate::Category::Hardware
+    }
+
+    fn title(&self) -> String {
+        "Volume".into()
+    }
+
+    fn activate(&mut self, _x: i32, _y: i32) {
+        self.change(self.volume, !self.muted);
+    }
+
+    fn scroll(&mut self, delta: i32, orientation: Orientation) {
+        if orientation != Orientation::Vertical || delta == 0 {
+            return;
+        }
+        let volume = if delta > 0 {
+            self.volume.saturating_add(self.step)
+        } else {
+            self.volume.saturating_sub(self.step)
+        };
+        self.change(volume, false);
+    }
+
+    fn icon_name(&self) -> String {
+        let level = match self.volume {
+            _ if self.muted => "muted",
+            0 => "muted",
+            1..=33 => "low",
+            34..=66 => "medium",
+            _ => "high",
+        };
+        format!("audio-volume-{level}-symbolic")
+    }
+
+    fn tool_tip(&self) -> ToolTip {
+        let muted = if self.muted { " (muted)" } else { "" };
+        ToolTip {
+            title: format!("Volume {}%{muted}", self.volume),
+            ..Default::default()
+        }
+    }
+
+    fn menu(&self) -> Vec<MenuItem<Self>> {
+        let selected = VOLUME_LEVELS
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, level)| level.abs_diff(self.volume))
+            .map_or(0, |(index, _)| index);
+        vec![
+            CheckmarkItem {
+                label: "Mute".into(),
+                checked: self.muted,
+                activate: Box::new(|this: &mut Self| this.change(this.volume, !this.muted)),
+                ..Default::default()
+            }
+            .into(),
+            MenuItem::Separator,
+            RadioGroup {
+                selected,
+                select: Box::new(|this: &mut Self, index| this.change(VOLUME_LEVELS[index], false)),
+                options: VOLUME_LEVELS
+                    .iter()
+                    .map(|level| RadioItem {
+                        label: format!("{level}%").into(),
+                        ..Default::default()
+                    })
+                    .collect(),
+            }
+            .into(),
+        ]
+    }
+}
+
+// 3x5 pixel digits, a row per byte from the top, the lowest 3 bits are the pixels
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b001, 0b001, 0b001],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+/// A 32x32 icon with `number` in white on a dark badge at the bottom right, e.g. for
+/// [`Tray::overlay_icon_pixmap`]
+///
+/// Numbers above 999 are shown as 999.
+pub fn number_badge(number: u32) -> Icon {
+    const SIZE: usize = 32;
+    // every pixel of the digits is 2x2
+    const SCALE: usize = 2;
+    const PADDING: usize = 2;
+
+    let digits: Vec<usize> = number
+        .min(999)
+        .to_string()
+        .bytes()
+        .map(|digit| (digit - b'0') as usize)
+        .collect();
+    let text_width = (digits.len() * 4 - 1) * SCALE;
+    let text_height = 5 * SCALE;
+    let badge_width = text_width + PADDING * 2;
+    let badge_height = text_height + PADDING * 2;
+    let (left, top) = (SIZE - badge_width, SIZE - badge_height);
+
+    let mut data = vec![0; SIZE * SIZE * 4];
+    let mut fill = |x: usize, y: usize, argb: [u8; 4]| {
+        let offset = (y * SIZE + x) * 4;
+        data[offset..offset + 4].copy_from_slice(&argb);
+    };
+    for y in top..SIZE {
+        for x in left..SIZE {
+            fill(x, y, [0xe0, 0x20, 0x20, 0x20]);
+        }
+    }
+    for (position, &digit) in digits.iter().enumerate() {
+        for (row, bits) in DIGITS[digit].iter().enumerate() {
+            for column in 0..3 {
+                if bits & (0b100 >> column) == 0 {
+                    continue;
+                }
+                let x = left + PADDING + (position * 4 + column) * SCALE;
+                let y = top + PADDING + row * SCALE;
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    fill(x + dx, y + dy, [0xff; 4]);
+                }
+            }
+        }
+    }
+    Icon {
+        width: SIZE as i32,
+        height: SIZE as i32,
+        data,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_number_badge() {
+        let badge = number_badge(7);
+        assert_eq!(badge.data.len(), 32 * 32 * 4);
+        // the top left pixel of "7" at (32 - 10 + 2, 32 - 14 + 2)
+        let offset = (20 * 32 + 24) * 4;
+        assert_eq!(badge.data[offset..offset + 4], [0xff; 4]);
+        // outside the badge
+        assert_eq!(badge.data[..4], [0; 4]);
+        assert_eq!(number_badge(12345).data, number_badge(999).data);
+    }
+
+    #[test]
+    fn test_icon_names() {
+        let mut battery = BatteryTray::new("battery");
+        battery.set_level(44, true);
+        assert_eq!(battery.icon_name(), "battery-level-40-charging-symbolic");
+        battery.set_level(3, false);
+        assert_eq!(battery.status(), Status::NeedsAttention);
+        assert_eq!(battery.attention_level(), AttentionLevel::Critical);
+
+        let mut volume = VolumeTray::new("volume");
+        volume.scroll(1, Orientation::Vertical);
+        assert_eq!(volume.volume(), 55);
+        assert_eq!(volume.icon_name(), "audio-volume-medium-symbolic");
+        volume.activate(0, 0);
+        assert_eq!(volume.icon_name(), "audio-volume-muted-symbolic");
+    }
+}
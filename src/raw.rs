@@ -0,0 +1,115 @@
+//! D-Bus objects of a tray, without the service loop
+//!
+//! For applications that already run their own [`zbus::Connection`]. The objects implement the
+//! `org.kde.StatusNotifierItem` and `com.canonical.dbusmenu` interfaces for a [`Tray`], the
+//! application serves them, registers the item to the watcher and calls
+//! [`RawTray::notify_changed`] after changing the tray. [`RawTray::run`] sends the signals the
+//! tray delays, e.g. for dynamic labels, run it with the connection.
+//!
+//! The objects must be served at [`ITEM_PATH`] and [`MENU_PATH`]. Builder options and the
+//! callbacks about the watcher are features of the service loop, they are not available here.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # async fn f<T: ksni::Tray>(tray: T) -> zbus::Result<()> {
+//! use ksni::raw::{RawTray, ITEM_PATH, MENU_PATH};
+//!
+//! let raw = RawTray::new(tray);
+//! let conn = zbus::connection::Builder::session()?
+//!     .serve_at(ITEM_PATH, raw.status_notifier_item())?
+//!     .serve_at(MENU_PATH, raw.dbus_menu())?
+//!     .build()
+//!     .await?;
+//! // register `conn.unique_name()` to org.kde.StatusNotifierWatcher...
+//!
+//! raw.update(&conn, |tray: &mut T| { /* change the tray */ }).await?;
+//! raw.run(&conn).await;
+//! # Ok(()) }
+//! ```
+
+use std::sync::Arc;
+
+use zbus::zvariant::ObjectPath;
+
+use crate::compat::{mpsc, Mutex};
+use crate::service::{Config, Service};
+use crate::{HandleReuest, Tray};
+
+pub use crate::dbus_interface::{DbusMenu, StatusNotifierItem};
+
+/// Path of the [`StatusNotifierItem`] object
+pub const ITEM_PATH: ObjectPath<'static> = crate::dbus_interface::SNI_PATH;
+/// Path of the [`DbusMenu`] object, announced by the `Menu` property of the item
+pub const MENU_PATH: ObjectPath<'static> = crate::dbus_interface::MENU_PATH;
+
+/// The state shared by the D-Bus objects of a tray
+pub struct RawTray<T> {
+    service: Arc<Mutex<Service<T>>>,
+    // delayed signals of the service, see `RawTray::run`
+    refresh: Arc<Mutex<mpsc::UnboundedReceiver<HandleReuest>>>,
+}
+
+impl<T> Clone for RawTray<T> {
+    fn clone(&self) -> Self {
+        RawTray {
+            service: self.service.clone(),
+            refresh: self.refresh.clone(),
+        }
+    }
+}
+
+impl<T: Tray> RawTray<T> {
+    pub fn new(tray: T) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        RawTray {
+            service: Service::new(tray, Config::default(), sender),
+            refresh: Arc::new(Mutex::new(receiver)),
+        }
+    }
+
+    /// The `org.kde.StatusNotifierItem` object, serve it at [`ITEM_PATH`]
+    pub fn status_notifier_item(&self) -> StatusNotifierItem<T> {
+        StatusNotifierItem::new(self.service.clone())
+    }
+
+    /// The `com.canonical.dbusmenu` object, serve it at [`MENU_PATH`]
+    pub fn dbus_menu(&self) -> DbusMenu<T> {
+        DbusMenu::new(self.service.clone())
+    }
+
+    /// Update the tray, then send the signals of what changed
+    pub async fn update<R, F: FnOnce(&mut T) -> R>(
+        &self,
+        conn: &zbus::Connection,
+        f: F,
+    ) -> zbus::Result<R> {
+        let mut service = self.service.lock().await;
+        let r = f(&mut service.tray);
+        service.update(conn).await.map(|()| r)
+    }
+
+    /// Send the signals of what changed since the last call
+    ///
+    /// Menu callbacks and activations through D-Bus notify on their own.
+    pub async fn notify_changed(&self, conn: &zbus::Connection) -> zbus::Result<()> {
+        self.service.lock().await.update(conn).await
+    }
+
+    /// Send the signals the tray delays, e.g. the changes of
+    /// [`DynamicLabel`](crate::menu::DynamicLabel)s
+    ///
+    /// Never returns, run it as long as the objects are served, e.g. in the `select!` loop of
+    /// the application. Errors of the signals are ignored.
+    pub async fn run(&self, conn: &zbus::Connection) {
+        let mut refresh = self.refresh.lock().await;
+        self.service.lock().await.schedule_label_refresh();
+        while let Some(msg) = refresh.recv().await {
+            let mut service = self.service.lock().await;
+            let _ = match msg {
+                HandleReuest::RefreshLabels => service.refresh_labels(conn).await,
+                _ => service.update(conn).await,
+            };
+        }
+    }
+}
@@ -1,61 +1,143 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::future::Future;
 use std::hash::{Hash, Hasher};
-use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant, SystemTime};
 
+use futures_util::future::{self, Either};
 use futures_util::StreamExt;
 use paste::paste;
 use zbus::fdo::DBusProxy;
-use zbus::zvariant::{OwnedValue, Str};
+use zbus::zvariant::{ObjectPath, OwnedValue, Str, Value};
 use zbus::Connection;
 
-use crate::compat::{self, mpsc, select, Mutex};
+use crate::appearance;
+use crate::compat::{self, mpsc, oneshot, select, Mutex};
 use crate::dbus_interface::{
-    DbusMenu, Layout, StatusNotifierItem, StatusNotifierWatcherProxy, MENU_PATH, SNI_PATH,
+    Application, DbusMenu, Encoded, Layout, StatusNotifierItem, StatusNotifierWatcherProxy,
+    MENU_PATH, SNI_PATH,
 };
+use crate::idle::IdleMonitor;
 use crate::menu;
-use crate::{Error, HandleReuest, OfflineReason, Tray};
+use crate::notify;
+use crate::{Error, HandleReuest, Hint, OfflineReason, PropertyFlags, Tray};
 
 static INSTANCE_COUNTER: AtomicUsize = AtomicUsize::new(1);
+const MAX_NAME_ATTEMPTS: usize = 16;
+// two activations within this interval are a double click, see `HostEvent::DoubleClick`
+const DOUBLE_CLICK_INTERVAL: Duration = Duration::from_millis(400);
+// for the signal log
+const SNI_INTERFACE: &str = "org.kde.StatusNotifierItem";
+const MENU_INTERFACE: &str = "com.canonical.dbusmenu";
+// see `TrayServiceBuilder::restore_state`
+const RESTORED_STATE_TIMEOUT: Duration = Duration::from_secs(5);
+// icon, overlay, attention and tooltip pixmaps, with room for the previous ones
+const MAX_SCALED_PIXMAPS: usize = 8;
+// see `Service::encoded`, pixmaps of the same properties with room for the previous ones
+const MAX_ENCODED_PROPERTIES: usize = 8;
+// bumped on every change of the encoding of `TrayStateBlob`
+const STATE_VERSION: u8 = 1;
 
+// how the service loop of a connection ended
+pub(crate) enum LoopExit {
+    Closed(crate::CloseReason),
+    // see `Handle::restart`, the bus name is requested again by the next connection
+    Restart {
+        bus_name: String,
+        reply: oneshot::Sender<Result<(), Error>>,
+    },
+}
+
+// `bus_name` is the name of the previous connection, see `Handle::restart`
 pub(crate) async fn run<T: Tray>(
     service: Arc<Mutex<Service<T>>>,
     mut handle_rx: mpsc::UnboundedReceiver<HandleReuest>,
     own_name: bool,
-) -> Result<impl Future<Output = ()>, Error> {
+    bus_name: Option<String>,
+) -> Result<
+    (
+        crate::RegistrationInfo,
+        Connection,
+        impl Future<Output = (LoopExit, mpsc::UnboundedReceiver<HandleReuest>)>,
+    ),
+    Error,
+> {
     let sni_obj = StatusNotifierItem::new(service.clone());
     let menu_obj = DbusMenu::new(service.clone());
 
+    let method_timeout = service.lock().await.config.method_timeout;
+    let external_menu = service.lock().await.external_menu_path.clone();
+    let session = service.lock().await.config.session.clone();
+    let mut conn_builder = session.connection_builder().map_err(Error::Dbus)?;
+    if let Some(timeout) = method_timeout {
+        conn_builder = conn_builder.method_timeout(timeout);
+    }
     // for those `expect`, see: https://github.com/dbus2/zbus/issues/403
-    let conn = zbus::connection::Builder::session()
-        .map_err(|e| Error::Dbus(e))?
+    conn_builder = conn_builder
         .internal_executor(false) // avoid extra thread when async-io enabled
         .serve_at(SNI_PATH, sni_obj)
-        .expect("SNI_PATH should be valid")
-        .serve_at(MENU_PATH, menu_obj)
-        .expect("MENU_PATH should be valid")
-        .build()
-        .await
-        .map_err(|e| Error::Dbus(e))?;
+        .expect("SNI_PATH should be valid");
+    if external_menu.is_none() {
+        conn_builder = conn_builder
+            .serve_at(MENU_PATH, menu_obj)
+            .expect("MENU_PATH should be valid");
+    }
+    let application_id = service.lock().await.config.application_id.clone();
+    if let Some(id) = &application_id {
+        conn_builder = conn_builder
+            .serve_at(application_path(id), Application::new(service.clone()))
+            .map_err(Error::Dbus)?;
+    }
+    let conn = conn_builder.build().await.map_err(Error::Dbus)?;
+
+    if let Some(id) = &application_id {
+        // without the flag the request is queued until the other instance exits
+        let flags = zbus::fdo::RequestNameFlags::DoNotQueue.into();
+        match conn.request_name_with_flags(id.as_str(), flags).await {
+            Ok(_) => {}
+            Err(zbus::Error::NameTaken) => {
+                activate_application(&conn, id).await;
+                return Err(Error::AlreadyRunning);
+            }
+            Err(e) => return Err(Error::Dbus(e)),
+        }
+    }
 
     let name = if own_name {
-        let name = format!(
-            "org.kde.StatusNotifierItem-{}-{}",
-            std::process::id(),
-            INSTANCE_COUNTER.fetch_add(1, Ordering::AcqRel)
-        );
-        conn.request_name(&*name).await.map_err(|e| {
-            assert_ne!(e, zbus::Error::NameTaken, "generated name should be unique");
-            Error::Dbus(e)
-        })?;
-        name
+        let mut attempts = 0;
+        let mut previous = bus_name;
+        loop {
+            let name = previous.take().unwrap_or_else(|| {
+                format!(
+                    "org.kde.StatusNotifierItem-{}-{}",
+                    std::process::id(),
+                    INSTANCE_COUNTER.fetch_add(1, Ordering::AcqRel)
+                )
+            });
+            match conn.request_name(&*name).await {
+                Ok(_) => break name,
+                // another process with the same PID (e.g. in another container sharing the
+                // session bus) owns it, try the next counter value
+                Err(zbus::Error::NameTaken) if attempts < MAX_NAME_ATTEMPTS => attempts += 1,
+                Err(e) => return Err(Error::Dbus(e)),
+            }
+        }
     } else {
         conn.unique_name()
             .expect("unique name should be set after connected")
             .to_string()
     };
 
+    // dropped with the service loop, the message stream must not outlive the connection
+    let (count_messages_stop, stop) = oneshot::channel::<()>();
+    let stats = service.lock().await.stats.clone();
+    if stats.count_messages {
+        let stats = stats.clone();
+        let conn = conn.clone();
+        compat::spawn(async move { stats.count_received(&conn, stop).await });
+    }
+
     if cfg!(feature = "async-io") {
         let executor = conn.executor().clone();
         // must start the executor before register_status_notifier_item
@@ -67,6 +149,41 @@ pub(crate) async fn run<T: Tray>(
         });
     }
 
+    // the changes before the registration are sent after it, not while hosts read the item
+    let mut changed_before_registration = false;
+    let watch_color_scheme = service.lock().await.config.watch_color_scheme;
+    if watch_color_scheme {
+        if let Some(scheme) = appearance::read(&conn).await {
+            service.lock().await.tray.color_scheme_changed(scheme);
+            changed_before_registration = true;
+        }
+        let conn = conn.clone();
+        let sender = service.lock().await.refresh_sender.clone();
+        compat::spawn(async move {
+            let _ = appearance::watch(conn, sender).await;
+        });
+    }
+
+    // dropped with the service loop, a restart starts another monitor for the new connection
+    let (idle_monitor_stop, stop) = oneshot::channel::<()>();
+    let watch_session_idle = service.lock().await.config.watch_session_idle;
+    if watch_session_idle {
+        if let Some((monitor, idle)) = IdleMonitor::new(&conn).await {
+            let mut service = service.lock().await;
+            service.stats.session_idle.store(idle, Ordering::Release);
+            service.tray.session_idle(idle);
+            changed_before_registration = true;
+            let sender = service.refresh_sender.clone();
+            compat::spawn(async move {
+                let _ = monitor.watch(sender, stop).await;
+            });
+        }
+    }
+
+    // before hosts read the properties
+    let prefetch = service.lock().await.prefetch_icon_theme();
+    prefetch.await;
+
     let snw_object = StatusNotifierWatcherProxy::new(&conn)
         .await
         .expect("macro generated dbus Proxy should be valid");
@@ -82,11 +199,21 @@ pub(crate) async fn run<T: Tray>(
                 Error::Watcher(fdo_err)
             }
         })?;
+    stats.report(crate::Metric::Registered);
+    let capabilities = crate::host::capabilities(&conn).await;
+    if let Err(e) = service
+        .lock()
+        .await
+        .set_host_capabilities(&conn, capabilities)
+        .await
+    {
+        stats.report(crate::Metric::Error(e.to_string()));
+    }
 
     if !snw_object
         .is_status_notifier_host_registered()
         .await
-        .map_err(|e| Error::Dbus(e))?
+        .map_err(Error::Dbus)?
     {
         return Err(Error::WontShow);
     }
@@ -97,14 +224,47 @@ pub(crate) async fn run<T: Tray>(
     let mut name_changed_signal = dbus_object
         .receive_name_owner_changed_with_args(&[(0, "org.kde.StatusNotifierWatcher")])
         .await
-        .map_err(|e| Error::Dbus(e))?;
+        .map_err(Error::Dbus)?;
+    // only handled if the name is owned, the unique name is never lost
+    let mut name_lost_signal = dbus_object
+        .receive_name_lost_with_args(&[(0, name.as_str())])
+        .await
+        .map_err(Error::Dbus)?;
+    let mut name_acquired_signal = dbus_object
+        .receive_name_acquired_with_args(&[(0, name.as_str())])
+        .await
+        .map_err(Error::Dbus)?;
+
+    if changed_before_registration {
+        let _ = service.lock().await.update(&conn).await;
+    }
+    service.lock().await.schedule_label_refresh();
+    service.lock().await.schedule_restored_state_expiry();
+
+    #[cfg(feature = "global-hotkey")]
+    service.lock().await.bind_global_shortcuts(&conn, true);
 
+    let info = crate::RegistrationInfo {
+        bus_name: name.clone(),
+        unique_name: conn
+            .unique_name()
+            .expect("unique name should be set after connected")
+            .to_string(),
+        item_path: SNI_PATH.to_string(),
+        menu_path: external_menu.unwrap_or(MENU_PATH).to_string(),
+        process_id: std::process::id(),
+    };
+    let handle_conn = conn.clone();
     let service_loop = async move {
-        loop {
+        let _idle_monitor_stop = idle_monitor_stop;
+        let _count_messages_stop = count_messages_stop;
+        // the unique name while the well-known name is lost
+        let mut registered_as = name.clone();
+        let exit = loop {
             select! {
                 Some(event) = name_changed_signal.next() => {
                     let args = event.args().expect("dbus daemon should follow the specification");
-                    let service = service.lock().await;
+                    let mut service = service.lock().await;
                     match args.new_owner.as_ref() {
                         Some(_new_owner) => {
                             if args.old_owner.is_none() {
@@ -112,7 +272,11 @@ pub(crate) async fn run<T: Tray>(
                                 service.tray.watcher_online();
                             }
 
-                            if let Err(e) = snw_object.register_status_notifier_item(&name).await {
+                            if let Err(e) = snw_object
+                                .register_status_notifier_item(&registered_as)
+                                .await
+                            {
+                                stats.report(crate::Metric::Error(e.to_string()));
                                 let fdo_err: zbus::fdo::Error = e.into();
                                 let reason = if let zbus::fdo::Error::ZBus(e) = fdo_err {
                                     OfflineReason::Error(Error::Dbus(e))
@@ -121,7 +285,14 @@ pub(crate) async fn run<T: Tray>(
                                 };
                                 if !service.tray.watcher_offline(reason) {
                                     let _ = conn.close().await;
-                                    break;
+                                    break LoopExit::Closed(crate::CloseReason::WatcherOffline);
+                                }
+                            } else {
+                                stats.report(crate::Metric::Reregistered);
+                                // the host may have changed
+                                let capabilities = crate::host::capabilities(&conn).await;
+                                if let Err(e) = service.set_host_capabilities(&conn, capabilities).await {
+                                    stats.report(crate::Metric::Error(e.to_string()));
                                 }
                             }
                             // TODO: check is_status_notifier_host_registered?
@@ -130,248 +301,1398 @@ pub(crate) async fn run<T: Tray>(
                         None => {
                             if !service.tray.watcher_offline(OfflineReason::No) {
                                 let _ = conn.close().await;
-                                break;
+                                break LoopExit::Closed(crate::CloseReason::WatcherOffline);
                             }
                         }
                     }
                 }
+                Some(_) = name_lost_signal.next() => {
+                    if !own_name {
+                        continue;
+                    }
+                    let service = service.lock().await;
+                    service.tray.name_lost(&name);
+                    // keep the item shown until the name is acquired again
+                    registered_as = conn
+                        .unique_name()
+                        .expect("unique name should be set after connected")
+                        .to_string();
+                    if let Err(e) = snw_object.register_status_notifier_item(&registered_as).await {
+                        stats.report(crate::Metric::Error(e.to_string()));
+                    }
+                    // zbus still considers the name owned, and would not request it again
+                    let _ = conn.release_name(name.as_str()).await;
+                    // without `DoNotQueue` the request waits behind the current owner,
+                    // `NameAcquired` is received once it releases the name
+                    let flags = zbus::fdo::RequestNameFlags::AllowReplacement.into();
+                    if let Err(e) = conn.request_name_with_flags(name.as_str(), flags).await {
+                        stats.report(crate::Metric::Error(e.to_string()));
+                    }
+                }
+                Some(_) = name_acquired_signal.next() => {
+                    if !own_name {
+                        continue;
+                    }
+                    let service = service.lock().await;
+                    registered_as = name.clone();
+                    if let Err(e) = snw_object.register_status_notifier_item(&name).await {
+                        stats.report(crate::Metric::Error(e.to_string()));
+                    }
+                    service.tray.name_acquired(&name);
+                }
                 Some(msg) = handle_rx.recv() => {
                     match msg {
-                        HandleReuest::Update(singal) => {
+                        HandleReuest::Update(hint, singal, _permit) => {
+                            stats.queued_updates.fetch_sub(1, Ordering::AcqRel);
                             let mut service = service.lock().await;
-                            let _ = service.update(&conn).await;
+                            service.drop_restored_state();
+                            if let Err(e) = service.update_hint(&conn, hint).await {
+                                stats.report(crate::Metric::Error(e.to_string()));
+                            }
                             let _ = singal.send(());
                         }
+                        HandleReuest::Refresh => {
+                            let mut service = service.lock().await;
+                            // sent when the session is active again
+                            if !service.session_idle() {
+                                if let Err(e) = service.update(&conn).await {
+                                    stats.report(crate::Metric::Error(e.to_string()));
+                                }
+                            }
+                        }
+                        HandleReuest::RestoredStateExpired => {
+                            let mut service = service.lock().await;
+                            if service.drop_restored_state() {
+                                if let Err(e) = service.update_hint(&conn, Hint::Properties).await {
+                                    stats.report(crate::Metric::Error(e.to_string()));
+                                }
+                            }
+                        }
+                        HandleReuest::RefreshLabels => {
+                            let mut service = service.lock().await;
+                            if let Err(e) = service.refresh_labels(&conn).await {
+                                stats.report(crate::Metric::Error(e.to_string()));
+                            }
+                        }
+                        #[cfg(feature = "global-hotkey")]
+                        HandleReuest::GlobalShortcut(trigger) => {
+                            let mut service = service.lock().await;
+                            service.call_global_shortcut(&conn, &trigger).await;
+                        }
+                        HandleReuest::ColorScheme(scheme) => {
+                            let mut service = service.lock().await;
+                            service.tray.color_scheme_changed(scheme);
+                            let _ = service.update(&conn).await;
+                        }
+                        HandleReuest::SessionIdle(idle) => {
+                            let mut service = service.lock().await;
+                            if let Err(e) = service.set_session_idle(&conn, idle).await {
+                                stats.report(crate::Metric::Error(e.to_string()));
+                            }
+                        }
+                        HandleReuest::Connection(reply) => {
+                            let _ = reply.send(conn.clone());
+                        }
                         HandleReuest::Shutdown(singal) => {
+                            // not closed by the server when the connection is
+                            service
+                                .lock()
+                                .await
+                                .close_attention_notification(&conn)
+                                .await;
                             let _ = conn.close().await;
                             let _ = singal.send(());
-                            break;
+                            break LoopExit::Closed(crate::CloseReason::Shutdown);
+                        }
+                        HandleReuest::Restart(reply) => {
+                            // released first, the next connection may request them before the
+                            // bus noticed that this one is closed
+                            if own_name {
+                                let _ = conn.release_name(name.as_str()).await;
+                            }
+                            if let Some(id) = &application_id {
+                                let _ = conn.release_name(id.as_str()).await;
+                            }
+                            let _ = conn.close().await;
+                            break LoopExit::Restart {
+                                bus_name: name,
+                                reply,
+                            };
                         }
                     }
                 }
             }
-        }
+        };
+        (exit, handle_rx)
     };
-    Ok(service_loop)
+    Ok((info, handle_conn, service_loop))
 }
 
-pub(crate) struct Service<T> {
-    pub tray: T,
-    flattened_menu: Vec<(menu::RawMenuItem<T>, Vec<usize>)>,
-    prop_monitor: PropertiesMonitor,
-    item_id_offset: i32,
-    pub revision: u32,
+// waits for the tray to be shown before connecting, see `TrayServiceBuilder::defer_connection`
+pub(crate) async fn run_deferred<T: Tray>(
+    service: Arc<Mutex<Service<T>>>,
+    mut handle_rx: mpsc::UnboundedReceiver<HandleReuest>,
+    own_name: bool,
+    registration: Weak<Registration>,
+) -> crate::CloseReason {
+    loop {
+        let Some(msg) = handle_rx.recv().await else {
+            return crate::CloseReason::Aborted;
+        };
+        match msg {
+            HandleReuest::Update(_, singal, permit) => {
+                let mut service = service.lock().await;
+                service.apply_sync_updates();
+                if service.get_status() == crate::Status::Passive {
+                    service.stats.queued_updates.fetch_sub(1, Ordering::AcqRel);
+                    let _ = singal.send(());
+                    continue;
+                }
+                // handled by the service loop once connected, nothing was checked for changes
+                // before
+                let _ =
+                    service
+                        .refresh_sender
+                        .send(HandleReuest::Update(Hint::All, singal, permit));
+                break;
+            }
+            HandleReuest::Shutdown(singal) => {
+                let _ = singal.send(());
+                return crate::CloseReason::Shutdown;
+            }
+            // not connected yet, nothing to restart
+            HandleReuest::Restart(reply) => {
+                let _ = reply.send(Ok(()));
+            }
+            // answered with `None`, see `TrayServiceBuilder::defer_connection`
+            HandleReuest::Connection(_) => {}
+            // nothing to refresh without a connection
+            _ => {}
+        }
+    }
+    match run(service.clone(), handle_rx, own_name, None).await {
+        Ok((info, conn, service_loop)) => {
+            if let Some(registration) = registration.upgrade() {
+                registration.set(info, conn);
+            }
+            serve(service, own_name, registration, service_loop).await
+        }
+        Err(e) => {
+            let reason = crate::CloseReason::ConnectionFailed(e.to_string());
+            service
+                .lock()
+                .await
+                .tray
+                .watcher_offline(OfflineReason::Error(e));
+            reason
+        }
+    }
 }
 
-impl<T: Tray> Service<T> {
-    pub fn new(tray: T) -> Arc<Mutex<Self>> {
-        let flattened_menu = menu::menu_flatten(T::menu(&tray));
-        let prop_monitor = PropertiesMonitor::new(&tray);
-        Arc::new(Mutex::new(Service {
-            tray,
-            flattened_menu,
-            prop_monitor,
-            item_id_offset: 0,
-            revision: 0,
-        }))
+// runs the service loop, and connects again after `Handle::restart`
+pub(crate) async fn serve<T: Tray>(
+    service: Arc<Mutex<Service<T>>>,
+    own_name: bool,
+    registration: Weak<Registration>,
+    service_loop: impl Future<Output = (LoopExit, mpsc::UnboundedReceiver<HandleReuest>)>,
+) -> crate::CloseReason {
+    let mut service_loop = Either::Left(service_loop);
+    loop {
+        let (exit, handle_rx) = service_loop.await;
+        let (bus_name, reply) = match exit {
+            LoopExit::Closed(reason) => return reason,
+            LoopExit::Restart { bus_name, reply } => (bus_name, reply),
+        };
+        match run(service.clone(), handle_rx, own_name, Some(bus_name)).await {
+            Ok((info, conn, next)) => {
+                if let Some(registration) = registration.upgrade() {
+                    registration.set(info, conn);
+                }
+                let _ = reply.send(Ok(()));
+                service_loop = Either::Right(next);
+            }
+            Err(e) => {
+                let reason = crate::CloseReason::ConnectionFailed(e.to_string());
+                let _ = reply.send(Err(e));
+                return reason;
+            }
+        }
     }
+}
 
-    async fn update_properties(&mut self, conn: &Connection) -> zbus::Result<()> {
-        let sni_obj = conn
-            .object_server()
-            .interface::<_, StatusNotifierItem<T>>(SNI_PATH)
-            .await?;
-        let menu_obj = conn
-            .object_server()
-            .interface::<_, DbusMenu<T>>(MENU_PATH)
-            .await?;
+// filled in once the tray is connected, shared by the handles
+#[derive(Default)]
+pub(crate) struct Registration {
+    // replaced by `Handle::restart`
+    info: std::sync::Mutex<Option<crate::RegistrationInfo>>,
+    connection: std::sync::Mutex<Option<Connection>>,
+    // see `Handle::closed`
+    close_reason: std::sync::Mutex<Option<crate::CloseReason>>,
+    close_waiters: std::sync::Mutex<Vec<oneshot::Sender<crate::CloseReason>>>,
+}
 
-        if self.text_direction_changed() {
-            menu_obj
-                .get_mut()
-                .await
-                .text_direction_changed(menu_obj.signal_emitter())
-                .await?;
-        }
+// sets the close reason once the service loop ended or was dropped
+pub(crate) struct CloseGuard {
+    registration: Arc<Registration>,
+    reason: Option<crate::CloseReason>,
+}
 
-        if self.status_changed() {
-            StatusNotifierItem::<T>::new_status(
-                sni_obj.signal_emitter(),
-                &self.get_status().to_string(),
-            )
-            .await?;
-            menu_obj
-                .get_mut()
-                .await
-                .status_changed(menu_obj.signal_emitter())
-                .await?;
+impl CloseGuard {
+    pub fn new(registration: Arc<Registration>) -> Self {
+        CloseGuard {
+            registration,
+            reason: None,
         }
+    }
 
-        if self.icon_theme_path_changed() {
-            sni_obj
-                .get_mut()
-                .await
-                .icon_theme_path_changed(sni_obj.signal_emitter())
-                .await?;
-            menu_obj
-                .get_mut()
-                .await
-                .icon_theme_path_changed(menu_obj.signal_emitter())
-                .await?;
-        }
+    pub fn set(&mut self, reason: crate::CloseReason) {
+        self.reason = Some(reason);
+    }
+}
 
-        if self.category_changed() {
-            sni_obj
-                .get_mut()
-                .await
-                .category_changed(sni_obj.signal_emitter())
-                .await?;
+impl Drop for CloseGuard {
+    fn drop(&mut self) {
+        let reason = self.reason.take().unwrap_or(crate::CloseReason::Aborted);
+        *lock(&self.registration.close_reason) = Some(reason.clone());
+        for waiter in lock(&self.registration.close_waiters).drain(..) {
+            let _ = waiter.send(reason.clone());
         }
+    }
+}
 
-        if self.window_id_changed() {
-            sni_obj
-                .get_mut()
-                .await
-                .window_id_changed(sni_obj.signal_emitter())
-                .await?;
-        }
+impl Registration {
+    pub fn set(&self, info: crate::RegistrationInfo, conn: Connection) {
+        *lock(&self.info) = Some(info);
+        *lock(&self.connection) = Some(conn);
+    }
 
-        // TODO: assert the id is consistent
+    // None until connected
+    pub fn info(&self) -> Option<crate::RegistrationInfo> {
+        lock(&self.info).clone()
+    }
 
-        if self.title_changed() {
-            StatusNotifierItem::<T>::new_title(sni_obj.signal_emitter()).await?;
-        }
-        if self.icon_name_changed() || self.icon_pixmap_changed() {
-            StatusNotifierItem::<T>::new_icon(sni_obj.signal_emitter()).await?;
-        }
-        if self.overlay_icon_name_changed() || self.overlay_icon_pixmap_changed() {
-            StatusNotifierItem::<T>::new_overlay_icon(sni_obj.signal_emitter()).await?;
+    pub fn connection(&self) -> Option<Connection> {
+        lock(&self.connection).clone()
+    }
+
+    // the reason if the service loop ended, a receiver of it otherwise
+    pub fn closed(&self) -> Result<crate::CloseReason, oneshot::Receiver<crate::CloseReason>> {
+        let mut waiters = lock(&self.close_waiters);
+        if let Some(reason) = lock(&self.close_reason).clone() {
+            return Ok(reason);
         }
-        if self.attention_icon_name_changed()
-            || self.attention_icon_pixmap_changed()
-            || self.attention_movie_name_changed()
-        {
-            StatusNotifierItem::<T>::new_attention_icon(sni_obj.signal_emitter()).await?;
+        let (tx, rx) = oneshot::channel();
+        waiters.push(tx);
+        Err(rx)
+    }
+}
+
+/// Options of the service, see `TrayServiceBuilder`
+#[derive(Default)]
+pub(crate) struct Config {
+    pub min_tool_tip_interval: Option<Duration>,
+    pub min_layout_update_interval: Option<Duration>,
+    pub method_timeout: Option<Duration>,
+    pub activate_debounce: Option<Duration>,
+    pub watch_color_scheme: bool,
+    pub watch_session_idle: bool,
+    pub channel_capacity: Option<usize>,
+    pub blocking_callbacks: bool,
+    pub drop_stale_menu_events: bool,
+    pub stable_menu_ids: bool,
+    pub ayatana_compat: bool,
+    pub desktop_profile: crate::DesktopProfile,
+    pub prefer_icon_pixmap: Option<bool>,
+    pub coalesce_signals: bool,
+    pub signal_log: usize,
+    pub count_messages: bool,
+    pub max_menu_icon_data: Option<usize>,
+    // (max_children, more_label), see `TrayServiceBuilder::paginate_menu`
+    pub menu_pagination: Option<(usize, String)>,
+    pub shutdown_on_panic: bool,
+    pub volatile_menu_properties: Vec<String>,
+    pub application_id: Option<String>,
+    pub max_icon_size: Option<u32>,
+    pub event_map: HashMap<crate::HostEvent, crate::TrayAction>,
+    pub metrics_hook: Option<MetricsHook>,
+    pub session: crate::SessionTarget,
+    pub restore_state: Option<TrayStateBlob>,
+    pub slow_menu_threshold: Option<Duration>,
+}
+
+// see `TrayServiceBuilder::metrics_hook`
+pub(crate) type MetricsHook = Arc<dyn Fn(crate::Metric) + Send + Sync>;
+
+// `org.example.App` is served at `/org/example/App`
+fn application_path(id: &str) -> String {
+    format!("/{}", id.replace('.', "/").replace('-', "_"))
+}
+
+// see `TrayServiceBuilder::application_id`, errors are ignored, this instance exits anyway
+async fn activate_application(conn: &Connection, id: &str) {
+    let mut platform_data = HashMap::new();
+    if let Ok(token) = std::env::var("XDG_ACTIVATION_TOKEN") {
+        platform_data.insert("activation-token", zbus::zvariant::Value::from(token));
+    }
+    let _ = conn
+        .call_method(
+            Some(id),
+            application_path(id).as_str(),
+            Some("org.freedesktop.Application"),
+            "Activate",
+            &(platform_data,),
+        )
+        .await;
+}
+
+// shared with the handles, readable while the service loop is busy
+#[derive(Default)]
+pub(crate) struct Stats {
+    pub queued_updates: AtomicUsize,
+    // see `TrayServiceBuilder::watch_session_idle`
+    pub session_idle: AtomicBool,
+    last_update: std::sync::Mutex<Option<(Instant, Duration)>>,
+    last_signal: std::sync::Mutex<Option<Instant>>,
+    // last `AboutToShow` or `GetLayout` of the root, and the time until the following click
+    menu_requested: std::sync::Mutex<Option<Instant>>,
+    menu_latency: std::sync::Mutex<Option<Duration>>,
+    // time of `Tray::menu` and the diff in the last update, and the number of items
+    menu_diff: std::sync::Mutex<Option<(Duration, usize)>>,
+    // see `TrayServiceBuilder::slow_menu_threshold`
+    slow_menu_threshold: Option<Duration>,
+    // see `TrayServiceBuilder::signal_log`
+    log_capacity: usize,
+    log: std::sync::Mutex<VecDeque<crate::SignalRecord>>,
+    // see `TrayServiceBuilder::count_messages`
+    count_messages: bool,
+    interfaces: std::sync::Mutex<HashMap<String, crate::InterfaceStats>>,
+    metrics_hook: Option<MetricsHook>,
+}
+
+impl Stats {
+    pub fn new(config: &Config) -> Self {
+        Stats {
+            log_capacity: config.signal_log,
+            count_messages: config.count_messages,
+            metrics_hook: config.metrics_hook.clone(),
+            slow_menu_threshold: config.slow_menu_threshold,
+            ..Default::default()
         }
-        if self.tool_tip_changed() {
-            StatusNotifierItem::<T>::new_tool_tip(sni_obj.signal_emitter()).await?;
+    }
+
+    pub fn report(&self, metric: crate::Metric) {
+        if let Some(hook) = &self.metrics_hook {
+            hook(metric);
         }
-        Ok(())
     }
 
-    async fn update_menu(&mut self, conn: &Connection) -> zbus::Result<()> {
-        let new_menu = menu::menu_flatten(self.tray.menu());
-        let mut all_updated_props = Vec::new();
-        let mut all_removed_props = Vec::new();
-        let default = crate::menu::RawMenuItem::default();
-        let mut layout_updated = false;
-        for (index, (old, new)) in self
-            .flattened_menu
-            .iter()
-            .chain(std::iter::repeat(&(default, vec![])))
-            .zip(new_menu.iter())
-            .enumerate()
-        {
-            let (old_item, old_childs) = old;
-            let (new_item, new_childs) = new;
+    fn count(&self, interface: &str, f: impl FnOnce(&mut crate::InterfaceStats)) {
+        let mut interfaces = lock(&self.interfaces);
+        match interfaces.get_mut(interface) {
+            Some(stats) => f(stats),
+            None => f(interfaces.entry(interface.to_owned()).or_default()),
+        }
+    }
 
-            if let Some((updated_props, removed_props)) = old_item.diff(new_item) {
-                if !updated_props.is_empty() {
-                    all_updated_props.push((self.index2id(index), updated_props));
-                }
-                if !removed_props.is_empty() {
-                    all_removed_props.push((self.index2id(index), removed_props));
-                }
-            }
-            if old_childs != new_childs {
-                layout_updated = true;
-                break;
+    // counts the messages received by the connection until it's closed
+    async fn count_received(&self, conn: &Connection, mut stop: oneshot::Receiver<()>) {
+        let mut messages = zbus::MessageStream::from(conn);
+        while let Either::Left((Some(msg), _)) = future::select(messages.next(), &mut stop).await {
+            let Ok(msg) = msg else {
+                continue;
+            };
+            let header = msg.header();
+            let Some(interface) = header.interface() else {
+                continue;
+            };
+            match msg.message_type() {
+                zbus::message::Type::MethodCall => self.count(interface, |s| s.method_calls += 1),
+                zbus::message::Type::Signal => self.count(interface, |s| s.signals_received += 1),
+                _ => (),
             }
         }
+    }
 
-        let menu_obj = conn
-            .object_server()
-            .interface::<_, DbusMenu<T>>(MENU_PATH)
-            .await?;
-        if layout_updated {
-            // The layout has been changed, bump ID offset to invalidate all items,
-            // which is required to avoid unexpected behaviors on some system tray
-            self.revision += 1;
-            self.item_id_offset += self.flattened_menu.len() as i32;
-            DbusMenu::<T>::layout_updated(menu_obj.signal_emitter(), self.revision, 0).await?;
-        } else if !all_updated_props.is_empty() || !all_removed_props.is_empty() {
-            DbusMenu::<T>::items_properties_updated(
-                menu_obj.signal_emitter(),
-                all_updated_props,
-                all_removed_props,
-            )
-            .await?;
+    pub fn menu_requested(&self) {
+        *lock(&self.menu_requested) = Some(Instant::now());
+    }
+
+    fn menu_clicked(&self) {
+        let Some(requested) = lock(&self.menu_requested).take() else {
+            return;
+        };
+        let latency = requested.elapsed();
+        *lock(&self.menu_latency) = Some(latency);
+        self.report(crate::Metric::MenuLatency(latency));
+    }
+
+    fn menu_diffed(&self, duration: Duration, items: usize) {
+        *lock(&self.menu_diff) = Some((duration, items));
+        if self
+            .slow_menu_threshold
+            .is_some_and(|threshold| duration > threshold)
+        {
+            self.report(crate::Metric::SlowMenuUpdate { duration, items });
         }
-        // Always update menu_cache since `on_clicked` can be updated
-        // and we can not detect that
-        self.flattened_menu = new_menu;
-        Ok(())
     }
 
-    async fn update(&mut self, conn: &Connection) -> zbus::Result<()> {
-        self.update_properties(&conn).await?;
-        self.update_menu(&conn).await
+    fn updated(&self, duration: Duration) {
+        *lock(&self.last_update) = Some((Instant::now(), duration));
     }
 
-    // Return None if item not exists
-    fn id2index(&self, id: i32) -> Option<usize> {
-        let number_of_items = self.flattened_menu.len();
-        let offset = self.item_id_offset;
-        if id == 0 && number_of_items > 0 {
-            // ID of the root item is always 0
-            return Some(0);
-        } else if id <= offset {
-            // == illegal id, bug in index2id or dbus peer
-            //  < expired id
-            return None;
+    // awaits the emitting future and records the signal once it's sent
+    async fn emit(
+        &self,
+        interface: &'static str,
+        signal: &'static str,
+        emit: impl Future<Output = zbus::Result<()>>,
+        summary: impl FnOnce() -> String,
+    ) -> zbus::Result<()> {
+        emit.await?;
+        self.signal_sent(interface, signal, summary);
+        Ok(())
+    }
+
+    // `summary` is only called if the signal log is enabled
+    fn signal_sent(
+        &self,
+        interface: &'static str,
+        signal: &'static str,
+        summary: impl FnOnce() -> String,
+    ) {
+        *lock(&self.last_signal) = Some(Instant::now());
+        self.report(crate::Metric::SignalSent { interface, signal });
+        if self.count_messages {
+            self.count(interface, |s| s.signals_sent += 1);
         }
-        let index: usize = (id - offset).try_into().expect("unreachable!");
-        if index < number_of_items {
-            Some(index)
-        } else {
-            None
+        if self.log_capacity == 0 {
+            return;
+        }
+        let mut log = lock(&self.log);
+        if log.len() == self.log_capacity {
+            log.pop_front();
         }
+        log.push_back(crate::SignalRecord {
+            time: SystemTime::now(),
+            interface,
+            signal,
+            summary: summary(),
+        });
     }
 
-    fn index2id(&self, index: usize) -> i32 {
-        // ID of the root item is always 0
-        if index == 0 {
-            0
-        } else {
-            <i32 as TryFrom<_>>::try_from(index)
-                .expect("index overflow")
-                .checked_add(self.item_id_offset)
-                .expect("id overflow")
+    pub fn signal_log(&self) -> Vec<crate::SignalRecord> {
+        lock(&self.log).iter().cloned().collect()
+    }
+
+    pub fn snapshot(&self, handles: usize) -> crate::ServiceStats {
+        let last_update = *lock(&self.last_update);
+        let menu_diff = *lock(&self.menu_diff);
+        crate::ServiceStats {
+            handles,
+            queued_updates: self.queued_updates.load(Ordering::Acquire),
+            last_update: last_update.map(|(at, _)| at),
+            last_update_duration: last_update.map(|(_, duration)| duration),
+            last_signal: *lock(&self.last_signal),
+            last_menu_latency: *lock(&self.menu_latency),
+            last_menu_diff_duration: menu_diff.map(|(duration, _)| duration),
+            menu_items: menu_diff.map_or(0, |(_, items)| items),
+            interfaces: lock(&self.interfaces).clone(),
         }
     }
 }
 
-// dbus methods
+pub(crate) struct Service<T> {
+    pub tray: T,
+    config: Config,
+    flattened_menu: Vec<(menu::RawMenuItem<T>, Vec<usize>)>,
+    prop_monitor: PropertiesMonitor,
+    menu_ids: MenuIds,
+    pub revision: u32,
+    tool_tip_throttle: Throttle,
+    layout_throttle: Throttle,
+    // ids in the last emitted layout while a `LayoutUpdated` is delayed, the host doesn't know
+    // the others yet
+    shown_menu_ids: Option<HashSet<i32>>,
+    label_refresh_pending: bool,
+    // id of the notification, see `Tray::attention_notification`
+    attention_notification: Option<u32>,
+    overrides: Overrides,
+    activate_debounce: Debounce<()>,
+    secondary_activate_debounce: Debounce<()>,
+    // from ProvideXdgActivationToken, for the next activation
+    xdg_activation_token: Option<String>,
+    // to detect double clicks
+    last_activate: Option<Instant>,
+    // keyed by the item id
+    click_debounce: Debounce<i32>,
+    // timestamp of the last applied click, keyed by the item id
+    click_timestamps: HashMap<i32, u32>,
+    // path of the item showing its choices, see `StandardItem::confirm`
+    confirming: Option<menu::MenuPath>,
+    pub stats: Arc<Stats>,
+    // see `Handle::try_update_sync`
+    pub sync_updates: SyncUpdates<T>,
+    // resolved from the desktop profile
+    prefer_icon_pixmap: bool,
+    // detected after registration, see `menu::MenuGenerationContext`
+    pub host_capabilities: Option<crate::HostCapabilities>,
+    // see `Tray::external_menu_path`, the menu is not served if set
+    external_menu_path: Option<ObjectPath<'static>>,
+    // changes of `TrayServiceBuilder::volatile_menu_properties` were not sent
+    volatile_pending: bool,
+    // set by `Handle::flush_menu`
+    pub flush_requested: bool,
+    // see `Handle::set_menu_enabled`, overrides `enabled` of all items
+    menu_enabled: bool,
+    menu_enabled_changed: bool,
+    // sizes already passed to `Tray::on_icon_downscaled`
+    downscaled_icons: std::sync::Mutex<HashSet<(i32, i32)>>,
+    // downscaled pixmaps keyed by the hash of the originals, property getters only get `&self`
+    scaled_pixmaps: std::sync::Mutex<HashMap<u64, Vec<crate::Icon>>>,
+    // see `Service::encoded`
+    encoded_properties: std::sync::Mutex<HashMap<u64, OwnedValue>>,
+    // used to wake up the service loop for delayed signals
+    refresh_sender: mpsc::UnboundedSender<HandleReuest>,
+    // overrides set by `TrayServiceBuilder::restore_state`
+    restored: PropertyFlags,
+    // see `Handle::set_locale`, the system locale until it's called
+    locale: String,
+    // `None` until the service loop binds them, see `Service::bind_global_shortcuts`
+    #[cfg(feature = "global-hotkey")]
+    bound_shortcuts: Option<BoundShortcuts>,
+    // bumped whenever the properties may have changed, see `StatusNotifierItem::read`
+    generation: u64,
+}
+
 impl<T: Tray> Service<T> {
-    /// Build a menu tree from flattened menu
-    /// Return None if parent_id not found
-    pub fn build_layout(
-        &self,
-        parent_id: i32,
-        recursion_depth: Option<usize>,
-        property_names: Vec<String>,
-    ) -> Option<Layout> {
-        let root = self.id2index(parent_id)?;
+    pub fn new(
+        tray: T,
+        config: Config,
+        refresh_sender: mpsc::UnboundedSender<HandleReuest>,
+    ) -> Arc<Mutex<Self>> {
+        let external_menu_path = tray.external_menu_path();
+        let mut flattened_menu = if external_menu_path.is_some() {
+            menu::menu_flatten(Vec::new())
+        } else {
+            flattened_menu(&tray, &Default::default())
+        };
+        if let Some((max_children, more_label)) = &config.menu_pagination {
+            menu::paginate(&mut flattened_menu, *max_children, more_label);
+        }
+        for (item, _) in &mut flattened_menu {
+            item.refresh_dynamic_label(&tray);
+            if let Some(max) = config.max_menu_icon_data {
+                item.limit_icon_data(max);
+            }
+        }
+        let stats = Arc::new(Stats::new(&config));
+        let profile = config.desktop_profile;
+        let prop_monitor = PropertiesMonitor::new(&tray, profile);
+        let menu_ids = MenuIds::new(flattened_menu.len());
+        let prefer_icon_pixmap = config
+            .prefer_icon_pixmap
+            .unwrap_or_else(|| profile.prefers_icon_pixmap());
+        let mut service = Service {
+            tray,
+            config,
+            flattened_menu,
+            prop_monitor,
+            menu_ids,
+            revision: 0,
+            tool_tip_throttle: Throttle::default(),
+            layout_throttle: Throttle::default(),
+            shown_menu_ids: None,
+            label_refresh_pending: false,
+            attention_notification: None,
+            overrides: Overrides::default(),
+            activate_debounce: Debounce::default(),
+            secondary_activate_debounce: Debounce::default(),
+            xdg_activation_token: None,
+            last_activate: None,
+            click_debounce: Debounce::default(),
+            click_timestamps: HashMap::new(),
+            confirming: None,
+            stats,
+            prefer_icon_pixmap,
+            sync_updates: Default::default(),
+            host_capabilities: None,
+            external_menu_path,
+            volatile_pending: false,
+            generation: 0,
+            #[cfg(feature = "global-hotkey")]
+            bound_shortcuts: None,
+            flush_requested: false,
+            menu_enabled: true,
+            menu_enabled_changed: false,
+            downscaled_icons: Default::default(),
+            scaled_pixmaps: Default::default(),
+            encoded_properties: Default::default(),
+            refresh_sender,
+            restored: PropertyFlags::empty(),
+            locale: crate::system_locale(),
+        };
+        if let Some(state) = service.config.restore_state.take() {
+            service.restore_state(state);
+        }
+        Arc::new(Mutex::new(service))
+    }
 
-        let mut items: Vec<Option<(Layout, Vec<usize>)>> = self
-            .flattened_menu
+    // see `Handle::set_locale`, returns false if the locale is already used
+    pub fn set_locale(&mut self, locale: &str) -> bool {
+        if self.locale == locale {
+            return false;
+        }
+        self.locale = locale.to_owned();
+        self.tray.locale_changed(locale);
+        self.bump_generation();
+        true
+    }
+
+    // update the service again after `delay`
+    fn schedule_refresh(&self, delay: Duration) {
+        self.send_after(delay, HandleReuest::Refresh);
+    }
+
+    // drops the restored state even if the app never updates the tray
+    pub fn schedule_restored_state_expiry(&self) {
+        if !self.restored.is_empty() {
+            self.send_after(RESTORED_STATE_TIMEOUT, HandleReuest::RestoredStateExpired);
+        }
+    }
+
+    fn send_after(&self, delay: Duration, msg: HandleReuest) {
+        let sender = self.refresh_sender.clone();
+        compat::spawn(async move {
+            compat::sleep(delay).await;
+            let _ = sender.send(msg);
+        });
+    }
+
+    // start a timer for the shortest interval of the dynamic labels, if there is any
+    pub fn schedule_label_refresh(&mut self) {
+        if self.label_refresh_pending {
+            return;
+        }
+        let interval = self
+            .flattened_menu
+            .iter()
+            .filter_map(|(item, _)| item.dynamic_label.as_ref())
+            .map(menu::DynamicLabel::refresh_interval)
+            .min();
+        if let Some(interval) = interval {
+            self.label_refresh_pending = true;
+            self.send_after(interval, HandleReuest::RefreshLabels);
+        }
+    }
+
+    // see `Tray::attention_notification`, a missing notification server doesn't fail the update
+    async fn update_attention_notification(&mut self, conn: &Connection) {
+        // read without storing the hashes, the properties are checked for changes after this
+        let needs_attention = self.current_status() == crate::Status::NeedsAttention;
+        self.close_attention_notification(conn).await;
+        let Some(notification) = needs_attention
+            .then(|| self.tray.attention_notification())
+            .flatten()
+        else {
+            return;
+        };
+        let app_name = match self.current_title() {
+            title if title.is_empty() => self.get_id(),
+            title => title,
+        };
+        let desktop_entry = self.config.application_id.as_deref();
+        match notify::show(conn, &app_name, desktop_entry, &notification).await {
+            Ok(id) => self.attention_notification = Some(id),
+            Err(e) => self.stats.report(crate::Metric::Error(e.to_string())),
+        }
+    }
+
+    async fn close_attention_notification(&mut self, conn: &Connection) {
+        if let Some(id) = self.attention_notification.take() {
+            if let Err(e) = notify::close(conn, id).await {
+                self.stats.report(crate::Metric::Error(e.to_string()));
+            }
+        }
+    }
+
+    fn session_idle(&self) -> bool {
+        self.stats.session_idle.load(Ordering::Acquire)
+    }
+
+    // see `Tray::session_idle`, the paused timers are restarted when the session is active again
+    async fn set_session_idle(&mut self, conn: &Connection, idle: bool) -> zbus::Result<()> {
+        if self.stats.session_idle.swap(idle, Ordering::AcqRel) == idle {
+            return Ok(());
+        }
+        self.tray.session_idle(idle);
+        self.update(conn).await?;
+        if !idle {
+            self.refresh_labels(conn).await?;
+        }
+        Ok(())
+    }
+
+    // the menu is generated again for the new host, see `menu::MenuGenerationContext`
+    async fn set_host_capabilities(
+        &mut self,
+        conn: &Connection,
+        capabilities: Option<crate::HostCapabilities>,
+    ) -> zbus::Result<()> {
+        if self.host_capabilities == capabilities {
+            return Ok(());
+        }
+        self.host_capabilities = capabilities;
+        self.update_hint(conn, Hint::Menu).await
+    }
+
+    // re-evaluate dynamic labels only, without calling `Tray::menu`
+    pub async fn refresh_labels(&mut self, conn: &Connection) -> zbus::Result<()> {
+        self.label_refresh_pending = false;
+        // restarted by `set_session_idle`
+        if self.session_idle() {
+            return Ok(());
+        }
+        let tray = &self.tray;
+        let changed: Vec<usize> = self
+            .flattened_menu
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, (item, _))| item.refresh_dynamic_label(tray).then_some(index))
+            .collect();
+        self.schedule_label_refresh();
+        if changed.is_empty() {
+            return Ok(());
+        }
+
+        let changed_labels = changed.len();
+        let label_filter = ["label".to_owned()];
+        let mut updated_props = Vec::new();
+        let mut removed_props = Vec::new();
+        for index in changed {
+            let props = self.flattened_menu[index].0.to_dbus_map(&label_filter);
+            if props.is_empty() {
+                removed_props.push((self.index2id(index), vec![Str::from_static("label")]));
+            } else {
+                updated_props.push((self.index2id(index), props));
+            }
+        }
+        let menu_obj = conn
+            .object_server()
+            .interface::<_, DbusMenu<T>>(MENU_PATH)
+            .await?;
+        self.stats
+            .emit(
+                MENU_INTERFACE,
+                "ItemsPropertiesUpdated",
+                DbusMenu::<T>::items_properties_updated(
+                    menu_obj.signal_emitter(),
+                    updated_props,
+                    removed_props,
+                ),
+                || format!("{changed_labels} labels"),
+            )
+            .await
+    }
+
+    async fn update_properties(&mut self, conn: &Connection) -> zbus::Result<()> {
+        let sni_obj = conn
+            .object_server()
+            .interface::<_, StatusNotifierItem<T>>(SNI_PATH)
+            .await?;
+        // not served with an external menu
+        let menu_obj = match self.external_menu_path {
+            Some(_) => None,
+            None => Some(
+                conn.object_server()
+                    .interface::<_, DbusMenu<T>>(MENU_PATH)
+                    .await?,
+            ),
+        };
+
+        if self.text_direction_changed() {
+            if let Some(menu_obj) = &menu_obj {
+                let menu = menu_obj.get_mut().await;
+                self.stats
+                    .emit(
+                        MENU_INTERFACE,
+                        "PropertiesChanged",
+                        menu.text_direction_changed(menu_obj.signal_emitter()),
+                        || "TextDirection".into(),
+                    )
+                    .await?;
+            }
+        }
+
+        if self.status_changed() {
+            let status = self.get_status().to_string();
+            self.stats
+                .emit(
+                    SNI_INTERFACE,
+                    "NewStatus",
+                    StatusNotifierItem::<T>::new_status(sni_obj.signal_emitter(), &status),
+                    || status.clone(),
+                )
+                .await?;
+            if let Some(menu_obj) = &menu_obj {
+                let menu = menu_obj.get_mut().await;
+                self.stats
+                    .emit(
+                        MENU_INTERFACE,
+                        "PropertiesChanged",
+                        menu.status_changed(menu_obj.signal_emitter()),
+                        || "Status".into(),
+                    )
+                    .await?;
+            }
+            self.update_attention_notification(conn).await;
+        }
+
+        if self.icon_theme_path_changed() {
+            let sni = sni_obj.get_mut().await;
+            self.stats
+                .emit(
+                    SNI_INTERFACE,
+                    "PropertiesChanged",
+                    sni.icon_theme_path_changed(sni_obj.signal_emitter()),
+                    || "IconThemePath".into(),
+                )
+                .await?;
+            drop(sni);
+            if let Some(menu_obj) = &menu_obj {
+                let menu = menu_obj.get_mut().await;
+                self.stats
+                    .emit(
+                        MENU_INTERFACE,
+                        "PropertiesChanged",
+                        menu.icon_theme_path_changed(menu_obj.signal_emitter()),
+                        || "IconThemePath".into(),
+                    )
+                    .await?;
+            }
+        }
+
+        if self.category_changed() {
+            let sni = sni_obj.get_mut().await;
+            self.stats
+                .emit(
+                    SNI_INTERFACE,
+                    "PropertiesChanged",
+                    sni.category_changed(sni_obj.signal_emitter()),
+                    || "Category".into(),
+                )
+                .await?;
+        }
+
+        if self.window_id_changed() {
+            let sni = sni_obj.get_mut().await;
+            self.stats
+                .emit(
+                    SNI_INTERFACE,
+                    "PropertiesChanged",
+                    sni.window_id_changed(sni_obj.signal_emitter()),
+                    || "WindowId".into(),
+                )
+                .await?;
+        }
+
+        // NOTE: `|` instead of `||`, both hashes must be refreshed
+        if (self.label_changed() | self.label_guide_changed()) && self.config.ayatana_compat {
+            let (label, guide) = (self.get_label(), self.get_label_guide());
+            self.stats
+                .emit(
+                    SNI_INTERFACE,
+                    "XAyatanaNewLabel",
+                    StatusNotifierItem::<T>::x_ayatana_new_label(
+                        sni_obj.signal_emitter(),
+                        &label,
+                        &guide,
+                    ),
+                    || label.clone(),
+                )
+                .await?;
+        }
+
+        // TODO: assert the id is consistent
+
+        // see `TrayServiceBuilder::coalesce_signals`
+        let mut invalidated = Vec::new();
+        macro_rules! new_signal {
+            ($signal:ident, $name:literal, [$($property:literal),+]) => {
+                if self.config.coalesce_signals {
+                    invalidated.extend([$($property),+]);
+                } else {
+                    self.stats
+                        .emit(
+                            SNI_INTERFACE,
+                            $name,
+                            StatusNotifierItem::<T>::$signal(sni_obj.signal_emitter()),
+                            String::new,
+                        )
+                        .await?;
+                }
+            };
+        }
+
+        if self.title_changed() {
+            new_signal!(new_title, "NewTitle", ["Title"]);
+        }
+        // the order of all pixmaps depends on the scale hint
+        // NOTE: `|` instead of `||`, every property must be compared to refresh its hash and cache
+        let icon_scale_hint_changed = self.icon_scale_hint_changed();
+        if icon_scale_hint_changed
+            | self.icon_name_changed()
+            | self.icon_pixmap_changed()
+            | self.icon_accessible_desc_changed()
+        {
+            new_signal!(
+                new_icon,
+                "NewIcon",
+                ["IconName", "IconPixmap", "IconAccessibleDesc"]
+            );
+        }
+        if icon_scale_hint_changed
+            | self.overlay_icon_name_changed()
+            | self.overlay_icon_pixmap_changed()
+        {
+            new_signal!(
+                new_overlay_icon,
+                "NewOverlayIcon",
+                ["OverlayIconName", "OverlayIconPixmap"]
+            );
+        }
+        if icon_scale_hint_changed
+            | self.attention_icon_name_changed()
+            | self.attention_icon_pixmap_changed()
+            | self.attention_movie_name_changed()
+            | self.attention_accessible_desc_changed()
+            | self.attention_level_changed()
+        {
+            new_signal!(
+                new_attention_icon,
+                "NewAttentionIcon",
+                [
+                    "AttentionIconName",
+                    "AttentionIconPixmap",
+                    "AttentionMovieName",
+                    "AttentionAccessibleDesc"
+                ]
+            );
+        }
+        let tool_tip_changed = self.tool_tip_changed() | icon_scale_hint_changed;
+        match self
+            .tool_tip_throttle
+            .check(tool_tip_changed, self.config.min_tool_tip_interval)
+        {
+            Throttled::Emit => {
+                new_signal!(new_tool_tip, "NewToolTip", ["ToolTip"]);
+            }
+            Throttled::Delay(delay) => self.schedule_refresh(delay),
+            Throttled::Skip => (),
+        }
+        if !invalidated.is_empty() {
+            self.stats
+                .emit(
+                    SNI_INTERFACE,
+                    "PropertiesChanged",
+                    zbus::fdo::Properties::properties_changed(
+                        sni_obj.signal_emitter(),
+                        zbus::names::InterfaceName::from_static_str_unchecked(SNI_INTERFACE),
+                        HashMap::new(),
+                        invalidated.as_slice().into(),
+                    ),
+                    || invalidated.join(", "),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    // `Tray::menu` of `tray`, as it's served
+    fn generate_menu(&self, tray: &T) -> Vec<(menu::RawMenuItem<T>, Vec<usize>)> {
+        let ctx = menu::MenuGenerationContext {
+            host_capabilities: self.host_capabilities,
+            revision: self.revision,
+        };
+        let mut new_menu = flattened_menu(tray, &ctx);
+        if let Some(path) = &self.confirming {
+            menu::show_confirmation(&mut new_menu, path);
+        }
+        if let Some((max_children, more_label)) = &self.config.menu_pagination {
+            menu::paginate(&mut new_menu, *max_children, more_label);
+        }
+        for (item, _) in &mut new_menu {
+            item.refresh_dynamic_label(tray);
+            if let Some(max) = self.config.max_menu_icon_data {
+                item.limit_icon_data(max);
+            }
+        }
+        new_menu
+    }
+
+    // changes from the served menu to `new_menu`, stops at the first change of the layout
+    fn diff_menu(&self, new_menu: &[(menu::RawMenuItem<T>, Vec<usize>)]) -> MenuDiff {
+        let mut diff = MenuDiff {
+            updated_props: Vec::new(),
+            removed_props: Vec::new(),
+            layout_updated: false,
+            volatile_changed: false,
+        };
+        let default = crate::menu::RawMenuItem::default();
+        let volatile = &self.config.volatile_menu_properties;
+        for (index, (old, new)) in self
+            .flattened_menu
+            .iter()
+            .chain(std::iter::repeat(&(default, vec![])))
+            .zip(new_menu.iter())
+            .enumerate()
+        {
+            let (old_item, old_childs) = old;
+            let (new_item, new_childs) = new;
+
+            if let Some((mut updated_props, mut removed_props)) = old_item.diff(new_item) {
+                // all items are shown as disabled
+                if !self.menu_enabled {
+                    updated_props.remove(&Str::from_static("enabled"));
+                    removed_props.retain(|name| name != "enabled");
+                }
+                if !volatile.is_empty() {
+                    let (updated, removed) = (updated_props.len(), removed_props.len());
+                    updated_props.retain(|name, _| !volatile.iter().any(|v| name == v.as_str()));
+                    removed_props.retain(|name| !volatile.iter().any(|v| name == v.as_str()));
+                    diff.volatile_changed |=
+                        updated != updated_props.len() || removed != removed_props.len();
+                }
+                if !updated_props.is_empty() {
+                    diff.updated_props
+                        .push((self.index2id(index), updated_props));
+                }
+                if !removed_props.is_empty() {
+                    diff.removed_props
+                        .push((self.index2id(index), removed_props));
+                }
+            }
+            if old_childs != new_childs {
+                diff.layout_updated = true;
+                break;
+            }
+        }
+        diff
+    }
+
+    // see `Handle::preview`, `tray` is compared with the served tray, nothing is sent
+    pub fn preview(&self, tray: &T) -> crate::UpdatePreview {
+        let properties = self.changed_properties(tray);
+        let (layout_changed, updated_items) = if self.external_menu_path.is_some() {
+            (false, 0)
+        } else {
+            let diff = self.diff_menu(&self.generate_menu(tray));
+            let mut ids: Vec<i32> = diff
+                .updated_props
+                .iter()
+                .map(|(id, _)| *id)
+                .chain(diff.removed_props.iter().map(|(id, _)| *id))
+                .collect();
+            ids.sort_unstable();
+            ids.dedup();
+            (diff.layout_updated, ids.len())
+        };
+        crate::UpdatePreview {
+            properties,
+            layout_changed,
+            updated_items,
+        }
+    }
+
+    async fn update_menu(&mut self, conn: &Connection) -> zbus::Result<()> {
+        if self.external_menu_path.is_some() {
+            return Ok(());
+        }
+        let start = Instant::now();
+        let new_menu = self.generate_menu(&self.tray);
+        // the item asking for a confirmation is gone
+        if !new_menu
+            .iter()
+            .any(|(item, _)| item.confirm_choice.is_some())
+        {
+            self.confirming = None;
+        }
+        let MenuDiff {
+            updated_props: mut all_updated_props,
+            removed_props: mut all_removed_props,
+            layout_updated,
+            volatile_changed,
+        } = self.diff_menu(&new_menu);
+        // the root isn't an item of the app
+        self.stats
+            .menu_diffed(start.elapsed(), new_menu.len().saturating_sub(1));
+
+        if std::mem::take(&mut self.menu_enabled_changed) && !layout_updated {
+            // the root is never shown
+            for (index, (item, _)) in new_menu.iter().enumerate().skip(1) {
+                if !item.enabled() {
+                    continue;
+                }
+                let id = self.index2id(index);
+                if self.menu_enabled {
+                    all_removed_props.push((id, vec![Str::from_static("enabled")]));
+                } else {
+                    all_updated_props.push((
+                        id,
+                        HashMap::from([(Str::from_static("enabled"), OwnedValue::from(false))]),
+                    ));
+                }
+            }
+        }
+
+        self.volatile_pending |= volatile_changed;
+        // see `Handle::flush_menu`, hosts read the whole layout again
+        let flush =
+            std::mem::take(&mut self.flush_requested) && std::mem::take(&mut self.volatile_pending);
+        // kept if the signal is delayed
+        let shown_ids = (layout_updated && self.shown_menu_ids.is_none())
+            .then(|| self.menu_ids.ids.iter().copied().collect());
+        if layout_updated {
+            // The layout has been changed, allocate new IDs to invalidate all items,
+            // which is required to avoid unexpected behaviors on some system tray
+            self.revision += 1;
+            self.menu_ids
+                .reassign(&self.flattened_menu, &new_menu, self.config.stable_menu_ids);
+            self.click_timestamps.clear();
+            self.volatile_pending = false;
+        } else if flush {
+            self.revision += 1;
+        }
+        let layout_updated = layout_updated || flush;
+        // Always update menu_cache since `on_clicked` can be updated
+        // and we can not detect that
+        // NOTE: update it before sending signals, so it stays consistent
+        // with the revision even if the signals failed to send
+        self.flattened_menu = new_menu;
+        self.schedule_label_refresh();
+        #[cfg(feature = "global-hotkey")]
+        self.bind_global_shortcuts(conn, false);
+
+        let menu_obj = conn
+            .object_server()
+            .interface::<_, DbusMenu<T>>(MENU_PATH)
+            .await?;
+        // a delayed signal carries the revision at the time it's sent
+        let emit_layout = match self
+            .layout_throttle
+            .check(layout_updated, self.config.min_layout_update_interval)
+        {
+            Throttled::Emit => true,
+            Throttled::Delay(delay) => {
+                self.schedule_refresh(delay);
+                false
+            }
+            Throttled::Skip => false,
+        };
+        if emit_layout {
+            self.shown_menu_ids = None;
+        } else if shown_ids.is_some() {
+            self.shown_menu_ids = shown_ids;
+        }
+        // items added by the delayed layout are read with it
+        if let Some(shown) = &self.shown_menu_ids {
+            all_updated_props.retain(|(id, _)| shown.contains(id));
+            all_removed_props.retain(|(id, _)| shown.contains(id));
+        }
+        if emit_layout {
+            self.stats
+                .emit(
+                    MENU_INTERFACE,
+                    "LayoutUpdated",
+                    DbusMenu::<T>::layout_updated(menu_obj.signal_emitter(), self.revision, 0),
+                    || format!("revision {}", self.revision),
+                )
+                .await?;
+        } else if !layout_updated
+            && (!all_updated_props.is_empty() || !all_removed_props.is_empty())
+        {
+            let (updated, removed) = (all_updated_props.len(), all_removed_props.len());
+            self.stats
+                .emit(
+                    MENU_INTERFACE,
+                    "ItemsPropertiesUpdated",
+                    DbusMenu::<T>::items_properties_updated(
+                        menu_obj.signal_emitter(),
+                        all_updated_props,
+                        all_removed_props,
+                    ),
+                    || format!("{updated} updated, {removed} removed"),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn update_hint(&mut self, conn: &Connection, hint: Hint) -> zbus::Result<()> {
+        self.apply_sync_updates();
+        // also covers changes made in callbacks, hosts read the properties again after the signals
+        self.bump_generation();
+        self.prefetch_icon_theme().await;
+        if !matches!(hint, Hint::Menu) {
+            // the properties of the tray are sent from now on
+            self.drop_restored_state();
+        }
+        let start = Instant::now();
+        let r = match hint {
+            Hint::Properties => self.update_properties(conn).await,
+            Hint::Menu => self.update_menu(conn).await,
+            Hint::All => {
+                // a failed signal (e.g. the connection is broken) should not stop the menu from
+                // being updated
+                let r = self.update_properties(conn).await;
+                self.update_menu(conn).await.and(r)
+            }
+        };
+        self.stats.updated(start.elapsed());
+        r
+    }
+
+    pub async fn update(&mut self, conn: &Connection) -> zbus::Result<()> {
+        self.update_hint(conn, Hint::All).await
+    }
+
+    // Return None if item not exists
+    fn id2index(&self, id: i32) -> Option<usize> {
+        self.menu_ids.index(id)
+    }
+
+    fn index2id(&self, index: usize) -> i32 {
+        self.menu_ids.id(index)
+    }
+}
+
+// shortcuts bound with the portal, the binding task stops once `_stop` is dropped
+#[cfg(feature = "global-hotkey")]
+struct BoundShortcuts {
+    shortcuts: Vec<(String, String)>,
+    _stop: Option<oneshot::Sender<()>>,
+}
+
+// ids of the items of the served menu, by index
+//
+// The root is always 0, any other item gets a new id on every layout change. With
+// `TrayServiceBuilder::stable_menu_ids`, items with a key keep the id of the item with the same
+// key in the previous layout. Ids are not reused before they wrap around, an expired id doesn't
+// point to a new item.
+struct MenuIds {
+    ids: Vec<i32>,
+    indices: HashMap<i32, usize>,
+    next: i32,
+}
+
+impl MenuIds {
+    fn new(len: usize) -> Self {
+        let mut ids = Self::with_ids(Vec::new(), 1);
+        ids.renew(len);
+        ids
+    }
+
+    fn with_ids(ids: Vec<i32>, next: i32) -> Self {
+        let indices = ids
+            .iter()
+            .enumerate()
+            .map(|(index, &id)| (id, index))
+            .collect();
+        MenuIds { ids, indices, next }
+    }
+
+    fn allocate(&mut self) -> i32 {
+        loop {
+            let id = self.next;
+            // wraps around, the root id and the ids still in use are skipped
+            self.next = id.checked_add(1).unwrap_or(1);
+            if id > 0 && !self.indices.contains_key(&id) {
+                return id;
+            }
+        }
+    }
+
+    // None if the item doesn't exist, or the id expired
+    fn index(&self, id: i32) -> Option<usize> {
+        self.indices.get(&id).copied()
+    }
+
+    fn id(&self, index: usize) -> i32 {
+        // only out of range in a diff stopped by a layout change, which sends no properties
+        self.ids.get(index).copied().unwrap_or(-1)
+    }
+
+    // new ids for a menu of `len` items, all current ids expire
+    fn renew(&mut self, len: usize) {
+        let ids = (0..len)
+            .map(|index| if index == 0 { 0 } else { self.allocate() })
+            .collect();
+        *self = Self::with_ids(ids, self.next);
+    }
+
+    // ids of `new` after a layout change, all ids of `old` expire unless kept by `stable`
+    fn reassign<T>(
+        &mut self,
+        old: &[(menu::RawMenuItem<T>, Vec<usize>)],
+        new: &[(menu::RawMenuItem<T>, Vec<usize>)],
+        stable: bool,
+    ) {
+        if !stable {
+            self.renew(new.len());
+            return;
+        }
+        let mut kept = HashMap::new();
+        for (index, (item, _)) in old.iter().enumerate().skip(1) {
+            if let Some(key) = item.key() {
+                kept.entry(key).or_insert(self.id(index));
+            }
+        }
+        // the kept ids are still in use, new ones can't collide with them
+        let ids = new
+            .iter()
+            .enumerate()
+            .map(|(index, (item, _))| {
+                if index == 0 {
+                    return 0;
+                }
+                item.key()
+                    .and_then(|key| kept.remove(key))
+                    .unwrap_or_else(|| self.allocate())
+            })
+            .collect();
+        *self = Self::with_ids(ids, self.next);
+    }
+}
+
+// dbus methods
+impl<T: Tray> Service<T> {
+    /// Build a menu tree from flattened menu
+    /// Return None if parent_id not found
+    pub fn build_layout(
+        &self,
+        parent_id: i32,
+        recursion_depth: Option<usize>,
+        property_names: Vec<String>,
+    ) -> Option<Layout> {
+        let root = self.id2index(parent_id)?;
+
+        let mut items: Vec<Option<(Layout, Vec<usize>)>> = self
+            .flattened_menu
             .iter()
             .enumerate()
             .map(|(index, (item, submenu))| {
+                let mut properties = self.item_properties(index, item, &property_names);
+                // NOTE: decided by the menu instead of the children in the layout,
+                // which may have been cut by recursion_depth
+                if !submenu.is_empty() {
+                    properties.insert(
+                        "children-display".into(),
+                        Str::from_static("submenu").into(),
+                    );
+                }
                 (
                     Layout {
                         id: self.index2id(index),
-                        properties: item.to_dbus_map(&property_names),
+                        properties,
                         children: Vec::with_capacity(submenu.len()),
                     },
                     submenu.clone(),
@@ -383,19 +1704,13 @@ impl<T: Tray> Service<T> {
 
         // depth first
         while let Some(current) = stack.pop() {
-            let (layout, pending_children) = &mut items[current]
+            let (_, pending_children) = &mut items[current]
                 .as_mut()
                 .expect("stack pointer should always point to a valid item");
             if pending_children.is_empty() {
-                if !layout.children.is_empty() {
-                    layout.properties.insert(
-                        "children-display".into(),
-                        Str::from_static("submenu").into(),
-                    );
-                }
                 // if there's a parent, move current to parent's children
                 if let Some(parent) = stack.pop() {
-                    let current = std::mem::replace(&mut items[current], None);
+                    let current = items[current].take();
                     let layout = current.expect("should have been unwrapped once already").0;
                     stack.push(parent);
                     items[parent]
@@ -410,7 +1725,8 @@ impl<T: Tray> Service<T> {
             } else {
                 stack.push(current);
                 let child = pending_children.remove(0);
-                if recursion_depth.map_or(true, |depth| depth + 1 >= stack.len()) {
+                // stack.len() is the depth of the child, root's children are level 1
+                if recursion_depth.map_or(true, |depth| depth >= stack.len()) {
                     stack.push(child);
                 }
             }
@@ -419,91 +1735,493 @@ impl<T: Tray> Service<T> {
         Some(root_item.0)
     }
 
-    pub fn get_menu_item(
+    // `RawMenuItem::to_dbus_map` with the override of `Handle::set_menu_enabled`
+    fn item_properties(
         &self,
-        id: i32,
+        index: usize,
+        item: &menu::RawMenuItem<T>,
         property_filter: &[String],
-    ) -> Option<HashMap<String, OwnedValue>> {
-        self.id2index(id)
-            .map(|index| self.flattened_menu[index].0.to_dbus_map(property_filter))
-    }
+    ) -> menu::PropertyMap {
+        let mut properties = item.to_dbus_map(property_filter);
+        if !self.menu_enabled
+            && index != 0
+            && (property_filter.is_empty() || property_filter.iter().any(|name| name == "enabled"))
+        {
+            properties.insert(Str::from_static("enabled"), OwnedValue::from(false));
+        }
+        properties
+    }
+
+    pub fn get_menu_item(&self, id: i32, property_filter: &[String]) -> Option<menu::PropertyMap> {
+        self.id2index(id).map(|index| {
+            let (item, submenu) = &self.flattened_menu[index];
+            let mut properties = self.item_properties(index, item, property_filter);
+            if !submenu.is_empty()
+                && (property_filter.is_empty()
+                    || property_filter
+                        .iter()
+                        .any(|name| name == "children-display"))
+            {
+                properties.insert(
+                    "children-display".into(),
+                    Str::from_static("submenu").into(),
+                );
+            }
+            properties
+        })
+    }
 
-    pub async fn event(
+    // returns true if a callback of the tray was called and the service should be updated, see
+    // `StandardItem::no_auto_update`
+    pub fn event(
         &mut self,
-        conn: &Connection,
-        do_update: bool,
         id: i32,
         event_id: &str,
-        _data: OwnedValue,
-        _timestamp: u32,
-    ) -> zbus::fdo::Result<()> {
+        data: OwnedValue,
+        timestamp: u32,
+    ) -> zbus::fdo::Result<bool> {
+        if event_id == "clicked" {
+            assert_ne!(id, 0, "ROOT MENU ITEM CLICKED");
+            let index = self
+                .id2index(id)
+                .ok_or_else(|| zbus::fdo::Error::InvalidArgs("id not found".to_string()))?;
+            self.stats.menu_clicked();
+            // shown as disabled, the host may still send clicks
+            if !self.menu_enabled {
+                return Ok(false);
+            }
+            if !self
+                .click_debounce
+                .accept(id, self.config.activate_debounce)
+            {
+                return Ok(false);
+            }
+            if self.config.drop_stale_menu_events && timestamp != 0 {
+                let last = self.click_timestamps.entry(id).or_insert(timestamp);
+                // X11 timestamps wrap around
+                if (last.wrapping_sub(timestamp) as i32) > 0 {
+                    return Ok(false);
+                }
+                *last = timestamp;
+            }
+            return Ok(menu::with_event_timestamp(timestamp, || {
+                self.activate_index(index)
+            }));
+        }
+        let index = self
+            .id2index(id)
+            .ok_or_else(|| zbus::fdo::Error::InvalidArgs("id not found".to_string()))?;
+        let path = self.flattened_menu[index].0.path.clone();
         match event_id {
-            "clicked" => {
-                assert_ne!(id, 0, "ROOT MENU ITEM CLICKED");
-                let index = self
-                    .id2index(id)
-                    .ok_or_else(|| zbus::fdo::Error::InvalidArgs("id not found".to_string()))?;
-                (self.flattened_menu[index].0.on_clicked)(&mut self.tray, index);
-                if do_update {
-                    self.update(&conn).await?;
+            "opened" => self.tray.menu_opened(&path),
+            "closed" => self.tray.menu_closed(&path),
+            "hovered" => return Ok(false),
+            _ => {
+                let data = menu::MenuEventData::from(data);
+                if !self.tray.menu_event(&path, event_id, &data) {
+                    self.tray.unknown_menu_event(&path, event_id);
+                    return Ok(false);
                 }
             }
+        }
+        Ok(true)
+    }
+
+    pub fn blocking_callbacks(&self) -> bool {
+        self.config.blocking_callbacks
+    }
+
+    // see `TrayServiceBuilder::shutdown_on_panic`, the panic is resumed after asking the service
+    // task to shutdown, which can't catch the panics of D-Bus calls
+    pub fn shutdown_on_panic<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+        if !self.config.shutdown_on_panic {
+            return f(self);
+        }
+        let sender = self.refresh_sender.clone();
+        match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| f(self))) {
+            Ok(r) => r,
+            Err(e) => {
+                let (tx, _) = oneshot::channel();
+                let _ = sender.send(HandleReuest::Shutdown(tx));
+                std::panic::resume_unwind(e)
+            }
+        }
+    }
+
+    // returns true if the tray was called, same for the other `call_*`
+    pub fn call_activate(&mut self, x: i32, y: i32) -> bool {
+        use crate::HostEvent;
+        let now = compat::now();
+        if self.config.event_map.contains_key(&HostEvent::DoubleClick)
+            && self
+                .last_activate
+                .is_some_and(|last| now.duration_since(last) < DOUBLE_CLICK_INTERVAL)
+        {
+            self.last_activate = None;
+            return self.dispatch(HostEvent::DoubleClick, x, y);
+        }
+        self.last_activate = Some(now);
+        if !self
+            .activate_debounce
+            .accept((), self.config.activate_debounce)
+        {
+            return false;
+        }
+        self.dispatch(HostEvent::Activate, x, y)
+    }
+
+    pub fn provide_xdg_activation_token(&mut self, token: String) {
+        self.xdg_activation_token = Some(token);
+    }
+
+    pub fn call_secondary_activate(&mut self, x: i32, y: i32) -> bool {
+        if !self
+            .secondary_activate_debounce
+            .accept((), self.config.activate_debounce)
+        {
+            // the token belongs to this activation
+            self.xdg_activation_token = None;
+            return false;
+        }
+        self.dispatch(crate::HostEvent::SecondaryActivate, x, y)
+    }
+
+    // calls the action mapped to `event`, see `TrayServiceBuilder::map_event`
+    fn dispatch(&mut self, event: crate::HostEvent, x: i32, y: i32) -> bool {
+        use crate::{HostEvent, TrayAction};
+        let action = self
+            .config
+            .event_map
+            .get(&event)
+            .copied()
+            .unwrap_or(match event {
+                HostEvent::Activate | HostEvent::DoubleClick => TrayAction::Activate,
+                HostEvent::SecondaryActivate => TrayAction::SecondaryActivate,
+            });
+        let xdg_activation_token = self.xdg_activation_token.take();
+        match action {
+            TrayAction::Activate => {
+                self.tray.activate_with_info(crate::ActivateInfo {
+                    x,
+                    y,
+                    xdg_activation_token,
+                });
+                true
+            }
+            TrayAction::SecondaryActivate => {
+                self.tray.secondary_activate(x, y);
+                true
+            }
+            TrayAction::Ignore => false,
+        }
+    }
+
+    // (trigger, description) of the menu items with a shortcut
+    #[cfg(feature = "global-hotkey")]
+    fn global_shortcuts(&self) -> Vec<(String, String)> {
+        let mut shortcuts: Vec<(String, String)> = Vec::new();
+        for (item, _) in &self.flattened_menu {
+            if let Some(trigger) = crate::global_shortcuts::portal_trigger(item.shortcut()) {
+                if !shortcuts.iter().any(|(t, _)| *t == trigger) {
+                    shortcuts.push((trigger, item.label().replace('_', "")));
+                }
+            }
+        }
+        shortcuts
+    }
+
+    // bind the shortcuts of the menu with the portal, again if they are changed by a menu update.
+    // `reconnected` binds them on a new connection of the service loop
+    #[cfg(feature = "global-hotkey")]
+    fn bind_global_shortcuts(&mut self, conn: &Connection, reconnected: bool) {
+        let shortcuts = self.global_shortcuts();
+        match &self.bound_shortcuts {
+            // not served by the service loop, e.g. `RawTray`
+            None if !reconnected => return,
+            Some(bound) if !reconnected && bound.shortcuts == shortcuts => return,
             _ => (),
         }
-        Ok(())
+        // the previous binding task stops once its sender is replaced
+        let stop = (!shortcuts.is_empty()).then(|| {
+            let (stop_sender, stop) = oneshot::channel();
+            let conn = conn.clone();
+            let shortcuts = shortcuts.clone();
+            let sender = self.refresh_sender.clone();
+            compat::spawn(async move {
+                // the portal is optional, shortcuts are still displayed as hints without it
+                let _ = crate::global_shortcuts::bind(conn, shortcuts, sender, stop).await;
+            });
+            stop_sender
+        });
+        self.bound_shortcuts = Some(BoundShortcuts {
+            shortcuts,
+            _stop: stop,
+        });
     }
 
-    pub async fn call_activate(&mut self, conn: &Connection, x: i32, y: i32) {
-        self.tray.activate(x, y);
-        let _ = self.update(conn).await;
+    #[cfg(feature = "global-hotkey")]
+    async fn call_global_shortcut(&mut self, conn: &Connection, trigger: &str) {
+        if !self.menu_enabled {
+            return;
+        }
+        let index = self.flattened_menu.iter().position(|(item, _)| {
+            item.enabled()
+                && crate::global_shortcuts::portal_trigger(item.shortcut()).as_deref()
+                    == Some(trigger)
+        });
+        if let Some(index) = index {
+            if self.activate_index(index) {
+                let _ = self.update(conn).await;
+            }
+        }
     }
 
-    pub async fn call_secondary_activate(&mut self, conn: &Connection, x: i32, y: i32) {
-        self.tray.secondary_activate(x, y);
-        let _ = self.update(conn).await;
+    // calls the item like a click of the host, returns true if the tray should be updated
+    fn activate_index(&mut self, index: usize) -> bool {
+        let (item, _) = &self.flattened_menu[index];
+        // see `StandardItem::confirm`, the choices are hidden by the next update
+        let confirming = self.confirming.take();
+        let index = match item.confirm_choice {
+            Some(true) => {
+                let confirmed = self.flattened_menu.iter().position(|(item, _)| {
+                    Some(&item.path) == confirming.as_ref() && item.confirm.is_some()
+                });
+                match confirmed {
+                    Some(index) => index,
+                    None => return true,
+                }
+            }
+            Some(false) => return true,
+            None if item.confirm.is_some() => {
+                self.confirming = Some(item.path.clone());
+                return confirming.as_ref() != Some(&item.path);
+            }
+            None => index,
+        };
+        let (item, _) = &self.flattened_menu[index];
+        (item.on_clicked)(&mut self.tray, &item.path);
+        self.tray.menu_item_activated(&item.path);
+        !item.no_auto_update || confirming.is_some()
     }
 
-    pub async fn call_scroll(
-        &mut self,
-        conn: &Connection,
-        delta: i32,
-        orientation: crate::Orientation,
-    ) {
-        self.tray.scroll(delta, orientation);
-        let _ = self.update(conn).await;
+    // see `Handle::activate_item`, returns `None` if there's no enabled item at `path`
+    pub fn activate_item(&mut self, path: &menu::MenuPath) -> Option<bool> {
+        if !self.menu_enabled || path.is_root() {
+            return None;
+        }
+        let index = self.flattened_menu.iter().position(|(item, _)| {
+            item.path == *path && item.enabled() && item.confirm_choice.is_none()
+        })?;
+        Some(self.activate_index(index))
+    }
+
+    pub fn call_scroll(&mut self, delta: i32, orientation: crate::Orientation) -> bool {
+        self.tray
+            .scroll_event(crate::ScrollEvent { delta, orientation });
+        true
     }
 }
 
+// whether a property keeps its value between updates, see `def_properties_monitor`
+macro_rules! is_cached {
+    () => {
+        false
+    };
+    (cached) => {
+        true
+    };
+}
+
+// the `Tray` method of a property, or its getter taking the desktop profile
+macro_rules! tray_property {
+    ($tray:expr, $profile:expr, $name:ident) => {
+        $tray.$name()
+    };
+    ($tray:expr, $profile:expr, $name:ident, $getter:path) => {
+        $getter($tray, $profile)
+    };
+}
+
 macro_rules! def_properties_monitor {
-    ($( $name:ident : $type:path ),+) => {
+    ($( $(#[$cached:ident])? $name:ident : $type:path $(=> $getter:path)? ),+) => {
         struct PropertiesMonitor {
+            // not tracked, see `Tray::const_properties`
+            const_properties: PropertyFlags,
+            // passed to the getters, see `TrayServiceBuilder::desktop_profile`
+            profile: crate::DesktopProfile,
+            // values of `#[cached]` properties, replaced by the diff pass
+            cache: PropertyCache,
             $($name: AtomicU64),*
         }
 
+        #[derive(Default)]
+        struct PropertyCache {
+            $($name: std::sync::Mutex<Option<$type>>),*
+        }
+
         impl PropertiesMonitor {
-            fn new<T: Tray>(tray: &T) -> Self {
-                Self {
-                    $($name: AtomicU64::new(hash_of(tray.$name()))),*
+            fn new<T: Tray>(tray: &T, profile: crate::DesktopProfile) -> Self {
+                let const_properties = tray.const_properties();
+                paste! {
+                    Self {
+                        const_properties,
+                        profile,
+                        cache: PropertyCache::default(),
+                        $($name: AtomicU64::new(
+                            if const_properties.contains(PropertyFlags::[<$name:upper>]) {
+                                0
+                            } else {
+                                hash_of(tray_property!(tray, profile, $name $(, $getter)?))
+                            }
+                        )),*
+                    }
+                }
+            }
+
+            // drops the cached values, they are read from the tray again
+            fn invalidate(&self, properties: PropertyFlags) {
+                paste! {
+                    $(
+                        if properties.contains(PropertyFlags::[<$name:upper>]) {
+                            *lock(&self.cache.$name) = None;
+                        }
+                    )*
+                }
+            }
+        }
+        paste! {
+            /// A value replacing the result of a [`Tray`] property method
+            ///
+            /// See [`Handle::override_property`]
+            ///
+            /// [`Handle::override_property`]: crate::Handle::override_property
+            #[derive(Clone, Debug)]
+            #[non_exhaustive]
+            pub enum PropertyValue {
+                $(
+                    #[doc = concat!("Replaces [`Tray::", stringify!($name), "`]")]
+                    [<$name:camel>]($type),
+                )*
+            }
+
+            #[derive(Default)]
+            struct Overrides {
+                $($name: Option<$type>),*
+            }
+
+            impl PropertyValue {
+                fn flag(&self) -> PropertyFlags {
+                    match self {
+                        $(PropertyValue::[<$name:camel>](_) => PropertyFlags::[<$name:upper>],)*
+                    }
+                }
+            }
+        }
+
+        impl<T> Service<T> {
+            paste! {
+                pub fn set_override(&mut self, value: PropertyValue) {
+                    match value {
+                        $(PropertyValue::[<$name:camel>](v) => {
+                            self.overrides.$name = Some(v);
+                            self.prop_monitor.invalidate(PropertyFlags::[<$name:upper>]);
+                            self.bump_generation();
+                            // kept after the first update
+                            self.restored =
+                                self.restored.difference(PropertyFlags::[<$name:upper>]);
+                        })*
+                    }
+                }
+
+                pub fn clear_overrides(&mut self, properties: PropertyFlags) {
+                    $(
+                        if properties.contains(PropertyFlags::[<$name:upper>]) {
+                            self.overrides.$name = None;
+                        }
+                    )*
+                    self.prop_monitor.invalidate(properties);
+                    self.bump_generation();
                 }
+
             }
         }
+
         impl<T: Tray> Service<T> {
             paste! {
+                // properties of `tray` differing from the last values, nothing is stored
+                fn changed_properties(&self, tray: &T) -> PropertyFlags {
+                    let mut changed = PropertyFlags::empty();
+                    $(
+                        let flag = PropertyFlags::[<$name:upper>];
+                        if !self.prop_monitor.const_properties.contains(flag) {
+                            let new = match &self.overrides.$name {
+                                Some(v) => hash_of(v),
+                                None => hash_of(tray_property!(
+                                    tray,
+                                    self.prop_monitor.profile,
+                                    $name $(, $getter)?
+                                )),
+                            };
+                            if new != self.prop_monitor.$name.load(Ordering::Acquire) {
+                                changed |= flag;
+                            }
+                        }
+                    )*
+                    changed
+                }
+
                 $(
+                    // the override, or the value from the tray
+                    fn [<current_ $name>](&self) -> $type {
+                        match &self.overrides.$name {
+                            Some(v) => v.clone(),
+                            None => tray_property!(
+                                &self.tray,
+                                self.prop_monitor.profile,
+                                $name $(, $getter)?
+                            ),
+                        }
+                    }
+
+                    // the hash of the value returned by the getter, if it's served from the
+                    // cache, see `Service::encoded`
+                    #[allow(dead_code)]
+                    fn [<$name _cached_hash>](&self) -> Option<u64> {
+                        lock(&self.prop_monitor.cache.$name)
+                            .is_some()
+                            .then(|| self.prop_monitor.$name.load(Ordering::Acquire))
+                    }
+
                     /// generated by def_properties_monitor
                     pub fn [<$name _changed>](&self) -> bool {
-                        let new = hash_of(self.tray.$name());
+                        if self.prop_monitor.const_properties.contains(PropertyFlags::[<$name:upper>]) {
+                            return false;
+                        }
+                        let value = self.[<current_ $name>]();
+                        let new = hash_of(&value);
                         // TODO: Relaxed should be fine
                         let old = self.prop_monitor.$name.swap(new, Ordering::AcqRel);
+                        if is_cached!($($cached)?) {
+                            *lock(&self.prop_monitor.cache.$name) = Some(value);
+                        }
                         new != old
                     }
                     /// generated by def_properties_monitor
+                    // `icon_scale_hint` isn't a D-Bus property, only its changes are tracked
+                    #[allow(dead_code)]
                     pub fn [<get_ $name>](&self) -> $type {
-                        let r = self.tray.$name();
-                        self.prop_monitor.$name.store(
-                            hash_of(self.tray.$name()),
-                            Ordering::Release,
-                        );
+                        if is_cached!($($cached)?) {
+                            if let Some(v) = &*lock(&self.prop_monitor.cache.$name) {
+                                return v.clone();
+                            }
+                        }
+                        let r = self.[<current_ $name>]();
+                        if !self.prop_monitor.const_properties.contains(PropertyFlags::[<$name:upper>]) {
+                            self.prop_monitor.$name.store(hash_of(&r), Ordering::Release);
+                        }
+                        if is_cached!($($cached)?) {
+                            *lock(&self.prop_monitor.cache.$name) = Some(r.clone());
+                        }
                         r
                     }
                 )*
@@ -513,21 +2231,210 @@ macro_rules! def_properties_monitor {
 }
 
 // dbus properties monitor, tracks hash of properties
+//
+// `#[cached]` properties are expensive to build, their value is kept from the last diff pass
+// and property reads are served from it instead of calling the tray. Their `*_changed` must be
+// called on every diff pass, or the cache keeps the previous value. The pixmaps are also kept
+// converted to `Value`, see `Service::encoded`
 def_properties_monitor! {
     category: crate::Category,
     title: String,
     status: crate::Status,
     window_id: i32,
     icon_theme_path: String,
-    icon_name: String,
+    icon_name: String => resolved_icon_name,
+    #[cached]
     icon_pixmap: Vec<crate::Icon>,
+    icon_scale_hint: Option<u32>,
+    icon_accessible_desc: String,
     overlay_icon_name: String,
+    #[cached]
     overlay_icon_pixmap: Vec<crate::Icon>,
     attention_icon_name: String,
+    #[cached]
     attention_icon_pixmap: Vec<crate::Icon>,
     attention_movie_name: String,
-    tool_tip: crate::ToolTip,
-    text_direction: crate::TextDirection
+    attention_accessible_desc: String,
+    attention_level: crate::AttentionLevel,
+    #[cached]
+    tool_tip: crate::ToolTip => shown_tool_tip,
+    text_direction: crate::TextDirection,
+    label: String,
+    label_guide: String
+}
+
+// `Tray::icon_name`, or the first of `Tray::icon_names` the icon theme has. The theme is read by
+// `Service::prefetch_icon_theme`
+fn resolved_icon_name<T: Tray>(tray: &T, _profile: crate::DesktopProfile) -> String {
+    let name = tray.icon_name();
+    if name.is_empty() {
+        return crate::icons::resolve_cached(&tray.icon_names());
+    }
+    name
+}
+
+// `Tray::tool_tip_opt`, a cleared tooltip is sent empty
+fn shown_tool_tip<T: Tray>(tray: &T, _profile: crate::DesktopProfile) -> crate::ToolTip {
+    tray.tool_tip_opt().unwrap_or_default()
+}
+
+/// Properties and menu ids of a running tray, see [`Handle::export_state`]
+///
+/// [`Handle::export_state`]: crate::Handle::export_state
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrayStateBlob {
+    revision: u32,
+    menu_ids: Vec<i32>,
+    next_menu_id: i32,
+    properties: HashMap<String, OwnedValue>,
+}
+
+impl TrayStateBlob {
+    /// Encode the state to be saved, e.g. to a file or passed to the new process
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let state = (
+            STATE_VERSION,
+            self.revision,
+            &self.menu_ids,
+            self.next_menu_id,
+            &self.properties,
+        );
+        zbus::zvariant::to_bytes(blob_context(), &state)
+            .expect("the properties of a tray can always be encoded")
+            .to_vec()
+    }
+
+    /// Returns `None` if `bytes` is not a state from [`Self::to_bytes`] of this version of ksni
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let data = zbus::zvariant::serialized::Data::new(bytes, blob_context());
+        let ((version, revision, menu_ids, next_menu_id, properties), _) =
+            data.deserialize::<(u8, _, Vec<i32>, _, _)>().ok()?;
+        if version != STATE_VERSION || !valid_menu_ids(&menu_ids, next_menu_id) {
+            return None;
+        }
+        Some(TrayStateBlob {
+            revision,
+            menu_ids,
+            next_menu_id,
+            properties,
+        })
+    }
+}
+
+// the restored ids are used as they are, new ones are allocated from `next`
+fn valid_menu_ids(ids: &[i32], next: i32) -> bool {
+    let mut seen = HashSet::new();
+    next > 0 && ids.first() == Some(&0) && ids[1..].iter().all(|&id| id > 0 && seen.insert(id))
+}
+
+fn blob_context() -> zbus::zvariant::serialized::Context {
+    zbus::zvariant::serialized::Context::new_dbus(zbus::zvariant::LE, 0)
+}
+
+impl<T: Tray> Service<T> {
+    // see `Handle::export_state`, the values sent to the host
+    pub fn export_state(&self) -> TrayStateBlob {
+        fn value<V: Into<zbus::zvariant::Value<'static>>>(v: V) -> OwnedValue {
+            OwnedValue::try_from(v.into()).expect("a value without file descriptors")
+        }
+        let properties = HashMap::from([
+            ("Title".to_owned(), value(self.get_title())),
+            ("Status".to_owned(), value(self.get_status())),
+            ("IconName".to_owned(), value(self.get_icon_name())),
+            ("IconPixmap".to_owned(), value(self.get_icon_pixmap())),
+            ("ToolTip".to_owned(), value(self.get_tool_tip())),
+        ]);
+        TrayStateBlob {
+            revision: self.revision,
+            menu_ids: self.menu_ids.ids.clone(),
+            next_menu_id: self.menu_ids.next,
+            properties,
+        }
+    }
+}
+
+impl<T> Service<T> {
+    // the tray or the overrides are changed, property reads must not be served from an older
+    // snapshot
+    pub fn bump_generation(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    // see `TrayServiceBuilder::restore_state`, the properties are overridden until the first
+    // update
+    fn restore_state(&mut self, state: TrayStateBlob) {
+        self.revision = state.revision;
+        // kept if the menu looks the same, otherwise all restored ids expire
+        let mut restored = MenuIds::with_ids(state.menu_ids, state.next_menu_id);
+        if restored.ids.len() != self.flattened_menu.len() {
+            restored.renew(self.flattened_menu.len());
+        }
+        self.menu_ids = restored;
+        let mut properties = state.properties;
+        let mut take = |name: &str| properties.remove(name);
+        let values = [
+            take("Title")
+                .and_then(|v| String::try_from(v).ok())
+                .map(PropertyValue::Title),
+            take("Status")
+                .and_then(|v| String::try_from(v).ok())
+                .and_then(|v| crate::Status::from_name(&v))
+                .map(PropertyValue::Status),
+            take("IconName")
+                .and_then(|v| String::try_from(v).ok())
+                .map(PropertyValue::IconName),
+            take("IconPixmap")
+                .and_then(|v| Vec::<crate::Icon>::try_from(v).ok())
+                .map(PropertyValue::IconPixmap),
+            take("ToolTip")
+                .and_then(|v| crate::ToolTip::try_from(zbus::zvariant::Value::from(v)).ok())
+                .map(PropertyValue::ToolTip),
+        ];
+        let mut restored = PropertyFlags::empty();
+        for value in values.into_iter().flatten() {
+            restored |= value.flag();
+            self.set_override(value);
+        }
+        self.restored = restored;
+    }
+
+    // the app has updated the tray, its values replace the restored ones
+    //
+    // Returns false if nothing was restored.
+    pub fn drop_restored_state(&mut self) -> bool {
+        if self.restored.is_empty() {
+            return false;
+        }
+        let restored = std::mem::take(&mut self.restored);
+        self.clear_overrides(restored);
+        true
+    }
+
+    /// Returns the old tray
+    pub fn replace_tray(&mut self, tray: T) -> T {
+        self.prop_monitor.invalidate(PropertyFlags::all());
+        self.bump_generation();
+        std::mem::replace(&mut self.tray, tray)
+    }
+
+    // see `Handle::set_menu_enabled`, returns false if it's unchanged
+    pub fn set_menu_enabled(&mut self, enabled: bool) -> bool {
+        if self.menu_enabled == enabled {
+            return false;
+        }
+        self.menu_enabled = enabled;
+        self.menu_enabled_changed = !self.menu_enabled_changed;
+        true
+    }
+
+    // run the closures of `Handle::try_update_sync` in order, before any later change
+    pub fn apply_sync_updates(&mut self) {
+        run_sync_updates(&self.sync_updates, &mut self.tray);
+    }
 }
 
 impl<T: Tray> Service<T> {
@@ -536,6 +2443,358 @@ impl<T: Tray> Service<T> {
     pub fn get_id(&self) -> String {
         self.tray.id()
     }
+
+    // see `TrayServiceBuilder::ayatana_compat`
+    pub fn ayatana_label(&self) -> (String, String) {
+        if self.config.ayatana_compat {
+            (self.get_label(), self.get_label_guide())
+        } else {
+            Default::default()
+        }
+    }
+
+    /// Path of the dbusmenu, see `Tray::external_menu_path`
+    pub fn menu_path(&self) -> ObjectPath<'static> {
+        self.external_menu_path.clone().unwrap_or(MENU_PATH)
+    }
+
+    /// `Tray::icon_name`, hidden by `TrayServiceBuilder::prefer_icon_pixmap`
+    pub fn shown_icon_name(&self) -> String {
+        if self.prefer_icon_pixmap && !self.get_icon_pixmap().is_empty() {
+            return String::new();
+        }
+        self.get_icon_name()
+    }
+
+    /// `Tray::icon_pixmap`, or the pixmaps of `Tray::icon_name` in the icon theme with the
+    /// "icon-theme" feature
+    pub fn shown_icon_pixmap(&self) -> Vec<crate::Icon> {
+        self.or_themed(self.get_icon_pixmap(), || self.get_icon_name())
+    }
+
+    /// `Tray::attention_level_icon_name` of the current level or a lower one, falls back to
+    /// `Tray::attention_icon_name`
+    pub fn shown_attention_icon_name(&self) -> String {
+        self.attention_icon_name_of(self.get_attention_level(), || {
+            self.get_attention_icon_name()
+        })
+    }
+
+    fn attention_icon_name_of(
+        &self,
+        level: crate::AttentionLevel,
+        fallback: impl FnOnce() -> String,
+    ) -> String {
+        use crate::AttentionLevel::*;
+        [Critical, Warning]
+            .into_iter()
+            .filter(|l| *l <= level)
+            .map(|l| self.tray.attention_level_icon_name(l))
+            .find(|name| !name.is_empty())
+            .unwrap_or_else(fallback)
+    }
+
+    /// Same as `shown_icon_pixmap`, for the attention icon
+    pub fn shown_attention_icon_pixmap(&self) -> Vec<crate::Icon> {
+        self.or_themed(self.get_attention_icon_pixmap(), || {
+            self.shown_attention_icon_name()
+        })
+    }
+
+    #[allow(unused_variables)]
+    fn or_themed(
+        &self,
+        pixmap: Vec<crate::Icon>,
+        name: impl FnOnce() -> String,
+    ) -> Vec<crate::Icon> {
+        #[cfg(feature = "icon-theme")]
+        if pixmap.is_empty() {
+            let name = name();
+            if !name.is_empty() {
+                // not looked up yet if the name was changed by another task since the update
+                return crate::icon_theme::cached_lookup(&name).unwrap_or_default();
+            }
+        }
+        pixmap
+    }
+
+    // `Tray::icon_names` are resolved and the pixmaps of the icon names are read off the runtime,
+    // `resolved_icon_name` and `or_themed` only use the cache. Called before the diff pass, the
+    // names are not stored. The future doesn't borrow the service, it can be awaited after
+    // unlocking it
+    fn prefetch_icon_theme(&self) -> impl Future<Output = ()> + Send + 'static {
+        #[cfg(feature = "icon-theme")]
+        let names = {
+            // resolved on the blocking thread, see `resolved_icon_name`
+            let icon_names = match &self.overrides.icon_name {
+                Some(name) => vec![name.clone()],
+                None => match self.tray.icon_name() {
+                    name if name.is_empty() => self.tray.icon_names(),
+                    name => vec![name],
+                },
+            };
+            let attention_icon_name = self
+                .attention_icon_name_of(self.current_attention_level(), || {
+                    self.current_attention_icon_name()
+                });
+            vec![icon_names, vec![attention_icon_name]]
+        };
+        async move {
+            #[cfg(feature = "icon-theme")]
+            crate::icon_theme::prefetch(names).await;
+        }
+    }
+
+    /// `value` converted to a `Value`, cached by `key`
+    ///
+    /// zbus converts every byte of a pixmap to a `Value` on every read, the pixmaps are only
+    /// converted once per change. `key` is `None` if the value isn't cached, it's checked again
+    /// after `value` stored the hash of what it read.
+    pub fn encoded<V: Into<Value<'static>>>(
+        &self,
+        key: impl Fn() -> Option<u64>,
+        value: impl FnOnce() -> V,
+    ) -> Encoded<V> {
+        let clone = |v: &OwnedValue| v.try_clone().expect("pixmaps have no file descriptors");
+        if let Some(v) = key().and_then(|key| lock(&self.encoded_properties).get(&key).map(clone)) {
+            return Encoded::new(v);
+        }
+        let v = OwnedValue::try_from(value().into()).expect("pixmaps have no file descriptors");
+        if let Some(key) = key() {
+            let mut encoded = lock(&self.encoded_properties);
+            // only the current properties are needed
+            if encoded.len() >= MAX_ENCODED_PROPERTIES {
+                encoded.clear();
+            }
+            encoded.insert(key, clone(&v));
+        }
+        Encoded::new(v)
+    }
+
+    // keys of `Service::encoded`, from all that's read to build the property
+    pub fn icon_pixmap_key(&self) -> Option<u64> {
+        let themed = self.themed_key(|| self.current_icon_name());
+        let key = ("IconPixmap", self.icon_pixmap_cached_hash()?, themed);
+        Some(hash_of((key, self.current_icon_scale_hint())))
+    }
+
+    pub fn overlay_icon_pixmap_key(&self) -> Option<u64> {
+        let key = ("OverlayIconPixmap", self.overlay_icon_pixmap_cached_hash()?);
+        Some(hash_of((key, self.current_icon_scale_hint())))
+    }
+
+    pub fn attention_icon_pixmap_key(&self) -> Option<u64> {
+        let themed = self.themed_key(|| self.shown_attention_icon_name());
+        let key = (
+            "AttentionIconPixmap",
+            self.attention_icon_pixmap_cached_hash()?,
+            themed,
+        );
+        Some(hash_of((key, self.current_icon_scale_hint())))
+    }
+
+    pub fn tool_tip_key(&self) -> Option<u64> {
+        let key = ("ToolTip", self.tool_tip_cached_hash()?);
+        Some(hash_of((key, self.current_icon_scale_hint())))
+    }
+
+    // the icon name looked up if the pixmap is empty, see `or_themed`
+    #[allow(unused_variables)]
+    fn themed_key(&self, name: impl FnOnce() -> String) -> Option<(String, bool)> {
+        #[cfg(feature = "icon-theme")]
+        let key = {
+            let name = name();
+            let cached = crate::icon_theme::is_cached(&name);
+            Some((name, cached))
+        };
+        #[cfg(not(feature = "icon-theme"))]
+        let key = None;
+        key
+    }
+
+    /// Downscale pixmaps to `TrayServiceBuilder::max_icon_size`, then reorder them according to
+    /// `Tray::icon_scale_hint`
+    pub fn order_pixmap(&self, mut icons: Vec<crate::Icon>) -> Vec<crate::Icon> {
+        if let Some(max_size) = self.config.max_icon_size {
+            icons = self.downscale_pixmap(icons, max_size);
+        }
+        // the hash is stored by the diff pass, which also signals the reordered pixmaps
+        if let Some(scale) = self.current_icon_scale_hint() {
+            crate::tray::sort_icons_for_scale(&mut icons, scale);
+        }
+        icons
+    }
+}
+
+impl<T: Tray> Service<T> {
+    // the scaled pixmaps are kept, the properties are read again for every host
+    fn downscale_pixmap(&self, icons: Vec<crate::Icon>, max_size: u32) -> Vec<crate::Icon> {
+        let max = max_size as i64;
+        if icons
+            .iter()
+            .all(|icon| i64::from(icon.width.max(icon.height)) <= max)
+        {
+            return icons;
+        }
+        let key = hash_of(&icons);
+        let mut scaled = lock(&self.scaled_pixmaps);
+        if let Some(icons) = scaled.get(&key) {
+            return icons.clone();
+        }
+        // only the pixmaps of the current properties are needed
+        if scaled.len() >= MAX_SCALED_PIXMAPS {
+            scaled.clear();
+        }
+        let icons: Vec<_> = icons
+            .into_iter()
+            .map(|icon| match icon.downscale(max_size) {
+                Some(small) => {
+                    if lock(&self.downscaled_icons).insert((icon.width, icon.height)) {
+                        self.tray.on_icon_downscaled(icon.width, icon.height);
+                    }
+                    small
+                }
+                None => icon,
+            })
+            .collect();
+        scaled.insert(key, icons.clone());
+        icons
+    }
+}
+
+pub type SyncUpdates<T> = Arc<std::sync::Mutex<VecDeque<Box<dyn FnOnce(&mut T) + Send>>>>;
+
+/// Limits how often a signal is emitted, the last change is always delivered
+#[derive(Default)]
+struct Throttle {
+    last_emitted: Option<Instant>,
+    // a refresh has been scheduled
+    pending: bool,
+}
+
+enum Throttled {
+    Emit,
+    Delay(Duration),
+    Skip,
+}
+
+impl Throttle {
+    fn check(&mut self, changed: bool, interval: Option<Duration>) -> Throttled {
+        if !changed && !self.pending {
+            return Throttled::Skip;
+        }
+        let now = compat::now();
+        let elapsed = self.last_emitted.map(|last| now.duration_since(last));
+        match (interval, elapsed) {
+            (Some(interval), Some(elapsed)) if elapsed < interval => {
+                if self.pending {
+                    Throttled::Skip
+                } else {
+                    self.pending = true;
+                    Throttled::Delay(interval - elapsed)
+                }
+            }
+            _ => {
+                self.pending = false;
+                self.last_emitted = Some(now);
+                Throttled::Emit
+            }
+        }
+    }
+}
+
+/// Drops repeated events within an interval
+struct Debounce<K> {
+    // key and time of the last accepted event
+    last: Option<(K, Instant)>,
+}
+
+impl<K> Default for Debounce<K> {
+    fn default() -> Self {
+        Debounce { last: None }
+    }
+}
+
+impl<K: PartialEq> Debounce<K> {
+    // returns false if the event should be dropped
+    fn accept(&mut self, key: K, interval: Option<Duration>) -> bool {
+        let Some(interval) = interval else {
+            return true;
+        };
+        let now = compat::now();
+        if let Some((last_key, last)) = &self.last {
+            if *last_key == key && now.duration_since(*last) < interval {
+                return false;
+            }
+        }
+        self.last = Some((key, now));
+        true
+    }
+}
+
+// see `menu::validate`
+fn checked_menu<T: Tray>(tray: &T, ctx: &menu::MenuGenerationContext) -> Vec<crate::MenuItem<T>> {
+    let menu = tray.menu_with(ctx);
+    #[cfg(feature = "menu-validation")]
+    for warning in menu::validate(&menu) {
+        tray.on_menu_warning(warning);
+    }
+    menu
+}
+
+// see `Service::diff_menu`
+struct MenuDiff {
+    updated_props: Vec<(i32, menu::PropertyMap)>,
+    removed_props: Vec<(i32, Vec<Str<'static>>)>,
+    layout_updated: bool,
+    volatile_changed: bool,
+}
+
+// `Tray::raw_menu`, or the flattened `Tray::menu`
+fn flattened_menu<T: Tray>(
+    tray: &T,
+    ctx: &menu::MenuGenerationContext,
+) -> Vec<(menu::RawMenuItem<T>, Vec<usize>)> {
+    menu::generating(|| flattened_menu_unguarded(tray, ctx))
+}
+
+fn flattened_menu_unguarded<T: Tray>(
+    tray: &T,
+    ctx: &menu::MenuGenerationContext,
+) -> Vec<(menu::RawMenuItem<T>, Vec<usize>)> {
+    let Some(raw_menu) = tray.raw_menu() else {
+        return menu::menu_flatten(checked_menu(tray, ctx));
+    };
+    match menu::check_flattened(&raw_menu) {
+        Ok(()) => {
+            let mut raw_menu = raw_menu;
+            menu::assign_paths(&mut raw_menu);
+            #[cfg(feature = "menu-validation")]
+            for warning in menu::validate_raw(&raw_menu) {
+                tray.on_menu_warning(warning);
+            }
+            raw_menu
+        }
+        // the layout requests would fail or never end
+        Err(index) => {
+            tray.on_menu_warning(menu::MenuWarning::InvalidRawMenu { index });
+            menu::menu_flatten(Vec::new())
+        }
+    }
+}
+
+fn run_sync_updates<T>(queue: &SyncUpdates<T>, tray: &mut T) {
+    loop {
+        // not locked while running, `f` may queue another one
+        let Some(f) = lock(queue).pop_front() else {
+            break;
+        };
+        f(tray);
+    }
+}
+
+pub fn lock<V>(m: &std::sync::Mutex<V>) -> std::sync::MutexGuard<'_, V> {
+    m.lock().unwrap_or_else(|e| e.into_inner())
 }
 
 fn hash_of<T: Hash>(v: T) -> u64 {
@@ -544,3 +2803,579 @@ fn hash_of<T: Hash>(v: T) -> u64 {
     v.hash(&mut hasher);
     hasher.finish()
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::menu::{StandardItem, SubMenu};
+    use crate::MenuItem;
+
+    #[cfg(all(feature = "tokio", feature = "test-util"))]
+    #[tokio::test(start_paused = true)]
+    async fn test_throttle() {
+        let interval = Some(Duration::from_secs(1));
+        let mut throttle = Throttle::default();
+        assert!(matches!(throttle.check(true, interval), Throttled::Emit));
+        crate::testing::advance_time(Duration::from_millis(400)).await;
+        assert!(matches!(
+            throttle.check(true, interval),
+            Throttled::Delay(delay) if delay == Duration::from_millis(600)
+        ));
+        assert!(matches!(throttle.check(true, interval), Throttled::Skip));
+        crate::testing::advance_time(Duration::from_millis(600)).await;
+        assert!(matches!(throttle.check(false, interval), Throttled::Emit));
+        assert!(matches!(throttle.check(false, interval), Throttled::Skip));
+    }
+
+    #[cfg(all(feature = "tokio", feature = "test-util"))]
+    #[tokio::test(start_paused = true)]
+    async fn test_debounce() {
+        let interval = Some(Duration::from_millis(500));
+        let mut debounce = Debounce::default();
+        assert!(debounce.accept(1, interval));
+        assert!(!debounce.accept(1, interval));
+        assert!(debounce.accept(2, interval));
+        crate::testing::advance_time(Duration::from_millis(500)).await;
+        assert!(debounce.accept(2, interval));
+    }
+
+    // needs dbus-daemon for a private bus, skipped without it
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_name_reacquired() {
+        use std::io::BufRead;
+
+        struct Daemon(std::process::Child);
+        impl Drop for Daemon {
+            fn drop(&mut self) {
+                let _ = self.0.kill();
+                let _ = self.0.wait();
+            }
+        }
+        struct Watcher;
+        #[zbus::interface(name = "org.kde.StatusNotifierWatcher")]
+        impl Watcher {
+            fn register_status_notifier_item(&self, _service: &str) {}
+            #[zbus(property)]
+            fn is_status_notifier_host_registered(&self) -> bool {
+                true
+            }
+        }
+        async fn next_event(received: &mut mpsc::UnboundedReceiver<&'static str>) -> &'static str {
+            tokio::time::timeout(Duration::from_secs(5), received.recv())
+                .await
+                .expect("no name callback")
+                .unwrap()
+        }
+        struct Named(mpsc::UnboundedSender<&'static str>);
+        impl Tray for Named {
+            fn id(&self) -> String {
+                "named".into()
+            }
+            fn name_lost(&self, _name: &str) {
+                let _ = self.0.send("lost");
+            }
+            fn name_acquired(&self, _name: &str) {
+                let _ = self.0.send("acquired");
+            }
+        }
+
+        let Ok(daemon) = std::process::Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address=1"])
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::null())
+            .spawn()
+        else {
+            return;
+        };
+        let mut daemon = Daemon(daemon);
+        let mut address = String::new();
+        std::io::BufReader::new(daemon.0.stdout.take().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+        let address = address.trim_end().to_owned();
+        let _watcher = zbus::connection::Builder::address(address.as_str())
+            .unwrap()
+            .name("org.kde.StatusNotifierWatcher")
+            .unwrap()
+            .serve_at("/StatusNotifierWatcher", Watcher)
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+
+        let (events, mut received) = mpsc::unbounded_channel();
+        let config = Config {
+            session: crate::SessionTarget::Address(address.clone()),
+            ..Default::default()
+        };
+        let (sender, _receiver) = mpsc::unbounded_channel();
+        let service = Service::new(Named(events), config, sender);
+        let (_handle_tx, handle_rx) = mpsc::unbounded_channel();
+        let (info, conn, service_loop) = run(service, handle_rx, true, None).await.unwrap();
+        tokio::spawn(service_loop);
+        let name = info.bus_name;
+
+        // the tray can't take the name back while it's held without `AllowReplacement`
+        let other = zbus::connection::Builder::address(address.as_str())
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+        let flags = zbus::fdo::RequestNameFlags::ReplaceExisting.into();
+        other
+            .request_name_with_flags(name.as_str(), flags)
+            .await
+            .unwrap();
+        assert_eq!(next_event(&mut received).await, "lost");
+
+        // the tray is queued behind the owner instead of failing with `NameTaken`
+        let dbus = DBusProxy::new(&other).await.unwrap();
+        let tray = conn.unique_name().unwrap().to_string();
+        let queued = async {
+            while !dbus
+                .list_queued_owners(name.as_str().try_into().unwrap())
+                .await
+                .unwrap()
+                .iter()
+                .skip(1)
+                .any(|owner| owner.as_str() == tray)
+            {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), queued)
+            .await
+            .expect("the tray is not queued for its name");
+        other.release_name(name.as_str()).await.unwrap();
+        assert_eq!(next_event(&mut received).await, "acquired");
+    }
+
+    #[test]
+    fn test_state_version() {
+        let state = TrayStateBlob {
+            revision: 3,
+            menu_ids: vec![0, 1],
+            next_menu_id: 2,
+            properties: HashMap::new(),
+        };
+        let mut bytes = state.to_bytes();
+        assert_eq!(TrayStateBlob::from_bytes(&bytes), Some(state));
+        bytes[0] = STATE_VERSION + 1;
+        assert_eq!(TrayStateBlob::from_bytes(&bytes), None);
+    }
+
+    #[test]
+    fn test_invalid_state() {
+        let blob = |menu_ids: Vec<i32>, next_menu_id| {
+            TrayStateBlob {
+                revision: 0,
+                menu_ids,
+                next_menu_id,
+                properties: HashMap::new(),
+            }
+            .to_bytes()
+        };
+        assert!(TrayStateBlob::from_bytes(&blob(vec![0, 1, 2], i32::MAX)).is_some());
+        assert_eq!(TrayStateBlob::from_bytes(&blob(vec![], 1)), None);
+        assert_eq!(TrayStateBlob::from_bytes(&blob(vec![3, 1, 2], 4)), None);
+        assert_eq!(TrayStateBlob::from_bytes(&blob(vec![0, 1, 1], 2)), None);
+        assert_eq!(TrayStateBlob::from_bytes(&blob(vec![0, -1], 1)), None);
+        assert_eq!(TrayStateBlob::from_bytes(&blob(vec![0, 1], 0)), None);
+    }
+
+    #[test]
+    fn test_nested_sync_updates() {
+        let queue: SyncUpdates<Vec<i32>> = Default::default();
+        let inner = queue.clone();
+        lock(&queue).push_back(Box::new(move |v: &mut Vec<i32>| {
+            v.push(1);
+            lock(&inner).push_back(Box::new(|v: &mut Vec<i32>| v.push(2)));
+        }));
+        let mut v = Vec::new();
+        run_sync_updates(&queue, &mut v);
+        assert_eq!(v, [1, 2]);
+        assert!(lock(&queue).is_empty());
+    }
+
+    fn item(label: &'static str) -> MenuItem<()> {
+        StandardItem {
+            label: label.into(),
+            ..Default::default()
+        }
+        .into()
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_shutdown_on_panic() {
+        struct Empty;
+        impl Tray for Empty {
+            fn id(&self) -> String {
+                "test".into()
+            }
+        }
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let config = Config {
+            shutdown_on_panic: true,
+            ..Default::default()
+        };
+        let service = Service::new(Empty, config, sender);
+        let mut service = service.try_lock().unwrap();
+        assert_eq!(service.shutdown_on_panic(|_| 1), 1);
+        assert!(receiver.try_recv().is_err());
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            service.shutdown_on_panic(|_| panic!("callback"))
+        }));
+        assert!(panicked.is_err());
+        assert!(matches!(receiver.try_recv(), Ok(HandleReuest::Shutdown(_))));
+    }
+
+    // a notification server on a peer-to-peer connection, returns the server and the client
+    #[cfg(feature = "tokio")]
+    async fn notification_server(
+        log: Arc<std::sync::Mutex<Vec<String>>>,
+    ) -> zbus::Result<(Connection, Connection)> {
+        struct Notifications(Arc<std::sync::Mutex<Vec<String>>>);
+        #[zbus::interface(name = "org.freedesktop.Notifications")]
+        impl Notifications {
+            #[allow(clippy::too_many_arguments)]
+            fn notify(
+                &self,
+                app_name: String,
+                _replaces_id: u32,
+                _icon: String,
+                summary: String,
+                _body: String,
+                _actions: Vec<String>,
+                _hints: HashMap<String, OwnedValue>,
+                _timeout: i32,
+            ) -> u32 {
+                lock(&self.0).push(format!("notify {app_name}: {summary}"));
+                1
+            }
+            fn close_notification(&self, id: u32) {
+                lock(&self.0).push(format!("close {id}"));
+            }
+        }
+        let (server, client) = tokio::net::UnixStream::pair()?;
+        let guid = zbus::Guid::generate();
+        let server = zbus::connection::Builder::unix_stream(server)
+            .server(guid)?
+            .p2p()
+            .serve_at("/org/freedesktop/Notifications", Notifications(log))?
+            .build();
+        let client = zbus::connection::Builder::unix_stream(client).p2p().build();
+        let (server, client) = future::join(server, client).await;
+        Ok((server?, client?))
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_attention_notification() {
+        struct Alert(&'static str);
+        impl Tray for Alert {
+            fn id(&self) -> String {
+                "alert".into()
+            }
+            fn title(&self) -> String {
+                self.0.into()
+            }
+            fn status(&self) -> crate::Status {
+                crate::Status::NeedsAttention
+            }
+            fn attention_notification(&self) -> Option<notify::Notification> {
+                Some(notify::Notification {
+                    summary: "Battery low".into(),
+                    ..Default::default()
+                })
+            }
+        }
+        let log = Arc::default();
+        let (_server, conn) = notification_server(Arc::clone(&log)).await.unwrap();
+        let (sender, _receiver) = mpsc::unbounded_channel();
+        let service = Service::new(Alert(""), Config::default(), sender);
+        let mut service = service.try_lock().unwrap();
+
+        service.tray.0 = "Alert";
+        service.update_attention_notification(&conn).await;
+        assert_eq!(service.attention_notification, Some(1));
+        // the title is still sent
+        assert!(service.title_changed());
+
+        service.close_attention_notification(&conn).await;
+        assert_eq!(service.attention_notification, None);
+        assert_eq!(*lock(&log), ["notify Alert: Battery low", "close 1"]);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_confirm() {
+        #[derive(Default)]
+        struct Confirm {
+            deleted: u32,
+            activated: Vec<menu::MenuPath>,
+        }
+        impl Tray for Confirm {
+            fn id(&self) -> String {
+                "confirm".into()
+            }
+            fn menu(&self) -> Vec<MenuItem<Self>> {
+                vec![StandardItem {
+                    label: "Delete".into(),
+                    confirm: Some(Default::default()),
+                    activate: Box::new(|this: &mut Self| this.deleted += 1),
+                    ..Default::default()
+                }
+                .into()]
+            }
+            fn menu_item_activated(&mut self, path: &menu::MenuPath) {
+                self.activated.push(path.clone());
+            }
+        }
+        let (sender, _receiver) = mpsc::unbounded_channel();
+        let service = Service::new(Confirm::default(), Config::default(), sender);
+        let mut service = service.try_lock().unwrap();
+        // index of the item with `label` in the menu generated by the next update
+        let mut click = |label: &str| {
+            service.flattened_menu = service.generate_menu(&service.tray);
+            let index = service
+                .flattened_menu
+                .iter()
+                .position(|(item, _)| item.label() == label)
+                .unwrap();
+            assert!(service.activate_index(index));
+            (service.tray.deleted, service.tray.activated.len())
+        };
+
+        assert_eq!(click("Delete"), (0, 0));
+        assert_eq!(click("No"), (0, 0));
+        assert_eq!(click("Delete"), (0, 0));
+        assert_eq!(click("Yes"), (1, 1));
+        assert_eq!(service.tray.activated, [menu::MenuPath::from(vec![0])]);
+        assert!(service.confirming.is_none());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_encoded() {
+        struct Pixmap(u8);
+        impl Tray for Pixmap {
+            fn id(&self) -> String {
+                "pixmap".into()
+            }
+            fn icon_pixmap(&self) -> Vec<crate::Icon> {
+                vec![crate::Icon {
+                    width: 1,
+                    height: 1,
+                    data: vec![self.0; 4],
+                }]
+            }
+        }
+        let (sender, _receiver) = mpsc::unbounded_channel();
+        let service = Service::new(Pixmap(0), Config::default(), sender);
+        let mut service = service.try_lock().unwrap();
+        // the encoded pixmap, and whether it was built
+        let encode = |service: &Service<Pixmap>| {
+            let mut built = false;
+            let encoded = service.encoded(
+                || service.icon_pixmap_key(),
+                || {
+                    built = true;
+                    service.order_pixmap(service.shown_icon_pixmap())
+                },
+            );
+            (Value::from(encoded), built)
+        };
+
+        let (first, built) = encode(&service);
+        assert!(built);
+        assert_eq!(encode(&service), (first.try_clone().unwrap(), false));
+        service.tray.0 = 1;
+        assert!(service.icon_pixmap_changed());
+        let (second, built) = encode(&service);
+        assert!(built);
+        assert_ne!(first, second);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_scale_hint_changed() {
+        struct Scaled(Option<u32>);
+        impl Tray for Scaled {
+            fn id(&self) -> String {
+                "scaled".into()
+            }
+            fn icon_scale_hint(&self) -> Option<u32> {
+                self.0
+            }
+        }
+        let (sender, _receiver) = mpsc::unbounded_channel();
+        let service = Service::new(Scaled(None), Config::default(), sender);
+        let mut service = service.try_lock().unwrap();
+        service.tray.0 = Some(2);
+        // a pixmap read before the diff pass doesn't hide the change
+        service.order_pixmap(Vec::new());
+        assert!(service.icon_scale_hint_changed());
+        assert!(!service.icon_scale_hint_changed());
+    }
+
+    #[test]
+    fn test_menu_ids() {
+        fn keyed(key: &'static str) -> MenuItem<()> {
+            StandardItem {
+                label: key.into(),
+                key: Some(key.into()),
+                ..Default::default()
+            }
+            .into()
+        }
+        let old = menu::menu_flatten(vec![
+            keyed("a"),
+            SubMenu {
+                label: "b".into(),
+                key: Some("b".into()),
+                submenu: vec![item("b1"), keyed("b2")],
+                ..Default::default()
+            }
+            .into(),
+        ]);
+        // an item inserted before the submenu, one removed from it
+        let new = menu::menu_flatten(vec![
+            item("new"),
+            keyed("a"),
+            SubMenu {
+                label: "b".into(),
+                key: Some("b".into()),
+                submenu: vec![keyed("b2")],
+                ..Default::default()
+            }
+            .into(),
+        ]);
+
+        let mut ids = MenuIds::new(old.len());
+        assert_eq!(ids.ids, [0, 1, 2, 3, 4]);
+        ids.reassign(&old, &new, false);
+        assert_eq!(ids.ids, [0, 5, 6, 7, 8]);
+        assert_eq!(ids.index(1), None);
+
+        let mut ids = MenuIds::new(old.len());
+        ids.reassign(&old, &new, true);
+        assert_eq!(ids.ids, [0, 5, 1, 2, 4]);
+        assert_eq!(ids.index(3), None);
+        assert_eq!(ids.index(4), Some(4));
+
+        let mut ids = MenuIds::with_ids(vec![0, 1, 3], i32::MAX);
+        assert_eq!(ids.allocate(), i32::MAX);
+        assert_eq!(ids.allocate(), 2);
+        assert_eq!(ids.allocate(), 4);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_delayed_layout() {
+        struct Items(Vec<&'static str>);
+        impl Tray for Items {
+            fn id(&self) -> String {
+                "items".into()
+            }
+            fn menu(&self) -> Vec<MenuItem<Self>> {
+                let item = |label: &&'static str| {
+                    StandardItem {
+                        label: (*label).into(),
+                        ..Default::default()
+                    }
+                    .into()
+                };
+                self.0.iter().map(item).collect()
+            }
+        }
+        let config = Config {
+            min_layout_update_interval: Some(Duration::from_secs(3600)),
+            signal_log: 16,
+            ..Default::default()
+        };
+        let (sender, _receiver) = mpsc::unbounded_channel();
+        let service = Service::new(Items(vec!["a"]), config, sender);
+        let (server, client) = tokio::net::UnixStream::pair().unwrap();
+        let server = zbus::connection::Builder::unix_stream(server)
+            .server(zbus::Guid::generate())
+            .unwrap()
+            .p2p()
+            .serve_at(MENU_PATH, DbusMenu::new(service.clone()))
+            .unwrap()
+            .build();
+        let client = zbus::connection::Builder::unix_stream(client).p2p().build();
+        let (conn, _client) = future::join(server, client).await;
+        let conn = conn.unwrap();
+        let mut service = service.lock().await;
+
+        // the second layout is delayed, the host doesn't know the ids in it when "a" is changed
+        for labels in [vec!["a", "b"], vec!["a", "b", "c"], vec!["a2", "b", "c"]] {
+            service.tray.0 = labels;
+            service.update_menu(&conn).await.unwrap();
+        }
+        let signals: Vec<_> = service
+            .stats
+            .signal_log()
+            .into_iter()
+            .map(|record| record.signal)
+            .collect();
+        assert_eq!(signals, ["LayoutUpdated"]);
+    }
+
+    #[cfg(all(feature = "tokio", feature = "global-hotkey"))]
+    #[tokio::test]
+    async fn test_rebind_global_shortcuts() {
+        struct Shortcuts(Vec<&'static str>);
+        impl Tray for Shortcuts {
+            fn id(&self) -> String {
+                "shortcuts".into()
+            }
+            fn menu(&self) -> Vec<MenuItem<Self>> {
+                let item = |key: &&'static str| {
+                    StandardItem {
+                        label: (*key).into(),
+                        shortcut: vec![vec!["Control".into(), (*key).into()]],
+                        ..Default::default()
+                    }
+                    .into()
+                };
+                self.0.iter().map(item).collect()
+            }
+        }
+        let (sender, _receiver) = mpsc::unbounded_channel();
+        let service = Service::new(Shortcuts(vec!["o"]), Config::default(), sender);
+        let (server, client) = tokio::net::UnixStream::pair().unwrap();
+        let server = zbus::connection::Builder::unix_stream(server)
+            .server(zbus::Guid::generate())
+            .unwrap()
+            .p2p()
+            .serve_at(MENU_PATH, DbusMenu::new(service.clone()))
+            .unwrap()
+            .build();
+        let client = zbus::connection::Builder::unix_stream(client).p2p().build();
+        let (conn, _client) = future::join(server, client).await;
+        let conn = conn.unwrap();
+        let mut service = service.lock().await;
+        let bound = |service: &Service<Shortcuts>| {
+            service
+                .bound_shortcuts
+                .as_ref()
+                .map(|bound| bound.shortcuts.len())
+        };
+
+        // only bound by the service loop
+        service.update_menu(&conn).await.unwrap();
+        assert_eq!(bound(&service), None);
+        service.bind_global_shortcuts(&conn, true);
+        assert_eq!(bound(&service), Some(1));
+
+        service.tray.0 = vec!["o", "q"];
+        service.update_menu(&conn).await.unwrap();
+        assert_eq!(bound(&service), Some(2));
+        service.tray.0 = vec![];
+        service.update_menu(&conn).await.unwrap();
+        assert_eq!(bound(&service), Some(0));
+    }
+}
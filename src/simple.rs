@@ -0,0 +1,135 @@
+//! The `TrayService` API of ksni 0.2
+//!
+//! A [`Handle`] is available before the service is started, updates made before
+//! [`TrayService::spawn`] are applied to the tray directly. Built on the
+//! [`blocking`](crate::blocking) API, do NOT use it in an async context.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use ksni::simple::TrayService;
+//!
+//! struct MyTray {
+//!     count: u32,
+//! }
+//!
+//! impl ksni::Tray for MyTray {
+//!     fn id(&self) -> String {
+//!         "my-tray".into()
+//!     }
+//!     fn title(&self) -> String {
+//!         self.count.to_string()
+//!     }
+//! }
+//!
+//! let service = TrayService::new(MyTray { count: 0 });
+//! let handle = service.handle();
+//! service.spawn().unwrap();
+//!
+//! handle.update(|tray| tray.count += 1);
+//! ```
+
+use std::sync::{Arc, Mutex};
+
+use crate::{blocking, compat, Error, Tray, TrayServiceBuilder};
+
+enum State<T> {
+    NotStarted(T),
+    Running(blocking::Handle<T>),
+    // failed to start
+    Stopped,
+}
+
+/// A tray that is not started yet
+pub struct TrayService<T> {
+    state: Arc<Mutex<State<T>>>,
+}
+
+impl<T: Tray> TrayService<T> {
+    pub fn new(tray: T) -> Self {
+        TrayService {
+            state: Arc::new(Mutex::new(State::NotStarted(tray))),
+        }
+    }
+
+    /// Get a handle to update the tray, before or after it's started
+    pub fn handle(&self) -> Handle<T> {
+        Handle {
+            state: self.state.clone(),
+        }
+    }
+
+    /// Run the tray service in background
+    pub fn spawn(self) -> Result<(), Error> {
+        self.start(|builder| builder.spawn_blocking())
+    }
+
+    /// Run the tray service on the current thread, returns after it's shutdown
+    pub fn run(self) -> Result<(), Error> {
+        let mut service_loop = None;
+        self.start(|builder| {
+            let (handle, run) = compat::block_on(builder.build())?;
+            service_loop = Some(run);
+            Ok(handle.into_blocking())
+        })?;
+        if let Some(service_loop) = service_loop {
+            compat::block_on(service_loop);
+        }
+        Ok(())
+    }
+
+    fn start(
+        self,
+        f: impl FnOnce(TrayServiceBuilder<T>) -> Result<blocking::Handle<T>, Error>,
+    ) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let State::NotStarted(tray) = std::mem::replace(&mut *state, State::Stopped) else {
+            unreachable!("a TrayService is started only once");
+        };
+        *state = State::Running(f(TrayServiceBuilder::new(tray))?);
+        Ok(())
+    }
+}
+
+/// Handle to a [`TrayService`]
+pub struct Handle<T> {
+    state: Arc<Mutex<State<T>>>,
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Handle {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<T> Handle<T> {
+    /// Update the tray
+    ///
+    /// Returns the result of `f`, returns `None` if the tray service has been shutdown or
+    /// failed to start.
+    pub fn update<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> Option<R> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let handle = match &mut *state {
+            State::NotStarted(tray) => return Some(f(tray)),
+            State::Running(handle) => handle.clone(),
+            State::Stopped => return None,
+        };
+        // the service loop doesn't need the state, but don't block other handles
+        drop(state);
+        handle.update(f)
+    }
+
+    /// Shutdown the tray service and wait for it, does nothing if it's not running
+    pub fn shutdown(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let State::Running(handle) = &*state else {
+            return;
+        };
+        let handle = handle.clone();
+        *state = State::Stopped;
+        drop(state);
+        handle.shutdown().wait();
+    }
+}
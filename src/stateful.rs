@@ -0,0 +1,155 @@
+//! A tray whose appearance is described per state
+//!
+//! Many trays show a connection or a job, e.g. "disconnected / connecting / connected / error".
+//! Instead of matching on the state in every [`Tray`] method, implement [`TrayState`] for the
+//! state type, and switch states with [`StatefulTray::set_state`].
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use ksni::menu::StandardItem;
+//! use ksni::stateful::{StatefulTray, TrayState};
+//! use ksni::{MenuItem, TrayMethods};
+//!
+//! enum Vpn {
+//!     Disconnected,
+//!     Connected { server: String },
+//! }
+//!
+//! impl TrayState for Vpn {
+//!     fn icon_name(&self) -> String {
+//!         match self {
+//!             Vpn::Disconnected => "network-vpn-disconnected".into(),
+//!             Vpn::Connected { .. } => "network-vpn".into(),
+//!         }
+//!     }
+//!     fn menu(&self) -> Vec<MenuItem<Self>> {
+//!         match self {
+//!             Vpn::Disconnected => vec![StandardItem {
+//!                 label: "Connect".into(),
+//!                 activate: Box::new(|this: &mut Self| {
+//!                     *this = Vpn::Connected {
+//!                         server: "example.com".into(),
+//!                     }
+//!                 }),
+//!                 ..Default::default()
+//!             }
+//!             .into()],
+//!             Vpn::Connected { server } => vec![StandardItem {
+//!                 label: format!("Disconnect from {server}").into(),
+//!                 activate: Box::new(|this: &mut Self| *this = Vpn::Disconnected),
+//!                 ..Default::default()
+//!             }
+//!             .into()],
+//!         }
+//!     }
+//! }
+//!
+//! # async fn f() -> Result<(), ksni::Error> {
+//! let handle = StatefulTray::new("vpn", Vpn::Disconnected)
+//!     .title("VPN")
+//!     .spawn()
+//!     .await?;
+//! handle.update(|tray| tray.set_state(Vpn::Disconnected)).await;
+//! # Ok(()) }
+//! ```
+
+use crate::{MenuItem, Status, ToolTip, Tray};
+
+/// Appearance of a [`StatefulTray`] in a state
+pub trait TrayState: Send + 'static {
+    /// See [`Tray::icon_name`]
+    fn icon_name(&self) -> String {
+        Default::default()
+    }
+
+    /// See [`Tray::tool_tip`]
+    fn tool_tip(&self) -> ToolTip {
+        Default::default()
+    }
+
+    /// See [`Tray::status`]
+    fn status(&self) -> Status {
+        Status::Active
+    }
+
+    /// The menu in this state, callbacks may change the state
+    fn menu(&self) -> Vec<MenuItem<Self>>
+    where
+        Self: Sized,
+    {
+        Default::default()
+    }
+}
+
+/// A [`Tray`] showing its current [`TrayState`], see the
+/// [module level documentation](self)
+pub struct StatefulTray<S> {
+    id: String,
+    title: String,
+    state: S,
+}
+
+impl<S: TrayState> StatefulTray<S> {
+    pub fn new(id: impl Into<String>, state: S) -> Self {
+        StatefulTray {
+            id: id.into(),
+            title: String::new(),
+            state,
+        }
+    }
+
+    /// See [`Tray::title`]
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+
+    pub fn state_mut(&mut self) -> &mut S {
+        &mut self.state
+    }
+
+    /// Switch to another state, returns the previous one
+    pub fn set_state(&mut self, state: S) -> S {
+        std::mem::replace(&mut self.state, state)
+    }
+}
+
+impl<S: TrayState> Tray for StatefulTray<S> {
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    fn title(&self) -> String {
+        self.title.clone()
+    }
+
+    fn icon_name(&self) -> String {
+        self.state.icon_name()
+    }
+
+    fn tool_tip(&self) -> ToolTip {
+        self.state.tool_tip()
+    }
+
+    fn status(&self) -> Status {
+        self.state.status()
+    }
+
+    fn menu(&self) -> Vec<MenuItem<Self>> {
+        self.state
+            .menu()
+            .into_iter()
+            .map(|item| {
+                item.map(
+                    |tray: &Self| Some(&tray.state),
+                    |tray: &mut Self| Some(&mut tray.state),
+                )
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,137 @@
+//! Helpers for end-to-end tests of trays
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use ksni::TrayMethods;
+//! # struct MyTray;
+//! # impl ksni::Tray for MyTray {
+//! #     fn id(&self) -> String { "my-tray".into() }
+//! #     fn icon_name(&self) -> String { "idle".into() }
+//! # }
+//! # async fn f() -> Result<(), Box<dyn std::error::Error>> {
+//! let handle = MyTray.spawn().await?;
+//! let probe = ksni::testing::TrayProbe::new(&handle).await?;
+//! probe.assert_icon_name("idle").await;
+//! # Ok(()) }
+//! ```
+
+use zbus::proxy::CacheProperties;
+use zbus::zvariant::{OwnedValue, Value};
+
+use crate::dbus_interface::SNI_PATH;
+use crate::{Handle, Status, ToolTip};
+
+/// Reads the properties exported by a spawned tray over D-Bus, the way a host sees them
+///
+/// Property values are read on every call, not cached
+pub struct TrayProbe {
+    proxy: zbus::Proxy<'static>,
+}
+
+impl TrayProbe {
+    /// Create a probe of the tray behind `handle`
+    ///
+    /// Returns an error if the tray service has been shutdown
+    pub async fn new<T>(handle: &Handle<T>) -> zbus::Result<Self> {
+        let conn = handle
+            .connection()
+            .await
+            .ok_or_else(|| zbus::Error::Failure("the tray service has been shutdown".into()))?;
+        let destination = conn
+            .unique_name()
+            .expect("unique name should be set after connected")
+            .to_owned();
+        let proxy = zbus::proxy::Builder::new(&conn)
+            .destination(destination)?
+            .path(SNI_PATH)?
+            .interface("org.kde.StatusNotifierItem")?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?;
+        Ok(TrayProbe { proxy })
+    }
+
+    /// Read any property of the `org.kde.StatusNotifierItem` interface
+    pub async fn property<R>(&self, name: &str) -> zbus::Result<R>
+    where
+        R: TryFrom<OwnedValue>,
+        R::Error: Into<zbus::Error>,
+    {
+        self.proxy.get_property(name).await
+    }
+
+    pub async fn id(&self) -> zbus::Result<String> {
+        self.property("Id").await
+    }
+
+    pub async fn title(&self) -> zbus::Result<String> {
+        self.property("Title").await
+    }
+
+    /// The status, as the string sent to the host
+    pub async fn status(&self) -> zbus::Result<String> {
+        self.property("Status").await
+    }
+
+    pub async fn icon_name(&self) -> zbus::Result<String> {
+        self.property("IconName").await
+    }
+
+    pub async fn tooltip(&self) -> zbus::Result<ToolTip> {
+        let value: OwnedValue = self.property("ToolTip").await?;
+        Ok(ToolTip::try_from(Value::from(value))?)
+    }
+
+    /// Panics if the title is not `expected`
+    pub async fn assert_title(&self, expected: &str) {
+        let title = self.title().await.expect("failed to read Title");
+        assert_eq!(title, expected, "unexpected Title");
+    }
+
+    /// Panics if the status is not `expected`
+    pub async fn assert_status(&self, expected: Status) {
+        let status = self.status().await.expect("failed to read Status");
+        assert_eq!(status, expected.to_string(), "unexpected Status");
+    }
+
+    /// Panics if the icon name is not `expected`
+    pub async fn assert_icon_name(&self, expected: &str) {
+        let icon_name = self.icon_name().await.expect("failed to read IconName");
+        assert_eq!(icon_name, expected, "unexpected IconName");
+    }
+
+    /// Panics if the tooltip is not `expected`
+    pub async fn assert_tooltip(&self, expected: &ToolTip) {
+        let tooltip = self.tooltip().await.expect("failed to read ToolTip");
+        assert_eq!(&tooltip, expected, "unexpected ToolTip");
+    }
+}
+
+/// Pause the clock of the timers in ksni, e.g. throttles and debounces
+///
+/// While paused, the time only moves forward with [`advance_time`], so tests of timers don't
+/// wait for real time. With the "tokio" feature this is [`tokio::time::pause`], which must be
+/// called in a current-thread runtime and applies to the whole runtime. With "async-io" the clock
+/// is global to the process.
+#[cfg(feature = "test-util")]
+#[cfg_attr(docsrs, doc(cfg(feature = "test-util")))]
+pub fn pause_time() {
+    crate::compat::clock::pause()
+}
+
+/// Resume the clock paused by [`pause_time`]
+#[cfg(feature = "test-util")]
+#[cfg_attr(docsrs, doc(cfg(feature = "test-util")))]
+pub fn resume_time() {
+    crate::compat::clock::resume()
+}
+
+/// Move the paused clock forward, the timers due wake up before it returns
+///
+/// Panics if the clock is not paused
+#[cfg(feature = "test-util")]
+#[cfg_attr(docsrs, doc(cfg(feature = "test-util")))]
+pub async fn advance_time(duration: std::time::Duration) {
+    crate::compat::clock::advance(duration).await
+}
@@ -18,9 +18,65 @@ pub enum Orientation {
     Vertical,
 }
 
+/// Details of an activation, see [`Tray::activate_with_info`]
+///
+/// [`Tray::activate_with_info`]: crate::Tray::activate_with_info
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ActivateInfo {
+    /// Screen coordinates, a hint where to show windows
+    pub x: i32,
+    pub y: i32,
+    /// XDG activation token sent by the host before the activation, pass it to the window
+    /// toolkit (or set `XDG_ACTIVATION_TOKEN` for launched processes) to be allowed to raise a
+    /// window on Wayland
+    pub xdg_activation_token: Option<String>,
+}
+
+/// A scroll over the item, see [`Tray::scroll_event`]
+///
+/// [`Tray::scroll_event`]: crate::Tray::scroll_event
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ScrollEvent {
+    /// Amount of scroll, the unit depends on the host
+    pub delta: i32,
+    pub orientation: Orientation,
+}
+
+/// An interaction reported by the host, see [`TrayServiceBuilder::map_event`]
+///
+/// [`TrayServiceBuilder::map_event`]: crate::TrayServiceBuilder::map_event
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum HostEvent {
+    /// `Activate`, usually a left click
+    Activate,
+    /// `SecondaryActivate`, usually a middle click
+    SecondaryActivate,
+    /// Two `Activate` within a short interval
+    ///
+    /// The first one is still reported as [`HostEvent::Activate`], hosts don't report double
+    /// clicks, ksni can't tell them apart before the second click.
+    DoubleClick,
+}
+
+/// What to do on a [`HostEvent`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum TrayAction {
+    /// Call [`Tray::activate`](crate::Tray::activate)
+    Activate,
+    /// Call [`Tray::secondary_activate`](crate::Tray::secondary_activate)
+    SecondaryActivate,
+    /// Do nothing
+    Ignore,
+}
+
 /// Category of this item.
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Type, Serialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Type)]
 #[zvariant(signature = "s")]
+#[non_exhaustive]
 pub enum Category {
     /// The item describes the status of a generic application, for instance
     /// the current state of a media player. In the case where the category of
@@ -38,6 +94,45 @@ pub enum Category {
     /// The item describes the state and control of a particular hardware,
     /// such as an indicator of the battery charge or sound card volume control.
     Hardware,
+    /// A category not defined by the specification, sent verbatim
+    ///
+    /// Useful to pass through the category of a proxied item, hosts that don't know it usually
+    /// treat it as [`Category::ApplicationStatus`].
+    Other(String),
+}
+
+impl Category {
+    /// The name used on D-Bus
+    pub fn as_str(&self) -> &str {
+        match self {
+            Category::ApplicationStatus => "ApplicationStatus",
+            Category::Communications => "Communications",
+            Category::SystemServices => "SystemServices",
+            Category::Hardware => "Hardware",
+            Category::Other(s) => s,
+        }
+    }
+}
+
+impl Serialize for Category {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+/// Names not defined by the specification are parsed as [`Category::Other`]
+impl std::str::FromStr for Category {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "ApplicationStatus" => Category::ApplicationStatus,
+            "Communications" => Category::Communications,
+            "SystemServices" => Category::SystemServices,
+            "Hardware" => Category::Hardware,
+            _ => Category::Other(s.to_owned()),
+        })
+    }
 }
 
 // The Value dervie macro can only handle `dict` or `a{sv}` values
@@ -50,7 +145,7 @@ impl From<Category> for Value<'_> {
 
 impl fmt::Display for Category {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        self.serialize(f)
+        f.write_str(self.as_str())
     }
 }
 
@@ -72,6 +167,20 @@ pub enum Status {
     NeedsAttention,
 }
 
+/// How urgent the [`Status::NeedsAttention`] of the item is
+///
+/// Not part of the specification, ksni only uses it to choose the attention icon, see
+/// [`Tray::attention_level`](crate::Tray::attention_level)
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[non_exhaustive]
+pub enum AttentionLevel {
+    /// Shows [`Tray::attention_icon_name`](crate::Tray::attention_icon_name)
+    #[default]
+    Normal,
+    Warning,
+    Critical,
+}
+
 // The Value dervie macro can only handle `dict` or `a{sv}` values
 // so we impl it manually
 impl From<Status> for Value<'_> {
@@ -80,12 +189,75 @@ impl From<Status> for Value<'_> {
     }
 }
 
+impl Status {
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Passive" => Some(Self::Passive),
+            "Active" => Some(Self::Active),
+            "NeedsAttention" => Some(Self::NeedsAttention),
+            _ => None,
+        }
+    }
+}
+
 impl fmt::Display for Status {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         self.serialize(f)
     }
 }
 
+/// Color scheme preferred by the user
+///
+/// See [`Tray::color_scheme_changed`]
+///
+/// [`Tray::color_scheme_changed`]: crate::Tray::color_scheme_changed
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum ColorScheme {
+    /// No preference, or the desktop does not tell
+    #[default]
+    NoPreference,
+    /// Prefers dark appearance, the panel is likely dark
+    Dark,
+    /// Prefers light appearance
+    Light,
+}
+
+/// Names of an icon for light and dark color schemes
+///
+/// # Examples
+///
+/// ```
+/// use ksni::{ColorScheme, ThemedIcon};
+///
+/// let icon = ThemedIcon::new("my-app-symbolic", "my-app-symbolic-dark");
+/// assert_eq!(icon.name(ColorScheme::Dark), "my-app-symbolic-dark");
+/// assert_eq!(icon.name(ColorScheme::NoPreference), "my-app-symbolic");
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct ThemedIcon {
+    /// Used on light panels and when there is no preference
+    pub light: String,
+    /// Used on dark panels
+    pub dark: String,
+}
+
+impl ThemedIcon {
+    pub fn new(light: impl Into<String>, dark: impl Into<String>) -> Self {
+        ThemedIcon {
+            light: light.into(),
+            dark: dark.into(),
+        }
+    }
+
+    /// The icon name for `scheme`
+    pub fn name(&self, scheme: ColorScheme) -> String {
+        match scheme {
+            ColorScheme::Dark => self.dark.clone(),
+            ColorScheme::Light | ColorScheme::NoPreference => self.light.clone(),
+        }
+    }
+}
+
 /// Extra information associated to the item
 ///
 /// That can be visualized for instance by a tooltip (or by any other mean the
@@ -94,7 +266,7 @@ impl fmt::Display for Status {
 /// See [`Tray::tool_tip`]
 ///
 /// [`Tray::tool_tip`]: crate::Tray::tool_tip
-#[derive(Clone, Debug, Default, Hash, Type, Value, Serialize)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Type, Value, Serialize)]
 pub struct ToolTip {
     /// Freedesktop-compliant name for an icon.
     pub icon_name: String,
@@ -107,6 +279,40 @@ pub struct ToolTip {
     pub description: String,
 }
 
+impl ToolTip {
+    /// Create a tooltip with a title
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let tool_tip = ksni::ToolTip::new("Downloads")
+    ///     .description("3 files remaining")
+    ///     .icon_name("folder-download");
+    /// assert_eq!(tool_tip.title, "Downloads");
+    /// ```
+    pub fn new(title: impl Into<String>) -> Self {
+        ToolTip {
+            title: title.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    pub fn icon_name(mut self, icon_name: impl Into<String>) -> Self {
+        self.icon_name = icon_name.into();
+        self
+    }
+
+    pub fn icon_pixmap(mut self, icon_pixmap: Vec<Icon>) -> Self {
+        self.icon_pixmap = icon_pixmap;
+        self
+    }
+}
+
 /// An ARGB32 image
 ///
 /// # Example
@@ -138,10 +344,201 @@ pub struct ToolTip {
 /// ```
 ///
 /// [image crate]: https://crates.io/crates/image/
-#[derive(Clone, Debug, Hash, Type, Value, Serialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Type, Value, Serialize)]
 pub struct Icon {
     pub width: i32,
     pub height: i32,
     /// ARGB32 format, network byte order
     pub data: Vec<u8>,
 }
+
+/// A set of [`Tray`] properties
+///
+/// See [`Tray::const_properties`]
+///
+/// [`Tray`]: crate::Tray
+/// [`Tray::const_properties`]: crate::Tray::const_properties
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct PropertyFlags(u32);
+
+impl PropertyFlags {
+    pub const CATEGORY: Self = Self(1 << 0);
+    pub const TITLE: Self = Self(1 << 1);
+    pub const STATUS: Self = Self(1 << 2);
+    pub const WINDOW_ID: Self = Self(1 << 3);
+    pub const ICON_THEME_PATH: Self = Self(1 << 4);
+    pub const ICON_NAME: Self = Self(1 << 5);
+    pub const ICON_PIXMAP: Self = Self(1 << 6);
+    pub const ICON_SCALE_HINT: Self = Self(1 << 7);
+    pub const OVERLAY_ICON_NAME: Self = Self(1 << 8);
+    pub const OVERLAY_ICON_PIXMAP: Self = Self(1 << 9);
+    pub const ATTENTION_ICON_NAME: Self = Self(1 << 10);
+    pub const ATTENTION_ICON_PIXMAP: Self = Self(1 << 11);
+    pub const ATTENTION_MOVIE_NAME: Self = Self(1 << 12);
+    pub const TOOL_TIP: Self = Self(1 << 13);
+    pub const TEXT_DIRECTION: Self = Self(1 << 14);
+    pub const ICON_ACCESSIBLE_DESC: Self = Self(1 << 15);
+    pub const ATTENTION_ACCESSIBLE_DESC: Self = Self(1 << 16);
+    pub const ATTENTION_LEVEL: Self = Self(1 << 17);
+    pub const LABEL: Self = Self(1 << 18);
+    pub const LABEL_GUIDE: Self = Self(1 << 19);
+
+    /// No property
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// All properties
+    pub const fn all() -> Self {
+        Self((1 << 20) - 1)
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns `true` if all properties in `other` are contained in `self`
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    /// Properties in `self` but not in `other`
+    pub const fn difference(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+}
+
+impl std::ops::BitOr for PropertyFlags {
+    type Output = Self;
+    fn bitor(self, rhs: Self) -> Self {
+        self.union(rhs)
+    }
+}
+
+impl std::ops::BitOrAssign for PropertyFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        *self = self.union(rhs);
+    }
+}
+
+// Logical size of the tray icon on most hosts (Plasma, GNOME Shell AppIndicator extension)
+const LOGICAL_ICON_SIZE: u32 = 22;
+
+/// Sort icons by how well they fit a panel with the given scale factor
+///
+/// Icons that are at least as large as the physical icon size come first (smallest first),
+/// followed by the smaller ones (largest first).
+pub(crate) fn sort_icons_for_scale(icons: &mut [Icon], scale: u32) {
+    let target = LOGICAL_ICON_SIZE.saturating_mul(scale.max(1));
+    icons.sort_by_key(|icon| {
+        let size = icon.width.max(icon.height).max(0) as u32;
+        if size >= target {
+            (false, size - target)
+        } else {
+            (true, target - size)
+        }
+    });
+}
+
+impl Icon {
+    /// A copy fitting in `max_size` x `max_size` with the same aspect ratio, `None` if the icon
+    /// already fits or its data doesn't match the size
+    pub(crate) fn downscale(&self, max_size: u32) -> Option<Icon> {
+        let (width, height) = (self.width.max(0) as usize, self.height.max(0) as usize);
+        let max = max_size.max(1) as usize;
+        if width.max(height) <= max || self.data.len() != width * height * 4 {
+            return None;
+        }
+        let (new_width, new_height) = if width >= height {
+            (max, (height * max / width).max(1))
+        } else {
+            ((width * max / height).max(1), max)
+        };
+        // source pixels covered by a destination pixel
+        let span = |i: usize, old: usize, new: usize| {
+            i * old / new..((i + 1) * old / new).max(i * old / new + 1)
+        };
+
+        let mut data = Vec::with_capacity(new_width * new_height * 4);
+        for y in 0..new_height {
+            for x in 0..new_width {
+                // alpha, then the colors weighted by alpha
+                let mut sum = [0u64; 4];
+                let mut count = 0;
+                for sy in span(y, height, new_height) {
+                    for sx in span(x, width, new_width) {
+                        let pixel = &self.data[(sy * width + sx) * 4..][..4];
+                        let alpha = u64::from(pixel[0]);
+                        sum[0] += alpha;
+                        for c in 1..4 {
+                            sum[c] += u64::from(pixel[c]) * alpha;
+                        }
+                        count += 1;
+                    }
+                }
+                data.push((sum[0] / count) as u8);
+                for c in &sum[1..] {
+                    data.push(c.checked_div(sum[0]).unwrap_or(0) as u8);
+                }
+            }
+        }
+        Some(Icon {
+            width: new_width as i32,
+            height: new_height as i32,
+            data,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_category() {
+        assert_eq!(Category::Hardware.to_string(), "Hardware");
+        assert_eq!("Hardware".parse(), Ok(Category::Hardware));
+        let other: Category = "Printing".parse().unwrap();
+        assert_eq!(other, Category::Other("Printing".into()));
+        assert_eq!(other.to_string(), "Printing");
+    }
+
+    #[test]
+    fn test_sort_icons_for_scale() {
+        let icon = |size| Icon {
+            width: size,
+            height: size,
+            data: Vec::new(),
+        };
+        let sizes = |icons: &[Icon]| icons.iter().map(|i| i.width).collect::<Vec<_>>();
+
+        let mut icons = vec![icon(16), icon(22), icon(32), icon(48), icon(64)];
+        sort_icons_for_scale(&mut icons, 1);
+        assert_eq!(sizes(&icons), [22, 32, 48, 64, 16]);
+        sort_icons_for_scale(&mut icons, 2);
+        assert_eq!(sizes(&icons), [48, 64, 32, 22, 16]);
+        sort_icons_for_scale(&mut icons, 4);
+        assert_eq!(sizes(&icons), [64, 48, 32, 22, 16]);
+    }
+
+    #[test]
+    fn test_downscale() {
+        let icon = |width, height, pixel: [u8; 4]| Icon {
+            width,
+            height,
+            data: pixel.repeat((width * height) as usize),
+        };
+        let red = icon(512, 256, [0xff, 0xff, 0, 0]);
+        assert_eq!(red.downscale(512), None);
+        assert_eq!(red.downscale(64), Some(icon(64, 32, [0xff, 0xff, 0, 0])));
+
+        // transparent pixels don't darken the color
+        let mut half = icon(2, 1, [0xff, 0, 0xff, 0]);
+        half.data[..4].copy_from_slice(&[0, 0, 0, 0]);
+        assert_eq!(half.downscale(1), Some(icon(1, 1, [0x7f, 0, 0xff, 0])));
+    }
+}
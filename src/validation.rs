@@ -0,0 +1,315 @@
+use std::fmt;
+
+use crate::menu::{self, MenuPath, MenuWarning};
+use crate::{icons, Icon, MenuItem, Tray};
+
+/// A problem of a tray found by [`validate`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Diagnostic {
+    /// [`Tray::id`] is empty, hosts use it to remember the position of the tray
+    EmptyId,
+    /// The tray has neither an icon name nor an icon pixmap, hosts show an empty space
+    NoIcon,
+    /// An icon name is not a standard icon, see [`icons::is_standard`], and there's neither an
+    /// icon theme path nor a pixmap to fall back to
+    ///
+    /// Fine if the app installs the icon, hosts show an empty space if the icon theme doesn't
+    /// have it. `property` is `"IconName"` or `"AttentionIconName"`.
+    UnknownIconName {
+        property: &'static str,
+        name: String,
+    },
+    /// The data of a pixmap is not `width * height * 4` bytes long, hosts ignore it
+    ///
+    /// `property` is the name of the D-Bus property, e.g. `"IconPixmap"`, `index` the position
+    /// of the pixmap in it.
+    IconSizeMismatch {
+        property: &'static str,
+        index: usize,
+        width: i32,
+        height: i32,
+        len: usize,
+    },
+    /// The description of [`Tray::tool_tip`] has unbalanced tags or an unescaped `&`
+    InvalidToolTipMarkup { reason: String },
+    /// A visible item, except separators, has a label without any displayed character
+    EmptyLabel { path: MenuPath },
+    /// A problem of the menu, see [`menu::validate`]
+    Menu(MenuWarning),
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Diagnostic::*;
+        match self {
+            EmptyId => write!(f, "the id is empty"),
+            NoIcon => write!(f, "neither an icon name nor an icon pixmap is set"),
+            UnknownIconName { property, name } => write!(
+                f,
+                "{property} `{name}` is not a standard icon and has no pixmap fallback"
+            ),
+            IconSizeMismatch {
+                property,
+                index,
+                width,
+                height,
+                len,
+            } => write!(
+                f,
+                "pixmap {index} of {property} is {width}x{height} but has {len} bytes of data"
+            ),
+            InvalidToolTipMarkup { reason } => write!(f, "invalid tooltip markup: {reason}"),
+            EmptyLabel { path } => write!(f, "item {path} has an empty label"),
+            Menu(warning) => warning.fmt(f),
+        }
+    }
+}
+
+/// Check a tray for the most common integration errors, before it's spawned
+///
+/// Calls every getter of the tray once, and checks the id, the icons, the markup of the tooltip
+/// and the menu. Returns an empty list if nothing was found.
+///
+/// # Examples
+///
+/// ```
+/// struct MyTray;
+///
+/// impl ksni::Tray for MyTray {
+///     fn id(&self) -> String {
+///         "my-tray".into()
+///     }
+///     fn icon_name(&self) -> String {
+///         "help-about".into()
+///     }
+/// }
+///
+/// assert!(ksni::validate(&MyTray).is_empty());
+/// ```
+pub fn validate<T: Tray>(tray: &T) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    if tray.id().is_empty() {
+        diagnostics.push(Diagnostic::EmptyId);
+    }
+    // only called, like the host does, e.g. to catch panics
+    let _ = (
+        tray.category(),
+        tray.title(),
+        tray.status(),
+        tray.window_id(),
+    );
+    let _ = tray.icon_accessible_desc();
+    let _ = tray.attention_level();
+    let _ = (
+        tray.attention_accessible_desc(),
+        tray.attention_movie_name(),
+    );
+    let _ = tray.overlay_icon_name();
+
+    let icon_name = match tray.icon_name() {
+        name if name.is_empty() => icons::resolve(&tray.icon_names()),
+        name => name,
+    };
+    let icon_pixmap = tray.icon_pixmap();
+    if icon_name.is_empty() && icon_pixmap.is_empty() {
+        diagnostics.push(Diagnostic::NoIcon);
+    }
+    let attention_icon_pixmap = tray.attention_icon_pixmap();
+    if tray.icon_theme_path().is_empty() {
+        for (property, name, pixmap) in [
+            ("IconName", icon_name, &icon_pixmap),
+            (
+                "AttentionIconName",
+                tray.attention_icon_name(),
+                &attention_icon_pixmap,
+            ),
+        ] {
+            if !name.is_empty() && pixmap.is_empty() && !icons::is_standard(&name) {
+                diagnostics.push(Diagnostic::UnknownIconName { property, name });
+            }
+        }
+    }
+    let tool_tip = tray.tool_tip_opt().unwrap_or_default();
+    for (property, icons) in [
+        ("IconPixmap", &icon_pixmap),
+        ("OverlayIconPixmap", &tray.overlay_icon_pixmap()),
+        ("AttentionIconPixmap", &attention_icon_pixmap),
+        ("ToolTip", &tool_tip.icon_pixmap),
+    ] {
+        check_icons(property, icons, &mut diagnostics);
+    }
+    if let Err(reason) = check_markup(&tool_tip.description) {
+        diagnostics.push(Diagnostic::InvalidToolTipMarkup { reason });
+    }
+
+    let menu = tray.menu_with(&Default::default());
+    for (path, item) in menu::iter(&menu) {
+        let (label, visible) = match item {
+            MenuItem::Standard(item) => (&item.label, item.visible),
+            MenuItem::Checkmark(item) => (&item.label, item.visible),
+            MenuItem::SubMenu(item) => (&item.label, item.visible),
+            MenuItem::RadioGroup(group) => {
+                if group
+                    .options
+                    .iter()
+                    .any(|option| option.visible && is_empty_label(&option.label))
+                {
+                    diagnostics.push(Diagnostic::EmptyLabel { path });
+                }
+                continue;
+            }
+            MenuItem::Separator => continue,
+        };
+        if visible && is_empty_label(label) {
+            diagnostics.push(Diagnostic::EmptyLabel { path });
+        }
+    }
+    diagnostics.extend(menu::validate(&menu).into_iter().map(Diagnostic::Menu));
+    diagnostics
+}
+
+fn check_icons(property: &'static str, icons: &[Icon], diagnostics: &mut Vec<Diagnostic>) {
+    for (index, icon) in icons.iter().enumerate() {
+        let expected = i64::from(icon.width) * i64::from(icon.height) * 4;
+        if icon.width <= 0 || icon.height <= 0 || icon.data.len() as i64 != expected {
+            diagnostics.push(Diagnostic::IconSizeMismatch {
+                property,
+                index,
+                width: icon.width,
+                height: icon.height,
+                len: icon.data.len(),
+            });
+        }
+    }
+}
+
+// underscores only mark the access key
+fn is_empty_label(label: &str) -> bool {
+    label
+        .replace("__", "x")
+        .chars()
+        .all(|c| c == '_' || c.is_whitespace())
+}
+
+// tags of the tooltip markup must be balanced, `&` must start an entity
+fn check_markup(markup: &str) -> Result<(), String> {
+    const EMPTY_TAGS: [&str; 3] = ["br", "hr", "img"];
+
+    let mut open = Vec::new();
+    let mut rest = markup;
+    while let Some(start) = rest.find(['<', '&']) {
+        let after = &rest[start + 1..];
+        if rest[start..].starts_with('&') {
+            let end = after
+                .find(';')
+                .filter(|&end| end > 0 && !after[..end].contains([' ', '&', '<']))
+                .ok_or("`&` is not escaped as `&amp;`")?;
+            rest = &after[end + 1..];
+            continue;
+        }
+        let end = after.find('>').ok_or("a tag is not closed with `>`")?;
+        let tag = &after[..end];
+        rest = &after[end + 1..];
+        if let Some(name) = tag.strip_prefix('/') {
+            let name = name.trim().to_ascii_lowercase();
+            match open.pop() {
+                Some(last) if last == name => {}
+                Some(last) => return Err(format!("`</{name}>` closes `<{last}>`")),
+                None => return Err(format!("`</{name}>` closes no tag")),
+            }
+            continue;
+        }
+        let name = tag
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if name.is_empty() {
+            return Err("a tag has no name".into());
+        }
+        if !tag.ends_with('/') && !EMPTY_TAGS.contains(&name.as_str()) {
+            open.push(name);
+        }
+    }
+    match open.pop() {
+        Some(name) => Err(format!("`<{name}>` is not closed")),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::menu::StandardItem;
+    use crate::ToolTip;
+
+    struct MyTray;
+
+    impl Tray for MyTray {
+        fn id(&self) -> String {
+            String::new()
+        }
+        fn icon_pixmap(&self) -> Vec<Icon> {
+            vec![Icon {
+                width: 2,
+                height: 2,
+                data: vec![0; 8],
+            }]
+        }
+        fn tool_tip(&self) -> ToolTip {
+            ToolTip {
+                description: "<b>unclosed".into(),
+                ..Default::default()
+            }
+        }
+        fn menu(&self) -> Vec<MenuItem<Self>> {
+            vec![
+                StandardItem {
+                    label: "_".into(),
+                    ..Default::default()
+                }
+                .into(),
+                StandardItem {
+                    label: "__".into(),
+                    ..Default::default()
+                }
+                .into(),
+            ]
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(
+            validate(&MyTray),
+            vec![
+                Diagnostic::EmptyId,
+                Diagnostic::IconSizeMismatch {
+                    property: "IconPixmap",
+                    index: 0,
+                    width: 2,
+                    height: 2,
+                    len: 8,
+                },
+                Diagnostic::InvalidToolTipMarkup {
+                    reason: "`<b>` is not closed".into()
+                },
+                Diagnostic::EmptyLabel {
+                    path: MenuPath::from(vec![0])
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_markup() {
+        assert!(check_markup("").is_ok());
+        assert!(check_markup("<b>bold</b> &amp; <i>italic</i><br/><br>").is_ok());
+        assert!(check_markup("<a href=\"x\">link</a> <img src=\"x\"/>").is_ok());
+        assert!(check_markup("a & b").is_err());
+        assert!(check_markup("<b><i>x</b></i>").is_err());
+        assert!(check_markup("</b>").is_err());
+        assert!(check_markup("<b").is_err());
+    }
+}
@@ -0,0 +1,117 @@
+//! D-Bus values, re-exported from the version of zbus used by ksni
+//!
+//! The types are the ones of [`zbus::zvariant`], e.g. for
+//! [`TrayProbe::property`](crate::testing::TrayProbe::property), so no direct dependency on
+//! zbus is needed. Use [`IntoValue`] to build an [`OwnedValue`] from common Rust types.
+//!
+//! # Examples
+//!
+//! ```
+//! use ksni::value::prelude::*;
+//! use ksni::value::OwnedValue;
+//!
+//! let value: OwnedValue = vec!["a", "b"].into_value();
+//! assert_eq!(Vec::<String>::try_from(value).unwrap(), ["a", "b"]);
+//! ```
+
+use std::collections::HashMap;
+
+pub use zbus::zvariant::{
+    Error, ObjectPath, OwnedObjectPath, OwnedValue, Signature, Str, Structure, Type, Value,
+};
+
+/// Brings [`IntoValue`] into scope
+pub mod prelude {
+    pub use super::IntoValue;
+}
+
+/// Conversion to an [`OwnedValue`]
+///
+/// A [`Value`] may hold file descriptors, which can fail to be duplicated, use
+/// `OwnedValue::try_from` for it.
+pub trait IntoValue {
+    fn into_value(self) -> OwnedValue;
+}
+
+impl IntoValue for OwnedValue {
+    fn into_value(self) -> OwnedValue {
+        self
+    }
+}
+
+mod sealed {
+    // types that never hold a file descriptor
+    pub trait Plain {}
+}
+
+// only fails if a file descriptor can't be duplicated
+fn owned(value: Value<'_>) -> OwnedValue {
+    OwnedValue::try_from(value).expect("a value without file descriptors")
+}
+
+macro_rules! impl_into_value {
+    ($($type:ty),+) => {
+        $(
+            impl sealed::Plain for $type {}
+
+            impl IntoValue for $type {
+                fn into_value(self) -> OwnedValue {
+                    owned(Value::from(self))
+                }
+            }
+        )+
+    };
+}
+
+impl_into_value!(
+    bool,
+    u8,
+    i16,
+    u16,
+    i32,
+    u32,
+    i64,
+    u64,
+    f64,
+    String,
+    &str,
+    ObjectPath<'_>
+);
+
+impl<T: sealed::Plain> sealed::Plain for Vec<T> {}
+
+impl<T> IntoValue for Vec<T>
+where
+    T: sealed::Plain + Type + Into<Value<'static>>,
+{
+    fn into_value(self) -> OwnedValue {
+        owned(Value::from(self))
+    }
+}
+
+impl<V: sealed::Plain> sealed::Plain for HashMap<String, V> {}
+
+impl<V> IntoValue for HashMap<String, V>
+where
+    V: sealed::Plain + Type + Into<Value<'static>>,
+{
+    fn into_value(self) -> OwnedValue {
+        owned(Value::from(self))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_into_value() {
+        assert_eq!(bool::try_from(true.into_value()), Ok(true));
+        assert_eq!(String::try_from("x".into_value()).unwrap(), "x");
+        let map = HashMap::from([("k".to_owned(), 1u32)]);
+        assert_eq!(
+            HashMap::<String, u32>::try_from(map.clone().into_value()).unwrap(),
+            map
+        );
+    }
+}